@@ -2,21 +2,52 @@ use crate::dts::ast::{
     AnyDirective, Cell, DtsFile, Include, Node, NodeItem, NodePayload, Path, Primary, Property,
     PropertyValue, Reference, ReferencedNode, WithToken,
 };
-use crate::dts::data::{HasSource, HasSpan, Span};
+use crate::dts::data::{HasSource, HasSpan, Location};
 use crate::dts::error_codes::ErrorCode;
 use crate::dts::import_guard::ImportGuard;
+use crate::dts::property_type::StandardProperty;
+use crate::dts::tree::DeviceTree;
 use crate::dts::{Diagnostic, FileType, Position, Project};
+use itertools::Itertools;
 use std::collections::HashMap;
-use std::path::{Path as StdPath, PathBuf};
+use std::path::PathBuf;
 use std::sync::Arc;
 
-/// Something that can be labeled.
+/// Something that can be labeled, together with the path of the node that it belongs to.
 /// Used when analyzing a device-tree
 #[derive(Clone)]
 enum Labeled {
-    Node(Arc<Node>),
-    #[allow(unused)]
-    Property(Arc<Property>),
+    Node(Arc<Node>, Path),
+    Property(Arc<Property>, Path),
+    // A label placed in between or inside the values of a property
+    Value(Arc<Property>, Path, WithToken<String>),
+}
+
+impl Labeled {
+    fn label(&self) -> Option<&WithToken<String>> {
+        match self {
+            Labeled::Node(node, _) => node.label.as_ref(),
+            Labeled::Property(property, _) => property.label.as_ref(),
+            Labeled::Value(_, _, label) => Some(label),
+        }
+    }
+
+    // Nodes and properties can be redefined, keeping their labels.
+    // Therefore, labels on items with the same path are not considered duplicates.
+    fn is_redefinition_of(&self, other: &Labeled) -> bool {
+        match (self, other) {
+            (Labeled::Node(_, path), Labeled::Node(_, other_path)) => path == other_path,
+            (Labeled::Property(property, path), Labeled::Property(other, other_path)) => {
+                path == other_path && property.name.item() == other.name.item()
+            }
+            (Labeled::Value(property, path, _), Labeled::Value(other, other_path, _)) => {
+                !Arc::ptr_eq(property, other)
+                    && path == other_path
+                    && property.name.item() == other.name.item()
+            }
+            _ => false,
+        }
+    }
 }
 
 /// Struct containing all important information when analyzing a device-tree.
@@ -37,6 +68,7 @@ impl Analysis {
 pub struct AnalysisContext {
     labels: HashMap<String, Labeled>,
     flat_nodes: HashMap<Path, Arc<Node>>,
+    tree: DeviceTree,
 }
 
 pub struct AnalysisResult {
@@ -47,11 +79,31 @@ pub struct AnalysisResult {
 impl AnalysisContext {
     pub fn get_node_by_label(&self, label: &str) -> Option<&Arc<Node>> {
         match self.labels.get(label) {
-            Some(Labeled::Node(node)) => Some(node),
+            Some(Labeled::Node(node, _)) => Some(node),
             _ => None,
         }
     }
 
+    /// Returns the definition of a label, no matter whether it labels a node,
+    /// a property or a position inside a property value.
+    pub fn get_label(&self, label: &str) -> Option<&WithToken<String>> {
+        self.labels.get(label).and_then(Labeled::label)
+    }
+
+    /// Returns the doc comment of the node or property that a label is attached to.
+    pub fn get_label_doc_comment(&self, label: &str) -> Option<&String> {
+        match self.labels.get(label)? {
+            Labeled::Node(node, _) => node.doc_comment.as_ref(),
+            Labeled::Property(property, _) => property.doc_comment.as_ref(),
+            Labeled::Value(..) => None,
+        }
+    }
+
+    /// The device tree after applying all nodes of the file and its includes
+    pub fn tree(&self) -> &DeviceTree {
+        &self.tree
+    }
+
     pub fn get_node_by_path(&self, path: &Path) -> Option<&Arc<Node>> {
         self.flat_nodes.get(path)
     }
@@ -72,6 +124,8 @@ pub struct FileContext<'a> {
     unresolved_references: Vec<WithToken<Reference>>,
     file_type: FileType,
     is_plugin: bool,
+    // The base tree that an overlay is applied to, if one is configured
+    base: Option<&'a AnalysisContext>,
     first_non_include: bool,
     dts_header_seen: bool,
 }
@@ -81,19 +135,43 @@ impl FileContext<'_> {
         self.diagnostics.push(diagnostic);
     }
 
+    fn add_label(&mut self, label: &WithToken<String>, labeled: Labeled) {
+        if let Some(existing) = self.labels.get(label.item()) {
+            if !labeled.is_redefinition_of(existing) {
+                self.add_diagnostic(Diagnostic::new(
+                    label.span(),
+                    label.source(),
+                    ErrorCode::DuplicateLabel,
+                    format!("Duplicate label '{}'", label.item()),
+                ));
+                return;
+            }
+        }
+        self.labels.insert(label.item().clone(), labeled);
+    }
+
     pub fn resolve_reference(&self, label: &String) -> Option<(&Path, &Arc<Node>)> {
         self.flat_nodes
             .iter()
             .find(|(_, value)| value.label.as_ref().map(|node| node.item()) == Some(label))
     }
+
+    // Overlays may refer to all nodes of their base tree
+    fn resolve_in_base(&self, reference: &Reference) -> Option<Path> {
+        if !self.is_plugin {
+            return None;
+        }
+        self.base?.tree().resolve(reference)
+    }
 }
 
 impl FileContext<'_> {
-    pub fn into_result(self) -> AnalysisResult {
+    pub fn into_result(self, tree: DeviceTree) -> AnalysisResult {
         AnalysisResult {
             context: AnalysisContext {
                 flat_nodes: self.flat_nodes,
                 labels: self.labels,
+                tree,
             },
             diagnostics: self.diagnostics,
         }
@@ -115,6 +193,7 @@ impl Analysis {
             unresolved_references: Vec::default(),
             project,
             is_plugin: file_type == FileType::DtSourceOverlay,
+            base: project.overlay_base(&file.source),
             dts_header_seen: false,
             first_non_include: false,
         };
@@ -143,7 +222,10 @@ impl Analysis {
                         ctx.first_non_include = true;
                         ctx.is_plugin = true
                     }
-                    AnyDirective::OmitIfNoRef(..) => ctx.first_non_include = true,
+                    AnyDirective::OmitIfNoRef(_, reference) => {
+                        self.resolve_reference(&mut ctx, reference);
+                        ctx.first_non_include = true
+                    }
                     AnyDirective::DeletedNode(_, reference) => {
                         self.resolve_reference(&mut ctx, reference);
                     }
@@ -156,7 +238,6 @@ impl Analysis {
                     self.analyze_referenced_node(&mut ctx, referenced_node);
                     ctx.first_non_include = true
                 }
-                Primary::CStyleInclude(_) => {}
             }
         }
         if !ctx.dts_header_seen && ctx.file_type == FileType::DtSource {
@@ -168,7 +249,27 @@ impl Analysis {
             ))
         }
         self.resolve_references(&mut ctx);
-        ctx.into_result()
+        let (tree, diagnostics) = DeviceTree::build(file, project, ctx.is_plugin);
+        ctx.diagnostics.extend(diagnostics);
+        // Only the tree of a complete device tree source shows which items have been deleted.
+        // Referenced nodes in includes and overlays may refer to nodes that are not known yet.
+        if ctx.file_type == FileType::DtSource && !ctx.is_plugin {
+            ctx.flat_nodes.retain(|path, _| tree.node(path).is_some());
+            ctx.labels.retain(|label, _| tree.has_label(label));
+            // Bindings describe complete nodes, which only a complete device tree contains
+            let binding_diagnostics = project.bindings.validate(&tree);
+            // A binding describes a property more precisely than the standard property table,
+            // so it replaces the table's diagnostics for the same problem.
+            ctx.diagnostics.retain(|diagnostic| {
+                !binding_diagnostics.iter().any(|binding_diagnostic| {
+                    binding_diagnostic.kind == diagnostic.kind
+                        && Location::new(binding_diagnostic.source.clone(), binding_diagnostic.span)
+                            .contains_span(&diagnostic.source, diagnostic.span)
+                })
+            });
+            ctx.diagnostics.extend(binding_diagnostics);
+        }
+        ctx.into_result(tree)
     }
 
     fn analyze_include(&mut self, ctx: &mut FileContext<'_>, parent: &DtsFile, include: &Include) {
@@ -210,19 +311,31 @@ impl Analysis {
     fn unresolved_reference_error(
         &self,
         ctx: &mut FileContext<'_>,
-        span: Span,
-        source: Arc<StdPath>,
+        reference: &WithToken<Reference>,
     ) {
         // Do not emit unresolved reference errors when we are not a plugin.
         // This will emit false positives as references can only be resolved with the full
         // device-tree information.
         if ctx.file_type == FileType::DtSource && !ctx.is_plugin {
             ctx.add_diagnostic(Diagnostic::new(
-                span,
-                source,
+                reference.span(),
+                reference.source(),
                 ErrorCode::UnresolvedReference,
                 "Reference cannot be resolved",
             ));
+        } else if ctx.is_plugin && ctx.base.is_some() {
+            let message = match reference.item() {
+                Reference::Label(label) => {
+                    format!("Label '{label}' is not defined in the base tree")
+                }
+                Reference::Path(path) => format!("Path {path} does not exist in the base tree"),
+            };
+            ctx.add_diagnostic(Diagnostic::new(
+                reference.span(),
+                reference.source(),
+                ErrorCode::UnresolvedOverlayReference,
+                message,
+            ));
         }
     }
 
@@ -233,15 +346,20 @@ impl Analysis {
     ) -> Path {
         match reference.item() {
             Reference::Label(label) => match ctx.resolve_reference(label) {
-                None => {
-                    self.unresolved_reference_error(ctx, reference.span(), reference.source());
-                    Path::empty()
-                }
                 Some((path, _)) => path.clone(),
+                None => match ctx.resolve_in_base(reference.item()) {
+                    Some(path) => path,
+                    None => {
+                        self.unresolved_reference_error(ctx, reference);
+                        Path::empty()
+                    }
+                },
             },
             Reference::Path(path) => {
-                if !ctx.flat_nodes.contains_key(path) {
-                    self.unresolved_reference_error(ctx, reference.span(), reference.source());
+                if !ctx.flat_nodes.contains_key(path)
+                    && ctx.resolve_in_base(reference.item()).is_none()
+                {
+                    self.unresolved_reference_error(ctx, reference);
                 };
                 path.clone()
             }
@@ -249,10 +367,10 @@ impl Analysis {
     }
 
     pub fn analyze_referenced_node(&mut self, ctx: &mut FileContext<'_>, node: &ReferencedNode) {
-        let path = if ctx.file_type == FileType::DtSource {
+        let path = if ctx.file_type == FileType::DtSource || ctx.is_plugin && ctx.base.is_some() {
             self.resolve_reference(ctx, &node.reference)
         } else {
-            // This is an include; simply assume the 'root' path
+            // This is an include or an overlay without base; simply assume the 'root' path
             Path::empty()
         };
         self.analyze_node_payload(ctx, &node.payload, path);
@@ -260,28 +378,19 @@ impl Analysis {
 
     pub fn resolve_references(&self, ctx: &mut FileContext<'_>) {
         for reference in ctx.unresolved_references.clone() {
-            let span = reference.span();
-            let source = reference.source();
-            match &reference.item() {
-                Reference::Label(label) => match ctx.labels.get(label) {
-                    Some(_) => {}
-                    None => {
-                        self.unresolved_reference_error(ctx, span, source);
-                    }
-                },
-                Reference::Path(path) => {
-                    if !ctx.flat_nodes.contains_key(path) {
-                        self.unresolved_reference_error(ctx, span, source);
-                    }
-                }
+            let resolved = match reference.item() {
+                Reference::Label(label) => ctx.labels.contains_key(label),
+                Reference::Path(path) => ctx.flat_nodes.contains_key(path),
+            };
+            if !resolved && ctx.resolve_in_base(reference.item()).is_none() {
+                self.unresolved_reference_error(ctx, &reference);
             }
         }
     }
 
     pub fn analyze_node(&mut self, ctx: &mut FileContext<'_>, node: Arc<Node>, path: Path) {
         if let Some(label) = &node.label {
-            ctx.labels
-                .insert(label.item().clone(), Labeled::Node(node.clone()));
+            ctx.add_label(label, Labeled::Node(node.clone(), path.clone()));
         }
         ctx.flat_nodes.insert(path.clone(), node.clone());
         self.analyze_node_payload(ctx, &node.payload, path)
@@ -295,7 +404,7 @@ impl Analysis {
     ) {
         for item in &payload.items {
             match item {
-                NodeItem::Property(property) => self.analyze_property(ctx, property.clone()),
+                NodeItem::Property(property) => self.analyze_property(ctx, property.clone(), &path),
                 NodeItem::Node(node) => {
                     self.analyze_node(ctx, node.clone(), path.with_child(node.name.item().clone()))
                 }
@@ -307,7 +416,7 @@ impl Analysis {
 
     fn check_is_string_list(&mut self, ctx: &mut FileContext<'_>, values: &Vec<PropertyValue>) {
         for value in values {
-            if !matches!(value, PropertyValue::String(_)) {
+            if !matches!(value, PropertyValue::String(_) | PropertyValue::Label(_)) {
                 ctx.add_diagnostic(Diagnostic::new(
                     value.span(),
                     value.source(),
@@ -318,18 +427,68 @@ impl Analysis {
         }
     }
 
-    fn check_is_single_string(&mut self, _ctx: &mut FileContext<'_>, values: &[PropertyValue]) {
-        if values.len() != 1 {}
-    }
-
-    fn check_is_single_u32(&mut self, _ctx: &mut FileContext<'_>, values: &[PropertyValue]) {
-        if values.len() != 1 {}
+    fn check_standard_property(
+        &mut self,
+        ctx: &mut FileContext<'_>,
+        property: &Property,
+        standard: &StandardProperty,
+    ) {
+        if !standard
+            .types
+            .iter()
+            .any(|property_type| property_type.accepts(&property.values))
+        {
+            let types = standard
+                .types
+                .iter()
+                .map(|property_type| property_type.to_string())
+                .join(" or ");
+            ctx.add_diagnostic(Diagnostic::new(
+                property.span(),
+                property.name.source(),
+                ErrorCode::UnexpectedPropertyType,
+                format!("{} property should be {types}", property.name.item()),
+            ));
+            return;
+        }
+        for value in &property.values {
+            let PropertyValue::String(string) = value else {
+                continue;
+            };
+            let string = String::from_utf8_lossy(string.item());
+            if !standard.allows(&string) {
+                ctx.add_diagnostic(Diagnostic::new(
+                    value.span(),
+                    value.source(),
+                    ErrorCode::InvalidPropertyValue,
+                    format!(
+                        "'{string}' is not a valid {} value, expected one of {}",
+                        property.name.item(),
+                        standard
+                            .values
+                            .iter()
+                            .map(|value| format!("'{value}'"))
+                            .join(", ")
+                    ),
+                ));
+            }
+        }
     }
 
-    pub fn analyze_property(&mut self, ctx: &mut FileContext<'_>, property: Arc<Property>) {
+    pub fn analyze_property(
+        &mut self,
+        ctx: &mut FileContext<'_>,
+        property: Arc<Property>,
+        path: &Path,
+    ) {
         if let Some(label) = &property.label {
-            ctx.labels
-                .insert(label.item().clone(), Labeled::Property(property.clone()));
+            ctx.add_label(label, Labeled::Property(property.clone(), path.clone()));
+        }
+        for label in property.value_labels() {
+            ctx.add_label(
+                label,
+                Labeled::Value(property.clone(), path.clone(), label.clone()),
+            );
         }
         for value in &property.values {
             self.analyze_property_value(ctx, value)
@@ -337,9 +496,15 @@ impl Analysis {
 
         match property.name.as_str() {
             "compatible" => self.check_is_string_list(ctx, &property.values),
-            "model" => self.check_is_single_string(ctx, &property.values),
-            "phandle" => self.check_is_single_u32(ctx, &property.values),
-            _ => {}
+            // The value of `phandle` is checked when the tree is built
+            "phandle" => {}
+            name => {
+                if let Some(standard) =
+                    StandardProperty::get(name).filter(|standard| standard.applies_to(path))
+                {
+                    self.check_standard_property(ctx, &property, standard)
+                }
+            }
         }
     }
 
@@ -347,7 +512,9 @@ impl Analysis {
         match value {
             PropertyValue::String(_) => {}
             PropertyValue::ByteStrings(..) => {}
-            PropertyValue::Cells(_, cells, _) => {
+            PropertyValue::Incbin(..) => {}
+            PropertyValue::Label(_) => {}
+            PropertyValue::Cells(_, _, cells, _) => {
                 for cell in cells {
                     self.analyze_cell(ctx, cell)
                 }
@@ -360,7 +527,8 @@ impl Analysis {
         match value {
             Cell::Number(_) => {}
             Cell::Reference(reference) => self.analyze_reference(ctx, reference),
-            Cell::Expression => {}
+            Cell::Expression(..) => {}
+            Cell::Label(_) => {}
         }
     }
 
@@ -561,6 +729,95 @@ mod test {
         )
     }
 
+    #[test]
+    pub fn standard_property_types() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    model = \"vendor,board\";
+    #address-cells = \"2\";
+    #size-cells = <1>;
+    node-a {
+        model = \"a\", \"b\";
+        status = <1>;
+        interrupt-controller;
+        #interrupt-cells = <2>;
+    };
+    node-b {
+        model = <1>;
+        status = \"broken\";
+        ranges;
+        reg = <0 0x1000>;
+    };
+    node-c {
+        status = \"fail-overheated\";
+        interrupt-parent = <&intc>;
+        #gpio-cells = <1 2>;
+        size = \"large\";
+        reusable = <1>;
+    };
+    intc: intc {};
+    reserved-memory {
+        region {
+            size = \"large\";
+            reusable;
+        };
+    };
+};",
+        );
+        let (diagnostics, _) = code.get_analyzed_file();
+        assert_eq_unordered!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    code.s1("#address-cells = \"2\";").span(),
+                    code.source(),
+                    ErrorCode::UnexpectedPropertyType,
+                    "#address-cells property should be a single 32-bit integer"
+                ),
+                Diagnostic::new(
+                    code.s1("model = \"a\", \"b\";").span(),
+                    code.source(),
+                    ErrorCode::UnexpectedPropertyType,
+                    "model property should be a single string"
+                ),
+                Diagnostic::new(
+                    code.s1("model = <1>;").span(),
+                    code.source(),
+                    ErrorCode::UnexpectedPropertyType,
+                    "model property should be a single string"
+                ),
+                Diagnostic::new(
+                    code.s1("status = <1>;").span(),
+                    code.source(),
+                    ErrorCode::UnexpectedPropertyType,
+                    "status property should be a single string"
+                ),
+                Diagnostic::new(
+                    code.s1("\"broken\"").span(),
+                    code.source(),
+                    ErrorCode::InvalidPropertyValue,
+                    "'broken' is not a valid status value, expected one of \
+                     'okay', 'disabled', 'reserved', 'fail', 'fail-sss'"
+                ),
+                Diagnostic::new(
+                    code.s1("#gpio-cells = <1 2>;").span(),
+                    code.source(),
+                    ErrorCode::UnexpectedPropertyType,
+                    "#gpio-cells property should be a single 32-bit integer"
+                ),
+                Diagnostic::new(
+                    code.s("size = \"large\";", 2).span(),
+                    code.source(),
+                    ErrorCode::UnexpectedPropertyType,
+                    "size property should be an array of cells"
+                ),
+            ]
+        )
+    }
+
     #[test]
     pub fn referenced_node_in_same_file() {
         let code = Code::new(
@@ -600,4 +857,119 @@ mod test {
             ]
         )
     }
+
+    #[test]
+    pub fn labels_inside_property_values() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/{
+    node: some_node {
+        reg = start: <0 size: 0x10> end:;
+        data = [00 middle: 11];
+        ref-to-size = &size;
+    };
+};",
+        );
+        let (diagnostics, context) = code.get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        for label in ["start", "size", "end", "middle"] {
+            assert_eq!(
+                context.get_label(label).map(|label| label.span()),
+                Some(code.s1(&format!("{label}:")).span())
+            );
+            assert!(context.get_node_by_label(label).is_none());
+        }
+        assert!(context.get_label("node").is_some());
+    }
+
+    #[test]
+    pub fn duplicate_labels() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/{
+    label: some_node {
+        reg = <0 label: 0x10>;
+        prop = label: \"value\";
+    };
+    label: some_node {};
+    other: other_node {
+        other: sub_node {};
+    };
+};",
+        );
+        let (diagnostics, _) = code.get_analyzed_file();
+        assert_eq_unordered!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    code.s("label:", 2).span(),
+                    code.source(),
+                    ErrorCode::DuplicateLabel,
+                    "Duplicate label 'label'"
+                ),
+                Diagnostic::new(
+                    code.s("label:", 3).span(),
+                    code.source(),
+                    ErrorCode::DuplicateLabel,
+                    "Duplicate label 'label'"
+                ),
+                Diagnostic::new(
+                    code.s("other:", 2).span(),
+                    code.source(),
+                    ErrorCode::DuplicateLabel,
+                    "Duplicate label 'other'"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn duplicate_labels_on_nodes_with_the_same_name() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    a {
+        label: x {
+            prop: prop = <1>;
+        };
+    };
+    b {
+        label: x {
+            prop: prop = <2>;
+        };
+    };
+};
+/ {
+    a {
+        label: x {
+            prop: prop = <3>;
+        };
+    };
+};",
+        );
+        let (diagnostics, _) = code.get_analyzed_file();
+        assert_eq_unordered!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    code.s("label:", 2).span(),
+                    code.source(),
+                    ErrorCode::DuplicateLabel,
+                    "Duplicate label 'label'"
+                ),
+                Diagnostic::new(
+                    code.s("prop:", 2).span(),
+                    code.source(),
+                    ErrorCode::DuplicateLabel,
+                    "Duplicate label 'prop'"
+                ),
+            ]
+        );
+    }
 }
@@ -1,14 +1,18 @@
 use crate::dts::data::HasSource;
+use crate::dts::expression::Expression;
 use crate::dts::tokens::Token;
 use crate::dts::{HasSpan, Span};
 use itertools::Itertools;
 use std::fmt::{Display, Formatter, LowerHex};
+use std::fs::File;
 use std::io;
+use std::io::{Read, Seek, SeekFrom};
 use std::ops::Deref;
 use std::path::{Path as StdPath, PathBuf};
 use std::sync::Arc;
 
 #[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct WithToken<T> {
     item: T,
     token: Token,
@@ -140,6 +144,42 @@ impl Display for Path {
     }
 }
 
+// Defines a function that serializes the fields of a tuple variant as a struct with named fields,
+// e.g., `Cells(bits, start, cells, end)` as `{"bits": .., "start": .., "cells": .., "end": ..}`.
+// Use with `#[serde(serialize_with = "...")]` on the variant.
+#[cfg(feature = "serde")]
+macro_rules! serialize_fields {
+    ($function:ident, $name:literal, $($field:ident: $type:ty),+ $(,)?) => {
+        #[allow(clippy::ptr_arg, clippy::borrowed_box)]
+        fn $function<S: serde::Serializer>(
+            $($field: &$type,)+
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeStruct;
+            let mut state = serializer.serialize_struct($name, [$(stringify!($field)),+].len())?;
+            $(state.serialize_field(stringify!($field), $field)?;)+
+            state.end()
+        }
+    };
+}
+#[cfg(feature = "serde")]
+pub(crate) use serialize_fields;
+
+// Node names and paths are serialized as they are written, e.g., `cpu@0` or `/cpus/cpu@0`
+#[cfg(feature = "serde")]
+impl serde::Serialize for NodeName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Path {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl Path {
     pub fn new(elements: Vec<NodeName>) -> Path {
         Path { elements }
@@ -160,6 +200,18 @@ impl Path {
     pub fn iter(&self) -> impl Iterator<Item = &NodeName> {
         self.elements.iter()
     }
+
+    /// Splits this path into the path of the parent and the name of the node.
+    /// Returns `None` for the root path.
+    pub fn split_last(&self) -> Option<(Path, &NodeName)> {
+        let (last, parent) = self.elements.split_last()?;
+        Some((Path::new(parent.to_vec()), last))
+    }
+
+    /// Returns whether `other` is this path or one of its ancestors.
+    pub fn starts_with(&self, other: &Path) -> bool {
+        self.elements.starts_with(&other.elements)
+    }
 }
 
 impl From<&str> for Path {
@@ -189,11 +241,41 @@ impl Display for Reference {
     }
 }
 
+// References are serialized as they are written, e.g., `&label` or `&{/path}`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Reference {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Values of cells are stored as 64-bit numbers to accommodate all possible element widths.
+/// The value is already truncated to the width of the array that the cell is part of.
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Cell {
-    Number(WithToken<u32>),
+    Number(WithToken<u64>),
     Reference(WithToken<Reference>),
-    Expression,
+    // An expression in parentheses, together with its value
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_expression_cell"))]
+    Expression(Expression, u64),
+    // A label in between two cells, e.g. `<0 label: 1>`.
+    // Labels do not occupy any space in the array.
+    Label(WithToken<String>),
+}
+
+#[cfg(feature = "serde")]
+serialize_fields!(serialize_expression_cell, "Expression", expression: Expression, value: u64);
+
+impl Cell {
+    /// The numeric value of this cell or `None`, if this cell is a reference or a label.
+    pub fn value(&self) -> Option<u64> {
+        match self {
+            Cell::Number(number) => Some(*number.item()),
+            Cell::Reference(_) | Cell::Label(_) => None,
+            Cell::Expression(_, value) => Some(*value),
+        }
+    }
 }
 
 impl Display for Cell {
@@ -201,27 +283,182 @@ impl Display for Cell {
         match self {
             Cell::Number(num) => write!(f, "0x{num:x}"),
             Cell::Reference(reference) => write!(f, "{reference}"),
-            Cell::Expression => write!(f, "(not implemented)"),
+            Cell::Expression(expression, _) => write!(f, "{expression}"),
+            Cell::Label(label) => write!(f, "{label}:"),
+        }
+    }
+}
+
+/// An element of a byte string, e.g. `[0011 label: 22]`
+#[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ByteString {
+    Bytes(WithToken<Vec<u8>>),
+    Label(WithToken<String>),
+}
+
+impl Display for ByteString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ByteString::Bytes(bytes) => {
+                for byte in bytes.item() {
+                    write!(f, "{byte:02x}")?;
+                }
+                Ok(())
+            }
+            ByteString::Label(label) => write!(f, "{label}:"),
+        }
+    }
+}
+
+/// The `/bits/ n` prefix of a cell array, defining the width of each element.
+/// Possible widths are 8, 16, 32 and 64 bits.
+#[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Bits {
+    pub directive: Token,
+    pub width: WithToken<u32>,
+}
+
+impl Display for Bits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/bits/ {}", self.width)
+    }
+}
+
+/// Binary data included from a file, optionally restricted to a range of bytes.
+/// ```text
+/// data = /incbin/("firmware.bin", 0x10, 0x200);
+/// ```
+#[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Incbin {
+    pub directive: Token,
+    pub file_name: WithToken<String>,
+    pub offset: Option<Cell>,
+    pub length: Option<Cell>,
+    // Include paths depend on the environment and are not part of the syntax tree
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub include_paths: Vec<PathBuf>,
+    pub end: Token,
+}
+
+impl HasSpan for Incbin {
+    fn span(&self) -> Span {
+        self.directive.start().to(self.end.end())
+    }
+}
+
+impl HasSource for Incbin {
+    fn source(&self) -> Arc<StdPath> {
+        self.directive.source()
+    }
+}
+
+impl Display for Incbin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/incbin/(\"{}\"", self.file_name)?;
+        if let (Some(offset), Some(length)) = (&self.offset, &self.length) {
+            write!(f, ", {offset}, {length}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Incbin {
+    pub fn path(&self) -> Result<PathBuf, io::Error> {
+        resolve_path(self.file_name.item(), &self.include_paths)
+    }
+
+    /// The offset of the first included byte
+    pub fn offset(&self) -> u64 {
+        self.offset
+            .as_ref()
+            .and_then(|offset| offset.value())
+            .unwrap_or(0)
+    }
+
+    /// The number of included bytes or `None`, if all bytes up to the end of the file are included.
+    /// As with dtc, a length of `-1` includes all bytes up to the end of the file.
+    pub fn length(&self) -> Option<u64> {
+        self.length
+            .as_ref()
+            .and_then(|length| length.value())
+            .filter(|length| *length != u64::MAX)
+    }
+
+    /// Whether the included bytes are part of a file with the given size
+    pub fn fits(&self, size: u64) -> bool {
+        let end = match self.length() {
+            Some(length) => self.offset().checked_add(length),
+            None => Some(self.offset()),
+        };
+        end.is_some_and(|end| end <= size)
+    }
+
+    /// Reads the included bytes from the file system.
+    pub fn bytes(&self) -> Result<Vec<u8>, io::Error> {
+        let mut file = File::open(self.path()?)?;
+        if !self.fits(file.metadata()?.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("'{}' is too small for the included range", self.file_name),
+            ));
         }
+        file.seek(SeekFrom::Start(self.offset()))?;
+        let mut bytes = vec![];
+        file.take(self.length().unwrap_or(u64::MAX))
+            .read_to_end(&mut bytes)?;
+        Ok(bytes)
     }
 }
 
 // LRM 2.2.4 Property Values
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum PropertyValue {
-    String(WithToken<String>),
-    Cells(Token, Vec<Cell>, Token),
+    String(WithToken<Vec<u8>>),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_cells"))]
+    Cells(Option<Bits>, Token, Vec<Cell>, Token),
     Reference(WithToken<Reference>),
-    ByteStrings(Token, Vec<WithToken<Vec<u8>>>, Token),
-}
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_byte_strings"))]
+    ByteStrings(Token, Vec<ByteString>, Token),
+    Incbin(Box<Incbin>),
+    // A label in between two values, e.g. `start: "value" end:`
+    Label(WithToken<String>),
+}
+
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_cells,
+    "Cells",
+    bits: Option<Bits>,
+    start: Token,
+    cells: Vec<Cell>,
+    end: Token,
+);
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_byte_strings,
+    "ByteStrings",
+    start: Token,
+    strings: Vec<ByteString>,
+    end: Token,
+);
 
 impl HasSpan for PropertyValue {
     fn span(&self) -> Span {
         match self {
             PropertyValue::String(str) => str.span(),
-            PropertyValue::Cells(start, _, end) => start.start().to(end.end()),
+            PropertyValue::Cells(bits, start, _, end) => bits
+                .as_ref()
+                .map(|bits| bits.directive.start())
+                .unwrap_or(start.start())
+                .to(end.end()),
             PropertyValue::Reference(reference) => reference.span(),
             PropertyValue::ByteStrings(start, _, end) => start.start().to(end.end()),
+            PropertyValue::Incbin(incbin) => incbin.span(),
+            PropertyValue::Label(label) => label.span(),
         }
     }
 }
@@ -230,9 +467,44 @@ impl HasSource for PropertyValue {
     fn source(&self) -> Arc<StdPath> {
         match self {
             PropertyValue::String(str) => str.token.source(),
-            PropertyValue::Cells(start, ..) => start.source.clone(),
+            PropertyValue::Cells(_, start, ..) => start.source.clone(),
             PropertyValue::Reference(reference) => reference.token.source.clone(),
             PropertyValue::ByteStrings(start, ..) => start.source.clone(),
+            PropertyValue::Incbin(incbin) => incbin.source(),
+            PropertyValue::Label(label) => label.source(),
+        }
+    }
+}
+
+impl PropertyValue {
+    /// Returns the width of the elements in bits, if this value is a cell array.
+    /// Arrays without a `/bits/` directive consist of 32-bit elements.
+    pub fn cell_width(&self) -> Option<u32> {
+        match self {
+            PropertyValue::Cells(bits, ..) => {
+                Some(bits.as_ref().map(|bits| *bits.width.item()).unwrap_or(32))
+            }
+            _ => None,
+        }
+    }
+
+    /// All labels placed directly before or inside this value.
+    pub fn labels(&self) -> Box<dyn Iterator<Item = &WithToken<String>> + '_> {
+        match self {
+            PropertyValue::Label(label) => Box::new(std::iter::once(label)),
+            PropertyValue::Cells(_, _, cells, _) => {
+                Box::new(cells.iter().filter_map(|cell| match cell {
+                    Cell::Label(label) => Some(label),
+                    _ => None,
+                }))
+            }
+            PropertyValue::ByteStrings(_, strings, _) => {
+                Box::new(strings.iter().filter_map(|string| match string {
+                    ByteString::Label(label) => Some(label),
+                    _ => None,
+                }))
+            }
+            _ => Box::new(std::iter::empty()),
         }
     }
 }
@@ -241,9 +513,14 @@ impl Display for PropertyValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match &self {
             PropertyValue::String(string) => {
-                write!(f, "\"{string}\"")
+                write!(f, "\"")?;
+                write_escaped(f, string.item())?;
+                write!(f, "\"")
             }
-            PropertyValue::Cells(_, numbers, _) => {
+            PropertyValue::Cells(bits, _, numbers, _) => {
+                if let Some(bits) = bits {
+                    write!(f, "{bits} ")?;
+                }
                 write!(f, "<")?;
                 for (i, num) in numbers.iter().enumerate() {
                     write!(f, "{num}")?;
@@ -256,27 +533,51 @@ impl Display for PropertyValue {
             PropertyValue::Reference(reference) => write!(f, "{reference}",),
             PropertyValue::ByteStrings(_, strings, _) => {
                 write!(f, "[")?;
-                for (i, numbers) in strings.iter().enumerate() {
-                    for num in &numbers.item {
-                        write!(f, "{num:2x}")?;
-                    }
+                for (i, string) in strings.iter().enumerate() {
+                    write!(f, "{string}")?;
                     if i != strings.len() - 1 {
                         write!(f, " ")?;
                     }
                 }
                 write!(f, "]")
             }
+            PropertyValue::Incbin(incbin) => write!(f, "{incbin}"),
+            PropertyValue::Label(label) => write!(f, "{label}:"),
+        }
+    }
+}
+
+// Writes bytes as the contents of a string literal, escaping characters where necessary
+pub(crate) fn write_escaped(f: &mut impl std::fmt::Write, bytes: &[u8]) -> std::fmt::Result {
+    for chunk in bytes.utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            match ch {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                '\r' => write!(f, "\\r")?,
+                ch if ch.is_ascii_control() => write!(f, "\\x{:02x}", ch as u32)?,
+                ch => write!(f, "{ch}")?,
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(f, "\\x{byte:02x}")?;
         }
     }
+    Ok(())
 }
 
 // LRM 2.2.4 Property Values
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Property {
     pub label: Option<WithToken<String>>,
     pub name: WithToken<String>,
     pub values: Vec<PropertyValue>,
     pub end: Token,
+    /// The doc comment in front of the property, e.g., `/** The clock frequency */`
+    pub doc_comment: Option<String>,
 }
 
 impl HasSpan for Property {
@@ -301,9 +602,16 @@ impl Property {
             name,
             values: vec![],
             end,
+            doc_comment: None,
         }
     }
 
+    /// All labels placed in between or inside the values of this property,
+    /// e.g. `reg = start: <0 size: 0x10>;`
+    pub fn value_labels(&self) -> impl Iterator<Item = &WithToken<String>> {
+        self.values.iter().flat_map(|value| value.labels())
+    }
+
     #[cfg(test)]
     pub fn simple(
         name: WithToken<String>,
@@ -316,6 +624,7 @@ impl Property {
             name,
             values: vec![value],
             end,
+            doc_comment: None,
         }
     }
 }
@@ -326,11 +635,21 @@ impl Display for Property {
             writeln!(f, "{};", self.name)
         } else {
             write!(f, "{} = ", self.name)?;
+            // Values are separated by commas; labels only by whitespace
+            let last_value = self
+                .values
+                .iter()
+                .rposition(|value| !matches!(value, PropertyValue::Label(_)));
             for (i, value) in self.values.iter().enumerate() {
-                write!(f, "{value}")?;
-                if i != self.values.len() - 1 {
-                    write!(f, ", ")?;
+                if i != 0 {
+                    let after_value = !matches!(self.values[i - 1], PropertyValue::Label(_));
+                    if after_value && last_value.is_some_and(|last| i <= last) {
+                        write!(f, ", ")?;
+                    } else {
+                        write!(f, " ")?;
+                    }
                 }
+                write!(f, "{value}")?;
             }
             writeln!(f, ";")
         }
@@ -338,21 +657,45 @@ impl Display for Property {
 }
 
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Node {
     pub label: Option<WithToken<String>>,
     pub name: WithToken<NodeName>,
     pub payload: NodePayload,
     pub omit_if_no_ref: Option<Token>,
+    /// The doc comment in front of the node or its label
+    pub doc_comment: Option<String>,
 }
 
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum NodeItem {
     Property(Arc<Property>),
     Node(Arc<Node>),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_deleted_node"))]
     DeletedNode(Token, WithToken<NodeName>),
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "serialize_deleted_property")
+    )]
     DeletedProperty(Token, WithToken<String>),
 }
 
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_deleted_node,
+    "DeletedNode",
+    directive: Token,
+    name: WithToken<NodeName>,
+);
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_deleted_property,
+    "DeletedProperty",
+    directive: Token,
+    name: WithToken<String>,
+);
+
 impl Display for NodeItem {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -367,6 +710,7 @@ impl Display for NodeItem {
 }
 
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct NodePayload {
     pub items: Vec<NodeItem>,
     pub end: Token,
@@ -403,6 +747,7 @@ impl Display for Node {
 }
 
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Memreserve {
     address: WithToken<u64>,
     length: WithToken<u64>,
@@ -412,6 +757,14 @@ impl Memreserve {
     pub fn new(address: WithToken<u64>, length: WithToken<u64>) -> Memreserve {
         Memreserve { address, length }
     }
+
+    pub fn address(&self) -> u64 {
+        *self.address.item()
+    }
+
+    pub fn length(&self) -> u64 {
+        *self.length.item()
+    }
 }
 
 impl Display for Memreserve {
@@ -425,6 +778,7 @@ impl Display for Memreserve {
 }
 
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct DtsFile {
     pub elements: Vec<Primary>,
     pub source: Arc<StdPath>,
@@ -446,9 +800,12 @@ impl Display for DtsFile {
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Include {
     pub include_token: Token,
     pub file_name: WithToken<String>,
+    // Include paths depend on the environment and are not part of the syntax tree
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub include_paths: Vec<PathBuf>,
 }
 
@@ -472,29 +829,55 @@ impl Display for Include {
 
 impl Include {
     pub fn path(&self) -> Result<PathBuf, io::Error> {
-        let include_resolved = self.include_paths.iter().find_map(|include_path| {
-            let path = include_path.join(self.file_name.to_string());
-            dunce::canonicalize(path).ok()
-        });
+        resolve_path(self.file_name.item(), &self.include_paths)
+    }
+}
 
-        if let Some(include_resolved) = include_resolved {
-            Ok(include_resolved)
-        } else {
-            dunce::canonicalize(self.file_name.to_string())
-        }
+// Resolves a file name relative to the include paths, falling back to the working directory
+fn resolve_path(file_name: &str, include_paths: &[PathBuf]) -> Result<PathBuf, io::Error> {
+    let include_resolved = include_paths.iter().find_map(|include_path| {
+        let path = include_path.join(file_name);
+        dunce::canonicalize(path).ok()
+    });
+
+    if let Some(include_resolved) = include_resolved {
+        Ok(include_resolved)
+    } else {
+        dunce::canonicalize(file_name)
     }
 }
 
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum AnyDirective {
     DtsHeader(Token),
     Plugin(Token),
     Memreserve(Memreserve),
     Include(Include),
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "serialize_deleted_reference")
+    )]
     DeletedNode(Token, WithToken<Reference>),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_omit_if_no_ref"))]
     OmitIfNoRef(Token, WithToken<Reference>),
 }
 
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_deleted_reference,
+    "DeletedNode",
+    directive: Token,
+    reference: WithToken<Reference>,
+);
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_omit_if_no_ref,
+    "OmitIfNoRef",
+    directive: Token,
+    reference: WithToken<Reference>,
+);
+
 impl Display for AnyDirective {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -509,6 +892,7 @@ impl Display for AnyDirective {
 }
 
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ReferencedNode {
     pub reference: WithToken<Reference>,
     pub payload: NodePayload,
@@ -527,12 +911,11 @@ impl Display for ReferencedNode {
 }
 
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Primary {
     Directive(AnyDirective),
     Root(Arc<Node>),
     ReferencedNode(ReferencedNode),
-    // C-style includes should be put into a separate pass
-    CStyleInclude(String),
 }
 
 impl Primary {
@@ -550,7 +933,6 @@ impl Display for Primary {
             Primary::Directive(directive) => write!(f, "{directive}"),
             Primary::Root(node) => write!(f, "{node}"),
             Primary::ReferencedNode(node) => write!(f, "{node}"),
-            Primary::CStyleInclude(include) => write!(f, "#include {include}"),
         }
     }
 }
@@ -0,0 +1,759 @@
+use crate::dts::ast::DtsFile;
+use crate::dts::data::{HasSource, HasSpan, Location, Position, Span};
+use crate::dts::diagnostics::Diagnostic;
+use crate::dts::parser::{Parser, ParserContext};
+use crate::dts::reader::ByteReader;
+use crate::dts::source_map::SourceMap;
+use crate::dts::source_text::SourceText;
+use crate::dts::tokens::{
+    doc_comment_text, CompilerDirective, Lexer, Token, TokenKind, TokenStream,
+};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The kinds of nodes in a concrete syntax tree.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum SyntaxKind {
+    /// A complete file
+    File,
+    /// A statement that starts with a compiler directive, e.g., `/dts-v1/;` or `/delete-node/ x;`
+    Directive,
+    /// `/include/ "file.dtsi"`
+    Include,
+    /// A node with a body, e.g., `label: node@0 { ... };` or `&label { ... };`
+    Node,
+    /// A property with or without values, e.g., `reg = <0 1>;`
+    Property,
+    /// `< ... >`
+    Cells,
+    /// `[ ... ]`
+    ByteString,
+    /// Tokens that do not start a statement, e.g., a superfluous `}`
+    Error,
+}
+
+/// The kinds of text in between tokens.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+    /// A preprocessor directive or line marker, including continuation lines
+    Preprocessor,
+    /// Text that could not be lexed, e.g., after an unterminated string
+    Skipped,
+}
+
+/// A token together with the text that it was lexed from.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct SyntaxToken {
+    pub token: Token,
+    pub text: String,
+    /// The file and span that the token originates from.
+    /// For preprocessed text, this can be an included file or the invocation of a macro.
+    pub origin: Location,
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> &TokenKind {
+        &self.token.kind
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+    Trivia(Trivia),
+}
+
+impl SyntaxElement {
+    fn as_token(&self) -> Option<&SyntaxToken> {
+        match self {
+            SyntaxElement::Token(token) => Some(token),
+            _ => None,
+        }
+    }
+}
+
+impl HasSpan for SyntaxElement {
+    fn span(&self) -> Span {
+        match self {
+            SyntaxElement::Node(node) => node.span(),
+            SyntaxElement::Token(token) => token.token.span(),
+            SyntaxElement::Trivia(trivia) => trivia.span,
+        }
+    }
+}
+
+impl Display for SyntaxElement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyntaxElement::Node(node) => write!(f, "{node}"),
+            SyntaxElement::Token(token) => write!(f, "{}", token.text),
+            SyntaxElement::Trivia(trivia) => write!(f, "{}", trivia.text),
+        }
+    }
+}
+
+/// A node of a concrete syntax tree.
+/// Nodes other than the file start and end with a token;
+/// trivia in between statements belongs to the enclosing node.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct SyntaxNode {
+    kind: SyntaxKind,
+    children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn children(&self) -> &[SyntaxElement] {
+        &self.children
+    }
+
+    /// The nodes that are direct children of this node
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            _ => None,
+        })
+    }
+
+    /// All tokens of this node and its descendants, without trivia.
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = vec![];
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    // Collects the tokens, as the parser sees them, together with the doc comment in front of them
+    fn documented_tokens(
+        &self,
+        doc_comment: &mut Option<String>,
+        tokens: &mut Vec<(Token, Option<String>)>,
+    ) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.documented_tokens(doc_comment, tokens),
+                SyntaxElement::Token(token) => {
+                    let token = Token {
+                        kind: token.token.kind.clone(),
+                        span: token.origin.span(),
+                        source: token.origin.source(),
+                    };
+                    tokens.push((token, doc_comment.take()));
+                }
+                SyntaxElement::Trivia(Trivia {
+                    kind: TriviaKind::Comment,
+                    text,
+                    ..
+                }) => {
+                    *doc_comment = text
+                        .strip_prefix("/*")
+                        .and_then(|text| text.strip_suffix("*/"))
+                        .and_then(doc_comment_text);
+                }
+                SyntaxElement::Trivia(_) => {}
+            }
+        }
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+                SyntaxElement::Trivia(_) => {}
+            }
+        }
+    }
+
+    /// Returns the token that contains `position`.
+    /// When `position` is in between two adjacent tokens, the token on the left is returned.
+    pub fn token_at(&self, position: Position) -> Option<&SyntaxToken> {
+        let child = self
+            .children
+            .iter()
+            .find(|child| child.span().start() <= position && position <= child.span().end())?;
+        match child {
+            SyntaxElement::Node(node) => node.token_at(position),
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Trivia(_) => None,
+        }
+    }
+
+    /// The first token of this node, ignoring trivia
+    pub fn first_token(&self) -> Option<&SyntaxToken> {
+        self.children.iter().find_map(|child| match child {
+            SyntaxElement::Node(node) => node.first_token(),
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Trivia(_) => None,
+        })
+    }
+}
+
+impl HasSpan for SyntaxNode {
+    fn span(&self) -> Span {
+        match (self.children.first(), self.children.last()) {
+            (Some(first), Some(last)) => first.span().start().to(last.span().end()),
+            _ => Position::zero().as_span(),
+        }
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for child in &self.children {
+            write!(f, "{child}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A lossless representation of a source file.
+/// Every byte of the source is part of exactly one token or trivia,
+/// so the source can be reconstructed using `to_string()`.
+/// This allows tools to modify parts of a file while leaving the rest untouched.
+///
+/// A tree is either built from the source as written, where preprocessor directives
+/// are kept as trivia and macros are not expanded, or from the output of the preprocessor.
+/// In both cases, the abstract syntax tree is derived from the tokens of the tree.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SyntaxTree {
+    root: SyntaxNode,
+    source: Arc<Path>,
+    // The location after the last token, which the parser reports an unexpected end at
+    end: Location,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl SyntaxTree {
+    pub fn parse(text: &str, source: Arc<Path>) -> SyntaxTree {
+        let text = SourceText::new(text);
+        let lexer = Lexer::from_text(text.lexed.clone(), source.clone());
+        SyntaxTree::build(&text, lexer, source)
+    }
+
+    /// Builds the tree of preprocessed text.
+    /// Tokens keep their position in the preprocessed text,
+    /// but refer to the files and positions that they originate from using `source_map`.
+    pub fn parse_preprocessed(text: &str, source: Arc<Path>, source_map: SourceMap) -> SyntaxTree {
+        let text = SourceText::preprocessed(text);
+        let lexer = Lexer::with_source_map(
+            ByteReader::from_string(text.lexed.clone()),
+            source.clone(),
+            source_map,
+        );
+        SyntaxTree::build(&text, lexer, source)
+    }
+
+    fn build(text: &SourceText<'_>, mut lexer: Lexer<ByteReader>, source: Arc<Path>) -> SyntaxTree {
+        let mut elements = vec![];
+        let mut cursor = 0;
+        while let Some((token, origin)) = lexer.next_with_comments() {
+            let start = text.offset(token.start()).max(cursor);
+            gap(text, cursor, start, &mut elements);
+            let end = text.char_boundary(text.offset(token.end()).max(start));
+            let token_text = text.text[start..end].to_string();
+            cursor = end;
+            elements.push(match token.kind {
+                TokenKind::Comment(_) => SyntaxElement::Trivia(Trivia {
+                    kind: TriviaKind::Comment,
+                    text: token_text,
+                    span: token.span(),
+                }),
+                _ => SyntaxElement::Token(SyntaxToken {
+                    token,
+                    text: token_text,
+                    origin,
+                }),
+            });
+        }
+        // The text that could not be lexed is skipped
+        if let Some(skipped) = text.unlexed(cursor) {
+            gap(text, cursor, skipped, &mut elements);
+            elements.push(SyntaxElement::Trivia(Trivia {
+                kind: TriviaKind::Skipped,
+                text: text.text[skipped..].to_string(),
+                span: text.position(skipped).to(text.position(text.text.len())),
+            }));
+        } else {
+            gap(text, cursor, text.text.len(), &mut elements);
+        }
+
+        let mut builder = Builder {
+            elements: elements.into(),
+        };
+        let mut children = vec![];
+        builder.items(&mut children, false);
+        SyntaxTree {
+            root: SyntaxNode {
+                kind: SyntaxKind::File,
+                children,
+            },
+            source,
+            end: Location::new(lexer.source(), lexer.pos().as_span()),
+            diagnostics: lexer.take_diagnostics(),
+        }
+    }
+
+    pub fn root(&self) -> &SyntaxNode {
+        &self.root
+    }
+
+    /// Diagnostics found while lexing, such as malformed escape sequences
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Parses the tokens of this tree into an abstract syntax tree.
+    /// Returns the file, if it could be parsed, and all diagnostics that were found.
+    /// Positions in the result refer to the files that the tokens originate from.
+    pub fn to_ast(&self, context: ParserContext) -> (Result<DtsFile, Diagnostic>, Vec<Diagnostic>) {
+        let mut tokens = vec![];
+        self.root.documented_tokens(&mut None, &mut tokens);
+        let mut parser = Parser::new(
+            Tokens {
+                tokens: tokens.into_iter(),
+                file_source: self.source.clone(),
+                source: self.source.clone(),
+                pos: Position::zero(),
+                last_pos: Position::zero(),
+                doc_comment: None,
+                end: self.end.clone(),
+                diagnostics: self.diagnostics.clone(),
+            },
+            context,
+        );
+        let file = parser.file();
+        (file, parser.diagnostics)
+    }
+}
+
+impl HasSource for SyntaxTree {
+    fn source(&self) -> Arc<Path> {
+        self.source.clone()
+    }
+}
+
+impl Display for SyntaxTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.root)
+    }
+}
+
+// Adds the trivia in between `start` and `end` that is not a comment,
+// i.e., whitespace and preprocessor directives.
+fn gap(text: &SourceText<'_>, start: usize, end: usize, elements: &mut Vec<SyntaxElement>) {
+    let mut offset = start;
+    while offset < end {
+        let line = text.position(offset).line() as usize;
+        let line_end = text.line_end(line);
+        let (kind, next) = if text.directives.contains(&line) && offset < line_end {
+            (TriviaKind::Preprocessor, line_end.min(end))
+        } else {
+            // Whitespace extends up to the next directive
+            let next = (line + 1..text.line_count())
+                .find(|line| text.directives.contains(line))
+                .map_or(end, |line| text.line_start(line).clamp(offset + 1, end));
+            (TriviaKind::Whitespace, next)
+        };
+        elements.push(SyntaxElement::Trivia(Trivia {
+            kind,
+            text: text.text[offset..next].to_string(),
+            span: text.position(offset).to(text.position(next)),
+        }));
+        offset = next;
+    }
+}
+
+// Groups a flat list of tokens and trivia into nodes.
+struct Builder {
+    elements: VecDeque<SyntaxElement>,
+}
+
+impl Builder {
+    fn peek_kind(&self) -> Option<&TokenKind> {
+        self.elements
+            .iter()
+            .find_map(SyntaxElement::as_token)
+            .map(SyntaxToken::kind)
+    }
+
+    // Moves the next token, and the trivia in front of it, into `children`
+    fn bump(&mut self, children: &mut Vec<SyntaxElement>) {
+        while let Some(element) = self.elements.pop_front() {
+            let is_token = matches!(element, SyntaxElement::Token(_));
+            children.push(element);
+            if is_token {
+                return;
+            }
+        }
+    }
+
+    fn items(&mut self, children: &mut Vec<SyntaxElement>, in_node: bool) {
+        loop {
+            match self.peek_kind() {
+                None => {
+                    children.extend(self.elements.drain(..));
+                    return;
+                }
+                Some(TokenKind::CloseBrace) if in_node => return,
+                Some(TokenKind::CloseBrace) => {
+                    children.extend(self.take_trivia());
+                    let mut error = vec![];
+                    self.bump(&mut error);
+                    children.push(SyntaxElement::Node(SyntaxNode {
+                        kind: SyntaxKind::Error,
+                        children: error,
+                    }));
+                }
+                Some(_) => {
+                    children.extend(self.take_trivia());
+                    let statement = self.statement();
+                    children.push(SyntaxElement::Node(statement));
+                }
+            }
+        }
+    }
+
+    fn take_trivia(&mut self) -> Vec<SyntaxElement> {
+        let mut trivia = vec![];
+        while let Some(SyntaxElement::Trivia(_)) = self.elements.front() {
+            trivia.extend(self.elements.pop_front());
+        }
+        trivia
+    }
+
+    fn statement(&mut self) -> SyntaxNode {
+        let mut children = vec![];
+        let first = self.peek_kind().cloned();
+        if first == Some(TokenKind::Directive(CompilerDirective::Include)) {
+            self.bump(&mut children);
+            if let Some(TokenKind::String(_)) = self.peek_kind() {
+                self.bump(&mut children);
+            }
+            return SyntaxNode {
+                kind: SyntaxKind::Include,
+                children,
+            };
+        }
+        let kind = match first {
+            Some(TokenKind::Directive(_)) => SyntaxKind::Directive,
+            Some(TokenKind::Semicolon) => SyntaxKind::Error,
+            _ => SyntaxKind::Property,
+        };
+        loop {
+            match self.peek_kind() {
+                None | Some(TokenKind::CloseBrace) => break,
+                Some(TokenKind::Semicolon) => {
+                    self.bump(&mut children);
+                    break;
+                }
+                Some(TokenKind::OpenBrace) => {
+                    self.bump(&mut children);
+                    self.items(&mut children, true);
+                    if let Some(TokenKind::CloseBrace) = self.peek_kind() {
+                        self.bump(&mut children);
+                    }
+                    if let Some(TokenKind::Semicolon) = self.peek_kind() {
+                        self.bump(&mut children);
+                    }
+                    return SyntaxNode {
+                        kind: SyntaxKind::Node,
+                        children,
+                    };
+                }
+                Some(TokenKind::ChevronLeft) => {
+                    let cells = self.group(SyntaxKind::Cells, TokenKind::ChevronRight);
+                    children.push(SyntaxElement::Node(cells));
+                }
+                Some(TokenKind::OpenBracket) => {
+                    let bytes = self.group(SyntaxKind::ByteString, TokenKind::CloseBracket);
+                    children.push(SyntaxElement::Node(bytes));
+                }
+                Some(_) => self.bump(&mut children),
+            }
+        }
+        SyntaxNode { kind, children }
+    }
+
+    // A group of values, such as cells, that ends with `close`.
+    // Groups that are not closed end before the next statement.
+    fn group(&mut self, kind: SyntaxKind, close: TokenKind) -> SyntaxNode {
+        let mut children = vec![];
+        self.bump(&mut children);
+        // Cells may contain comparisons in parenthesized expressions, e.g., `<(1 > 2)>`
+        let mut depth = 0;
+        loop {
+            match self.peek_kind() {
+                None
+                | Some(TokenKind::Semicolon | TokenKind::OpenBrace | TokenKind::CloseBrace) => {
+                    break
+                }
+                Some(TokenKind::OpenParen) => depth += 1,
+                Some(TokenKind::CloseParen) => depth -= 1,
+                Some(other) if *other == close && depth <= 0 => {
+                    self.bump(&mut children);
+                    break;
+                }
+                Some(_) => {}
+            }
+            self.bump(&mut children);
+        }
+        SyntaxNode { kind, children }
+    }
+}
+
+// Replays the tokens of a syntax tree to the parser
+struct Tokens {
+    tokens: std::vec::IntoIter<(Token, Option<String>)>,
+    file_source: Arc<Path>,
+    // The file that the last token originates from
+    source: Arc<Path>,
+    pos: Position,
+    last_pos: Position,
+    doc_comment: Option<String>,
+    end: Location,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Iterator for Tokens {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.last_pos = self.pos;
+        let Some((token, doc_comment)) = self.tokens.next() else {
+            self.source = self.end.source();
+            self.pos = self.end.start();
+            self.doc_comment = None;
+            return None;
+        };
+        self.source = token.source();
+        self.pos = token.end();
+        self.doc_comment = doc_comment;
+        Some(token)
+    }
+}
+
+impl HasSource for Tokens {
+    fn source(&self) -> Arc<Path> {
+        self.source.clone()
+    }
+}
+
+impl TokenStream for Tokens {
+    fn pos(&self) -> Position {
+        self.pos
+    }
+
+    fn last_pos(&self) -> Position {
+        self.last_pos
+    }
+
+    fn file_source(&self) -> Arc<Path> {
+        self.file_source.clone()
+    }
+
+    fn doc_comment(&self) -> Option<String> {
+        self.doc_comment.clone()
+    }
+
+    fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::cst::{SyntaxElement, SyntaxNode, SyntaxTree, TriviaKind};
+    use crate::dts::reader::ByteReader;
+    use crate::dts::tokens::{Lexer, TokenKind};
+    use crate::dts::{HasSpan, Parser, ParserContext, Position, Preprocessor};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn parse(text: &str) -> SyntaxTree {
+        let tree = SyntaxTree::parse(text, Path::new("inline source").into());
+        assert_eq!(tree.to_string(), text);
+        tree
+    }
+
+    // Renders the nodes of a tree, e.g., `File(Directive Node(Property))`
+    fn structure(node: &SyntaxNode) -> String {
+        let children = node.nodes().map(structure).collect::<Vec<_>>().join(" ");
+        if children.is_empty() {
+            format!("{:?}", node.kind())
+        } else {
+            format!("{:?}({children})", node.kind())
+        }
+    }
+
+    fn trivia(tree: &SyntaxTree) -> Vec<(TriviaKind, &str)> {
+        tree.root()
+            .children()
+            .iter()
+            .filter_map(|child| match child {
+                SyntaxElement::Trivia(trivia) => Some((trivia.kind, trivia.text.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_source() {
+        for text in [
+            "",
+            "  \n\n",
+            "/dts-v1/;\n\n/ {\n\tmodel = \"ab\"; // comment\n};\n",
+            "/ {\r\n  a = <1 2>, [ab cd];\r\n  /* multi\r\n   line */\r\n};\r\n",
+            "#include <dt-bindings/gpio.h>\n#define X(a) \\\n\t(a + 1)\n/ { b = <X(1)>; };",
+            "/ { a = \"unterminated; };\n",
+            "/ { c = /* unterminated",
+            "/ { ünicode; /* ä */ };",
+            "}; / { a = <&{/path}>; };;",
+            "/ { x {",
+            include_str!("../../tests/simple.dts"),
+            include_str!("../../tests/test_delete_syntax_A.dts"),
+        ] {
+            parse(text);
+        }
+    }
+
+    #[test]
+    fn groups_statements() {
+        let tree = parse(
+            "\
+/dts-v1/;
+/include/ \"file.dtsi\"
+/memreserve/ 0x10 0x20;
+
+label: / {
+    #address-cells = <1>;
+    reg = /bits/ 16 <(1 > 2) 3>, [00 01];
+    empty;
+    node@0 {
+        /delete-property/ reg;
+    };
+};
+
+/delete-node/ &label;
+&label {};",
+        );
+        assert_eq!(
+            structure(tree.root()),
+            "File(Directive Include Directive Node(Property(Cells) Property(Cells ByteString) \
+             Property Node(Directive)) Directive Node)"
+        );
+    }
+
+    #[test]
+    fn keeps_trivia() {
+        let tree = parse("// header\n#include \"a.h\"\n\n/ {};  /* end */\n");
+        assert_eq!(
+            trivia(&tree),
+            vec![
+                (TriviaKind::Comment, "// header"),
+                (TriviaKind::Whitespace, "\n"),
+                (TriviaKind::Preprocessor, "#include \"a.h\""),
+                (TriviaKind::Whitespace, "\n\n"),
+                (TriviaKind::Whitespace, "  "),
+                (TriviaKind::Comment, "/* end */"),
+                (TriviaKind::Whitespace, "\n"),
+            ]
+        );
+        // Trivia inside a statement belongs to the statement
+        let tree = parse("/ { a = /* one */ <1>; };");
+        let property = tree.root().nodes().next().unwrap().nodes().next().unwrap();
+        assert_eq!(property.to_string(), "a = /* one */ <1>;");
+        assert_eq!(
+            property.span(),
+            Position::new(0, 4).to(Position::new(0, 22))
+        );
+    }
+
+    #[test]
+    fn skips_text_that_cannot_be_lexed() {
+        let tree = parse("/ { a = \"unterminated;\n};\n");
+        let node = tree.root().nodes().next().unwrap();
+        assert_eq!(node.to_string(), "/ { a = \"unterminated;\n};\n");
+        let last = node.children().last().unwrap();
+        match last {
+            SyntaxElement::Trivia(trivia) => {
+                assert_eq!(trivia.kind, TriviaKind::Skipped);
+                assert_eq!(trivia.text, "\"unterminated;\n};\n");
+            }
+            _ => panic!("Expected skipped text, got {last:?}"),
+        }
+    }
+
+    #[test]
+    fn finds_tokens() {
+        let tree = parse("/ {\n\tlabel: node {};\n};");
+        let token = tree.root().token_at(Position::new(1, 9)).unwrap();
+        assert_eq!(token.kind(), &TokenKind::Ident("node".into()));
+        assert_eq!(token.text, "node");
+        let token = tree.root().token_at(Position::new(1, 3)).unwrap();
+        assert_eq!(token.text, "label:");
+        assert_eq!(tree.root().token_at(Position::new(0, 3)).unwrap().text, "{");
+        assert!(tree.root().token_at(Position::new(0, 4)).is_none());
+    }
+
+    #[test]
+    fn derives_ast() {
+        for text in [
+            "/dts-v1/;\n/memreserve/ 0x10 0x20;\n/ {\n\t// comment\n\t/** doc */\n\tlabel: node@1 {\n\t\treg = <1 (2 + 3)>, \"a\", [00];\n\t};\n};\n&label { /delete-property/ reg; };",
+            "/ { a = <1 2 };",
+            "/ { a = \"\\q\"; b }",
+            "/ { a = ",
+            "/** a */ / { /** b */ /* c */ b; /** d */ // e\n d; };",
+            "/ { a = <1> $ #b = <(!1 | 2)>; };",
+        ] {
+            let tree = parse(text);
+            let (file, diagnostics) = tree.to_ast(ParserContext::default());
+            let mut parser = Parser::from_text(
+                ParserContext::default(),
+                text,
+                Path::new("inline source").into(),
+            );
+            let expected = parser.file();
+            assert_eq!(file, expected);
+            assert_eq!(diagnostics, parser.diagnostics);
+        }
+    }
+
+    #[test]
+    fn derives_ast_of_preprocessed_text() {
+        let source: Arc<Path> = Path::new("inline source").into();
+        let text = "#define X(a) (a + 1)\n/ {\n#if 1\n\ta = <X(2)>;\n#endif\n};\n# 1 \"other.dts\"\nb: &c {};\n";
+        let preprocessed = Preprocessor::new(vec![]).process(text, source.clone());
+        let tree = SyntaxTree::parse_preprocessed(
+            &preprocessed.text,
+            source.clone(),
+            preprocessed.source_map.clone(),
+        );
+        assert_eq!(tree.to_string(), preprocessed.text);
+        let (file, diagnostics) = tree.to_ast(ParserContext::default());
+
+        let lexer = Lexer::with_source_map(
+            ByteReader::from_string(preprocessed.text),
+            source,
+            preprocessed.source_map,
+        );
+        let mut parser = Parser::new(lexer, ParserContext::default());
+        let expected = parser.file();
+        assert_eq!(file, expected);
+        assert_eq!(diagnostics, parser.diagnostics);
+    }
+}
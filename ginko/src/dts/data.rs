@@ -6,10 +6,10 @@ use std::sync::Arc;
 ///
 /// * `FileType::DtSource`: Plain device-tree source files. Usually ends with `.dts`
 /// * `FileType::DtSourceInclude`: Device-tree source include files.
-///     Usually included by device-tree files and ends with `.dtsi`
+///   Usually included by device-tree files and ends with `.dtsi`
 /// * `FileType::DtSourceOverlay`: Device-tree source overlay files.
-///     Usually built as standalone and uses references to define where an overlay should be applied.
-///     Usually, these files end with `.dtso`
+///   Usually built as standalone and uses references to define where an overlay should be applied.
+///   Usually, these files end with `.dtso`
 /// * `FileType::Unknown`: Unknown file type.
 #[derive(Clone, Copy, Eq, PartialEq, Default, Debug)]
 pub enum FileType {
@@ -54,9 +54,16 @@ impl FileType {
 }
 
 impl From<&Path> for FileType {
+    /// Guesses the file type from the extension of a path.
+    /// Files with a `.tmp` extension, such as the preprocessed `some_tree.dts.tmp`
+    /// produced by Linux kernel builds, use the extension before `.tmp`.
     fn from(value: &Path) -> Self {
+        let value = match value.extension() {
+            Some(extension) if extension == "tmp" => value.file_stem().map(Path::new),
+            _ => Some(value),
+        };
         value
-            .extension()
+            .and_then(|path| path.extension())
             .and_then(|extension| extension.to_str())
             .map(FileType::from_file_ending)
             .unwrap_or_default()
@@ -67,6 +74,7 @@ impl From<&Path> for FileType {
 /// This is intentionally equivalent to the position defined by the LSP standard
 /// to make conversions easier.
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Position {
     line: u32,
     character: u32,
@@ -125,12 +133,12 @@ impl Position {
     pub fn offset_by_char(&self, count: i32) -> Position {
         Position::new(
             self.line,
-            self.character.checked_add_signed(count).expect(
-                &format!(
+            self.character.checked_add_signed(count).unwrap_or_else(|| {
+                panic!(
                     "[offset_by_char] Illegal position reached. self: {}, count: {}",
                     self, count
-                )[..],
-            ),
+                )
+            }),
         )
     }
 
@@ -162,6 +170,7 @@ impl Position {
 /// A span in a source text. Defined by it's starting position and end position
 /// where the start is inclusive but the end is not.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Span {
     start: Position,
     end: Position,
@@ -223,3 +232,39 @@ impl HasSpan for Span {
 pub trait HasSource {
     fn source(&self) -> Arc<Path>;
 }
+
+/// A span in a specific source file.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Location {
+    source: Arc<Path>,
+    span: Span,
+}
+
+impl Location {
+    pub fn new(source: Arc<Path>, span: Span) -> Location {
+        Location { source, span }
+    }
+
+    /// Returns whether the given span in `source` lies within this location.
+    pub fn contains_span(&self, source: &Path, span: Span) -> bool {
+        *self.source == *source && self.span.start <= span.start && span.end <= self.span.end
+    }
+
+    /// Returns whether the given position in `source` lies within this location.
+    pub fn contains(&self, source: &Path, position: &Position) -> bool {
+        *self.source == *source && self.span.contains(position)
+    }
+}
+
+impl HasSpan for Location {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl HasSource for Location {
+    fn source(&self) -> Arc<Path> {
+        self.source.clone()
+    }
+}
@@ -1,11 +1,12 @@
 use crate::dts::data::{HasSource, HasSpan, Span};
 use crate::dts::error_codes::{ErrorCode, SeverityMap};
 use crate::dts::import_guard::CyclicDependencyError;
+use crate::dts::literal::IntError;
+use crate::dts::source_map::MacroExpansion;
 use crate::dts::tokens::{Token, TokenKind};
 use itertools::Itertools;
 use std::fmt::{Display, Formatter};
 use std::io;
-use std::num::ParseIntError;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
@@ -62,6 +63,7 @@ impl Display for TokenKind {
             TokenKind::Ident(_) => write!(f, "identifier"),
             TokenKind::Label(_) => write!(f, "label"),
             TokenKind::String(_) => write!(f, "string"),
+            TokenKind::Char(_) => write!(f, "character literal"),
             TokenKind::UnparsedNumber(_) => write!(f, "number"),
             TokenKind::Directive(directive) => write!(f, "{directive}"),
             TokenKind::Ref(_) => write!(f, "reference"),
@@ -77,6 +79,8 @@ pub struct Diagnostic {
     pub span: Span,
     pub source: Arc<Path>,
     pub message: String,
+    /// The macro invocation that the diagnostic originates from, if any
+    pub expansion: Option<Box<MacroExpansion>>,
 }
 
 impl Diagnostic {
@@ -91,6 +95,7 @@ impl Diagnostic {
             source,
             span,
             message: message.into(),
+            expansion: None,
         }
     }
 
@@ -104,10 +109,11 @@ impl Diagnostic {
             source: token.source(),
             span: token.span,
             message: message.into(),
+            expansion: None,
         }
     }
 
-    pub fn parse_int_error(span: Span, source: Arc<Path>, err: ParseIntError) -> Diagnostic {
+    pub fn int_error(span: Span, source: Arc<Path>, err: IntError) -> Diagnostic {
         Diagnostic::new(span, source, ErrorCode::IntError, format!("{err}"))
     }
 
@@ -141,6 +147,21 @@ impl Diagnostic {
         Diagnostic::new(span, source, ErrorCode::Expected, msg)
     }
 
+    /// Returns a human-readable note describing the macro invocation
+    /// that this diagnostic originates from.
+    pub fn expansion_note(&self) -> Option<String> {
+        let expansion = self.expansion.as_ref()?;
+        Some(match &expansion.definition {
+            Some(definition) => format!(
+                "in expansion of macro `{}` defined at {}:{}",
+                expansion.name,
+                definition.source().display(),
+                definition.start().line() + 1
+            ),
+            None => format!("in expansion of macro `{}`", expansion.name),
+        })
+    }
+
     pub fn kind(&self) -> &ErrorCode {
         &self.kind
     }
@@ -172,7 +193,6 @@ impl<'a> DiagnosticPrinter<'a> {
     fn fmt_diagnostic(&self, f: &mut Formatter<'_>, diagnostic: &Diagnostic) -> std::fmt::Result {
         let start = diagnostic.span.start();
         let end = diagnostic.span.end();
-        debug_assert!(start.line() == end.line());
         let empty_string = "".to_string();
         let line = self
             .code
@@ -198,10 +218,16 @@ impl<'a> DiagnosticPrinter<'a> {
         )?;
         writeln!(f, "{} |", prefix_empty)?;
         writeln!(f, "{} | {}", prefix, line)?;
-        let len = if start.character() == end.character() {
+        // Spans covering multiple lines are underlined until the end of the first line
+        let end_character = if start.line() == end.line() {
+            end.character()
+        } else {
+            line.len() as u32
+        };
+        let len = if start.character() >= end_character {
             1
         } else {
-            end.character() - start.character()
+            end_character - start.character()
         };
         write!(
             f,
@@ -211,6 +237,10 @@ impl<'a> DiagnosticPrinter<'a> {
             "^".repeat(len as usize)
         )?;
         write!(f, " {}", diagnostic.message)?;
+        if let Some(note) = diagnostic.expansion_note() {
+            writeln!(f)?;
+            write!(f, "{} = note: {}", prefix_empty, note)?;
+        }
         Ok(())
     }
 }
@@ -228,13 +258,16 @@ impl<'a> Display for DiagnosticPrinter<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::dts::data::{HasSource, HasSpan};
+    use crate::dts::data::{HasSource, HasSpan, Location, Position, Span};
     use crate::dts::diagnostics::{Diagnostic, DiagnosticPrinter, ErrorCode};
     use crate::dts::error_codes::SeverityMap;
     use crate::dts::parser::Parser;
+    use crate::dts::source_map::MacroExpansion;
     use crate::dts::test::Code;
     use crate::dts::ParserContext;
     use itertools::Itertools;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
 
     #[test]
     fn display_missing_semicolon() {
@@ -296,4 +329,39 @@ warning --> fname:4:13
 ".to_string();
         assert_eq!(formatter_err, format!("{printer}"));
     }
+
+    #[test]
+    fn display_expansion_note() {
+        let source: Arc<Path> = PathBuf::from("fname").into();
+        let mut diagnostic = Diagnostic::new(
+            Span::new(Position::new(0, 9), Position::new(0, 12)),
+            source.clone(),
+            ErrorCode::IntError,
+            "Invalid number",
+        );
+        diagnostic.expansion = Some(Box::new(MacroExpansion {
+            name: "FOO".to_string(),
+            invocation: Location::new(source, diagnostic.span),
+            definition: Some(Location::new(
+                PathBuf::from("header.h").into(),
+                Span::new(Position::new(11, 8), Position::new(11, 11)),
+            )),
+            expanded: "0xzz".to_string(),
+        }));
+        let printer = DiagnosticPrinter {
+            diagnostics: &[diagnostic],
+            code: vec!["prop = <FOO>;".into()],
+            severity_map: SeverityMap::default(),
+        };
+        let formatter_err = "\
+error --> fname:1:10
+  |
+1 | prop = <FOO>;
+  |          ^^^ Invalid number
+  = note: in expansion of macro `FOO` defined at header.h:12
+
+"
+        .to_string();
+        assert_eq!(formatter_err, format!("{printer}"));
+    }
 }
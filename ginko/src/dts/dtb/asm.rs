@@ -0,0 +1,445 @@
+use crate::dts::dtb::writer::Blocks;
+use crate::dts::dtb::{
+    DtbOptions, FDT_BEGIN_NODE, FDT_END, FDT_END_NODE, FDT_MAGIC, FDT_PROP,
+    LAST_COMPATIBLE_VERSION, VERSION,
+};
+use crate::dts::tree::{Data, DeviceTree, MarkerKind, TreeNode};
+
+// All global symbols of the blob are prefixed with this, like with dtc
+const PREFIX: &str = "dt";
+
+// Writes assembler source in the layout of `dtc -O asm`.
+// The structure block is written directly, while the strings are collected
+// and written once the structure block is complete.
+struct Emitter {
+    asm: String,
+    blocks: Blocks,
+}
+
+impl Emitter {
+    fn line(&mut self, line: &str) {
+        self.asm.push_str(line);
+        self.asm.push('\n');
+    }
+
+    fn comment(&mut self, comment: &str) {
+        self.line(&format!("\t/* {comment} */"));
+    }
+
+    // A global label, e.g., of a node or of a property
+    fn label(&mut self, label: &str) {
+        self.line(&format!("\t.globl\t{label}"));
+        self.line(&format!("{label}:"));
+    }
+
+    // A section of the blob. The label is also available with a leading underscore.
+    fn section(&mut self, name: &str) {
+        self.label(&format!("{PREFIX}_{name}"));
+        self.line(&format!("_{PREFIX}_{name}:"));
+    }
+
+    fn cell(&mut self, cell: u32) {
+        for byte in cell.to_be_bytes() {
+            self.line(&format!("\t.byte\t0x{byte:02x}"));
+        }
+    }
+
+    // A cell whose value is computed by the assembler, e.g., the offset of a block
+    fn expression_cell(&mut self, expression: &str) {
+        self.line(&format!("\t.byte\t(({expression}) >> 24) & 0xff"));
+        self.line(&format!("\t.byte\t(({expression}) >> 16) & 0xff"));
+        self.line(&format!("\t.byte\t(({expression}) >> 8) & 0xff"));
+        self.line(&format!("\t.byte\t({expression}) & 0xff"));
+    }
+
+    // The distance between two sections
+    fn distance(&mut self, end: &str, start: &str) {
+        self.expression_cell(&format!("_{PREFIX}_{end} - _{PREFIX}_{start}"));
+    }
+
+    // Data is written as cells, followed by the bytes that do not form a full cell.
+    // Labels inside the data point to their offset.
+    fn data(&mut self, data: &Data) {
+        for marker in &data.markers {
+            if let MarkerKind::Label(label) = &marker.kind {
+                self.line(&format!("\t.globl\t{label}"));
+                self.line(&format!("{label}\t= . + {}", marker.offset));
+            }
+        }
+        let mut chunks = data.bytes.chunks_exact(4);
+        for chunk in chunks.by_ref() {
+            self.cell(u32::from_be_bytes(chunk.try_into().unwrap()));
+        }
+        for byte in chunks.remainder() {
+            self.line(&format!("\t.byte\t0x{byte:x}"));
+        }
+    }
+
+    fn node(&mut self, node: &TreeNode) {
+        for label in &node.labels {
+            self.label(label);
+        }
+        self.comment("FDT_BEGIN_NODE");
+        self.cell(FDT_BEGIN_NODE);
+        self.line(&format!("\t.asciz\t\"{}\"", node.name));
+        self.line("\t.balign\t4, 0");
+        for property in &node.properties {
+            let name_offset = self.blocks.string_offset(&property.name);
+            for label in &property.labels {
+                self.label(label);
+            }
+            self.comment("FDT_PROP");
+            self.cell(FDT_PROP);
+            self.cell(property.data.bytes.len() as u32);
+            self.cell(name_offset);
+            self.data(&property.data);
+            self.line("\t.balign\t4, 0");
+        }
+        for child in &node.children {
+            self.node(child);
+        }
+        self.comment("FDT_END_NODE");
+        self.cell(FDT_END_NODE);
+        for label in &node.labels {
+            self.label(&format!("{label}_end"));
+        }
+    }
+}
+
+impl DeviceTree {
+    /// Serializes this tree into assembler source that assembles to a flattened
+    /// device tree of version 17. The layout is the one of `dtc -O asm`, apart from the
+    /// comment at the top: the blob starts at the global symbol `dt_blob_start` and labels of
+    /// nodes and properties become global symbols that point into the structure block.
+    pub fn to_asm(&self, options: &DtbOptions) -> String {
+        let mut emitter = Emitter {
+            asm: String::new(),
+            blocks: Blocks::default(),
+        };
+        emitter.line("/* autogenerated by ginko, do not edit */\n");
+        emitter.section("blob_start");
+        emitter.section("header");
+        emitter.comment("magic");
+        emitter.cell(FDT_MAGIC);
+        emitter.comment("totalsize");
+        emitter.distance("blob_abs_end", "blob_start");
+        emitter.comment("off_dt_struct");
+        emitter.distance("struct_start", "blob_start");
+        emitter.comment("off_dt_strings");
+        emitter.distance("strings_start", "blob_start");
+        emitter.comment("off_mem_rsvmap");
+        emitter.distance("reserve_map", "blob_start");
+        emitter.comment("version");
+        emitter.cell(VERSION);
+        emitter.comment("last_comp_version");
+        emitter.cell(LAST_COMPATIBLE_VERSION);
+        emitter.comment("boot_cpuid_phys");
+        emitter.cell(options.boot_cpuid_phys);
+        emitter.comment("size_dt_strings");
+        emitter.distance("strings_end", "strings_start");
+        emitter.comment("size_dt_struct");
+        emitter.distance("struct_end", "struct_start");
+
+        emitter.line("\t.balign\t8, 0");
+        emitter.section("reserve_map");
+        emitter.line("/* Memory reserve map from source file */");
+        // 64-bit values are split into two cells since not all assemblers support `.quad`
+        for reservation in &self.reservations {
+            for value in [reservation.address, reservation.length] {
+                emitter.expression_cell(&format!("0x{:08x}", value >> 32));
+                emitter.expression_cell(&format!("0x{:08x}", value & 0xffffffff));
+            }
+        }
+        // The list of reservations is terminated by an empty entry
+        emitter.line("\t.long\t0, 0\n\t.long\t0, 0");
+
+        emitter.section("struct_start");
+        emitter.node(&self.root);
+        emitter.comment("FDT_END");
+        emitter.cell(FDT_END);
+        emitter.section("struct_end");
+
+        emitter.section("strings_start");
+        let strings = std::mem::take(&mut emitter.blocks.strings);
+        for name in strings
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+        {
+            // dtc separates the names of the string table with a space instead of a tab
+            emitter.line(&format!("\t.asciz \"{}\"", String::from_utf8_lossy(name)));
+        }
+        emitter.section("strings_end");
+
+        emitter.section("blob_end");
+        // Unlike in blobs, the padding is added on top of the minimum size
+        if let Some(min_size) = options.min_size {
+            emitter.line(&format!(
+                "\t.space\t{min_size} - (_{PREFIX}_blob_end - _{PREFIX}_blob_start), 0"
+            ));
+        }
+        if let Some(padding) = options.padding {
+            emitter.line(&format!("\t.space\t{padding}, 0"));
+        }
+        if let Some(alignment) = options.alignment {
+            emitter.line(&format!("\t.balign\t{alignment}, 0"));
+        }
+        emitter.section("blob_abs_end");
+        emitter.asm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::dtb::DtbOptions;
+    use crate::dts::test::compile_tree;
+
+    const HEADER: &str = "\
+/* autogenerated by ginko, do not edit */
+
+	.globl	dt_blob_start
+dt_blob_start:
+_dt_blob_start:
+	.globl	dt_header
+dt_header:
+_dt_header:
+	/* magic */
+	.byte	0xd0
+	.byte	0x0d
+	.byte	0xfe
+	.byte	0xed
+	/* totalsize */
+	.byte	((_dt_blob_abs_end - _dt_blob_start) >> 24) & 0xff
+	.byte	((_dt_blob_abs_end - _dt_blob_start) >> 16) & 0xff
+	.byte	((_dt_blob_abs_end - _dt_blob_start) >> 8) & 0xff
+	.byte	(_dt_blob_abs_end - _dt_blob_start) & 0xff
+	/* off_dt_struct */
+	.byte	((_dt_struct_start - _dt_blob_start) >> 24) & 0xff
+	.byte	((_dt_struct_start - _dt_blob_start) >> 16) & 0xff
+	.byte	((_dt_struct_start - _dt_blob_start) >> 8) & 0xff
+	.byte	(_dt_struct_start - _dt_blob_start) & 0xff
+	/* off_dt_strings */
+	.byte	((_dt_strings_start - _dt_blob_start) >> 24) & 0xff
+	.byte	((_dt_strings_start - _dt_blob_start) >> 16) & 0xff
+	.byte	((_dt_strings_start - _dt_blob_start) >> 8) & 0xff
+	.byte	(_dt_strings_start - _dt_blob_start) & 0xff
+	/* off_mem_rsvmap */
+	.byte	((_dt_reserve_map - _dt_blob_start) >> 24) & 0xff
+	.byte	((_dt_reserve_map - _dt_blob_start) >> 16) & 0xff
+	.byte	((_dt_reserve_map - _dt_blob_start) >> 8) & 0xff
+	.byte	(_dt_reserve_map - _dt_blob_start) & 0xff
+	/* version */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x11
+	/* last_comp_version */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x10
+	/* boot_cpuid_phys */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	/* size_dt_strings */
+	.byte	((_dt_strings_end - _dt_strings_start) >> 24) & 0xff
+	.byte	((_dt_strings_end - _dt_strings_start) >> 16) & 0xff
+	.byte	((_dt_strings_end - _dt_strings_start) >> 8) & 0xff
+	.byte	(_dt_strings_end - _dt_strings_start) & 0xff
+	/* size_dt_struct */
+	.byte	((_dt_struct_end - _dt_struct_start) >> 24) & 0xff
+	.byte	((_dt_struct_end - _dt_struct_start) >> 16) & 0xff
+	.byte	((_dt_struct_end - _dt_struct_start) >> 8) & 0xff
+	.byte	(_dt_struct_end - _dt_struct_start) & 0xff
+	.balign	8, 0
+	.globl	dt_reserve_map
+dt_reserve_map:
+_dt_reserve_map:
+/* Memory reserve map from source file */
+";
+
+    #[test]
+    fn empty_tree() {
+        let tree = compile_tree("/dts-v1/; / {};");
+        let expected = HEADER.to_string()
+            + "\t.long\t0, 0
+	.long	0, 0
+	.globl	dt_struct_start
+dt_struct_start:
+_dt_struct_start:
+	/* FDT_BEGIN_NODE */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x01
+	.asciz	\"\"
+	.balign	4, 0
+	/* FDT_END_NODE */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x02
+	/* FDT_END */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x09
+	.globl	dt_struct_end
+dt_struct_end:
+_dt_struct_end:
+	.globl	dt_strings_start
+dt_strings_start:
+_dt_strings_start:
+	.globl	dt_strings_end
+dt_strings_end:
+_dt_strings_end:
+	.globl	dt_blob_end
+dt_blob_end:
+_dt_blob_end:
+	.globl	dt_blob_abs_end
+dt_blob_abs_end:
+_dt_blob_abs_end:
+";
+        assert_eq!(tree.to_asm(&DtbOptions::default()), expected);
+    }
+
+    #[test]
+    fn labels_properties_and_reservations() {
+        let tree = compile_tree(
+            "\
+/dts-v1/;
+/memreserve/ 0x100000000 0x100;
+
+/ {
+    node: node@0 {
+        label: prop = [01 02 03 mid: 04 05];
+        linux,phandle = <1>;
+        phandle = <1>;
+    };
+};",
+        );
+        let asm = tree.to_asm(&DtbOptions {
+            boot_cpuid_phys: 2,
+            padding: Some(16),
+            alignment: Some(64),
+            ..Default::default()
+        });
+        assert!(asm.contains(
+            "\
+	/* boot_cpuid_phys */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x02
+"
+        ));
+        let reserve_map = &asm[HEADER.len()..asm.find("\t.long").unwrap()];
+        assert_eq!(
+            reserve_map
+                .lines()
+                .filter(|line| line.ends_with("& 0xff") && !line.contains(">>"))
+                .collect::<Vec<_>>(),
+            vec![
+                "\t.byte\t(0x00000001) & 0xff",
+                "\t.byte\t(0x00000000) & 0xff",
+                "\t.byte\t(0x00000000) & 0xff",
+                "\t.byte\t(0x00000100) & 0xff",
+            ]
+        );
+        let structure = &asm[asm.find("_dt_struct_start:\n").unwrap()..];
+        assert!(structure.starts_with(
+            "\
+_dt_struct_start:
+	/* FDT_BEGIN_NODE */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x01
+	.asciz	\"\"
+	.balign	4, 0
+	.globl	node
+node:
+	/* FDT_BEGIN_NODE */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x01
+	.asciz	\"node@0\"
+	.balign	4, 0
+	.globl	label
+label:
+	/* FDT_PROP */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x03
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x05
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.globl	mid
+mid	= . + 3
+	.byte	0x01
+	.byte	0x02
+	.byte	0x03
+	.byte	0x04
+	.byte	0x5
+	.balign	4, 0
+"
+        ));
+        assert!(structure.contains(
+            "\
+	/* FDT_END_NODE */
+	.byte	0x00
+	.byte	0x00
+	.byte	0x00
+	.byte	0x02
+	.globl	node_end
+node_end:
+"
+        ));
+        // `phandle` shares the tail of `linux,phandle`
+        assert!(asm.ends_with(
+            "\
+_dt_strings_start:
+	.asciz \"prop\"
+	.asciz \"linux,phandle\"
+	.globl	dt_strings_end
+dt_strings_end:
+_dt_strings_end:
+	.globl	dt_blob_end
+dt_blob_end:
+_dt_blob_end:
+	.space	16, 0
+	.balign	64, 0
+	.globl	dt_blob_abs_end
+dt_blob_abs_end:
+_dt_blob_abs_end:
+"
+        ));
+    }
+
+    #[test]
+    fn minimum_size_and_padding() {
+        let asm = compile_tree("/dts-v1/; / {};").to_asm(&DtbOptions {
+            padding: Some(16),
+            min_size: Some(0x100),
+            ..Default::default()
+        });
+        assert!(asm.ends_with(
+            "\
+_dt_blob_end:
+	.space	256 - (_dt_blob_end - _dt_blob_start), 0
+	.space	16, 0
+	.globl	dt_blob_abs_end
+dt_blob_abs_end:
+_dt_blob_abs_end:
+"
+        ));
+    }
+}
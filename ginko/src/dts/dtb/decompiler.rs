@@ -0,0 +1,513 @@
+use crate::dts::ast::{write_escaped, NodeName, Path, Reference};
+use crate::dts::tree::{
+    guess_type, Data, DeviceTree, Marker, MarkerKind, TreeNode, TreeProperty, ValueType,
+};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Properties that contain phandles, together with the name of the property
+// of the referenced node that gives the number of arguments following each phandle.
+const PHANDLE_PROPERTIES: [(&str, Option<&str>); 19] = [
+    ("interrupt-parent", None),
+    ("next-level-cache", None),
+    ("memory-region", None),
+    ("cpu-idle-states", None),
+    ("operating-points-v2", None),
+    ("clocks", Some("#clock-cells")),
+    ("assigned-clocks", Some("#clock-cells")),
+    ("assigned-clock-parents", Some("#clock-cells")),
+    ("resets", Some("#reset-cells")),
+    ("power-domains", Some("#power-domain-cells")),
+    ("phys", Some("#phy-cells")),
+    ("dmas", Some("#dma-cells")),
+    ("pwms", Some("#pwm-cells")),
+    ("mboxes", Some("#mbox-cells")),
+    ("iommus", Some("#iommu-cells")),
+    ("interrupts-extended", Some("#interrupt-cells")),
+    ("io-channels", Some("#io-channel-cells")),
+    ("thermal-sensors", Some("#thermal-sensor-cells")),
+    ("sound-dai", Some("#sound-dai-cells")),
+];
+
+// Returns `None` if the property is not known to contain phandles.
+// Otherwise, returns the name of the property that gives the number of arguments, if any.
+fn phandle_arguments(property: &str) -> Option<Option<&'static str>> {
+    if let Some((_, arguments)) = PHANDLE_PROPERTIES
+        .iter()
+        .find(|(name, _)| *name == property)
+    {
+        Some(*arguments)
+    } else if property.ends_with("-supply") {
+        Some(None)
+    } else if property == "gpios" || (property.ends_with("-gpios") && property != "nr-gpios") {
+        Some(Some("#gpio-cells"))
+    } else {
+        None
+    }
+}
+
+fn cells(data: &Data) -> Option<Vec<u32>> {
+    if !data.bytes.len().is_multiple_of(4) {
+        return None;
+    }
+    Some(
+        data.bytes
+            .chunks(4)
+            .map(|cell| u32::from_be_bytes(cell.try_into().unwrap()))
+            .collect(),
+    )
+}
+
+// An array of cells or bytes that is being printed
+struct Array {
+    prefix: String,
+    elements: Vec<String>,
+    end: char,
+}
+
+// Prints the value of a property, following the markers of the data
+struct ValuePrinter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    value_type: ValueType,
+    values: Vec<String>,
+    // Labels that precede the next value
+    labels: Vec<String>,
+    array: Option<Array>,
+}
+
+impl ValuePrinter<'_> {
+    fn label_prefix(&mut self) -> String {
+        self.labels
+            .drain(..)
+            .map(|label| format!("{label}: "))
+            .collect()
+    }
+
+    fn close_array(&mut self) {
+        if let Some(array) = self.array.take() {
+            self.values.push(format!(
+                "{}{}{}",
+                array.prefix,
+                array.elements.join(" "),
+                array.end
+            ))
+        }
+    }
+
+    fn array(&mut self) -> &mut Vec<String> {
+        if self.array.is_none() {
+            let mut prefix = self.label_prefix();
+            let end = match self.value_type {
+                ValueType::Cells(bits) => {
+                    if bits != 32 {
+                        prefix.push_str(&format!("/bits/ {bits} "));
+                    }
+                    prefix.push('<');
+                    '>'
+                }
+                _ => {
+                    prefix.push('[');
+                    ']'
+                }
+            };
+            self.array = Some(Array {
+                prefix,
+                elements: vec![],
+                end,
+            })
+        }
+        &mut self.array.as_mut().unwrap().elements
+    }
+
+    fn start_value(&mut self, value_type: ValueType) {
+        self.close_array();
+        self.value_type = value_type;
+        // Arrays are printed even if they are empty, e.g. `<>`
+        if value_type != ValueType::String {
+            self.array();
+        }
+    }
+
+    // Prints the bytes up to `end` according to the current type
+    fn flush(&mut self, end: usize) {
+        let end = end.min(self.bytes.len());
+        while self.offset < end {
+            let rest = &self.bytes[self.offset..end];
+            match self.value_type {
+                ValueType::String => {
+                    let len = rest
+                        .iter()
+                        .position(|byte| *byte == 0)
+                        .unwrap_or(rest.len());
+                    let mut string = self.label_prefix();
+                    string.push('"');
+                    write_escaped(&mut string, &rest[..len]).unwrap();
+                    string.push('"');
+                    self.values.push(string);
+                    self.offset += (len + 1).min(rest.len());
+                }
+                ValueType::Cells(bits) => {
+                    let len = ((bits / 8) as usize).min(rest.len());
+                    let value = rest[..len]
+                        .iter()
+                        .fold(0u64, |value, byte| (value << 8) | *byte as u64);
+                    self.array().push(format!("{value:#04x}"));
+                    self.offset += len;
+                }
+                ValueType::Bytes => {
+                    let byte = rest[0];
+                    self.array().push(format!("{byte:02x}"));
+                    self.offset += 1;
+                }
+            }
+        }
+    }
+
+    fn marker(&mut self, marker: &Marker) {
+        self.flush(marker.offset);
+        match &marker.kind {
+            MarkerKind::Type(value_type) => self.start_value(*value_type),
+            MarkerKind::Label(label) => match &mut self.array {
+                Some(array) => array.elements.push(format!("{label}:")),
+                None => self.labels.push(label.clone()),
+            },
+            MarkerKind::Phandle(reference) => {
+                if let ValueType::Cells(bits) = self.value_type {
+                    self.array().push(reference.to_string());
+                    self.offset = (self.offset + (bits / 8) as usize).min(self.bytes.len());
+                }
+            }
+            MarkerKind::Path(reference) => {
+                self.close_array();
+                let value = format!("{}{reference}", self.label_prefix());
+                self.values.push(value);
+                // Skip the path that the reference was resolved to
+                let rest = &self.bytes[self.offset..];
+                self.offset += rest
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .map_or(rest.len(), |len| len + 1);
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush(self.bytes.len());
+        self.close_array();
+        let mut value = self.values.join(", ");
+        for label in self.labels {
+            if !value.is_empty() {
+                value.push(' ');
+            }
+            value.push_str(&format!("{label}:"));
+        }
+        value
+    }
+}
+
+// Formats the value of a property.
+// Data without type information is printed as a single value of a guessed type.
+fn format_value(data: &Data) -> String {
+    let first_type = data.markers.iter().find_map(|marker| match marker.kind {
+        MarkerKind::Type(_) => Some(marker.offset),
+        _ => None,
+    });
+    let value_type = match first_type {
+        Some(0) => ValueType::String,
+        _ => guess_type(&data.bytes[..first_type.unwrap_or(data.bytes.len())]),
+    };
+    let mut printer = ValuePrinter {
+        bytes: &data.bytes,
+        offset: 0,
+        value_type,
+        values: vec![],
+        labels: vec![],
+        array: None,
+    };
+    if first_type != Some(0) {
+        printer.start_value(value_type);
+    }
+    for marker in &data.markers {
+        printer.marker(marker);
+    }
+    printer.finish()
+}
+
+fn write_labels(dts: &mut String, labels: &[String]) {
+    for label in labels {
+        write!(dts, "{label}: ").unwrap();
+    }
+}
+
+fn write_property(dts: &mut String, property: &TreeProperty, depth: usize) {
+    dts.push_str(&"\t".repeat(depth));
+    write_labels(dts, &property.labels);
+    dts.push_str(&property.name);
+    if !property.data.bytes.is_empty() || !property.data.markers.is_empty() {
+        write!(dts, " = {}", format_value(&property.data)).unwrap();
+    }
+    dts.push_str(";\n");
+}
+
+fn write_node(dts: &mut String, node: &TreeNode, depth: usize) {
+    dts.push_str(&"\t".repeat(depth));
+    write_labels(dts, &node.labels);
+    if depth == 0 {
+        dts.push('/');
+    } else {
+        write!(dts, "{}", node.name).unwrap();
+    }
+    dts.push_str(" {\n");
+    for property in &node.properties {
+        write_property(dts, property, depth + 1);
+    }
+    for child in &node.children {
+        dts.push('\n');
+        write_node(dts, child, depth + 1);
+    }
+    dts.push_str(&"\t".repeat(depth));
+    dts.push_str("};\n");
+}
+
+impl DeviceTree {
+    /// Prints this tree as device-tree source, using the layout of dtc.
+    /// Values are printed with the type they were defined with.
+    /// Values without type information, e.g., values read from a blob,
+    /// are printed as strings, cells or bytes depending on their content.
+    pub fn to_dts(&self) -> String {
+        let mut dts = String::from("/dts-v1/;\n\n");
+        for reservation in &self.reservations {
+            writeln!(
+                dts,
+                "/memreserve/\t0x{:016x} 0x{:016x};",
+                reservation.address, reservation.length
+            )
+            .unwrap();
+        }
+        if !self.reservations.is_empty() {
+            dts.push('\n');
+        }
+        write_node(&mut dts, &self.root, 0);
+        dts
+    }
+
+    /// Restores the labels of nodes from the `__symbols__` node
+    /// that dtc generates when compiling with `-@`.
+    pub fn restore_labels(&mut self) {
+        let Some(symbols) = self.root.child(&NodeName::simple("__symbols__")) else {
+            return;
+        };
+        let labels = symbols
+            .properties
+            .iter()
+            .filter_map(|property| {
+                let path = property.data.bytes.strip_suffix(&[0])?;
+                let path = std::str::from_utf8(path).ok()?;
+                Some((property.name.clone(), Path::from(path)))
+            })
+            .collect_vec();
+        for (label, path) in labels {
+            if let Some(node) = self.node_mut(&path) {
+                node.add_label(&label);
+            }
+        }
+    }
+
+    /// Restores references in properties that are known to contain phandles,
+    /// such as `interrupt-parent` or `clocks`.
+    /// References use the label of the referenced node if it has one and its path otherwise.
+    /// Properties that already carry markers or whose phandles cannot all be resolved
+    /// are left unchanged.
+    pub fn restore_references(&mut self) {
+        let mut phandles = HashMap::new();
+        self.walk(&mut |path, node| {
+            if let Some(phandle) = node.phandle() {
+                phandles.insert(phandle, path.clone());
+            }
+        });
+        let mut restored = vec![];
+        self.walk(&mut |path, node| {
+            for (idx, property) in node.properties.iter().enumerate() {
+                if let Some(markers) = self.phandle_markers(property, &phandles) {
+                    restored.push((path.clone(), idx, markers));
+                }
+            }
+        });
+        for (path, idx, markers) in restored {
+            if let Some(node) = self.node_mut(&path) {
+                node.properties[idx].data.markers = markers;
+            }
+        }
+    }
+
+    fn phandle_markers(
+        &self,
+        property: &TreeProperty,
+        phandles: &HashMap<u32, Path>,
+    ) -> Option<Vec<Marker>> {
+        let arguments = phandle_arguments(&property.name)?;
+        if !property.data.markers.is_empty() {
+            return None;
+        }
+        let cells = cells(&property.data)?;
+        let mut markers = vec![Marker {
+            offset: 0,
+            kind: MarkerKind::Type(ValueType::Cells(32)),
+        }];
+        let mut idx = 0;
+        while idx < cells.len() {
+            // A phandle of 0 marks an unused entry without arguments
+            if cells[idx] == 0 {
+                idx += 1;
+                continue;
+            }
+            let path = phandles.get(&cells[idx])?;
+            let node = self.node(path)?;
+            let reference = match node.labels.first() {
+                Some(label) => Reference::Label(label.clone()),
+                None => Reference::Path(path.clone()),
+            };
+            markers.push(Marker {
+                offset: idx * 4,
+                kind: MarkerKind::Phandle(reference),
+            });
+            let argument_count = match arguments {
+                Some(name) => node.property(name)?.data.as_cell()? as usize,
+                None => 0,
+            };
+            idx += 1 + argument_count;
+        }
+        (idx == cells.len()).then_some(markers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::dtb::DtbOptions;
+    use crate::dts::test::{compile_tree, Code};
+    use crate::dts::tree::DeviceTree;
+
+    fn decompile(code: &str) -> DeviceTree {
+        let blob = compile_tree(code).to_dtb(&DtbOptions::default());
+        DeviceTree::from_dtb(&blob).unwrap()
+    }
+
+    #[test]
+    fn guesses_value_types() {
+        let tree = decompile(
+            "\
+/dts-v1/;
+/memreserve/ 0x1000 0x100;
+
+/ {
+    empty;
+    string = \"a\\tb\";
+    strings = \"a\", \"b\";
+    cells = <1 0x100>;
+    bytes = [01 02 03];
+    bits = /bits/ 16 <1 2>;
+    node@0 {
+        mixed = \"a\", <1>;
+    };
+};",
+        );
+        assert_eq!(
+            tree.to_dts(),
+            "\
+/dts-v1/;
+
+/memreserve/\t0x0000000000001000 0x0000000000000100;
+
+/ {
+\tempty;
+\tstring = \"a\\tb\";
+\tstrings = \"a\", \"b\";
+\tcells = <0x01 0x100>;
+\tbytes = [01 02 03];
+\tbits = <0x10002>;
+
+\tnode@0 {
+\t\tmixed = [61 00 00 00 00 01];
+\t};
+};
+"
+        );
+    }
+
+    #[test]
+    fn prints_typed_values() {
+        let (diagnostics, context) = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    node: node {
+        lbl: prop = \"a\", mid: <1 &node inner: 2>, [ab], /bits/ 8 <3>, &node end:;
+    };
+};",
+        )
+        .get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(
+            context.tree().to_dts(),
+            "\
+/dts-v1/;
+
+/ {
+
+\tnode: node {
+\t\tlbl: prop = \"a\", mid: <0x01 &node inner: 0x02>, [ab], /bits/ 8 <0x03>, &node end:;
+\t\tphandle = <0x01>;
+\t};
+};
+"
+        );
+    }
+
+    #[test]
+    fn restores_labels_and_references() {
+        let mut tree = decompile(
+            "\
+/dts-v1/;
+
+/ {
+    // References are written as plain phandles
+    interrupt-parent = <1>;
+    interrupt-controller {
+        phandle = <1>;
+    };
+    clock {
+        #clock-cells = <1>;
+        phandle = <2>;
+    };
+    gpio {
+        #gpio-cells = <2>;
+        phandle = <3>;
+    };
+    device {
+        clocks = <2 0>, <2 1>;
+        reset-gpios = <0 3 4 0>;
+        // The third phandle does not exist
+        unknown-gpios = <3 1 2 7>;
+        vdd-supply = <2>;
+    };
+    __symbols__ {
+        intc = \"/interrupt-controller\";
+        clk = \"/clock\";
+        missing = \"/missing\";
+    };
+};",
+        );
+        tree.restore_labels();
+        tree.restore_references();
+        let dts = tree.to_dts();
+        assert!(dts.contains("\tinterrupt-parent = <&intc>;\n"));
+        assert!(dts.contains("\tintc: interrupt-controller {\n"));
+        assert!(dts.contains("\t\tclocks = <&clk 0x00 &clk 0x01>;\n"));
+        assert!(dts.contains("\t\treset-gpios = <0x00 &{/gpio} 0x04 0x00>;\n"));
+        assert!(dts.contains("\t\tunknown-gpios = <0x03 0x01 0x02 0x07>;\n"));
+        assert!(dts.contains("\t\tvdd-supply = <&clk>;\n"));
+    }
+}
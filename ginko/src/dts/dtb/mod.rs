@@ -0,0 +1,26 @@
+//! The flattened device tree (DTB) format as defined in chapter 5 of the
+//! Devicetree Specification.
+//!
+//! A blob consists of a header, the memory reservation block, the structure block
+//! containing nodes and properties, and the strings block containing property names.
+
+mod asm;
+mod decompiler;
+mod reader;
+mod writer;
+
+pub use reader::DtbError;
+pub use writer::DtbOptions;
+
+const FDT_MAGIC: u32 = 0xd00dfeed;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+// Size of the version 17 header
+const HEADER_SIZE: usize = 40;
+const VERSION: u32 = 17;
+const LAST_COMPATIBLE_VERSION: u32 = 16;
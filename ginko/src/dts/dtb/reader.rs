@@ -0,0 +1,376 @@
+use crate::dts::ast::NodeName;
+use crate::dts::dtb::{
+    FDT_BEGIN_NODE, FDT_END, FDT_END_NODE, FDT_MAGIC, FDT_NOP, FDT_PROP, VERSION,
+};
+use crate::dts::tree::{Data, DeviceTree, Reservation, TreeNode, TreeProperty};
+use std::fmt::{Display, Formatter};
+
+// The maximum depth of nodes, like the kernel's `FDT_MAX_DEPTH`.
+// Limits the recursion when reading corrupt blobs.
+const MAX_DEPTH: usize = 64;
+
+/// Errors that can occur when reading a blob.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum DtbError {
+    // The blob ends in the middle of the header or a block
+    Truncated,
+    BadMagic(u32),
+    UnsupportedVersion(u32),
+    // A block lies (partially) outside of the blob
+    BlockOutOfBounds(&'static str),
+    UnexpectedToken { offset: usize, token: u32 },
+    // A node name or property name that is not terminated by a NUL byte
+    UnterminatedString(usize),
+    InvalidStringOffset(u32),
+    // A node that is nested more than `MAX_DEPTH` levels deep
+    TooDeeplyNested(usize),
+}
+
+impl Display for DtbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DtbError::Truncated => write!(f, "Blob is truncated"),
+            DtbError::BadMagic(magic) => {
+                write!(f, "Bad magic number 0x{magic:08x}; not a device-tree blob")
+            }
+            DtbError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported blob version {version}")
+            }
+            DtbError::BlockOutOfBounds(block) => {
+                write!(f, "The {block} block lies outside of the blob")
+            }
+            DtbError::UnexpectedToken { offset, token } => write!(
+                f,
+                "Unexpected token 0x{token:08x} at offset 0x{offset:x} of the structure block"
+            ),
+            DtbError::UnterminatedString(offset) => write!(
+                f,
+                "Unterminated name at offset 0x{offset:x} of the structure block"
+            ),
+            DtbError::InvalidStringOffset(offset) => {
+                write!(f, "Property name offset 0x{offset:x} is out of bounds")
+            }
+            DtbError::TooDeeplyNested(offset) => write!(
+                f,
+                "Node at offset 0x{offset:x} of the structure block is nested more than \
+                 {MAX_DEPTH} levels deep"
+            ),
+        }
+    }
+}
+
+// Reads big-endian values from a block
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(bytes: &'a [u8]) -> Cursor<'a> {
+        Cursor { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DtbError> {
+        let bytes = self
+            .offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .ok_or(DtbError::Truncated)?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, DtbError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, DtbError> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // Reads a NUL-terminated name, including the terminator
+    fn name(&mut self) -> Result<String, DtbError> {
+        let rest = &self.bytes[self.offset.min(self.bytes.len())..];
+        let len = rest
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(DtbError::UnterminatedString(self.offset))?;
+        let name = String::from_utf8_lossy(&rest[..len]).into_owned();
+        self.offset += len + 1;
+        Ok(name)
+    }
+
+    // Skips the padding after names and property values
+    fn align(&mut self) {
+        self.offset = (self.offset + 3) & !3;
+    }
+}
+
+fn block<'a>(
+    blob: &'a [u8],
+    offset: u32,
+    size: usize,
+    name: &'static str,
+) -> Result<&'a [u8], DtbError> {
+    (offset as usize)
+        .checked_add(size)
+        .and_then(|end| blob.get(offset as usize..end))
+        .ok_or(DtbError::BlockOutOfBounds(name))
+}
+
+struct StructureReader<'a> {
+    structure: Cursor<'a>,
+    strings: &'a [u8],
+}
+
+impl StructureReader<'_> {
+    // Reads the next token, skipping `FDT_NOP` tokens
+    fn token(&mut self) -> Result<(usize, u32), DtbError> {
+        loop {
+            let offset = self.structure.offset;
+            let token = self.structure.u32()?;
+            if token != FDT_NOP {
+                return Ok((offset, token));
+            }
+        }
+    }
+
+    fn property_name(&self, offset: u32) -> Result<String, DtbError> {
+        let name = self
+            .strings
+            .get(offset as usize..)
+            .ok_or(DtbError::InvalidStringOffset(offset))?;
+        let len = name
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(DtbError::InvalidStringOffset(offset))?;
+        Ok(String::from_utf8_lossy(&name[..len]).into_owned())
+    }
+
+    // Reads the contents of a node at `depth` after its `FDT_BEGIN_NODE` token
+    fn node(&mut self, depth: usize) -> Result<TreeNode, DtbError> {
+        let mut node = TreeNode::new(NodeName::from(self.structure.name()?));
+        self.structure.align();
+        loop {
+            match self.token()? {
+                (_, FDT_PROP) => {
+                    let len = self.structure.u32()?;
+                    let name_offset = self.structure.u32()?;
+                    let bytes = self.structure.take(len as usize)?.to_vec();
+                    self.structure.align();
+                    node.properties.push(TreeProperty {
+                        name: self.property_name(name_offset)?,
+                        labels: vec![],
+                        data: Data {
+                            bytes,
+                            markers: vec![],
+                        },
+                        location: None,
+                    });
+                }
+                (offset, FDT_BEGIN_NODE) => {
+                    if depth >= MAX_DEPTH {
+                        return Err(DtbError::TooDeeplyNested(offset));
+                    }
+                    node.children.push(self.node(depth + 1)?)
+                }
+                (_, FDT_END_NODE) => return Ok(node),
+                (offset, token) => return Err(DtbError::UnexpectedToken { offset, token }),
+            }
+        }
+    }
+}
+
+impl DeviceTree {
+    /// Reads a flattened device tree of version 16 or 17.
+    /// Values in the resulting tree have no type information or labels.
+    pub fn from_dtb(blob: &[u8]) -> Result<DeviceTree, DtbError> {
+        let mut header = Cursor::new(blob);
+        let magic = header.u32()?;
+        if magic != FDT_MAGIC {
+            return Err(DtbError::BadMagic(magic));
+        }
+        let total_size = header.u32()? as usize;
+        let blob = blob.get(..total_size).ok_or(DtbError::Truncated)?;
+        let off_dt_struct = header.u32()?;
+        let off_dt_strings = header.u32()?;
+        let off_mem_rsvmap = header.u32()?;
+        let version = header.u32()?;
+        let last_compatible_version = header.u32()?;
+        if version < 16 || last_compatible_version > VERSION {
+            return Err(DtbError::UnsupportedVersion(version));
+        }
+        let _boot_cpuid_phys = header.u32()?;
+        let size_dt_strings = header.u32()? as usize;
+        // Version 16 has no size of the structure block; it ends with the `FDT_END` token
+        let size_dt_struct = if version >= 17 {
+            header.u32()? as usize
+        } else {
+            total_size.saturating_sub(off_dt_struct as usize)
+        };
+
+        let mut reservations = vec![];
+        // The reservation block has no size; it ends with an empty entry
+        let mut rsvmap = Cursor {
+            bytes: blob,
+            offset: off_mem_rsvmap as usize,
+        };
+        loop {
+            let address = rsvmap.u64()?;
+            let length = rsvmap.u64()?;
+            if address == 0 && length == 0 {
+                break;
+            }
+            reservations.push(Reservation { address, length });
+        }
+
+        let mut reader = StructureReader {
+            structure: Cursor::new(block(blob, off_dt_struct, size_dt_struct, "structure")?),
+            strings: block(blob, off_dt_strings, size_dt_strings, "strings")?,
+        };
+        let root = match reader.token()? {
+            (_, FDT_BEGIN_NODE) => reader.node(0)?,
+            (offset, token) => return Err(DtbError::UnexpectedToken { offset, token }),
+        };
+        match reader.token()? {
+            (_, FDT_END) => Ok(DeviceTree { reservations, root }),
+            (offset, token) => Err(DtbError::UnexpectedToken { offset, token }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::dtb::{DtbError, DtbOptions, FDT_BEGIN_NODE, FDT_MAGIC};
+    use crate::dts::test::compile_tree;
+    use crate::dts::tree::{DeviceTree, Reservation};
+
+    fn blob(code: &str) -> Vec<u8> {
+        compile_tree(code).to_dtb(&DtbOptions::default())
+    }
+
+    #[test]
+    fn reads_written_blob() {
+        let blob = blob(
+            "\
+/dts-v1/;
+/memreserve/ 0x1000 0x100;
+
+/ {
+    model = \"ab\";
+    node@0 {
+        empty;
+        reg = <0 1>;
+    };
+    other {};
+};",
+        );
+        let tree = DeviceTree::from_dtb(&blob).unwrap();
+        assert_eq!(
+            tree.reservations,
+            vec![Reservation {
+                address: 0x1000,
+                length: 0x100
+            }]
+        );
+        assert_eq!(tree.root.name.to_string(), "");
+        assert_eq!(tree.root.properties[0].name, "model");
+        assert_eq!(tree.root.properties[0].data.bytes, b"ab\0");
+        let node = &tree.root.children[0];
+        assert_eq!(node.name.to_string(), "node@0");
+        assert_eq!(node.properties[0].name, "empty");
+        assert!(node.properties[0].data.bytes.is_empty());
+        assert_eq!(node.properties[1].data.bytes, [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(tree.root.children[1].name.to_string(), "other");
+
+        // Reading is the inverse of writing
+        assert_eq!(tree.to_dtb(&DtbOptions::default()), blob);
+    }
+
+    #[test]
+    fn reads_version_16() {
+        let mut blob = blob("/dts-v1/; / { a = <1>; };");
+        // Version 16 has no size of the structure block, but the same layout otherwise
+        blob[20..24].copy_from_slice(&16u32.to_be_bytes());
+        blob[36..40].copy_from_slice(&0u32.to_be_bytes());
+        let tree = DeviceTree::from_dtb(&blob).unwrap();
+        assert_eq!(tree.root.properties[0].data.bytes, [0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn erroneous_blobs() {
+        let blob = blob("/dts-v1/; / { a = <1>; };");
+        assert_eq!(DeviceTree::from_dtb(&blob[..20]), Err(DtbError::Truncated));
+        assert_eq!(
+            DeviceTree::from_dtb(&blob[..blob.len() - 1]),
+            Err(DtbError::Truncated)
+        );
+        assert_eq!(
+            DeviceTree::from_dtb(b"/dts-v1/;\n/ {};\n"),
+            Err(DtbError::BadMagic(0x2f647473))
+        );
+
+        let mut old_version = blob.clone();
+        old_version[20..24].copy_from_slice(&2u32.to_be_bytes());
+        assert_eq!(
+            DeviceTree::from_dtb(&old_version),
+            Err(DtbError::UnsupportedVersion(2))
+        );
+
+        let mut bad_strings = blob.clone();
+        bad_strings[12..16].copy_from_slice(&0x1000u32.to_be_bytes());
+        assert_eq!(
+            DeviceTree::from_dtb(&bad_strings),
+            Err(DtbError::BlockOutOfBounds("strings"))
+        );
+
+        // Replace the `FDT_PROP` token of `a` with an unknown token
+        let mut bad_token = blob.clone();
+        bad_token[0x40..0x44].copy_from_slice(&7u32.to_be_bytes());
+        assert_eq!(
+            DeviceTree::from_dtb(&bad_token),
+            Err(DtbError::UnexpectedToken {
+                offset: 8,
+                token: 7
+            })
+        );
+
+        let mut bad_name = blob;
+        bad_name[0x48..0x4c].copy_from_slice(&0x100u32.to_be_bytes());
+        assert_eq!(
+            DeviceTree::from_dtb(&bad_name),
+            Err(DtbError::InvalidStringOffset(0x100))
+        );
+
+        // Nodes without names that are nested far deeper than any real tree
+        let structure = [FDT_BEGIN_NODE, 0].repeat(100_000);
+        let size_dt_struct = structure.len() as u32 * 4;
+        let size = 56 + size_dt_struct;
+        // The header is followed by an empty reservation block at offset 40
+        let header = [
+            FDT_MAGIC,
+            size,
+            56,
+            size,
+            40,
+            17,
+            16,
+            0,
+            0,
+            size_dt_struct,
+            0,
+            0,
+            0,
+            0,
+        ];
+        let deeply_nested: Vec<u8> = header
+            .iter()
+            .chain(&structure)
+            .flat_map(|word| word.to_be_bytes())
+            .collect();
+        assert_eq!(
+            DeviceTree::from_dtb(&deeply_nested),
+            Err(DtbError::TooDeeplyNested(0x208))
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::dts::dtb::{
+    FDT_BEGIN_NODE, FDT_END, FDT_END_NODE, FDT_MAGIC, FDT_PROP, HEADER_SIZE,
+    LAST_COMPATIBLE_VERSION, VERSION,
+};
+use crate::dts::tree::{DeviceTree, TreeNode};
+
+/// Options controlling the layout of a blob.
+/// These correspond to the options `-b`, `-p`, `-S` and `-a` of dtc.
+#[derive(Clone, Debug, Default)]
+pub struct DtbOptions {
+    /// The physical ID of the CPU that boots the system
+    pub boot_cpuid_phys: u32,
+    /// Number of zero bytes to append to the blob, leaving space for modifications
+    pub padding: Option<usize>,
+    /// Minimum size of the blob. Smaller blobs are padded with zeros.
+    /// Ignored when `padding` is set, except in assembler output, which adds both like dtc.
+    pub min_size: Option<usize>,
+    /// Pads the blob so that its size is a multiple of this value.
+    /// Must be a power of two.
+    pub alignment: Option<usize>,
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+// The structure and strings block of a blob
+#[derive(Default)]
+pub(super) struct Blocks {
+    structure: Vec<u8>,
+    pub(super) strings: Vec<u8>,
+}
+
+impl Blocks {
+    fn token(&mut self, token: u32) {
+        self.structure.extend(token.to_be_bytes());
+    }
+
+    fn pad_structure(&mut self) {
+        self.structure.resize(align(self.structure.len(), 4), 0);
+    }
+
+    // Returns the offset of a name in the strings block, adding the name if necessary.
+    // Like dtc, names are shared with the tails of longer names
+    // (e.g., `phandle` is found inside `linux,phandle`).
+    pub(super) fn string_offset(&mut self, name: &str) -> u32 {
+        let needle = name.as_bytes();
+        let existing = (0..self.strings.len()).find(|&offset| {
+            let tail = &self.strings[offset..];
+            tail.starts_with(needle) && tail.get(needle.len()) == Some(&0)
+        });
+        let offset = existing.unwrap_or_else(|| {
+            let offset = self.strings.len();
+            self.strings.extend(needle);
+            self.strings.push(0);
+            offset
+        });
+        offset as u32
+    }
+
+    fn node(&mut self, node: &TreeNode) {
+        self.token(FDT_BEGIN_NODE);
+        self.structure.extend(node.name.to_string().as_bytes());
+        self.structure.push(0);
+        self.pad_structure();
+        for property in &node.properties {
+            let name_offset = self.string_offset(&property.name);
+            self.token(FDT_PROP);
+            self.structure
+                .extend((property.data.bytes.len() as u32).to_be_bytes());
+            self.structure.extend(name_offset.to_be_bytes());
+            self.structure.extend(&property.data.bytes);
+            self.pad_structure();
+        }
+        for child in &node.children {
+            self.node(child);
+        }
+        self.token(FDT_END_NODE);
+    }
+}
+
+impl DeviceTree {
+    /// Serializes this tree into a flattened device tree of version 17.
+    /// The layout of the blob is identical to blobs produced by dtc.
+    pub fn to_dtb(&self, options: &DtbOptions) -> Vec<u8> {
+        let mut blocks = Blocks::default();
+        blocks.node(&self.root);
+        blocks.token(FDT_END);
+
+        let mut reservations = vec![];
+        for reservation in &self.reservations {
+            reservations.extend(reservation.address.to_be_bytes());
+            reservations.extend(reservation.length.to_be_bytes());
+        }
+        // The list of reservations is terminated by an empty entry
+        reservations.extend([0; 16]);
+
+        let off_mem_rsvmap = align(HEADER_SIZE, 8);
+        let off_dt_struct = off_mem_rsvmap + reservations.len();
+        let off_dt_strings = off_dt_struct + blocks.structure.len();
+        let size = off_dt_strings + blocks.strings.len();
+
+        let mut padding = match options.min_size {
+            Some(min_size) => min_size.saturating_sub(size),
+            None => 0,
+        };
+        if let Some(pad) = options.padding {
+            padding = pad;
+        }
+        if let Some(alignment) = options.alignment {
+            padding = align(size + padding, alignment) - size;
+        }
+        let total_size = size + padding;
+
+        let mut blob = Vec::with_capacity(total_size);
+        for field in [
+            FDT_MAGIC,
+            total_size as u32,
+            off_dt_struct as u32,
+            off_dt_strings as u32,
+            off_mem_rsvmap as u32,
+            VERSION,
+            LAST_COMPATIBLE_VERSION,
+            options.boot_cpuid_phys,
+            blocks.strings.len() as u32,
+            blocks.structure.len() as u32,
+        ] {
+            blob.extend(field.to_be_bytes());
+        }
+        blob.resize(off_mem_rsvmap, 0);
+        blob.extend(reservations);
+        blob.extend(blocks.structure);
+        blob.extend(blocks.strings);
+        blob.resize(total_size, 0);
+        blob
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::dtb::DtbOptions;
+    use crate::dts::test::compile_tree;
+
+    fn cells(cells: &[u32]) -> Vec<u8> {
+        cells.iter().flat_map(|cell| cell.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn empty_tree() {
+        let tree = compile_tree("/dts-v1/; / {};");
+        let mut expected = cells(&[0xd00dfeed, 0x48, 0x38, 0x48, 0x28, 17, 16, 0, 0, 0x10]);
+        expected.extend([0; 16]);
+        expected.extend(cells(&[1, 0, 2, 9]));
+        assert_eq!(tree.to_dtb(&DtbOptions::default()), expected);
+    }
+
+    #[test]
+    fn nodes_properties_and_reservations() {
+        let tree = compile_tree(
+            "\
+/dts-v1/;
+/memreserve/ 0x1000 0x100;
+
+/ {
+    model = \"ab\";
+    linux,phandle = <1>;
+    phandle = <1>;
+    node@0 {
+        empty;
+    };
+};",
+        );
+        let blob = tree.to_dtb(&DtbOptions {
+            boot_cpuid_phys: 3,
+            ..Default::default()
+        });
+
+        let mut structure = cells(&[1, 0]);
+        // model = "ab"
+        structure.extend(cells(&[3, 3, 0]));
+        structure.extend(b"ab\0\0");
+        // linux,phandle = <1>
+        structure.extend(cells(&[3, 4, 6, 1]));
+        // phandle shares the tail of `linux,phandle`
+        structure.extend(cells(&[3, 4, 12, 1]));
+        structure.extend(cells(&[1]));
+        structure.extend(b"node@0\0\0");
+        // empty
+        structure.extend(cells(&[3, 0, 20]));
+        structure.extend(cells(&[2, 2, 9]));
+        let strings = b"model\0linux,phandle\0empty\0";
+
+        let off_dt_struct = 40 + 32;
+        let off_dt_strings = off_dt_struct + structure.len();
+        let total_size = off_dt_strings + strings.len();
+        let mut expected = cells(&[
+            0xd00dfeed,
+            total_size as u32,
+            off_dt_struct as u32,
+            off_dt_strings as u32,
+            40,
+            17,
+            16,
+            3,
+            strings.len() as u32,
+            structure.len() as u32,
+        ]);
+        expected.extend(0x1000u64.to_be_bytes());
+        expected.extend(0x100u64.to_be_bytes());
+        expected.extend([0; 16]);
+        expected.extend(structure);
+        expected.extend(strings);
+        assert_eq!(blob, expected);
+    }
+
+    #[test]
+    fn padding() {
+        let tree = compile_tree("/dts-v1/; / {};");
+        let size = |options: DtbOptions| {
+            let blob = tree.to_dtb(&options);
+            let total_size = u32::from_be_bytes(blob[4..8].try_into().unwrap()) as usize;
+            assert_eq!(total_size, blob.len());
+            total_size
+        };
+        assert_eq!(size(DtbOptions::default()), 0x48);
+        assert_eq!(
+            size(DtbOptions {
+                padding: Some(10),
+                ..Default::default()
+            }),
+            0x52
+        );
+        assert_eq!(
+            size(DtbOptions {
+                min_size: Some(0x100),
+                ..Default::default()
+            }),
+            0x100
+        );
+        // The blob is larger than the minimum size
+        assert_eq!(
+            size(DtbOptions {
+                min_size: Some(0x10),
+                ..Default::default()
+            }),
+            0x48
+        );
+        assert_eq!(
+            size(DtbOptions {
+                alignment: Some(0x40),
+                ..Default::default()
+            }),
+            0x80
+        );
+        assert_eq!(
+            size(DtbOptions {
+                padding: Some(10),
+                alignment: Some(0x10),
+                ..Default::default()
+            }),
+            0x60
+        );
+    }
+}
@@ -28,6 +28,33 @@ pub enum ErrorCode {
     IOError,
     ErrorsInInclude,
     CyclicDependencyError,
+    UnbalancedConditional,
+    InvalidDirective,
+    InvalidCondition,
+    MacroArguments,
+    MacroRedefined,
+    ErrorDirective,
+    WarningDirective,
+    DivisionByZero,
+    IntegerOverflow,
+    InvalidBitsWidth,
+    ReferenceInNon32BitArray,
+    InvalidEscape,
+    UnknownEscape,
+    InvalidCharLiteral,
+    InvalidIncbinRange,
+    DuplicateLabel,
+    ReferenceToDeletedNode,
+    DeleteNonexistent,
+    InvalidPhandle,
+    DuplicatePhandle,
+    InvalidOverlay,
+    UnresolvedOverlayReference,
+    ConflictingPropertyType,
+    UnexpectedPropertyType,
+    MissingRequiredProperty,
+    InvalidPropertyValue,
+    UnexpectedNode,
 }
 
 /// The `SeverityMap` maps error codes to severities.
@@ -63,9 +90,36 @@ impl Default for SeverityMap {
             | IOError
             | ErrorsInInclude
             | CyclicDependencyError
+            | UnbalancedConditional
+            | InvalidDirective
+            | InvalidCondition
+            | MacroArguments
+            | ErrorDirective
+            | DivisionByZero
+            | InvalidBitsWidth
+            | ReferenceInNon32BitArray
+            | InvalidEscape
+            | InvalidCharLiteral
+            | InvalidIncbinRange
+            | DuplicateLabel
+            | ReferenceToDeletedNode
+            | InvalidPhandle
+            | DuplicatePhandle
+            | InvalidOverlay
+            | UnresolvedOverlayReference
             | IncorrectDirective => Severity::Error,
             NameTooLong
             | NonStringInCompatible
+            | MacroRedefined
+            | WarningDirective
+            | IntegerOverflow
+            | UnknownEscape
+            | DeleteNonexistent
+            | ConflictingPropertyType
+            | UnexpectedPropertyType
+            | MissingRequiredProperty
+            | InvalidPropertyValue
+            | UnexpectedNode
             | DuplicateDirective => Severity::Warning
         };
         SeverityMap { inner: map }
@@ -0,0 +1,612 @@
+#[cfg(feature = "serde")]
+use crate::dts::ast::serialize_fields;
+use crate::dts::ast::WithToken;
+use crate::dts::data::HasSource;
+use crate::dts::diagnostics::Diagnostic;
+use crate::dts::error_codes::ErrorCode;
+use crate::dts::literal;
+use crate::dts::tokens::{Reference, Token, TokenKind};
+use crate::dts::{HasSpan, Span};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum UnaryOperator {
+    Minus,
+    BitNot,
+    LogicalNot,
+}
+
+impl UnaryOperator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnaryOperator::Minus => "-",
+            UnaryOperator::BitNot => "~",
+            UnaryOperator::LogicalNot => "!",
+        }
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum BinaryOperator {
+    LogicalOr,
+    LogicalAnd,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+impl BinaryOperator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOperator::LogicalOr => "||",
+            BinaryOperator::LogicalAnd => "&&",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::BitXor => "^",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+        }
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Binary operators, ordered from lowest to highest precedence
+const BINARY_OPERATORS: [&[BinaryOperator]; 10] = {
+    use BinaryOperator::*;
+    [
+        &[LogicalOr],
+        &[LogicalAnd],
+        &[BitOr],
+        &[BitXor],
+        &[BitAnd],
+        &[Equal, NotEqual],
+        &[Less, Greater, LessEqual, GreaterEqual],
+        &[ShiftLeft, ShiftRight],
+        &[Add, Subtract],
+        &[Multiply, Divide, Modulo],
+    ]
+};
+
+/// An integer expression, as it can appear inside parentheses in a cell array.
+/// ```text
+/// some-prop = <(1 << 4) (0x10 | 0x2)>;
+/// ```
+#[derive(Eq, PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Expression {
+    Number(WithToken<u64>),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_parenthesized"))]
+    Parenthesized(Token, Box<Expression>, Token),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_unary"))]
+    Unary(WithToken<UnaryOperator>, Box<Expression>),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_binary"))]
+    Binary(Box<Expression>, WithToken<BinaryOperator>, Box<Expression>),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_conditional"))]
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>),
+}
+
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_parenthesized,
+    "Parenthesized",
+    open: Token,
+    expression: Box<Expression>,
+    close: Token,
+);
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_unary,
+    "Unary",
+    operator: WithToken<UnaryOperator>,
+    operand: Box<Expression>,
+);
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_binary,
+    "Binary",
+    left: Box<Expression>,
+    operator: WithToken<BinaryOperator>,
+    right: Box<Expression>,
+);
+#[cfg(feature = "serde")]
+serialize_fields!(
+    serialize_conditional,
+    "Conditional",
+    condition: Box<Expression>,
+    if_true: Box<Expression>,
+    if_false: Box<Expression>,
+);
+
+impl HasSpan for Expression {
+    fn span(&self) -> Span {
+        match self {
+            Expression::Number(number) => number.span(),
+            Expression::Parenthesized(open, _, close) => Span::new(open.start(), close.end()),
+            Expression::Unary(op, expr) => Span::new(op.start(), expr.end()),
+            Expression::Binary(lhs, _, rhs) => Span::new(lhs.start(), rhs.end()),
+            Expression::Conditional(condition, _, if_false) => {
+                Span::new(condition.start(), if_false.end())
+            }
+        }
+    }
+}
+
+impl HasSource for Expression {
+    fn source(&self) -> Arc<Path> {
+        match self {
+            Expression::Number(number) => number.source(),
+            Expression::Parenthesized(open, ..) => open.source(),
+            Expression::Unary(op, _) => op.source(),
+            Expression::Binary(lhs, ..) => lhs.source(),
+            Expression::Conditional(condition, ..) => condition.source(),
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Number(number) => write!(f, "0x{number:x}"),
+            Expression::Parenthesized(_, expr, _) => write!(f, "({expr})"),
+            Expression::Unary(op, expr) => write!(f, "{op}{expr}"),
+            Expression::Binary(lhs, op, rhs) => write!(f, "{lhs} {op} {rhs}"),
+            Expression::Conditional(condition, if_true, if_false) => {
+                write!(f, "{condition} ? {if_true} : {if_false}")
+            }
+        }
+    }
+}
+
+impl Expression {
+    /// Evaluates this expression using unsigned 64-bit arithmetic, like dtc does.
+    /// Division by zero and overflows are reported to `diagnostics`.
+    /// Like in C, operands of `&&`, `||` and `?:` that are not evaluated
+    /// do not produce diagnostics.
+    pub fn evaluate(&self, diagnostics: &mut Vec<Diagnostic>) -> u64 {
+        self.eval(true, diagnostics)
+    }
+
+    fn eval(&self, eval: bool, diagnostics: &mut Vec<Diagnostic>) -> u64 {
+        match self {
+            Expression::Number(number) => *number.item(),
+            Expression::Parenthesized(_, expr, _) => expr.eval(eval, diagnostics),
+            Expression::Unary(op, expr) => {
+                let value = expr.eval(eval, diagnostics);
+                match op.item() {
+                    UnaryOperator::Minus => value.wrapping_neg(),
+                    UnaryOperator::BitNot => !value,
+                    UnaryOperator::LogicalNot => (value == 0) as u64,
+                }
+            }
+            Expression::Binary(lhs, op, rhs) => {
+                let lhs_value = lhs.eval(eval, diagnostics);
+                let eval_rhs = match op.item() {
+                    BinaryOperator::LogicalOr => eval && lhs_value == 0,
+                    BinaryOperator::LogicalAnd => eval && lhs_value != 0,
+                    _ => eval,
+                };
+                let rhs_value = rhs.eval(eval_rhs, diagnostics);
+                match self.apply_binary(*op.item(), lhs_value, rhs_value) {
+                    Ok(value) => value,
+                    Err((value, diagnostic)) => {
+                        if eval_rhs {
+                            diagnostics.push(diagnostic);
+                        }
+                        value
+                    }
+                }
+            }
+            Expression::Conditional(condition, if_true, if_false) => {
+                let condition = condition.eval(eval, diagnostics);
+                let if_true = if_true.eval(eval && condition != 0, diagnostics);
+                let if_false = if_false.eval(eval && condition == 0, diagnostics);
+                if condition != 0 {
+                    if_true
+                } else {
+                    if_false
+                }
+            }
+        }
+    }
+
+    // Returns the fallback value along with a diagnostic, if the operation is erroneous
+    fn apply_binary(
+        &self,
+        op: BinaryOperator,
+        lhs: u64,
+        rhs: u64,
+    ) -> Result<u64, (u64, Diagnostic)> {
+        use BinaryOperator::*;
+        let overflow = |value: u64| {
+            Err((
+                value,
+                Diagnostic::new(
+                    self.span(),
+                    self.source(),
+                    ErrorCode::IntegerOverflow,
+                    format!("Result of {self} does not fit into 64 bits"),
+                ),
+            ))
+        };
+        Ok(match op {
+            LogicalOr => (lhs != 0 || rhs != 0) as u64,
+            LogicalAnd => (lhs != 0 && rhs != 0) as u64,
+            BitOr => lhs | rhs,
+            BitXor => lhs ^ rhs,
+            BitAnd => lhs & rhs,
+            Equal => (lhs == rhs) as u64,
+            NotEqual => (lhs != rhs) as u64,
+            Less => (lhs < rhs) as u64,
+            Greater => (lhs > rhs) as u64,
+            LessEqual => (lhs <= rhs) as u64,
+            GreaterEqual => (lhs >= rhs) as u64,
+            ShiftLeft | ShiftRight if rhs >= 64 => return overflow(0),
+            ShiftLeft => lhs << rhs,
+            ShiftRight => lhs >> rhs,
+            Add => match lhs.checked_add(rhs) {
+                Some(value) => value,
+                None => return overflow(lhs.wrapping_add(rhs)),
+            },
+            // Subtraction is allowed to wrap around to express negative numbers, e.g. `(0 - 1)`
+            Subtract => lhs.wrapping_sub(rhs),
+            Multiply => match lhs.checked_mul(rhs) {
+                Some(value) => value,
+                None => return overflow(lhs.wrapping_mul(rhs)),
+            },
+            Divide | Modulo if rhs == 0 => {
+                return Err((
+                    0,
+                    Diagnostic::new(
+                        self.span(),
+                        self.source(),
+                        ErrorCode::DivisionByZero,
+                        "Division by zero",
+                    ),
+                ))
+            }
+            Divide => lhs / rhs,
+            Modulo => lhs % rhs,
+        })
+    }
+}
+
+/// Parses the tokens of a parenthesized expression.
+/// `tokens` contains all tokens, including the opening and closing parentheses.
+pub(crate) fn parse(tokens: &[Token]) -> Result<Expression, Diagnostic> {
+    let mut parser = ExpressionParser { tokens, pos: 0 };
+    let expression = parser.primary()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expression),
+        Some(tok) => Err(Diagnostic::new(
+            tok.span(),
+            tok.source(),
+            ErrorCode::Expected,
+            "Unexpected token after expression",
+        )),
+    }
+}
+
+struct ExpressionParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+// Returns the operator character that a token represents, if any.
+// The lexer does not know about operators, so these are lexed as different kinds of tokens.
+fn operator_char(token: &Token) -> Option<char> {
+    match &token.kind {
+        TokenKind::Unknown(ch) if b"+-*%^~!|?:".contains(ch) => Some(*ch as char),
+        TokenKind::Slash => Some('/'),
+        TokenKind::ChevronLeft => Some('<'),
+        TokenKind::ChevronRight => Some('>'),
+        TokenKind::Equal => Some('='),
+        // An ampersand that is not followed by a label
+        TokenKind::Ref(Reference::Simple(label)) if label.is_empty() => Some('&'),
+        _ => None,
+    }
+}
+
+impl ExpressionParser<'_> {
+    fn operator_char_at(&self, offset: usize) -> Option<char> {
+        self.tokens.get(self.pos + offset).and_then(operator_char)
+    }
+
+    fn expect_operator(&mut self, ch: char) -> Result<&Token, Diagnostic> {
+        if self.operator_char_at(0) == Some(ch) {
+            self.pos += 1;
+            Ok(&self.tokens[self.pos - 1])
+        } else {
+            Err(self.expected(&format!("'{ch}'")))
+        }
+    }
+
+    fn expected(&self, what: &str) -> Diagnostic {
+        let tok = self
+            .tokens
+            .get(self.pos)
+            .unwrap_or_else(|| self.tokens.last().unwrap());
+        Diagnostic::new(
+            tok.span(),
+            tok.source(),
+            ErrorCode::Expected,
+            format!("Expected {what}"),
+        )
+    }
+
+    // Operators that consist of two characters are lexed as two tokens.
+    // Returns the operator and the number of tokens that it consists of.
+    fn peek_binary_operator(&self) -> Option<(BinaryOperator, usize)> {
+        let first = self.operator_char_at(0)?;
+        let all_operators = BINARY_OPERATORS.iter().flat_map(|ops| ops.iter());
+        if let Some(second) = self.operator_char_at(1) {
+            let text: String = [first, second].iter().collect();
+            if let Some(op) = all_operators.clone().find(|op| op.as_str() == text) {
+                return Some((*op, 2));
+            }
+        }
+        let text = first.to_string();
+        all_operators
+            .clone()
+            .find(|op| op.as_str() == text)
+            .map(|op| (*op, 1))
+    }
+
+    fn operator_token(&mut self, count: usize) -> Token {
+        let first = &self.tokens[self.pos];
+        let last = &self.tokens[self.pos + count - 1];
+        let token = Token {
+            kind: first.kind.clone(),
+            span: Span::new(first.start(), last.end()),
+            source: first.source(),
+        };
+        self.pos += count;
+        token
+    }
+
+    fn conditional(&mut self) -> Result<Expression, Diagnostic> {
+        let condition = self.binary(0)?;
+        if self.operator_char_at(0) != Some('?') {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let if_true = self.conditional()?;
+        self.expect_operator(':')?;
+        let if_false = self.conditional()?;
+        Ok(Expression::Conditional(
+            Box::new(condition),
+            Box::new(if_true),
+            Box::new(if_false),
+        ))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expression, Diagnostic> {
+        if level == BINARY_OPERATORS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some((op, count)) = self
+            .peek_binary_operator()
+            .filter(|(op, _)| BINARY_OPERATORS[level].contains(op))
+        {
+            let token = self.operator_token(count);
+            let rhs = self.binary(level + 1)?;
+            lhs = Expression::Binary(Box::new(lhs), WithToken::new(op, token), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expression, Diagnostic> {
+        let op = match self.operator_char_at(0) {
+            Some('-') => UnaryOperator::Minus,
+            Some('~') => UnaryOperator::BitNot,
+            Some('!') => UnaryOperator::LogicalNot,
+            _ => return self.primary(),
+        };
+        let token = self.operator_token(1);
+        let expr = self.unary()?;
+        Ok(Expression::Unary(WithToken::new(op, token), Box::new(expr)))
+    }
+
+    fn primary(&mut self) -> Result<Expression, Diagnostic> {
+        let Some(tok) = self.tokens.get(self.pos) else {
+            return Err(self.expected("expression"));
+        };
+        match &tok.kind {
+            TokenKind::UnparsedNumber(num) => {
+                self.pos += 1;
+                let value = literal::parse_int(num)
+                    .map_err(|err| Diagnostic::int_error(tok.span(), tok.source(), err))?;
+                Ok(Expression::Number(WithToken::new(value, tok.clone())))
+            }
+            TokenKind::Char(value) => {
+                self.pos += 1;
+                Ok(Expression::Number(WithToken::new(
+                    *value as u64,
+                    tok.clone(),
+                )))
+            }
+            TokenKind::OpenParen => {
+                self.pos += 1;
+                let expr = self.conditional()?;
+                let Some(close) = self
+                    .tokens
+                    .get(self.pos)
+                    .filter(|tok| tok.kind == TokenKind::CloseParen)
+                else {
+                    return Err(self.expected("')'"));
+                };
+                self.pos += 1;
+                Ok(Expression::Parenthesized(
+                    tok.clone(),
+                    Box::new(expr),
+                    close.clone(),
+                ))
+            }
+            _ => Err(self.expected("number or '('")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::error_codes::ErrorCode;
+    use crate::dts::expression::{parse, Expression};
+    use crate::dts::test::Code;
+    use crate::dts::tokens::Lexer;
+    use crate::dts::{Diagnostic, HasSource, HasSpan};
+    use itertools::Itertools;
+
+    // Expressions only appear in property values, i.e., after an equal sign
+    fn expression_code(text: &str) -> Code {
+        Code::new(&format!("= {text}"))
+    }
+
+    fn try_parse(code: &Code) -> Result<Expression, Diagnostic> {
+        let tokens = Lexer::from_text(code.code(), code.source())
+            .skip(1)
+            .collect_vec();
+        parse(&tokens)
+    }
+
+    fn parse_expression(code: &Code) -> Expression {
+        try_parse(code).expect("Expression does not parse")
+    }
+
+    fn evaluate(text: &str) -> (u64, Vec<Diagnostic>) {
+        let mut diagnostics = vec![];
+        let value = parse_expression(&expression_code(text)).evaluate(&mut diagnostics);
+        (value, diagnostics)
+    }
+
+    fn value(text: &str) -> u64 {
+        let (value, diagnostics) = evaluate(text);
+        assert_eq!(diagnostics, vec![]);
+        value
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(value("(1 + 2 * 3)"), 7);
+        assert_eq!(value("((1 + 2) * 3)"), 9);
+        assert_eq!(value("(10 / 3)"), 3);
+        assert_eq!(value("(10 % 3)"), 1);
+        assert_eq!(value("(1 - 2)"), u64::MAX);
+        assert_eq!(value("(-1)"), u64::MAX);
+        assert_eq!(value("(0x10 >> 2 | 1)"), 5);
+        assert_eq!(value("(1 << 4)"), 16);
+        assert_eq!(value("(1<<4)"), 16);
+        assert_eq!(value("(0xf0 & 0x3c ^ 0x1)"), 0x31);
+        assert_eq!(value("(~0)"), u64::MAX);
+        assert_eq!(value("(017)"), 15);
+    }
+
+    #[test]
+    fn logical_and_comparison() {
+        assert_eq!(value("(!0 && 2)"), 1);
+        assert_eq!(value("(0 || 0)"), 0);
+        assert_eq!(value("(1 < 2)"), 1);
+        assert_eq!(value("(1 <= 1)"), 1);
+        assert_eq!(value("(1 > 2)"), 0);
+        assert_eq!(value("(2 >= 3)"), 0);
+        assert_eq!(value("(2 == 2)"), 1);
+        assert_eq!(value("(2 != 2)"), 0);
+        assert_eq!(value("(1 ? 2 : 3)"), 2);
+        assert_eq!(value("(0 ? 2 : 0 ? 3 : 4)"), 4);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            parse_expression(&expression_code("((1+2) * -3 ? 4 : 5)")).to_string(),
+            "((0x1 + 0x2) * -0x3 ? 0x4 : 0x5)"
+        );
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let code = expression_code("(1 + 4 / 0)");
+        let mut diagnostics = vec![];
+        let value = parse_expression(&code).evaluate(&mut diagnostics);
+        assert_eq!(value, 1);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                code.s1("4 / 0").span(),
+                code.source(),
+                ErrorCode::DivisionByZero,
+                "Division by zero"
+            )]
+        );
+        assert_eq!(evaluate("(0 && 1 / 0)"), (0, vec![]));
+        assert_eq!(evaluate("(1 ? 1 : 1 % 0)"), (1, vec![]));
+    }
+
+    #[test]
+    fn overflow() {
+        let code = expression_code("(0xffffffffffffffff + 2)");
+        let mut diagnostics = vec![];
+        let value = parse_expression(&code).evaluate(&mut diagnostics);
+        assert_eq!(value, 1);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, ErrorCode::IntegerOverflow);
+        assert_eq!(
+            diagnostics[0].span,
+            code.s1("0xffffffffffffffff + 2").span()
+        );
+
+        let (_, diagnostics) = evaluate("(0x100000000 * 0x100000000)");
+        assert_eq!(diagnostics.len(), 1);
+        let (value, diagnostics) = evaluate("(1 << 64)");
+        assert_eq!(value, 0);
+        assert_eq!(diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn malformed_expressions() {
+        for text in ["(1 +)", "(1 2)", "(1 ? 2)", "(*1)", "((1)", "(&label)"] {
+            assert!(
+                try_parse(&expression_code(text)).is_err(),
+                "{text} should not parse"
+            );
+        }
+    }
+}
@@ -0,0 +1,687 @@
+//! A formatter for device-tree sources.
+//!
+//! The formatter only changes whitespace: all tokens, including comments, are kept as written,
+//! so that numbers keep their radix and strings keep their escape sequences.
+//! Lines containing preprocessor directives are not changed at all.
+
+use crate::dts::cst::{SyntaxElement, SyntaxTree, Trivia, TriviaKind};
+use crate::dts::data::{HasSpan, Position};
+use crate::dts::tokens::{CompilerDirective, Token, TokenKind};
+use std::path::Path;
+
+/// Options controlling the layout of formatted sources.
+#[derive(Clone, Debug)]
+pub struct FormatOptions {
+    /// Indent using tabs instead of spaces
+    pub use_tabs: bool,
+    /// Number of columns per level of indentation. This is also the width of a tab.
+    pub indent_width: usize,
+    /// Properties that exceed this width are wrapped in between their values
+    pub line_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            use_tabs: true,
+            indent_width: 8,
+            line_width: 80,
+        }
+    }
+}
+
+impl FormatOptions {
+    fn indentation(&self, depth: usize) -> String {
+        if self.use_tabs {
+            "\t".repeat(depth)
+        } else {
+            " ".repeat(depth * self.indent_width)
+        }
+    }
+
+    // Returns the column after `text` when it is written starting at `column`
+    fn advance(&self, mut column: usize, text: &str) -> usize {
+        for ch in text.chars() {
+            column = match ch {
+                '\n' => 0,
+                '\t' => (column / self.indent_width + 1) * self.indent_width,
+                _ => column + 1,
+            }
+        }
+        column
+    }
+}
+
+/// Formats a device-tree source.
+///
+/// Statements are put on separate lines and indented by their nesting depth.
+/// Comments and single blank lines between statements are kept.
+/// Values of properties stay on the lines they were written on
+/// and are aligned with the first value. Properties that are too long
+/// are wrapped in between their values.
+pub fn format_source(text: &str, options: &FormatOptions) -> String {
+    let tree = SyntaxTree::parse(text, Path::new("").into());
+    let mut layout = Layout {
+        options,
+        lines: vec![],
+        pieces: vec![],
+        depth: 0,
+        brackets: 0,
+        parens: 0,
+        after_include: false,
+        last_end: None,
+    };
+    layout.elements(tree.root().children());
+    layout.flush();
+    layout.render()
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Role {
+    // `<` and `[` opening values as well as `(`
+    Open,
+    Close,
+    // The `=` of a property
+    Assign,
+    // The comma in between values
+    Separator,
+    Other,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Break {
+    Never,
+    // The line is broken if the following piece does not fit
+    Allowed,
+    // The line was broken in the source or follows a line comment
+    Forced,
+}
+
+#[derive(Debug)]
+struct Piece {
+    text: String,
+    kind: TokenKind,
+    role: Role,
+    space_before: bool,
+    break_before: Break,
+}
+
+impl Piece {
+    fn is_comment(&self) -> bool {
+        matches!(self.kind, TokenKind::Comment(_))
+    }
+
+    fn is_line_comment(&self) -> bool {
+        self.is_comment() && self.text.starts_with("//")
+    }
+}
+
+enum Line {
+    Blank,
+    // Preprocessor directives and text that could not be lexed
+    Verbatim(String),
+    // A comment on its own line, with its indentation depth
+    Comment(usize, String),
+    // A statement with its indentation depth.
+    // Statements end with `;` or `{`.
+    Statement(usize, Vec<Piece>),
+}
+
+struct Layout<'a> {
+    options: &'a FormatOptions,
+    lines: Vec<Line>,
+    // The statement that is currently built
+    pieces: Vec<Piece>,
+    depth: usize,
+    brackets: usize,
+    parens: usize,
+    // Whether the last token was `/include/`, which is not terminated by a semicolon
+    after_include: bool,
+    // The end of the last token or directive
+    last_end: Option<Position>,
+}
+
+impl Layout<'_> {
+    // The number of line breaks in between the last token and `position`
+    fn line_breaks(&self, position: Position) -> u32 {
+        self.last_end
+            .map_or(0, |end| position.line().saturating_sub(end.line()))
+    }
+
+    // Blank lines are kept, but collapsed into one.
+    // There are no blank lines at the start of the file or a node.
+    fn blank_line(&mut self, line_breaks: u32) {
+        if line_breaks < 2 {
+            return;
+        }
+        let opens_node = |pieces: &[Piece]| {
+            pieces
+                .iter()
+                .rev()
+                .find(|piece| !piece.is_comment())
+                .is_some_and(|piece| piece.kind == TokenKind::OpenBrace)
+        };
+        match self.lines.last() {
+            None | Some(Line::Blank) => {}
+            Some(Line::Statement(_, pieces)) if opens_node(pieces) => {}
+            _ => self.lines.push(Line::Blank),
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.pieces.is_empty() {
+            let pieces = std::mem::take(&mut self.pieces);
+            self.lines.push(Line::Statement(self.depth, pieces));
+        }
+    }
+
+    fn elements(&mut self, elements: &[SyntaxElement]) {
+        for element in elements {
+            match element {
+                SyntaxElement::Node(node) => self.elements(node.children()),
+                SyntaxElement::Token(token) => self.token(&token.token, &token.text),
+                SyntaxElement::Trivia(trivia) => match trivia.kind {
+                    TriviaKind::Whitespace => {}
+                    TriviaKind::Comment => self.comment(trivia),
+                    TriviaKind::Preprocessor => self.verbatim(trivia),
+                    // The text that could not be lexed is kept as is
+                    TriviaKind::Skipped => {
+                        self.flush();
+                        let text = trivia.text.trim_end().to_string();
+                        self.lines.push(Line::Verbatim(text));
+                    }
+                },
+            }
+        }
+    }
+
+    // Preprocessor directives are not changed
+    fn verbatim(&mut self, trivia: &Trivia) {
+        self.flush();
+        self.blank_line(self.line_breaks(trivia.span.start()));
+        let text = trivia.text.trim_end_matches(['\n', '\r']);
+        self.lines.push(Line::Verbatim(text.to_string()));
+        self.last_end = Some(trivia.span.end());
+    }
+
+    fn token(&mut self, token: &Token, text: &str) {
+        let text = text.to_string();
+        let line_breaks = self.line_breaks(token.start());
+        let adjacent = self.last_end == Some(token.start());
+        self.last_end = Some(token.end());
+
+        if token.kind == TokenKind::CloseBrace {
+            self.flush();
+            self.depth = self.depth.saturating_sub(1);
+        }
+        let role = self.role(&token.kind);
+        match self.pieces.last() {
+            None => {
+                if token.kind != TokenKind::CloseBrace {
+                    self.blank_line(line_breaks);
+                }
+                self.pieces.push(Piece {
+                    text,
+                    kind: token.kind.clone(),
+                    role,
+                    space_before: false,
+                    break_before: Break::Never,
+                });
+            }
+            Some(previous) => {
+                let space_before = match (&previous.kind, &token.kind) {
+                    (_, TokenKind::Semicolon) => false,
+                    _ if role == Role::Separator => false,
+                    _ if previous.role == Role::Open || role == Role::Close => false,
+                    (_, TokenKind::OpenBrace | TokenKind::Comment(_)) => true,
+                    (TokenKind::Label(_) | TokenKind::Comment(_), _) => true,
+                    _ if previous.role == Role::Assign
+                        || previous.role == Role::Separator
+                        || role == Role::Assign =>
+                    {
+                        true
+                    }
+                    (TokenKind::Directive(_), kind) => *kind != TokenKind::OpenParen,
+                    _ => !adjacent,
+                };
+                let break_before = if previous.is_line_comment() {
+                    Break::Forced
+                } else if previous.role == Role::Separator {
+                    if line_breaks > 0 {
+                        Break::Forced
+                    } else {
+                        Break::Allowed
+                    }
+                } else if line_breaks > 0
+                    && self.brackets > 0
+                    && self.parens == 0
+                    && previous.role != Role::Open
+                    && role != Role::Close
+                {
+                    // Values that span multiple lines, such as tables
+                    Break::Forced
+                } else if space_before
+                    && self.brackets > 0
+                    && self.parens == 0
+                    && previous.role != Role::Open
+                    && role != Role::Close
+                    && !matches!(previous.kind, TokenKind::Label(_))
+                {
+                    // Long arrays are wrapped in between cells
+                    Break::Allowed
+                } else {
+                    Break::Never
+                };
+                self.pieces.push(Piece {
+                    text,
+                    kind: token.kind.clone(),
+                    role,
+                    space_before,
+                    break_before,
+                });
+            }
+        }
+
+        match &token.kind {
+            TokenKind::Semicolon if self.parens == 0 => {
+                self.brackets = 0;
+                self.flush();
+            }
+            TokenKind::OpenBrace => {
+                self.flush();
+                self.depth += 1;
+            }
+            TokenKind::String(_) if self.after_include => self.flush(),
+            _ => {}
+        }
+        self.after_include = token.kind == TokenKind::Directive(CompilerDirective::Include);
+    }
+
+    // Determines the role of a token and tracks the nesting of brackets
+    fn role(&mut self, kind: &TokenKind) -> Role {
+        match kind {
+            // `<` and `>` are operators inside of expressions
+            TokenKind::ChevronLeft if self.parens == 0 => {
+                self.brackets += 1;
+                Role::Open
+            }
+            TokenKind::OpenBracket => {
+                self.brackets += 1;
+                Role::Open
+            }
+            TokenKind::OpenParen => {
+                self.parens += 1;
+                Role::Open
+            }
+            TokenKind::ChevronRight if self.parens == 0 && self.brackets > 0 => {
+                self.brackets -= 1;
+                Role::Close
+            }
+            TokenKind::CloseBracket => {
+                self.brackets = self.brackets.saturating_sub(1);
+                Role::Close
+            }
+            TokenKind::CloseParen => {
+                self.parens = self.parens.saturating_sub(1);
+                Role::Close
+            }
+            TokenKind::Equal if self.brackets == 0 && self.parens == 0 => Role::Assign,
+            TokenKind::Comma if self.brackets == 0 && self.parens == 0 => Role::Separator,
+            _ => Role::Other,
+        }
+    }
+
+    fn comment(&mut self, trivia: &Trivia) {
+        let line_breaks = self.line_breaks(trivia.span.start());
+        let same_line = self.last_end.is_some() && line_breaks == 0;
+        self.last_end = Some(trivia.span.end());
+        let text = trivia.text.clone();
+        let piece = |break_before| Piece {
+            text: text.clone(),
+            // Comments are trivia in the syntax tree; the kind marks the piece as a comment
+            kind: TokenKind::Comment(text.clone()),
+            role: Role::Other,
+            space_before: true,
+            break_before,
+        };
+        // Comments on the same line as the preceding token stay there
+        if same_line {
+            if !self.pieces.is_empty() {
+                self.pieces.push(piece(Break::Never));
+                return;
+            }
+            if let Some(Line::Statement(_, pieces)) = self.lines.last_mut() {
+                pieces.push(piece(Break::Never));
+                return;
+            }
+        }
+        if !self.pieces.is_empty() {
+            self.pieces.push(piece(Break::Forced));
+            return;
+        }
+        self.blank_line(line_breaks);
+        self.lines.push(Line::Comment(self.depth, text));
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for line in &self.lines {
+            match line {
+                Line::Blank => {}
+                Line::Verbatim(text) => out.push_str(text),
+                Line::Comment(depth, text) => {
+                    let indentation = self.options.indentation(*depth);
+                    out.push_str(&indentation);
+                    out.push_str(&reindent_comment(text, &indentation));
+                }
+                Line::Statement(depth, pieces) => self.render_statement(&mut out, *depth, pieces),
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn render_statement(&self, out: &mut String, depth: usize, pieces: &[Piece]) {
+        let indentation = self.options.indentation(depth);
+        let indentation_width = self.options.advance(0, &indentation);
+        out.push_str(&indentation);
+        let mut column = indentation_width;
+        // The column of the first value of a property
+        let mut value_column = None;
+        // The columns after all open brackets
+        let mut open = vec![];
+        for (i, piece) in pieces.iter().enumerate() {
+            let space = usize::from(piece.space_before);
+            let wrap = match piece.break_before {
+                Break::Never => false,
+                Break::Forced => true,
+                Break::Allowed => {
+                    column + space + self.chunk_width(&pieces[i..]) > self.options.line_width
+                }
+            };
+            if wrap {
+                let align = open
+                    .last()
+                    .copied()
+                    .or(value_column)
+                    .unwrap_or(indentation_width + self.options.indent_width);
+                out.push('\n');
+                out.push_str(&indentation);
+                out.push_str(&" ".repeat(align.saturating_sub(indentation_width)));
+                column = align;
+            } else if piece.space_before {
+                out.push(' ');
+                column += 1;
+            }
+            let text = if piece.is_comment() {
+                reindent_comment(&piece.text, &indentation)
+            } else {
+                piece.text.clone()
+            };
+            out.push_str(&text);
+            column = self.options.advance(column, &text);
+            match piece.role {
+                Role::Open => open.push(column),
+                Role::Close => {
+                    open.pop();
+                }
+                Role::Assign if value_column.is_none() => value_column = Some(column + 1),
+                _ => {}
+            }
+        }
+    }
+
+    // The width of the pieces up to the next possible line break, excluding comments
+    fn chunk_width(&self, pieces: &[Piece]) -> usize {
+        let mut width = 0;
+        for (i, piece) in pieces.iter().enumerate() {
+            if i > 0 && piece.break_before != Break::Never {
+                break;
+            }
+            if piece.is_comment() {
+                continue;
+            }
+            if i > 0 && piece.space_before {
+                width += 1;
+            }
+            width = self.options.advance(width, &piece.text);
+        }
+        width
+    }
+}
+
+// Aligns the continuation lines of block comments that start with `*`
+fn reindent_comment(text: &str, indentation: &str) -> String {
+    let mut lines = text.split('\n');
+    let mut result = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        result.push('\n');
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('*') {
+            result.push_str(indentation);
+            result.push(' ');
+            result.push_str(trimmed);
+        } else {
+            result.push_str(line);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::format::{format_source, FormatOptions};
+    use crate::dts::tokens::Lexer;
+    use itertools::Itertools;
+    use std::path::Path;
+
+    fn format(text: &str) -> String {
+        let formatted = format_source(text, &FormatOptions::default());
+        // Formatting only changes whitespace and is idempotent
+        let kinds = |text: &str| {
+            Lexer::from_text(text, Path::new("").into())
+                .map(|token| token.kind)
+                .collect_vec()
+        };
+        assert_eq!(kinds(&formatted), kinds(text));
+        assert_eq!(
+            format_source(&formatted, &FormatOptions::default()),
+            formatted
+        );
+        formatted
+    }
+
+    #[test]
+    fn indents_statements() {
+        assert_eq!(
+            format(
+                "\
+/dts-v1/;  /memreserve/   0x1000 0x100;
+/ { model=\"board\";
+  node@0{  reg=< 0 1 >;
+      empty;
+   /delete-property/   empty;};
+};
+&{/node@0} { /delete-node/ child; };"
+            ),
+            "\
+/dts-v1/;
+/memreserve/ 0x1000 0x100;
+/ {
+\tmodel = \"board\";
+\tnode@0 {
+\t\treg = <0 1>;
+\t\tempty;
+\t\t/delete-property/ empty;
+\t};
+};
+&{/node@0} {
+\t/delete-node/ child;
+};
+"
+        );
+    }
+
+    #[test]
+    fn keeps_tokens_as_written() {
+        let code = "\
+/dts-v1/;
+
+/ {
+\tlabel: node {
+\t\tprop-label: cells = /bits/ 16 <0x10 020 'a' (1 << 2) &ref>;
+\t\tvalues = start: \"a\\x41\\n\", [0011 inner: 22] end:;
+\t\tdata = /incbin/(\"data.bin\", 0x10, 4);
+\t};
+};
+";
+        assert_eq!(format(code), code);
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        assert_eq!(
+            format(
+                "\
+/dts-v1/;
+
+
+/*
+    * Board
+    */
+/ {
+
+    // First property
+    a;  /* trailing */
+
+
+    b = <1>, // first
+        <2>;
+
+};
+"
+            ),
+            "\
+/dts-v1/;
+
+/*
+ * Board
+ */
+/ {
+\t// First property
+\ta; /* trailing */
+
+\tb = <1>, // first
+\t    <2>;
+};
+"
+        );
+    }
+
+    #[test]
+    fn aligns_and_wraps_values() {
+        assert_eq!(
+            format(
+                "\
+/ {
+    clocks = <&clk 1>,
+  <&clk 2>;
+    table = <1 2
+    3 4>;
+    compatible = \"vendor,first-compatible-string\", \"vendor,second-compatible-string\", \"vendor,third\";
+};"
+            ),
+            "\
+/ {
+\tclocks = <&clk 1>,
+\t         <&clk 2>;
+\ttable = <1 2
+\t         3 4>;
+\tcompatible = \"vendor,first-compatible-string\",
+\t             \"vendor,second-compatible-string\", \"vendor,third\";
+};
+"
+        );
+    }
+
+    #[test]
+    fn indentation_with_spaces() {
+        let options = FormatOptions {
+            use_tabs: false,
+            indent_width: 4,
+            line_width: 40,
+        };
+        assert_eq!(
+            format_source(
+                "/ { node { names = \"first\", \"second\", \"third\"; }; };",
+                &options
+            ),
+            "\
+/ {
+    node {
+        names = \"first\", \"second\",
+                \"third\";
+    };
+};
+"
+        );
+    }
+
+    #[test]
+    fn wraps_long_arrays() {
+        let options = FormatOptions {
+            line_width: 40,
+            ..FormatOptions::default()
+        };
+        let formatted = format_source(
+            "/ { cells = <1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20>, <21 22>; };",
+            &options,
+        );
+        assert_eq!(
+            formatted,
+            "\
+/ {
+\tcells = <1 2 3 4 5 6 7 8 9 10 11
+\t         12 13 14 15 16 17 18 19
+\t         20>, <21 22>;
+};
+"
+        );
+        assert_eq!(format_source(&formatted, &options), formatted);
+    }
+
+    #[test]
+    fn keeps_preprocessor_directives() {
+        let code = "\
+#include <dt-bindings/gpio/gpio.h>
+#define LED(n) \\
+    &gpio n   GPIO_ACTIVE_LOW
+
+/ {
+#ifdef HAS_LED
+\tled {
+\t\tgpios = <LED(3)>;
+\t};
+#endif
+\t#address-cells = <1>;
+};
+";
+        assert_eq!(format(code), code);
+    }
+
+    #[test]
+    fn keeps_text_that_cannot_be_lexed() {
+        assert_eq!(
+            format_source("/ {   a = \"unterminated;\n};", &FormatOptions::default()),
+            "/ {\n\ta =\n\"unterminated;\n};\n"
+        );
+    }
+
+    #[test]
+    fn keeps_non_ascii_text() {
+        assert_eq!(
+            format_source("/ { é = \"ü\"; };", &FormatOptions::default()),
+            "/ {\n\té = \"ü\";\n};\n"
+        );
+    }
+}
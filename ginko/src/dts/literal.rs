@@ -0,0 +1,170 @@
+use itertools::Itertools;
+use std::fmt::{Display, Formatter};
+
+/// Errors that can occur when parsing an integer literal.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum IntError {
+    // e.g. "0x"
+    NoDigits,
+    InvalidDigit { digit: char, radix: u32 },
+    InvalidSuffix(String),
+    // The literal does not fit into 64 bits
+    TooLarge,
+}
+
+impl Display for IntError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntError::NoDigits => write!(f, "Integer literal has no digits"),
+            IntError::InvalidDigit { digit, radix } => {
+                let base = match radix {
+                    8 => "octal",
+                    16 => "hexadecimal",
+                    _ => "decimal",
+                };
+                write!(f, "Invalid digit '{digit}' in {base} literal")
+            }
+            IntError::InvalidSuffix(suffix) => {
+                write!(f, "Invalid suffix '{suffix}' on integer literal")
+            }
+            IntError::TooLarge => write!(f, "Integer literal does not fit in 64 bits"),
+        }
+    }
+}
+
+// Integer suffixes as they can appear in C code, e.g., in macro-expanded headers.
+// Suffixes are irrelevant for the value, as all arithmetic is done using 64 bits.
+const SUFFIXES: [&str; 7] = ["u", "l", "ul", "lu", "ll", "ull", "llu"];
+
+/// Parses a decimal, octal (leading `0`) or hexadecimal (leading `0x`) integer literal.
+/// C-style suffixes such as `U`, `UL` or `ULL` are accepted and ignored.
+pub fn parse_int(text: &str) -> Result<u64, IntError> {
+    let (digits, radix) =
+        if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            (hex, 16)
+        } else if text.len() > 1 && text.starts_with('0') {
+            (&text[1..], 8)
+        } else {
+            (text, 10)
+        };
+    let suffix_start = digits
+        .find(|ch: char| !ch.is_ascii_hexdigit() || (radix != 16 && !ch.is_ascii_digit()))
+        .unwrap_or(digits.len());
+    let (digits, suffix) = digits.split_at(suffix_start);
+    if !suffix.is_empty() {
+        let is_suffix = SUFFIXES.contains(&suffix.to_ascii_lowercase().as_str())
+            // Mixed-case `lL` is not allowed
+            && !suffix.contains("lL")
+            && !suffix.contains("Ll");
+        if !is_suffix {
+            return Err(IntError::InvalidSuffix(suffix.to_string()));
+        }
+    }
+    if digits.is_empty() {
+        // A sole '0' is matched as octal with no digits
+        return if radix == 8 {
+            Ok(0)
+        } else {
+            Err(IntError::NoDigits)
+        };
+    }
+    digits.chars().try_fold(0u64, |value, ch| {
+        let digit = digit(ch, radix)?;
+        value
+            .checked_mul(radix as u64)
+            .and_then(|value| value.checked_add(digit as u64))
+            .ok_or(IntError::TooLarge)
+    })
+}
+
+/// Parses the hexadecimal digits of a byte string, e.g. `0a1b` in `[0a1b]`.
+/// Each pair of digits is one byte; the caller ensures that the number of digits is even.
+pub fn parse_bytes(text: &str) -> Result<Vec<u8>, IntError> {
+    text.chars()
+        .tuples()
+        .map(|(high, low)| Ok((digit(high, 16)? * 16 + digit(low, 16)?) as u8))
+        .collect()
+}
+
+fn digit(ch: char, radix: u32) -> Result<u32, IntError> {
+    ch.to_digit(radix)
+        .ok_or(IntError::InvalidDigit { digit: ch, radix })
+}
+
+/// Formats a value in hexadecimal, separating groups of four digits by underscores
+/// to make large values readable, e.g. `0x1_0000_0000`.
+pub fn format_hex(value: u64) -> String {
+    let digits = format!("{value:x}");
+    let mut result = String::from("0x");
+    for (i, ch) in digits.chars().enumerate() {
+        if i != 0 && (digits.len() - i) % 4 == 0 {
+            result.push('_');
+        }
+        result.push(ch);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::literal::{format_hex, parse_bytes, parse_int, IntError};
+
+    #[test]
+    fn literals() {
+        assert_eq!(parse_int("0"), Ok(0));
+        assert_eq!(parse_int("42"), Ok(42));
+        assert_eq!(parse_int("017"), Ok(15));
+        assert_eq!(parse_int("0x1f"), Ok(31));
+        assert_eq!(parse_int("0XFF"), Ok(255));
+        assert_eq!(parse_int("0xffffffffffffffff"), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn suffixes() {
+        assert_eq!(parse_int("0x1000UL"), Ok(0x1000));
+        assert_eq!(parse_int("10ULL"), Ok(10));
+        assert_eq!(parse_int("10u"), Ok(10));
+        assert_eq!(parse_int("0LL"), Ok(0));
+        assert_eq!(parse_int("07lu"), Ok(7));
+        assert_eq!(parse_int("10lL"), Err(IntError::InvalidSuffix("lL".into())));
+        assert_eq!(parse_int("10UU"), Err(IntError::InvalidSuffix("UU".into())));
+        assert_eq!(parse_int("10k"), Err(IntError::InvalidSuffix("k".into())));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_int("0x"), Err(IntError::NoDigits));
+        assert_eq!(
+            parse_int("09"),
+            Err(IntError::InvalidDigit {
+                digit: '9',
+                radix: 8
+            })
+        );
+        assert_eq!(parse_int("12a"), Err(IntError::InvalidSuffix("a".into())));
+        assert_eq!(parse_int("0x10000000000000000"), Err(IntError::TooLarge));
+        assert_eq!(parse_int("18446744073709551616"), Err(IntError::TooLarge));
+    }
+
+    #[test]
+    fn byte_strings() {
+        assert_eq!(parse_bytes(""), Ok(vec![]));
+        assert_eq!(parse_bytes("00aBff"), Ok(vec![0x00, 0xab, 0xff]));
+        assert_eq!(
+            parse_bytes("0g"),
+            Err(IntError::InvalidDigit {
+                digit: 'g',
+                radix: 16
+            })
+        );
+    }
+
+    #[test]
+    fn hex_formatting() {
+        assert_eq!(format_hex(0), "0x0");
+        assert_eq!(format_hex(0x1ff), "0x1ff");
+        assert_eq!(format_hex(0xffff), "0xffff");
+        assert_eq!(format_hex(0x10000), "0x1_0000");
+        assert_eq!(format_hex(0x1_0000_0000), "0x1_0000_0000");
+    }
+}
@@ -1,23 +1,51 @@
 /// Module for analyzing Device-Tree Source files
 mod analysis;
 mod ast;
+mod cst;
 mod data;
 mod diagnostics;
+mod dtb;
 mod error_codes;
+mod expression;
+mod format;
 mod import_guard;
+mod literal;
+mod overlay;
 mod parser;
+mod phandle;
+mod preprocessor;
 mod project;
+mod property_type;
 mod reader;
+mod schema;
+mod source_map;
+mod source_text;
 #[cfg(test)]
 mod test;
 mod tokens;
+mod tree;
 mod visitor;
+mod yaml;
 
-pub use ast::{AnyDirective, Node, NodeItem, NodePayload, Primary};
-pub use data::{FileType, HasSpan, Position, Span};
+pub use ast::{AnyDirective, DtsFile, Node, NodeItem, NodePayload, Primary, Property};
+pub use cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, SyntaxTree, Trivia, TriviaKind};
+pub use data::{FileType, HasSource, HasSpan, Location, Position, Span};
 pub use diagnostics::{Diagnostic, DiagnosticPrinter, Severity};
+pub use dtb::{DtbError, DtbOptions};
 pub use error_codes::{ErrorCode, SeverityMap};
+pub use expression::{BinaryOperator, Expression, UnaryOperator};
+pub use format::{format_source, FormatOptions};
+pub use literal::{parse_int, IntError};
 pub use parser::Parser;
 pub use parser::ParserContext;
+pub use phandle::PhandleStyle;
+pub use preprocessor::{Preprocessed, Preprocessor};
 pub use project::Project;
+pub use property_type::{PropertyType, StandardProperty};
+pub use schema::Bindings;
+pub use source_map::{MacroExpansion, SourceMap};
+pub use tree::{
+    Data, DeviceTree, Marker, MarkerKind, Reservation, TreeNode, TreeProperty, TypedValue,
+    ValueType,
+};
 pub use visitor::ItemAtCursor;
@@ -0,0 +1,873 @@
+use crate::dts::ast::{NodeName, Path, Reference};
+use crate::dts::data::{HasSource, HasSpan, Location, Position};
+use crate::dts::error_codes::ErrorCode;
+use crate::dts::phandle::PhandleStyle;
+use crate::dts::tree::{Data, DeviceTree, Marker, MarkerKind, TreeNode, TreeProperty, ValueType};
+use crate::dts::Diagnostic;
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::path::Path as StdPath;
+use std::sync::Arc;
+
+// Appends a value to a property, creating the property if necessary
+fn append_value(node: &mut TreeNode, name: &str, value_type: ValueType, bytes: &[u8]) {
+    let data = match node
+        .properties
+        .iter_mut()
+        .find(|property| property.name == name)
+    {
+        Some(property) => &mut property.data,
+        None => {
+            node.properties.push(TreeProperty {
+                name: name.to_string(),
+                labels: vec![],
+                data: Data::default(),
+                location: None,
+            });
+            &mut node.properties.last_mut().unwrap().data
+        }
+    };
+    data.markers.push(Marker {
+        offset: data.bytes.len(),
+        kind: MarkerKind::Type(value_type),
+    });
+    data.bytes.extend(bytes);
+}
+
+fn string_bytes(string: &str) -> Vec<u8> {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+impl TreeNode {
+    // Returns the child with the given name, creating it if it doesn't exist
+    fn child_or_insert(&mut self, name: &str) -> &mut TreeNode {
+        let name = NodeName::from(name.to_string());
+        match self.children.iter().position(|child| child.name == name) {
+            Some(idx) => &mut self.children[idx],
+            None => {
+                self.children.push(TreeNode::new(name));
+                self.children.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+impl DeviceTree {
+    /// Adds the `__symbols__` node that maps the labels of all nodes to their paths,
+    /// like dtc does when compiling with `-@`.
+    /// Labeled nodes are assigned a phandle so that overlays can reference them.
+    pub(crate) fn add_symbols(&mut self, style: PhandleStyle) {
+        let mut symbols = vec![];
+        self.walk(&mut |path, node| {
+            for label in &node.labels {
+                symbols.push((label.clone(), path.clone()));
+            }
+        });
+        if symbols.is_empty() {
+            return;
+        }
+        let node = self.root.child_or_insert("__symbols__");
+        for (label, path) in &symbols {
+            if node.property(label).is_none() {
+                append_value(
+                    node,
+                    label,
+                    ValueType::String,
+                    &string_bytes(&path.to_string()),
+                );
+            }
+        }
+        for (_, path) in symbols {
+            self.ensure_phandle(&path, style);
+        }
+    }
+
+    /// Adds the `__fixups__` node for references to labels that are not part of this tree
+    /// and the `__local_fixups__` node for references to nodes of this tree.
+    /// These nodes describe the locations of phandles that need to be adjusted
+    /// when applying this tree as an overlay.
+    pub(crate) fn add_fixups(&mut self) -> Vec<Diagnostic> {
+        let mut fixups = vec![];
+        let mut local_fixups = vec![];
+        let mut diagnostics = vec![];
+        self.walk(&mut |path, node| {
+            for property in &node.properties {
+                for marker in &property.data.markers {
+                    let MarkerKind::Phandle(reference) = &marker.kind else {
+                        continue;
+                    };
+                    if self.resolve(reference).is_some() {
+                        local_fixups.push((path.clone(), property.name.clone(), marker.offset));
+                        continue;
+                    }
+                    match reference {
+                        Reference::Label(label) => fixups.push((
+                            label.clone(),
+                            format!("{path}:{}:{}", property.name, marker.offset),
+                        )),
+                        Reference::Path(_) => {
+                            if let Some(location) = &property.location {
+                                diagnostics.push(Diagnostic::new(
+                                    location.span(),
+                                    location.source(),
+                                    ErrorCode::UnresolvedReference,
+                                    format!("Path reference {reference} cannot be resolved when applying the overlay"),
+                                ))
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        if !fixups.is_empty() {
+            let node = self.root.child_or_insert("__fixups__");
+            for (label, entry) in fixups {
+                append_value(node, &label, ValueType::String, &string_bytes(&entry));
+            }
+        }
+        if !local_fixups.is_empty() {
+            let node = self.root.child_or_insert("__local_fixups__");
+            for (path, property, offset) in local_fixups {
+                let node = path.iter().fold(&mut *node, |node, name| {
+                    node.child_or_insert(&name.to_string())
+                });
+                append_value(
+                    node,
+                    &property,
+                    ValueType::Cells(32),
+                    &(offset as u32).to_be_bytes(),
+                );
+            }
+        }
+        diagnostics
+    }
+}
+
+fn string_value(data: &Data) -> Option<&str> {
+    std::str::from_utf8(data.bytes.strip_suffix(&[0])?).ok()
+}
+
+fn type_name(value_type: ValueType) -> String {
+    match value_type {
+        ValueType::String => "a string".to_string(),
+        ValueType::Cells(bits) => format!("{bits}-bit cells"),
+        ValueType::Bytes => "bytes".to_string(),
+    }
+}
+
+// Returns a message if the types of an existing value and a new value don't match.
+// Only strings and cells are distinguished, as the difference between
+// bytes and cells cannot be guessed reliably.
+fn type_conflict(name: &str, existing: &Data, new: &Data) -> Option<String> {
+    if existing.bytes.is_empty() || new.bytes.is_empty() {
+        return None;
+    }
+    let (existing_type, new_type) = (existing.value_type(), new.value_type());
+    let conflict = match (existing_type, new_type) {
+        (ValueType::Cells(existing_bits), ValueType::Cells(new_bits)) => existing_bits != new_bits,
+        _ => (existing_type == ValueType::String) != (new_type == ValueType::String),
+    };
+    conflict.then(|| {
+        format!(
+            "Property '{name}' is {} in the base tree, but the overlay sets {}",
+            type_name(existing_type),
+            type_name(new_type)
+        )
+    })
+}
+
+// Parses an entry of the `__fixups__` node, e.g. `/fragment@0:target:0`
+fn parse_fixup(entry: &str) -> Option<(Path, &str, usize)> {
+    let mut parts = entry.rsplitn(3, ':');
+    let offset = parts.next()?.parse().ok()?;
+    let property = parts.next()?;
+    let path = parts.next()?;
+    Some((Path::from(path), property, offset))
+}
+
+struct OverlayApplier<'a> {
+    base: &'a mut DeviceTree,
+    overlay: DeviceTree,
+    source: Arc<StdPath>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl OverlayApplier<'_> {
+    // Items of overlays that are read from blobs have no location.
+    // Diagnostics for these items point to the start of the overlay file.
+    fn add_diagnostic(&mut self, location: Option<Location>, code: ErrorCode, message: String) {
+        let (span, source) = match location {
+            Some(location) => (location.span(), location.source()),
+            None => (Position::zero().as_span(), self.source.clone()),
+        };
+        self.diagnostics
+            .push(Diagnostic::new(span, source, code, message));
+    }
+
+    fn location(&self, path: &Path, property: &str) -> Option<Location> {
+        self.overlay
+            .node(path)?
+            .property(property)?
+            .location
+            .clone()
+    }
+
+    // Adds the largest phandle of the base tree to all phandles of the overlay
+    // and to all references that are listed in `__local_fixups__`
+    fn renumber_phandles(&mut self) {
+        let mut delta = 0;
+        self.base.walk(&mut |_, node| {
+            delta = delta.max(node.phandle().unwrap_or_default());
+        });
+
+        fn renumber(node: &mut TreeNode, delta: u32) {
+            for property in &mut node.properties {
+                if property.name != "phandle" && property.name != "linux,phandle" {
+                    continue;
+                }
+                if let Some(phandle) = property.data.as_cell() {
+                    if phandle != 0 && phandle != u32::MAX {
+                        property.data.bytes = phandle.wrapping_add(delta).to_be_bytes().to_vec();
+                    }
+                }
+            }
+            for child in &mut node.children {
+                renumber(child, delta);
+            }
+        }
+        renumber(&mut self.overlay.root, delta);
+
+        fn collect(node: &TreeNode, path: Path, fixups: &mut Vec<(Path, TreeProperty)>) {
+            for property in &node.properties {
+                fixups.push((path.clone(), property.clone()));
+            }
+            for child in &node.children {
+                collect(child, path.with_child(child.name.clone()), fixups);
+            }
+        }
+        let mut local_fixups = vec![];
+        if let Some(node) = self.overlay.node(&Path::from("/__local_fixups__")) {
+            collect(node, Path::empty(), &mut local_fixups);
+        }
+        for (path, fixup) in local_fixups {
+            let offsets = fixup.data.bytes.chunks(4).map(|chunk| {
+                chunk
+                    .iter()
+                    .fold(0, |value, byte| (value << 8) | *byte as usize)
+            });
+            for offset in offsets {
+                if !self.adjust_cell(&path, &fixup.name, offset, |cell| cell.wrapping_add(delta)) {
+                    self.add_diagnostic(
+                        fixup.location.clone(),
+                        ErrorCode::InvalidOverlay,
+                        format!(
+                            "Local fixup {path}:{}:{offset} does not point to a phandle",
+                            fixup.name
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    // Replaces the cell at `offset` of a property of the overlay.
+    // Returns `false` if the property does not contain a cell at that offset.
+    fn adjust_cell(
+        &mut self,
+        path: &Path,
+        property: &str,
+        offset: usize,
+        f: impl FnOnce(u32) -> u32,
+    ) -> bool {
+        let Some(cell) = self
+            .overlay
+            .node_mut(path)
+            .and_then(|node| {
+                node.properties
+                    .iter_mut()
+                    .find(|existing| existing.name == property)
+            })
+            .and_then(|property| property.data.bytes.get_mut(offset..offset + 4))
+        else {
+            return false;
+        };
+        let value = f(u32::from_be_bytes((&*cell).try_into().unwrap()));
+        cell.copy_from_slice(&value.to_be_bytes());
+        true
+    }
+
+    fn symbol(&self, label: &str) -> Option<Path> {
+        let symbols = self.base.node(&Path::from("/__symbols__"))?;
+        Some(Path::from(string_value(&symbols.property(label)?.data)?))
+    }
+
+    // Inserts the phandles of the base tree for all references listed in `__fixups__`
+    fn resolve_fixups(&mut self) {
+        let Some(fixups) = self.overlay.node(&Path::from("/__fixups__")) else {
+            return;
+        };
+        let entries = fixups
+            .properties
+            .iter()
+            .flat_map(|property| {
+                property
+                    .data
+                    .bytes
+                    .split(|byte| *byte == 0)
+                    .filter(|entry| !entry.is_empty())
+                    .map(|entry| {
+                        (
+                            property.name.clone(),
+                            String::from_utf8_lossy(entry).into_owned(),
+                            property.location.clone(),
+                        )
+                    })
+            })
+            .collect_vec();
+        for (label, entry, fixup_location) in entries {
+            let Some((path, property, offset)) = parse_fixup(&entry) else {
+                self.add_diagnostic(
+                    fixup_location,
+                    ErrorCode::InvalidOverlay,
+                    format!("Malformed fixup '{entry}'"),
+                );
+                continue;
+            };
+            let location = self.location(&path, property).or(fixup_location);
+            let Some(symbol) = self.symbol(&label) else {
+                self.add_diagnostic(
+                    location,
+                    ErrorCode::UnresolvedOverlayReference,
+                    format!("Label '{label}' is not defined in the base tree"),
+                );
+                continue;
+            };
+            let Some(phandle) = self.base.node(&symbol).and_then(|node| node.phandle()) else {
+                self.add_diagnostic(
+                    location,
+                    ErrorCode::UnresolvedOverlayReference,
+                    format!("Label '{label}' refers to {symbol}, which has no phandle"),
+                );
+                continue;
+            };
+            if !self.adjust_cell(&path, property, offset, |_| phandle) {
+                self.add_diagnostic(
+                    location,
+                    ErrorCode::InvalidOverlay,
+                    format!("Fixup '{entry}' does not point to a phandle"),
+                );
+            }
+        }
+    }
+
+    fn node_by_phandle(&self, phandle: u32) -> Option<Path> {
+        let mut result = None;
+        self.base.walk(&mut |path, node| {
+            if result.is_none() && node.phandle() == Some(phandle) {
+                result = Some(path.clone());
+            }
+        });
+        result
+    }
+
+    // Resolves a path or an alias of the base tree
+    fn resolve_path(&self, path: &str) -> Option<Path> {
+        let path = if path.starts_with('/') {
+            Path::from(path)
+        } else {
+            let aliases = self.base.node(&Path::from("/aliases"))?;
+            Path::from(string_value(&aliases.property(path)?.data)?)
+        };
+        self.base.node(&path).map(|_| path)
+    }
+
+    fn fragment_target(&mut self, fragment: &TreeNode) -> Option<Path> {
+        if let Some(target) = fragment.property("target") {
+            let phandle = target.data.as_cell();
+            // Unresolved fixups have already been reported
+            if phandle == Some(u32::MAX) {
+                return None;
+            }
+            let path = phandle.and_then(|phandle| self.node_by_phandle(phandle));
+            if path.is_none() {
+                self.add_diagnostic(
+                    target.location.clone(),
+                    ErrorCode::UnresolvedOverlayReference,
+                    format!(
+                        "Target of {} does not exist in the base tree",
+                        fragment.name
+                    ),
+                );
+            }
+            path
+        } else if let Some(target) = fragment.property("target-path") {
+            let path = string_value(&target.data).and_then(|path| self.resolve_path(path));
+            if path.is_none() {
+                self.add_diagnostic(
+                    target.location.clone(),
+                    ErrorCode::UnresolvedOverlayReference,
+                    format!(
+                        "Target path {} does not exist in the base tree",
+                        String::from_utf8_lossy(&target.data.bytes).trim_end_matches('\0')
+                    ),
+                );
+            }
+            path
+        } else {
+            self.add_diagnostic(
+                fragment.location.clone(),
+                ErrorCode::InvalidOverlay,
+                format!("{} has no target", fragment.name),
+            );
+            None
+        }
+    }
+
+    // Merges the contents of a node of the overlay into the node of the base tree at `path`
+    fn merge(&mut self, path: &Path, node: &TreeNode) {
+        for property in &node.properties {
+            let Some(target) = self.base.node(path) else {
+                return;
+            };
+            if let Some(message) = target
+                .property(&property.name)
+                .and_then(|existing| type_conflict(&property.name, &existing.data, &property.data))
+            {
+                self.add_diagnostic(
+                    property.location.clone(),
+                    ErrorCode::ConflictingPropertyType,
+                    message,
+                );
+            }
+            if let Some(target) = self.base.node_mut(path) {
+                target.set_property(property.clone());
+            }
+        }
+        for child in &node.children {
+            let Some(target) = self.base.node_mut(path) else {
+                return;
+            };
+            match target.child_mut(&child.name) {
+                Some(existing) => {
+                    for label in &child.labels {
+                        existing.add_label(label);
+                    }
+                }
+                None => {
+                    let mut new_child = TreeNode::new(child.name.clone());
+                    new_child.labels = child.labels.clone();
+                    new_child.location = child.location.clone();
+                    target.children.push(new_child);
+                }
+            }
+            self.merge(&path.with_child(child.name.clone()), child);
+        }
+    }
+
+    // Merges the contents of all fragments into their targets.
+    // Returns the targets of the fragments.
+    fn merge_fragments(&mut self) -> HashMap<NodeName, Path> {
+        let overlay_name = NodeName::simple("__overlay__");
+        let fragments = self
+            .overlay
+            .root
+            .children
+            .iter()
+            .filter(|fragment| fragment.child(&overlay_name).is_some())
+            .cloned()
+            .collect_vec();
+        let mut targets = HashMap::new();
+        for fragment in fragments {
+            let Some(target) = self.fragment_target(&fragment) else {
+                continue;
+            };
+            self.merge(&target, fragment.child(&overlay_name).unwrap());
+            targets.insert(fragment.name, target);
+        }
+        targets
+    }
+
+    // Adds the symbols of the overlay to the symbols of the base tree,
+    // replacing fragments by their targets
+    fn update_symbols(&mut self, targets: &HashMap<NodeName, Path>) {
+        let Some(symbols) = self.overlay.node(&Path::from("/__symbols__")) else {
+            return;
+        };
+        let mut updated = vec![];
+        for symbol in &symbols.properties {
+            let Some(path) = string_value(&symbol.data) else {
+                continue;
+            };
+            let path = Path::from(path);
+            let mut elements = path.iter();
+            // Symbols outside of fragments don't end up in the base tree
+            let Some(target) = elements.next().and_then(|fragment| targets.get(fragment)) else {
+                continue;
+            };
+            if elements.next() != Some(&NodeName::simple("__overlay__")) {
+                continue;
+            }
+            let path = elements.fold(target.clone(), |path, name| path.with_child(name.clone()));
+            updated.push((symbol.name.clone(), path));
+        }
+        if updated.is_empty() {
+            return;
+        }
+        let node = self.base.root.child_or_insert("__symbols__");
+        for (label, path) in updated {
+            node.set_property(TreeProperty {
+                name: label,
+                labels: vec![],
+                data: Data {
+                    bytes: string_bytes(&path.to_string()),
+                    markers: vec![Marker {
+                        offset: 0,
+                        kind: MarkerKind::Type(ValueType::String),
+                    }],
+                },
+                location: None,
+            });
+        }
+    }
+}
+
+impl DeviceTree {
+    /// Applies a compiled overlay to this tree, like `fdtoverlay` does.
+    ///
+    /// Phandles of the overlay are moved above the phandles of this tree,
+    /// references to labels of this tree are resolved using its `__symbols__` node
+    /// and the contents of all fragments are merged into their targets.
+    /// Diagnostics point to the overlay items that caused them. Items without location,
+    /// e.g., items of overlays that were read from a blob, are reported at the start of `source`.
+    pub fn apply_overlay(&mut self, overlay: &DeviceTree, source: Arc<StdPath>) -> Vec<Diagnostic> {
+        let mut applier = OverlayApplier {
+            base: self,
+            overlay: overlay.clone(),
+            source,
+            diagnostics: vec![],
+        };
+        applier.renumber_phandles();
+        applier.resolve_fixups();
+        let targets = applier.merge_fragments();
+        applier.update_symbols(&targets);
+        applier.diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::ast::Path;
+    use crate::dts::dtb::DtbOptions;
+    use crate::dts::test::Code;
+    use crate::dts::tree::DeviceTree;
+    use crate::dts::{Diagnostic, ErrorCode, HasSource, HasSpan, Position, Project};
+    use std::path::Path as StdPath;
+    use std::sync::Arc;
+
+    fn base() -> DeviceTree {
+        let mut project = Project::default();
+        project.generate_symbols = true;
+        let (diagnostics, context) = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    aliases {
+        serial0 = &uart0;
+    };
+    clk: clock {
+        #clock-cells = <1>;
+    };
+    soc {
+        uart0: uart@1000 {
+            status = \"disabled\";
+            clocks = <&clk 0>;
+        };
+    };
+};",
+        )
+        .get_analyzed_file_in(&project);
+        assert_eq!(diagnostics, vec![]);
+        context.tree().clone()
+    }
+
+    #[test]
+    pub fn compiles_fragments_and_fixups() {
+        let (diagnostics, context) = Code::new(
+            "\
+/dts-v1/;
+/plugin/;
+
+&uart0 {
+    status = \"okay\";
+    local: device {
+        clocks = <&clk 1>;
+        parent = <&local>;
+    };
+};
+
+&{/soc} {
+    other = <&clk &local>;
+};",
+        )
+        .get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        let dts = context.tree().to_dts();
+        assert_eq!(
+            dts,
+            "\
+/dts-v1/;
+
+/ {
+
+\tfragment@0 {
+\t\ttarget = <&uart0>;
+
+\t\t__overlay__ {
+\t\t\tstatus = \"okay\";
+
+\t\t\tlocal: device {
+\t\t\t\tclocks = <&clk 0x01>;
+\t\t\t\tparent = <&local>;
+\t\t\t\tphandle = <0x01>;
+\t\t\t};
+\t\t};
+\t};
+
+\tfragment@1 {
+\t\ttarget-path = \"/soc\";
+
+\t\t__overlay__ {
+\t\t\tother = <&clk &local>;
+\t\t};
+\t};
+
+\t__fixups__ {
+\t\tuart0 = \"/fragment@0:target:0\";
+\t\tclk = \"/fragment@0/__overlay__/device:clocks:0\", \"/fragment@1/__overlay__:other:0\";
+\t};
+
+\t__local_fixups__ {
+
+\t\tfragment@0 {
+
+\t\t\t__overlay__ {
+
+\t\t\t\tdevice {
+\t\t\t\t\tparent = <0x00>;
+\t\t\t\t};
+\t\t\t};
+\t\t};
+
+\t\tfragment@1 {
+
+\t\t\t__overlay__ {
+\t\t\t\tother = <0x04>;
+\t\t\t};
+\t\t};
+\t};
+};
+"
+        );
+        // Unresolved references keep their placeholder
+        let device = context
+            .tree()
+            .node(&Path::from("/fragment@0/__overlay__/device"))
+            .unwrap();
+        assert_eq!(
+            device.property("clocks").unwrap().data.bytes,
+            [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    pub fn generates_symbols() {
+        let mut project = Project::default();
+        project.generate_symbols = true;
+        let (diagnostics, context) = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    first: node {
+        ref = <&other>;
+    };
+    other: other {};
+};",
+        )
+        .get_analyzed_file_in(&project);
+        assert_eq!(diagnostics, vec![]);
+        let tree = context.tree();
+        let symbols = tree.node(&Path::from("/__symbols__")).unwrap();
+        assert_eq!(
+            symbols
+                .properties
+                .iter()
+                .map(|property| (property.name.as_str(), property.data.bytes.as_slice()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("first", b"/node\0".as_slice()),
+                ("other", b"/other\0".as_slice())
+            ]
+        );
+        // Referenced nodes are assigned phandles before labeled nodes
+        let phandle = |path: &str| tree.node(&Path::from(path)).unwrap().phandle();
+        assert_eq!(phandle("/other"), Some(1));
+        assert_eq!(phandle("/node"), Some(2));
+    }
+
+    #[test]
+    pub fn applies_overlays() {
+        let mut tree = base();
+        let mut project = Project::default();
+        project.generate_symbols = true;
+        let overlay = Code::new(
+            "\
+/dts-v1/;
+/plugin/;
+
+&uart0 {
+    status = \"okay\";
+    local: device {
+        clocks = <&clk 1>;
+        parent = <&local>;
+    };
+};
+
+&{/soc} {
+    other = <&uart0 &local>;
+};",
+        );
+        let (diagnostics, context) = overlay.get_analyzed_file_in(&project);
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(tree.apply_overlay(context.tree(), overlay.source()), vec![]);
+
+        let node = |path: &str| tree.node(&Path::from(path)).unwrap();
+        let bytes =
+            |path: &str, property: &str| node(path).property(property).unwrap().data.bytes.clone();
+        // The base tree has the phandles 1 (clk) and 2 (uart0)
+        assert_eq!(node("/clock").phandle(), Some(1));
+        assert_eq!(node("/soc/uart@1000").phandle(), Some(2));
+        assert_eq!(bytes("/soc/uart@1000", "status"), b"okay\0");
+        assert_eq!(node("/soc/uart@1000/device").phandle(), Some(3));
+        assert_eq!(
+            bytes("/soc/uart@1000/device", "clocks"),
+            [0, 0, 0, 1, 0, 0, 0, 1]
+        );
+        assert_eq!(bytes("/soc/uart@1000/device", "parent"), [0, 0, 0, 3]);
+        assert_eq!(bytes("/soc", "other"), [0, 0, 0, 2, 0, 0, 0, 3]);
+        assert_eq!(bytes("/__symbols__", "local"), b"/soc/uart@1000/device\0");
+        assert_eq!(node("/soc/uart@1000/device").labels, vec!["local"]);
+        assert!(tree.node(&Path::from("/fragment@0")).is_none());
+    }
+
+    #[test]
+    pub fn applies_compiled_overlays() {
+        let blob =
+            |tree: &DeviceTree| DeviceTree::from_dtb(&tree.to_dtb(&DtbOptions::default())).unwrap();
+        let mut tree = blob(&base());
+        let overlay = Code::new(
+            "\
+/dts-v1/;
+/plugin/;
+
+&{/soc/uart@1000} {
+    clocks = <&clk 2>;
+};",
+        );
+        let (diagnostics, context) = overlay.get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        let mut compiled = blob(context.tree());
+        // Targets may also be aliases
+        let fragment = &mut compiled.root.children[0];
+        assert_eq!(fragment.properties[0].name, "target-path");
+        fragment.properties[0].data.bytes = b"serial0\0".to_vec();
+        assert_eq!(tree.apply_overlay(&compiled, overlay.source()), vec![]);
+        let uart = tree.node(&Path::from("/soc/uart@1000")).unwrap();
+        assert_eq!(
+            uart.property("clocks").unwrap().data.bytes,
+            [0, 0, 0, 1, 0, 0, 0, 2]
+        );
+    }
+
+    #[test]
+    pub fn erroneous_overlays() {
+        let mut tree = base();
+        let overlay = Code::new(
+            "\
+/dts-v1/;
+/plugin/;
+
+&missing {
+    status = \"okay\";
+};
+
+&uart0 {
+    status = <1>;
+    clocks = <&clk 1>, <&undefined>;
+};
+
+&{/nonexistent} {
+    status = \"okay\";
+};",
+        );
+        let (diagnostics, context) = overlay.get_analyzed_file();
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                overlay.s1("status = <1>;").span(),
+                overlay.source(),
+                ErrorCode::UnexpectedPropertyType,
+                "status property should be a single string"
+            )]
+        );
+        assert_eq!(
+            tree.apply_overlay(context.tree(), overlay.source()),
+            vec![
+                Diagnostic::new(
+                    overlay.s1("&missing").span(),
+                    overlay.source(),
+                    ErrorCode::UnresolvedOverlayReference,
+                    "Label 'missing' is not defined in the base tree"
+                ),
+                Diagnostic::new(
+                    overlay.s1("clocks = <&clk 1>, <&undefined>;").span(),
+                    overlay.source(),
+                    ErrorCode::UnresolvedOverlayReference,
+                    "Label 'undefined' is not defined in the base tree"
+                ),
+                Diagnostic::new(
+                    overlay.s1("status = <1>;").span(),
+                    overlay.source(),
+                    ErrorCode::ConflictingPropertyType,
+                    "Property 'status' is a string in the base tree, but the overlay sets 32-bit cells"
+                ),
+                Diagnostic::new(
+                    overlay.s1("&{/nonexistent}").span(),
+                    overlay.source(),
+                    ErrorCode::UnresolvedOverlayReference,
+                    "Target path /nonexistent does not exist in the base tree"
+                ),
+            ]
+        );
+
+        // Items of compiled overlays are reported at the start of the file
+        let (_, context) = Code::new("/dts-v1/; /plugin/; &{/soc} {};").get_analyzed_file();
+        let mut overlay =
+            DeviceTree::from_dtb(&context.tree().to_dtb(&DtbOptions::default())).unwrap();
+        overlay.root.children[0].properties.clear();
+        let source: Arc<StdPath> = StdPath::new("overlay.dtbo").into();
+        assert_eq!(
+            tree.apply_overlay(&overlay, source.clone()),
+            vec![Diagnostic::new(
+                Position::zero().as_span(),
+                source,
+                ErrorCode::InvalidOverlay,
+                "fragment@0 has no target"
+            )]
+        );
+    }
+}
@@ -1,14 +1,17 @@
 use crate::dts::ast::{
-    AnyDirective, Cell, DtsFile, Include, Memreserve, Node, NodeItem, NodeName, NodePayload, Path,
-    Primary, Property, PropertyValue, ReferencedNode, WithToken,
+    AnyDirective, Bits, ByteString, Cell, DtsFile, Incbin, Include, Memreserve, Node, NodeItem,
+    NodeName, NodePayload, Path, Primary, Property, PropertyValue, ReferencedNode, WithToken,
 };
 use crate::dts::data::{HasSource, Span};
 use crate::dts::diagnostics::{Diagnostic, NameContext};
 use crate::dts::error_codes::ErrorCode;
-use crate::dts::reader::{ByteReader, Reader};
-use crate::dts::tokens::{CompilerDirective, Lexer, PeekingLexer, Reference, Token, TokenKind};
+use crate::dts::expression;
+use crate::dts::literal;
+use crate::dts::reader::ByteReader;
+use crate::dts::tokens::{
+    CompilerDirective, Lexer, PeekingLexer, Reference, Token, TokenKind, TokenStream,
+};
 use crate::dts::HasSpan;
-use itertools::Itertools;
 use std::path::{Path as StdPath, PathBuf};
 use std::sync::Arc;
 
@@ -19,22 +22,22 @@ pub struct ParserContext {
 
 /// The `Parser` class is responsible for syntactical analysis,
 /// transforming the input token stream into an AST.
-pub struct Parser<R>
+pub struct Parser<T>
 where
-    R: Reader + Sized,
+    T: TokenStream,
 {
-    lexer: PeekingLexer<R>,
+    lexer: PeekingLexer<T>,
     pub diagnostics: Vec<Diagnostic>,
     pub context: ParserContext,
 }
 
 type Result<T> = std::result::Result<T, Diagnostic>;
 
-impl<R> Parser<R>
+impl<T> Parser<T>
 where
-    R: Reader + Sized,
+    T: TokenStream,
 {
-    pub fn new(lexer: Lexer<R>, context: ParserContext) -> Parser<R> {
+    pub fn new(lexer: T, context: ParserContext) -> Parser<T> {
         Parser {
             lexer: PeekingLexer::from(lexer),
             diagnostics: vec![],
@@ -43,12 +46,12 @@ where
     }
 }
 
-impl Parser<ByteReader> {
+impl Parser<Lexer<ByteReader>> {
     pub fn from_text(
         context: ParserContext,
         text: impl Into<String>,
         source: Arc<StdPath>,
-    ) -> Parser<ByteReader> {
+    ) -> Parser<Lexer<ByteReader>> {
         let lexer = Lexer::from_text(text, source);
         Parser {
             lexer: lexer.into(),
@@ -58,9 +61,9 @@ impl Parser<ByteReader> {
     }
 }
 
-impl<R> Parser<R>
+impl<T> Parser<T>
 where
-    R: Reader + Sized,
+    T: TokenStream,
 {
     pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> {
         self.diagnostics.iter()
@@ -187,50 +190,78 @@ where
         }
     }
 
-    fn number_u32(&mut self) -> Result<Cell> {
+    // Checks that a value fits into an array element with the given width and truncates it.
+    // Like dtc, negative values (i.e., values where all truncated bits are set) are accepted.
+    fn truncate_to_width(
+        &mut self,
+        value: u64,
+        width: u32,
+        span: Span,
+        source: Arc<StdPath>,
+    ) -> u64 {
+        let mask = u64::MAX >> (64 - width);
+        if value > mask && value | mask != u64::MAX {
+            self.diagnostics.push(Diagnostic::new(
+                span,
+                source,
+                ErrorCode::IntError,
+                format!(
+                    "Value {} does not fit in {width}-bit cell; truncated to {}",
+                    literal::format_hex(value),
+                    literal::format_hex(value & mask)
+                ),
+            ))
+        }
+        value & mask
+    }
+
+    fn cell(&mut self, width: u32) -> Result<Cell> {
         let tok = self.lexer.expect_next()?;
         match &tok.kind {
             TokenKind::Ref(reference) => {
+                if width != 32 {
+                    self.diagnostics.push(Diagnostic::from_token(
+                        tok.clone(),
+                        ErrorCode::ReferenceInNon32BitArray,
+                        "References are only allowed in arrays with 32-bit elements",
+                    ));
+                }
                 Ok(Cell::Reference(self.reference(tok.clone(), reference)))
             }
-            TokenKind::UnparsedNumber(num) => {
-                let num = if num.len() == 1 {
-                    num.parse::<u32>()
-                } else if num.starts_with("0x") {
-                    u32::from_str_radix(&num.as_str()[2..], 16)
-                } else if num.starts_with('0') {
-                    u32::from_str_radix(&num.as_str()[1..], 8)
-                } else {
-                    num.parse::<u32>()
-                };
-                match num {
-                    Ok(num) => Ok(Cell::Number(WithToken::new(num, tok))),
-                    Err(err) => {
-                        self.diagnostics.push(Diagnostic::parse_int_error(
-                            tok.span(),
-                            tok.source(),
-                            err,
-                        ));
-                        Ok(Cell::Number(WithToken::new(0, tok)))
-                    }
+            TokenKind::Char(value) => Ok(Cell::Number(WithToken::new(*value as u64, tok))),
+            TokenKind::UnparsedNumber(num) => match literal::parse_int(num) {
+                Ok(num) => {
+                    let num = self.truncate_to_width(num, width, tok.span(), tok.source());
+                    Ok(Cell::Number(WithToken::new(num, tok)))
                 }
-            }
+                Err(err) => {
+                    self.diagnostics
+                        .push(Diagnostic::int_error(tok.span(), tok.source(), err));
+                    Ok(Cell::Number(WithToken::new(0, tok)))
+                }
+            },
             TokenKind::OpenParen => {
-                self.skip_expression_starting_with_paren();
-                if self.lexer.peek().is_none() {
-                    self.diagnostics.push(Diagnostic::from_token(
+                let mut tokens = vec![tok.clone()];
+                tokens.extend(self.expression_tokens_after_paren());
+                if tokens.last().map(|tok| &tok.kind) != Some(&TokenKind::CloseParen) {
+                    return Err(Diagnostic::from_token(
                         tok,
                         ErrorCode::UnbalancedParentheses,
                         "Unbalanced parentheses",
                     ));
                 }
-                Ok(Cell::Expression)
+                let expression = expression::parse(&tokens)?;
+                let value = expression.evaluate(&mut self.diagnostics);
+                let value =
+                    self.truncate_to_width(value, width, expression.span(), expression.source());
+                Ok(Cell::Expression(expression, value))
             }
             _ => Err(Diagnostic::expected(
                 tok.span(),
                 tok.source(),
                 &[
                     TokenKind::UnparsedNumber("".to_string()),
+                    TokenKind::Char(0),
                     TokenKind::Ref(Reference::Simple("".to_string())),
                     TokenKind::OpenParen,
                 ],
@@ -241,28 +272,14 @@ where
     fn memreserve_number_u64(&mut self) -> Result<WithToken<u64>> {
         let tok = self.lexer.expect_next()?;
         match &tok.kind {
-            TokenKind::UnparsedNumber(num) => {
-                let num = if num.len() == 1 {
-                    num.parse::<u64>()
-                } else if num.starts_with("0x") {
-                    u64::from_str_radix(&num.as_str()[2..], 16)
-                } else if num.starts_with('0') {
-                    u64::from_str_radix(&num.as_str()[1..], 8)
-                } else {
-                    num.parse::<u64>()
-                };
-                match num {
-                    Ok(num) => Ok(WithToken::new(num, tok)),
-                    Err(err) => {
-                        self.diagnostics.push(Diagnostic::parse_int_error(
-                            tok.span(),
-                            tok.source(),
-                            err,
-                        ));
-                        Ok(WithToken::new(0, tok))
-                    }
+            TokenKind::UnparsedNumber(num) => match literal::parse_int(num) {
+                Ok(num) => Ok(WithToken::new(num, tok)),
+                Err(err) => {
+                    self.diagnostics
+                        .push(Diagnostic::int_error(tok.span(), tok.source(), err));
+                    Ok(WithToken::new(0, tok))
                 }
-            }
+            },
             _ => Err(Diagnostic::expected(
                 self.lexer.last_pos().offset_by_char(1).as_span(),
                 self.lexer.source(),
@@ -283,21 +300,14 @@ where
                     ));
                     return Ok(WithToken::new(vec![], tok));
                 }
-                let mut bytes: Vec<u8> = Vec::with_capacity(raw_str.len() / 2);
-                for (first, second) in raw_str.bytes().map(|ch| ch.to_ascii_lowercase()).tuples() {
-                    match u8::from_str_radix(std::str::from_utf8(&[first, second]).unwrap(), 16) {
-                        Ok(byte) => bytes.push(byte),
-                        Err(err) => {
-                            self.diagnostics.push(Diagnostic::parse_int_error(
-                                tok.span(),
-                                tok.source(),
-                                err,
-                            ));
-                            return Ok(WithToken::new(vec![], tok));
-                        }
+                match literal::parse_bytes(raw_str) {
+                    Ok(bytes) => Ok(WithToken::new(bytes, tok)),
+                    Err(err) => {
+                        self.diagnostics
+                            .push(Diagnostic::int_error(tok.span(), tok.source(), err));
+                        Ok(WithToken::new(vec![], tok))
                     }
                 }
-                Ok(WithToken::new(bytes, tok))
             }
             _ => {
                 self.diagnostics.push(Diagnostic::expected(
@@ -313,94 +323,126 @@ where
         }
     }
 
-    fn skip_expression_starting_with_paren(&mut self) {
+    // Returns all tokens up to and including the closing parenthesis
+    // or up to the end of the file, if the parentheses are unbalanced.
+    fn expression_tokens_after_paren(&mut self) -> Vec<Token> {
+        let mut tokens = vec![];
         let mut depth = 1;
-        loop {
-            let peeked = self.lexer.peek();
-            if matches!(
-                peeked,
-                Some(Token {
-                    kind: TokenKind::OpenParen,
-                    ..
-                })
-            ) {
-                depth += 1;
-                self.skip_tok();
-            } else if matches!(
-                peeked,
-                Some(Token {
-                    kind: TokenKind::CloseParen,
-                    ..
-                })
-            ) {
-                depth -= 1;
-                self.skip_tok();
-                if depth == 0 {
-                    return;
-                }
-            } else if peeked.is_none() {
-                return;
-            } else {
-                self.skip_tok();
+        for tok in self.lexer.by_ref() {
+            match tok.kind {
+                TokenKind::OpenParen => depth += 1,
+                TokenKind::CloseParen => depth -= 1,
+                _ => {}
+            }
+            tokens.push(tok);
+            if depth == 0 {
+                break;
             }
         }
+        tokens
     }
 
-    pub fn property_value(&mut self) -> Result<PropertyValue> {
-        if matches!(
+    // /bits/ 16 <...>
+    fn bits(&mut self) -> Result<Option<Bits>> {
+        if !matches!(
             self.lexer.peek(),
             Some(Token {
                 kind: TokenKind::Directive(CompilerDirective::Bits),
                 ..
             })
         ) {
-            self.skip_tok();
-            let width = self.lexer.expect_next()?;
-            if !matches!(width.kind, TokenKind::UnparsedNumber(_)) {
+            return Ok(None);
+        }
+        let directive = self.lexer.expect_next()?;
+        let width = self.lexer.expect_next()?;
+        let value = match &width.kind {
+            TokenKind::UnparsedNumber(num) => match literal::parse_int(num) {
+                Ok(value @ (8 | 16 | 32 | 64)) => value as u32,
+                Err(err) => {
+                    self.diagnostics
+                        .push(Diagnostic::int_error(width.span(), width.source(), err));
+                    32
+                }
+                Ok(_) => {
+                    self.diagnostics.push(Diagnostic::from_token(
+                        width.clone(),
+                        ErrorCode::InvalidBitsWidth,
+                        "Array elements must be 8, 16, 32 or 64 bits",
+                    ));
+                    32
+                }
+            },
+            _ => {
                 self.diagnostics.push(Diagnostic::expected(
                     width.span(),
                     width.source(),
                     &[TokenKind::UnparsedNumber("".to_string())],
-                ))
+                ));
+                32
             }
-        }
+        };
+        Ok(Some(Bits {
+            directive,
+            width: WithToken::new(value, width),
+        }))
+    }
+
+    pub fn property_value(&mut self) -> Result<PropertyValue> {
+        let bits = self.bits()?;
         let tok = self.lexer.expect_next()?;
+        if bits.is_some() && tok.kind != TokenKind::ChevronLeft {
+            return Err(Diagnostic::expected(
+                tok.span(),
+                tok.source(),
+                &[TokenKind::ChevronLeft],
+            ));
+        }
         match &tok.kind {
             TokenKind::String(string) => {
                 Ok(PropertyValue::String(WithToken::new(string.clone(), tok)))
             }
             TokenKind::ChevronLeft => {
+                let width = bits.as_ref().map(|bits| *bits.width.item()).unwrap_or(32);
                 let mut cells: Vec<Cell> = vec![];
                 let end_tok: Token;
                 loop {
-                    self.skip_optional_label();
+                    if let Some(label) = self.optional_label() {
+                        cells.push(Cell::Label(label));
+                        continue;
+                    }
                     if self.lexer.peek_expect()?.kind == TokenKind::ChevronRight {
                         end_tok = self.lexer.expect_next()?;
                         break;
                     }
 
-                    match self.number_u32() {
+                    match self.cell(width) {
                         Ok(numb) => cells.push(numb),
                         Err(err) => self.diagnostics.push(err),
                     }
                 }
-                Ok(PropertyValue::Cells(tok, cells, end_tok))
+                Ok(PropertyValue::Cells(bits, tok, cells, end_tok))
             }
             TokenKind::Ref(reference) => Ok(PropertyValue::Reference(
                 self.reference(tok.clone(), reference),
             )),
+            TokenKind::Directive(CompilerDirective::Incbin) => {
+                Ok(PropertyValue::Incbin(Box::new(self.incbin(tok)?)))
+            }
             TokenKind::OpenBracket => {
-                let mut byte_strings: Vec<WithToken<Vec<u8>>> = vec![];
+                let mut byte_strings: Vec<ByteString> = vec![];
                 let end: Token;
                 loop {
-                    self.skip_optional_label();
+                    if let Some(label) = self.optional_label() {
+                        byte_strings.push(ByteString::Label(label));
+                        continue;
+                    }
                     let tok = self.lexer.peek_expect()?;
                     if tok.kind == TokenKind::CloseBracket {
                         end = tok.clone();
                         self.skip_tok();
                         break;
                     }
-                    byte_strings.push(self.byte_string()?);
+                    byte_strings.push(ByteString::Bytes(self.byte_string()?));
                 }
                 Ok(PropertyValue::ByteStrings(tok, byte_strings, end))
             }
@@ -408,33 +450,140 @@ where
                 tok.span(),
                 tok.source(),
                 &[
-                    TokenKind::String("".to_string()),
+                    TokenKind::String(vec![]),
                     TokenKind::ChevronLeft,
                     TokenKind::Ref(Reference::Simple("".to_string())),
                     TokenKind::OpenBracket,
+                    TokenKind::Directive(CompilerDirective::Incbin),
                 ],
             )),
         }
     }
 
-    fn skip_optional_label(&mut self) {
-        if matches!(
-            self.lexer.peek(),
-            Some(Token {
-                kind: TokenKind::Label(_),
-                ..
-            })
-        ) {
+    // Offset and length of an `/incbin/` directive
+    fn incbin_number(&mut self) -> Result<Cell> {
+        let cell = self.cell(64)?;
+        if let Cell::Reference(reference) = &cell {
+            return Err(Diagnostic::expected(
+                reference.span(),
+                reference.source(),
+                &[TokenKind::UnparsedNumber("".to_string())],
+            ));
+        }
+        Ok(cell)
+    }
+
+    // precondition: the directive has been consumed
+    // /incbin/("file.bin", 0x10, 0x20)
+    //         ^~~ cursor is here
+    fn incbin(&mut self, directive: Token) -> Result<Incbin> {
+        self.lexer.expect(TokenKind::OpenParen)?;
+        let name_tok = self.lexer.expect_next()?;
+        let TokenKind::String(file_name) = &name_tok.kind else {
+            return Err(Diagnostic::expected(
+                name_tok.span(),
+                name_tok.source(),
+                &[TokenKind::String(vec![])],
+            ));
+        };
+        let file_name = WithToken::new(
+            String::from_utf8_lossy(file_name).into_owned(),
+            name_tok.clone(),
+        );
+        let (offset, length) = if self.lexer.peek_kind() == Some(&TokenKind::Comma) {
             self.skip_tok();
+            let offset = self.incbin_number()?;
+            self.lexer.expect(TokenKind::Comma)?;
+            let length = self.incbin_number()?;
+            (Some(offset), Some(length))
+        } else {
+            (None, None)
+        };
+        let end = self.lexer.expect(TokenKind::CloseParen)?;
+        // Files are searched relative to the file containing the directive first
+        let mut include_paths = self.context.include_paths.clone();
+        if let Some(parent) = directive.source().parent() {
+            if !include_paths.iter().any(|path| path == parent) {
+                include_paths.insert(0, parent.into());
+            }
+        }
+        let incbin = Incbin {
+            directive,
+            file_name,
+            offset,
+            length,
+            include_paths,
+            end,
+        };
+        self.check_incbin(&incbin);
+        Ok(incbin)
+    }
+
+    fn check_incbin(&mut self, incbin: &Incbin) {
+        let size = match incbin.path().and_then(std::fs::metadata) {
+            Ok(metadata) => metadata.len(),
+            Err(err) => {
+                self.diagnostics.push(Diagnostic::io_error(
+                    incbin.file_name.span(),
+                    incbin.file_name.source(),
+                    err,
+                ));
+                return;
+            }
+        };
+        if !incbin.fits(size) {
+            let length = match incbin.length() {
+                Some(length) => format!("{} bytes", literal::format_hex(length)),
+                None => "bytes".to_string(),
+            };
+            self.diagnostics.push(Diagnostic::new(
+                incbin.span(),
+                incbin.source(),
+                ErrorCode::InvalidIncbinRange,
+                format!(
+                    "Cannot include {length} at offset {} from '{}' with a size of {} bytes",
+                    literal::format_hex(incbin.offset()),
+                    incbin.file_name.item(),
+                    literal::format_hex(size),
+                ),
+            ))
+        }
+    }
+
+    fn optional_label(&mut self) -> Option<WithToken<String>> {
+        let Some(Token {
+            kind: TokenKind::Label(label),
+            ..
+        }) = self.lexer.peek()
+        else {
+            return None;
+        };
+        let label = label.clone();
+        let tok = self.lexer.next()?;
+        self.check_is_label(tok.span(), &label);
+        Some(WithToken::new(label, tok))
+    }
+
+    // Labels in between property values, e.g. `start: "value" end:`
+    fn value_labels(&mut self, values: &mut Vec<PropertyValue>) {
+        while let Some(label) = self.optional_label() {
+            values.push(PropertyValue::Label(label));
         }
     }
 
+    fn take_lexer_diagnostics(&mut self) {
+        let diagnostics = self.lexer.take_diagnostics();
+        self.diagnostics.extend(diagnostics);
+    }
+
     pub fn property_values(&mut self) -> Result<Vec<PropertyValue>> {
         let mut values: Vec<PropertyValue> = vec![];
         loop {
-            self.skip_optional_label();
-            values.push(self.property_value()?);
-            self.skip_optional_label();
+            self.value_labels(&mut values);
+            let value = self.property_value();
+            self.take_lexer_diagnostics();
+            values.push(value?);
+            self.value_labels(&mut values);
             if self.lexer.peek_expect()?.kind == TokenKind::Comma {
                 self.skip_tok();
             } else {
@@ -478,6 +627,7 @@ where
         let mut node_discovered = false;
         loop {
             let tok = self.lexer.expect_next()?;
+            let doc_comment = self.lexer.doc_comment();
             if tok.kind == TokenKind::Directive(CompilerDirective::DeleteNode) {
                 let node_name = self.node_name()?;
                 self.expect_semicolon()?;
@@ -533,6 +683,7 @@ where
                         name: node_name,
                         label,
                         payload,
+                        doc_comment,
                     })));
                 }
                 TokenKind::Equal => {
@@ -553,6 +704,7 @@ where
                         name: ident,
                         values,
                         end: end_tok,
+                        doc_comment,
                     };
                     if node_discovered {
                         self.diagnostics.push(Diagnostic::new(
@@ -567,7 +719,10 @@ where
                 TokenKind::Semicolon => {
                     self.skip_tok();
                     self.check_is_property_name(ident.span(), &ident);
-                    let prop = Property::empty(ident, label, cloned_tok);
+                    let prop = Property {
+                        doc_comment,
+                        ..Property::empty(ident, label, cloned_tok)
+                    };
                     if node_discovered {
                         self.diagnostics.push(Diagnostic::new(
                             prop.span(),
@@ -627,6 +782,7 @@ where
             | TokenKind::Ident(_)
             | TokenKind::Label(_)
             | TokenKind::String(_)
+            | TokenKind::Char(_)
             | TokenKind::UnparsedNumber(_)
             | TokenKind::Directive(_)
             | TokenKind::Ref(_)
@@ -639,9 +795,7 @@ where
             TokenKind::Unknown(_) | TokenKind::Comment(_) => true,
         };
         let span = if skip {
-            let tok = self.lexer.expect_next()?;
-            self.lexer.insert_pseudo_kind(TokenKind::Semicolon);
-            tok.span()
+            self.lexer.expect_next()?.span()
         } else {
             self.lexer.last_pos().as_char_span()
         };
@@ -656,11 +810,13 @@ where
     pub fn file(&mut self) -> Result<DtsFile> {
         let mut elements: Vec<Primary> = vec![];
         while self.lexer.peek().is_some() {
-            elements.push(self.primary()?);
+            let primary = self.primary();
+            self.take_lexer_diagnostics();
+            elements.push(primary?);
         }
         Ok(DtsFile {
             elements,
-            source: self.lexer.source(),
+            source: self.lexer.file_source(),
         })
     }
 
@@ -679,6 +835,7 @@ where
 
     pub fn primary(&mut self) -> Result<Primary> {
         let token = self.lexer.expect_next()?;
+        let doc_comment = self.lexer.doc_comment();
         match &token.kind {
             TokenKind::Directive(CompilerDirective::DTSVersionHeader) => {
                 self.expect_semicolon()?;
@@ -696,28 +853,17 @@ where
                     Memreserve::new(address, length),
                 )))
             }
-            TokenKind::Ident(str) if str == "#include" => {
-                let tok = self.lexer.expect_next()?;
-                match tok.kind {
-                    TokenKind::String(include_str) => Ok(Primary::CStyleInclude(include_str)),
-                    _ => Err(Diagnostic::expected(
-                        tok.span(),
-                        tok.source(),
-                        &[TokenKind::String("".to_string())],
-                    )),
-                }
-            }
             TokenKind::Directive(CompilerDirective::Include) => {
                 let include_token = token;
                 let string_tok = self.lexer.expect_next()?;
                 // let tok_span = string_tok.span();
                 let path = match string_tok.kind.clone() {
-                    TokenKind::String(string) => string,
+                    TokenKind::String(string) => String::from_utf8_lossy(&string).into_owned(),
                     _ => {
                         return Err(Diagnostic::expected(
                             string_tok.span(),
                             string_tok.source(),
-                            &[TokenKind::String("".into())],
+                            &[TokenKind::String(vec![])],
                         ));
                     }
                 };
@@ -744,6 +890,7 @@ where
                     label: None,
                     payload: root_payload,
                     omit_if_no_ref: None,
+                    doc_comment,
                 })))
             }
             TokenKind::Ref(reference) => {
@@ -788,17 +935,20 @@ where
 #[cfg(test)]
 mod test {
     use crate::dts::ast::{
-        Cell, DtsFile, Memreserve, Node, NodeItem, NodeName, NodePayload, Path, Property,
-        PropertyValue, Reference, WithToken,
+        ByteString, Cell, DtsFile, Memreserve, Node, NodeItem, NodeName, NodePayload, Path,
+        Property, PropertyValue, Reference, WithToken,
     };
     use crate::dts::data::HasSource;
     use crate::dts::diagnostics::Diagnostic;
     use crate::dts::error_codes::ErrorCode;
-    use crate::dts::parser::Parser;
+    use crate::dts::parser::{Parser, ParserContext};
     use crate::dts::test::Code;
     use crate::dts::tokens::CompilerDirective::OmitIfNoRef;
     use crate::dts::tokens::TokenKind::{Directive, Equal, OpenBrace, Semicolon};
     use crate::dts::{AnyDirective, HasSpan, Position, Primary};
+    use assert_matches::assert_matches;
+    use itertools::Itertools;
+    use std::io;
     use std::sync::Arc;
     use std::vec;
 
@@ -807,12 +957,60 @@ mod test {
         let code = Code::new("\"\"");
         assert_eq!(
             code.parse_ok_no_diagnostics(Parser::property_value),
-            PropertyValue::String(WithToken::new("".into(), code.token()))
+            PropertyValue::String(WithToken::new(vec![], code.token()))
         );
         let code = Code::new("\"bar\"");
         assert_eq!(
             code.parse_ok_no_diagnostics(Parser::property_value),
-            PropertyValue::String(WithToken::new("bar".into(), code.token()))
+            PropertyValue::String(WithToken::new(b"bar".to_vec(), code.token()))
+        );
+    }
+
+    #[test]
+    pub fn escaped_strings() {
+        let code = Code::new(r#""\x41\101\t\"""#);
+        let value = code.parse_ok_no_diagnostics(Parser::property_value);
+        assert_eq!(
+            value,
+            PropertyValue::String(WithToken::new(b"AA\t\"".to_vec(), code.token()))
+        );
+        assert_eq!(value.to_string(), r#""AA\t\"""#);
+
+        let code = Code::new(r#"= "\q", "ok";"#);
+        let (values, diagnostics) = code.parse_ok(|parser| {
+            parser.skip_tok();
+            parser.property_values()
+        });
+        assert_eq!(values.len(), 2);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                code.s1(r"\q").span(),
+                code.source(),
+                ErrorCode::UnknownEscape,
+                "Unknown escape sequence '\\q'"
+            )]
+        );
+    }
+
+    #[test]
+    pub fn char_literal_cells() {
+        let code = Code::new(r"= <'a' ('0' + 1) '\n'>");
+        let value = code.parse_ok_no_diagnostics(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        let PropertyValue::Cells(_, _, cells, _) = value else {
+            panic!("Expected cells")
+        };
+        assert_eq!(
+            cells[0],
+            Cell::Number(WithToken::new(0x61, code.s1("'a'").token()))
+        );
+        assert_matches!(cells[1], Cell::Expression(_, 0x31));
+        assert_eq!(
+            cells[2],
+            Cell::Number(WithToken::new(0x0a, code.s1(r"'\n'").token()))
         );
     }
 
@@ -850,12 +1048,13 @@ mod test {
         let code = Code::new("<>");
         assert_eq!(
             code.parse_ok_no_diagnostics(Parser::property_value),
-            PropertyValue::Cells(code.s1("<").token(), vec![], code.s1(">").token())
+            PropertyValue::Cells(None, code.s1("<").token(), vec![], code.s1(">").token())
         );
         let code = Code::new("<0>");
         assert_eq!(
             code.parse_ok_no_diagnostics(Parser::property_value),
             PropertyValue::Cells(
+                None,
                 code.s1("<").token(),
                 vec![Cell::Number(WithToken::new(0, code.s1("0").token()))],
                 code.s1(">").token(),
@@ -865,6 +1064,7 @@ mod test {
         assert_eq!(
             code.parse_ok_no_diagnostics(Parser::property_value),
             PropertyValue::Cells(
+                None,
                 code.s1("<").token(),
                 vec![Cell::Number(WithToken::new(4, code.s1("4").token()))],
                 code.s1(">").token(),
@@ -874,6 +1074,7 @@ mod test {
         assert_eq!(
             code.parse_ok_no_diagnostics(Parser::property_value),
             PropertyValue::Cells(
+                None,
                 code.s1("<").token(),
                 vec![
                     Cell::Number(WithToken::new(4, code.s1("4").token())),
@@ -886,6 +1087,7 @@ mod test {
         assert_eq!(
             code.parse_ok_no_diagnostics(Parser::property_value),
             PropertyValue::Cells(
+                None,
                 code.s1("<").token(),
                 vec![
                     Cell::Number(WithToken::new(17, code.s1("17").token())),
@@ -898,6 +1100,7 @@ mod test {
         assert_eq!(
             code.parse_ok_no_diagnostics(Parser::property_value),
             PropertyValue::Cells(
+                None,
                 code.s1("<").token(),
                 vec![
                     Cell::Number(WithToken::new(17, code.s1("17").token())),
@@ -911,6 +1114,84 @@ mod test {
         );
     }
 
+    #[test]
+    pub fn sized_cell_properties() {
+        let code = Code::new("/bits/ 64 <0x100000000 (~0)>");
+        let value = code.parse_ok_no_diagnostics(Parser::property_value);
+        assert_eq!(value.cell_width(), Some(64));
+        assert_eq!(value.span(), code.span());
+        let PropertyValue::Cells(Some(bits), _, cells, _) = &value else {
+            panic!("Expected sized cells")
+        };
+        assert_eq!(bits.width, WithToken::new(64, code.s1("64").token()));
+        assert_eq!(
+            cells[0],
+            Cell::Number(WithToken::new(0x100000000, code.s1("0x100000000").token()))
+        );
+        assert_matches!(cells[1], Cell::Expression(_, u64::MAX));
+        assert_eq!(value.to_string(), "/bits/ 64 <0x100000000 (~0x0)>");
+
+        let code = Code::new("/bits/ 8 <0x1ff 0xff (~0)>");
+        let (value, diagnostics) = code.parse_ok(Parser::property_value);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                code.s1("0x1ff").span(),
+                code.source(),
+                ErrorCode::IntError,
+                "Value 0x1ff does not fit in 8-bit cell; truncated to 0xff"
+            )]
+        );
+        let PropertyValue::Cells(_, _, cells, _) = &value else {
+            panic!("Expected cells")
+        };
+        assert_matches!(cells[2], Cell::Expression(_, 0xff));
+
+        let code = Code::new("<0x100000000>");
+        let (_, diagnostics) = code.parse_ok(Parser::property_value);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                code.s1("0x100000000").span(),
+                code.source(),
+                ErrorCode::IntError,
+                "Value 0x1_0000_0000 does not fit in 32-bit cell; truncated to 0x0"
+            )]
+        );
+    }
+
+    #[test]
+    pub fn illegal_bits() {
+        let code = Code::new("/bits/ 7 <1>");
+        let (value, diagnostics) = code.parse_ok(Parser::property_value);
+        assert_eq!(value.cell_width(), Some(32));
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                code.s1("7").span(),
+                code.source(),
+                ErrorCode::InvalidBitsWidth,
+                "Array elements must be 8, 16, 32 or 64 bits"
+            )]
+        );
+
+        let code = Code::new("/bits/ 16 <&label 1>");
+        let (_, diagnostics) = code.parse_ok(Parser::property_value);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                code.s1("&label").span(),
+                code.source(),
+                ErrorCode::ReferenceInNon32BitArray,
+                "References are only allowed in arrays with 32-bit elements"
+            )]
+        );
+
+        let code = Code::new("/bits/ 16 \"string\"");
+        let (value, _) = code.parse(Parser::property_value);
+        assert!(value.is_err());
+    }
+
     #[test]
     pub fn reference_properties() {
         let code = Code::new("&my_ref");
@@ -947,10 +1228,10 @@ mod test {
             code.parse_ok_no_diagnostics(Parser::property_value),
             PropertyValue::ByteStrings(
                 code.s1("[").token(),
-                vec![WithToken::new(
+                vec![ByteString::Bytes(WithToken::new(
                     vec![0x00, 0x00, 0x12, 0x34, 0x56, 0x78],
                     code.s1("000012345678").token(),
-                )],
+                ))],
                 code.s1("]").token(),
             )
         );
@@ -960,12 +1241,12 @@ mod test {
             PropertyValue::ByteStrings(
                 code.s1("[").token(),
                 vec![
-                    WithToken::new(vec![0x00], code.s("00", 1).token()),
-                    WithToken::new(vec![0x00], code.s("00", 2).token()),
-                    WithToken::new(vec![0x12], code.s1("12").token()),
-                    WithToken::new(vec![0x34], code.s1("34").token()),
-                    WithToken::new(vec![0x56], code.s1("56").token()),
-                    WithToken::new(vec![0x78], code.s1("78").token()),
+                    ByteString::Bytes(WithToken::new(vec![0x00], code.s("00", 1).token())),
+                    ByteString::Bytes(WithToken::new(vec![0x00], code.s("00", 2).token())),
+                    ByteString::Bytes(WithToken::new(vec![0x12], code.s1("12").token())),
+                    ByteString::Bytes(WithToken::new(vec![0x34], code.s1("34").token())),
+                    ByteString::Bytes(WithToken::new(vec![0x56], code.s1("56").token())),
+                    ByteString::Bytes(WithToken::new(vec![0x78], code.s1("78").token())),
                 ],
                 code.s1("]").token(),
             )
@@ -976,12 +1257,23 @@ mod test {
             PropertyValue::ByteStrings(
                 code.s1("[").token(),
                 vec![
-                    WithToken::new(vec![0xAB], code.s1("AB").token()),
-                    WithToken::new(vec![0xCD], code.s1("CD").token()),
+                    ByteString::Bytes(WithToken::new(vec![0xAB], code.s1("AB").token())),
+                    ByteString::Bytes(WithToken::new(vec![0xCD], code.s1("CD").token())),
                 ],
                 code.s1("]").token(),
             )
         );
+        let code = Code::new("[0g]");
+        let (_, diagnostics) = code.parse_ok(Parser::property_value);
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                code.s1("0g").span(),
+                code.source(),
+                ErrorCode::IntError,
+                "Invalid digit 'g' in hexadecimal literal"
+            )]
+        );
     }
 
     #[test]
@@ -999,7 +1291,8 @@ mod test {
                         items: vec![],
                         end: code.s1(";").token(),
                     },
-                    omit_if_no_ref: None
+                    omit_if_no_ref: None,
+                    doc_comment: None
                 }))],
                 source: code.source(),
             }
@@ -1027,7 +1320,8 @@ mod test {
                             items: vec![],
                             end: code.s(";", 2).token(),
                         },
-                        omit_if_no_ref: None
+                        omit_if_no_ref: None,
+                        doc_comment: None
                     })),
                 ],
                 source: code.source(),
@@ -1070,11 +1364,13 @@ mod test {
                                     items: vec![],
                                     end: code.s(";", 2).token(),
                                 },
-                                omit_if_no_ref: None
+                                omit_if_no_ref: None,
+                                doc_comment: None
                             }))],
                             end: code.s(";", 3).token(),
                         },
-                        omit_if_no_ref: None
+                        omit_if_no_ref: None,
+                        doc_comment: None
                     })),
                 ],
                 source: code.source(),
@@ -1104,11 +1400,13 @@ mod test {
                     Primary::Directive(AnyDirective::DtsHeader(code.s1("/dts-v1/").token())),
                     Primary::Root(Arc::new(Node {
                         omit_if_no_ref: None,
+                        doc_comment: None,
                         label: None,
                         name: WithToken::new(NodeName::simple("/"), code.s("/", 3).token()),
                         payload: NodePayload {
                             items: vec![NodeItem::Node(Arc::new(Node {
                                 omit_if_no_ref: None,
+                                doc_comment: None,
                                 label: None,
                                 name: WithToken::new(
                                     NodeName::with_address("pic", "10000000"),
@@ -1126,6 +1424,7 @@ mod test {
                                                 .s1("<1>")
                                                 .parse_ok_no_diagnostics(Parser::property_values),
                                             end: code.s(";", 2).token(),
+                                            doc_comment: None,
                                         })),
                                         NodeItem::Property(Arc::new(Property {
                                             label: None,
@@ -1135,6 +1434,7 @@ mod test {
                                             ),
                                             values: vec![],
                                             end: code.s(";", 3).token(),
+                                            doc_comment: None,
                                         })),
                                         NodeItem::Property(Arc::new(Property {
                                             label: None,
@@ -1146,6 +1446,7 @@ mod test {
                                                 .s1("<0x10000000 0x100>")
                                                 .parse_ok_no_diagnostics(Parser::property_values),
                                             end: code.s(";", 4).token(),
+                                            doc_comment: None,
                                         })),
                                     ],
                                     end: code.s(";", 5).token(),
@@ -1224,6 +1525,7 @@ mod test {
                             end: code.s(";", 3).token(),
                         },
                         omit_if_no_ref: None,
+                        doc_comment: None,
                     })),
                 ],
                 source: code.source(),
@@ -1231,10 +1533,54 @@ mod test {
         );
     }
 
+    #[test]
+    fn integer_literals_with_suffixes() {
+        let code = Code::new("/memreserve/ 0x100000000ULL 0x1000UL;");
+        let (primary, diagnostics) = code.parse_ok(Parser::primary);
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(
+            primary,
+            Primary::Directive(AnyDirective::Memreserve(Memreserve::new(
+                WithToken::new(0x100000000, code.s1("0x100000000ULL").token()),
+                WithToken::new(0x1000, code.s1("0x1000UL").token()),
+            )))
+        );
+
+        let code = Code::new("<10U 0x10UL (1ULL << 4) 09 10k>");
+        let (value, diagnostics) = code.parse_ok(Parser::property_value);
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    code.s1("09").span(),
+                    code.source(),
+                    ErrorCode::IntError,
+                    "Invalid digit '9' in octal literal"
+                ),
+                Diagnostic::new(
+                    code.s1("10k").span(),
+                    code.source(),
+                    ErrorCode::IntError,
+                    "Invalid suffix 'k' on integer literal"
+                ),
+            ]
+        );
+        let PropertyValue::Cells(_, _, cells, _) = value else {
+            panic!("Expected cells")
+        };
+        assert_eq!(
+            cells[..2],
+            [
+                Cell::Number(WithToken::new(10, code.s1("10U").token())),
+                Cell::Number(WithToken::new(0x10, code.s1("0x10UL").token())),
+            ]
+        );
+        assert_matches!(cells[2], Cell::Expression(_, 16));
+    }
+
     #[test]
     fn labels() {
-        // Should simply parse; we ignore these labels for now
-        let _ = Code::new(
+        let file = Code::new(
             "\
     /dts-v1/;
     /memreserve/ 0x10000000 0x4000;
@@ -1243,39 +1589,114 @@ mod test {
         reg = reglabel: <0 sizelabel: 0x1000000>;
         prop = [ab cd ef byte4: 00 ff fe];
         str = start: \"string value\" end: ;
+        list = \"a\", middle: \"b\" end2:, \"c\";
     };
     ",
         )
         .parse_ok_no_diagnostics(Parser::file);
+        let Primary::Root(root) = &file.elements[2] else {
+            panic!("Expected root node")
+        };
+        let properties = root
+            .payload
+            .items
+            .iter()
+            .map(|item| match item {
+                NodeItem::Property(property) => property,
+                _ => panic!("Expected property"),
+            })
+            .collect_vec();
+        let labels = |property: &Property| {
+            property
+                .value_labels()
+                .map(|label| label.item().clone())
+                .collect_vec()
+        };
+        assert_eq!(labels(properties[0]), vec!["reglabel", "sizelabel"]);
+        assert_eq!(labels(properties[1]), vec!["byte4"]);
+        assert_eq!(labels(properties[2]), vec!["start", "end"]);
+        assert_eq!(labels(properties[3]), vec!["middle", "end2"]);
+
+        assert_eq!(
+            properties[0].to_string(),
+            "reg = reglabel: <0x0 sizelabel: 0x1000000>;\n"
+        );
+        assert_eq!(
+            properties[1].to_string(),
+            "prop = [ab cd ef byte4: 00 ff fe];\n"
+        );
+        assert_eq!(
+            properties[2].to_string(),
+            "str = start: \"string value\" end:;\n"
+        );
+        // A label after a value marks the same position as a label before the next value
+        assert_eq!(
+            properties[3].to_string(),
+            "list = \"a\", middle: \"b\", end2: \"c\";\n"
+        );
     }
 
     #[test]
     fn expressions() {
-        // Should simply parse; we ignore these expressions for now
-        let _ = Code::new(
-            "\
-    /dts-v1/;
-
-    / {
-        some_prop = <(1 + 1) (2 || (3 - 4)) ()>;
-    };
-    ",
-        )
-        .parse_ok_no_diagnostics(Parser::file);
+        let code = Code::new("= <(1 + 1) (2 || (3 - 4)) 5 (-1) ((1 << 4) | 0x3)>");
+        let value = code.parse_ok_no_diagnostics(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        let PropertyValue::Cells(_, _, cells, _) = value else {
+            panic!("Expected cells")
+        };
+        let values = cells
+            .iter()
+            .map(|cell| match cell {
+                Cell::Number(number) => *number.item(),
+                Cell::Expression(_, value) => *value,
+                Cell::Reference(_) | Cell::Label(_) => panic!("Unexpected cell {cell:?}"),
+            })
+            .collect_vec();
+        assert_eq!(values, vec![2, 1, 5, 0xffffffff, 0x13]);
+        assert_eq!(
+            cells[0].to_string(),
+            "(0x1 + 0x1)",
+            "Expressions are displayed with their literals in hexadecimal"
+        );
     }
 
     #[test]
-    fn c_style_includes() {
-        // Should simply parse; we ignore c-style includes for now
-        let _ = Code::new(
-            "\
-    /dts-v1/;
-    #include \"some_header\"
-
-    / {};
-    ",
-        )
-        .parse_ok_no_diagnostics(Parser::file);
+    fn erroneous_expressions() {
+        let code = Code::new("= <(0x100000000 + 1) (4 / (2 - 2)) 3>");
+        let (value, diagnostics) = code.parse_ok(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    code.s1("(0x100000000 + 1)").span(),
+                    code.source(),
+                    ErrorCode::IntError,
+                    "Value 0x1_0000_0001 does not fit in 32-bit cell; truncated to 0x1"
+                ),
+                Diagnostic::new(
+                    code.s1("4 / (2 - 2)").span(),
+                    code.source(),
+                    ErrorCode::DivisionByZero,
+                    "Division by zero"
+                ),
+            ]
+        );
+        let PropertyValue::Cells(_, _, cells, _) = value else {
+            panic!("Expected cells")
+        };
+        assert_eq!(cells.len(), 3);
+
+        let code = Code::new("= <(1 + 2>;");
+        let (_, diagnostics) = code.parse(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        assert_eq!(diagnostics[0].kind, ErrorCode::UnbalancedParentheses);
     }
 
     #[test]
@@ -1346,6 +1767,7 @@ mod test {
                 payload: NodePayload {
                     items: vec![NodeItem::Node(Arc::new(Node {
                         omit_if_no_ref: None,
+                        doc_comment: None,
                         label: None,
                         name: WithToken::new(NodeName::simple("node-2"), code.s1("node-2").token()),
                         payload: NodePayload {
@@ -1362,6 +1784,7 @@ mod test {
                     end: code.s(";", 3).token()
                 },
                 omit_if_no_ref: None,
+                doc_comment: None,
             }))
         );
     }
@@ -1424,10 +1847,12 @@ mod test {
                     Primary::Directive(AnyDirective::DtsHeader(code.s1("/dts-v1/").token())),
                     Primary::Root(Arc::new(Node {
                         omit_if_no_ref: None,
+                        doc_comment: None,
                         name: WithToken::new(NodeName::from("/"), code.s("/", 3).token()),
                         payload: NodePayload {
                             items: vec![NodeItem::Node(Arc::new(Node {
                                 omit_if_no_ref: Some(code.s1("/omit-if-no-ref/").token()),
+                                doc_comment: None,
                                 name: WithToken::new(
                                     NodeName::from("node1"),
                                     code.s("node1", 2).token()
@@ -1474,4 +1899,244 @@ mod test {
             }
         );
     }
+
+    fn incbin_context() -> (tempfile::TempDir, ParserContext) {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        std::fs::write(dir.path().join("data.bin"), b"0123456789").unwrap();
+        let context = ParserContext {
+            include_paths: vec![dir.path().to_path_buf()],
+        };
+        (dir, context)
+    }
+
+    #[test]
+    pub fn incbin_properties() {
+        let (_dir, context) = incbin_context();
+        let code = Code::with_context(r#"/incbin/("data.bin")"#, context.clone());
+        let value = code.parse_ok_no_diagnostics(Parser::property_value);
+        assert_eq!(value.to_string(), r#"/incbin/("data.bin")"#);
+        let PropertyValue::Incbin(incbin) = value else {
+            panic!("Expected /incbin/, got {value:?}")
+        };
+        assert_eq!(incbin.bytes().unwrap(), b"0123456789");
+
+        let code = Code::with_context(r#"= /incbin/("data.bin", 2, (1 + 2))"#, context.clone());
+        let value = code.parse_ok_no_diagnostics(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        assert_eq!(
+            value.to_string(),
+            r#"/incbin/("data.bin", 0x2, (0x1 + 0x2))"#
+        );
+        let PropertyValue::Incbin(incbin) = value else {
+            panic!("Expected /incbin/, got {value:?}")
+        };
+        assert_eq!(incbin.offset(), 2);
+        assert_eq!(incbin.length(), Some(3));
+        assert_eq!(incbin.bytes().unwrap(), b"234");
+
+        // A length of -1 includes all bytes up to the end of the file
+        let code = Code::with_context(r#"= /incbin/("data.bin", 7, (-1))"#, context);
+        let value = code.parse_ok_no_diagnostics(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        let PropertyValue::Incbin(incbin) = value else {
+            panic!("Expected /incbin/, got {value:?}")
+        };
+        assert_eq!(incbin.length(), None);
+        assert_eq!(incbin.bytes().unwrap(), b"789");
+    }
+
+    #[test]
+    pub fn erroneous_incbin_properties() {
+        let (_dir, context) = incbin_context();
+        let code = Code::with_context(r#"= /incbin/("data.bin", 8, 4)"#, context.clone());
+        let (_, diagnostics) = code.parse_ok(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                code.s1(r#"/incbin/("data.bin", 8, 4)"#).span(),
+                code.source(),
+                ErrorCode::InvalidIncbinRange,
+                "Cannot include 0x4 bytes at offset 0x8 from 'data.bin' with a size of 0xa bytes"
+            )]
+        );
+
+        // The length is not trusted when reading the file
+        let PropertyValue::Incbin(incbin) = code
+            .parse_ok(|parser| {
+                parser.skip_tok();
+                parser.property_value()
+            })
+            .0
+        else {
+            panic!("Expected /incbin/")
+        };
+        assert_eq!(
+            incbin.bytes().map_err(|err| err.kind()),
+            Err(io::ErrorKind::UnexpectedEof)
+        );
+
+        let code = Code::with_context(r#"= /incbin/("data.bin", 11, (-1))"#, context.clone());
+        let (_, diagnostics) = code.parse_ok(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                code.s1(r#"/incbin/("data.bin", 11, (-1))"#).span(),
+                code.source(),
+                ErrorCode::InvalidIncbinRange,
+                "Cannot include bytes at offset 0xb from 'data.bin' with a size of 0xa bytes"
+            )]
+        );
+
+        let code = Code::with_context(r#"/incbin/("missing.bin")"#, context.clone());
+        let (_, diagnostics) = code.parse_ok(Parser::property_value);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, ErrorCode::IOError);
+        assert_eq!(diagnostics[0].span, code.s1(r#""missing.bin""#).span());
+
+        let code = Code::with_context(r#"= /incbin/("data.bin", &label, 4)"#, context);
+        let (value, _) = code.parse(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        assert_matches!(
+            value,
+            Err(Diagnostic {
+                kind: ErrorCode::Expected,
+                ..
+            })
+        );
+    }
+
+    #[test]
+    pub fn doc_comments() {
+        let code = Code::new(
+            "\
+/** The root */
+/ {
+    /**
+     * The frequency
+     * in Hz
+     */
+    clock-frequency = <100>;
+    // Not a doc comment
+    status;
+    /** The UART */
+    // Interrupts the doc comment
+    no-doc;
+    /** The serial port */
+    uart: serial@0 {
+        /**/ empty-comment;
+    };
+};",
+        );
+        let file = code.parse_ok_no_diagnostics(Parser::file);
+        let Primary::Root(root) = &file.elements[0] else {
+            panic!("Expected root node, got {:?}", file.elements[0])
+        };
+        assert_eq!(root.doc_comment.as_deref(), Some("The root"));
+        let docs = root
+            .payload
+            .items
+            .iter()
+            .map(|item| match item {
+                NodeItem::Property(property) => property.doc_comment.as_deref(),
+                NodeItem::Node(node) => node.doc_comment.as_deref(),
+                _ => panic!("Unexpected item {item}"),
+            })
+            .collect_vec();
+        assert_eq!(
+            docs,
+            vec![
+                Some("The frequency\nin Hz"),
+                None,
+                None,
+                Some("The serial port")
+            ]
+        );
+        let NodeItem::Node(serial) = &root.payload.items[3] else {
+            unreachable!()
+        };
+        let NodeItem::Property(empty) = &serial.payload.items[0] else {
+            panic!("Expected property")
+        };
+        assert_eq!(empty.doc_comment, None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_file_as_json() {
+        let code = Code::new("/dts-v1/;\n/ {\n\tlabel: node@1 { reg = <1 &label>; };\n};");
+        let file = code.parse_ok_no_diagnostics(Parser::file);
+        let json = serde_json::to_value(&file).unwrap();
+        let root = &json["elements"][1]["Root"];
+        assert_eq!(root["name"]["item"], "/");
+        let node = &root["payload"]["items"][0]["Node"];
+        assert_eq!(node["label"]["item"], "label");
+        assert_eq!(node["name"]["item"], "node@1");
+        assert_eq!(
+            node["name"]["token"]["span"],
+            serde_json::json!({
+                "start": {"line": 2, "character": 8},
+                "end": {"line": 2, "character": 14}
+            })
+        );
+        let cells = &node["payload"]["items"][0]["Property"]["values"][0]["Cells"]["cells"];
+        assert_eq!(cells[0]["Number"]["item"], 1);
+        assert_eq!(cells[1]["Reference"]["item"], "&label");
+        assert_eq!(json["source"], file.source.to_str().unwrap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_values_with_named_fields() {
+        let code = Code::new("= /bits/ 16 <1 (-2)>");
+        let value = code.parse_ok_no_diagnostics(|parser| {
+            parser.skip_tok();
+            parser.property_value()
+        });
+        let span = |start: u32, end: u32| {
+            serde_json::json!({
+                "start": {"line": 0, "character": start},
+                "end": {"line": 0, "character": end}
+            })
+        };
+        let token = |start: u32, end: u32| serde_json::json!({"span": span(start, end), "source": "inline source"});
+        assert_eq!(
+            serde_json::to_value(&value).unwrap(),
+            serde_json::json!({
+                "Cells": {
+                    "bits": {
+                        "directive": token(2, 8),
+                        "width": {"item": 16, "token": token(9, 11)}
+                    },
+                    "start": token(12, 13),
+                    "cells": [
+                        {"Number": {"item": 1, "token": token(13, 14)}},
+                        {"Expression": {
+                            "expression": {"Parenthesized": {
+                                "open": token(15, 16),
+                                "expression": {"Unary": {
+                                    "operator": {"item": "Minus", "token": token(16, 17)},
+                                    "operand": {"Number": {"item": 2, "token": token(17, 18)}}
+                                }},
+                                "close": token(18, 19)
+                            }},
+                            "value": 0xfffe
+                        }}
+                    ],
+                    "end": token(19, 20)
+                }
+            })
+        );
+    }
 }
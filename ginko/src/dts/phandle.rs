@@ -0,0 +1,424 @@
+use crate::dts::ast::Path;
+use crate::dts::data::{HasSource, HasSpan, Location};
+use crate::dts::error_codes::ErrorCode;
+use crate::dts::tree::{Data, DeviceTree, Marker, MarkerKind, TreeNode, TreeProperty, ValueType};
+use crate::dts::Diagnostic;
+use std::collections::{HashMap, HashSet};
+
+/// Determines which properties are added to referenced nodes that have no phandle yet.
+/// Corresponds to the option `-H` of dtc.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum PhandleStyle {
+    /// Only the `phandle` property defined by the Devicetree Specification
+    #[default]
+    Epapr,
+    /// Only the legacy `linux,phandle` property
+    Linux,
+    /// Both `linux,phandle` and `phandle`
+    Both,
+}
+
+fn phandle_property(name: &str, phandle: u32) -> TreeProperty {
+    TreeProperty {
+        name: name.to_string(),
+        labels: vec![],
+        data: Data {
+            bytes: phandle.to_be_bytes().to_vec(),
+            markers: vec![Marker {
+                offset: 0,
+                kind: MarkerKind::Type(ValueType::Cells(32)),
+            }],
+        },
+        location: None,
+    }
+}
+
+fn add_phandle_properties(node: &mut TreeNode, phandle: u32, style: PhandleStyle) {
+    if style != PhandleStyle::Epapr && node.property("linux,phandle").is_none() {
+        node.properties
+            .push(phandle_property("linux,phandle", phandle));
+    }
+    if style != PhandleStyle::Linux && node.property("phandle").is_none() {
+        node.properties.push(phandle_property("phandle", phandle));
+    }
+}
+
+#[derive(Default)]
+struct Phandles {
+    nodes: HashMap<u32, Path>,
+    phandles: HashMap<Path, u32>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Phandles {
+    fn add_diagnostic(&mut self, location: &Option<Location>, code: ErrorCode, message: String) {
+        if let Some(location) = location {
+            self.diagnostics.push(Diagnostic::new(
+                location.span(),
+                location.source(),
+                code,
+                message,
+            ))
+        }
+    }
+
+    // Collects phandles that are given explicitly using the `phandle`
+    // or `linux,phandle` property and reports illegal and duplicate values
+    fn add_explicit(&mut self, tree: &DeviceTree) {
+        tree.walk(&mut |path, node| {
+            let mut explicit: Option<(u32, &Option<Location>)> = None;
+            for name in ["phandle", "linux,phandle"] {
+                let Some(property) = node.property(name) else {
+                    continue;
+                };
+                // `phandle = <&self>` requests a phandle for the node itself
+                if property.data.references().next().is_some() {
+                    let references_other = property
+                        .data
+                        .references()
+                        .filter_map(|reference| tree.resolve(reference))
+                        .any(|target| target != *path);
+                    if references_other {
+                        self.add_diagnostic(
+                            &property.location,
+                            ErrorCode::InvalidPhandle,
+                            format!("'{name}' is a reference to another node"),
+                        );
+                    }
+                    continue;
+                }
+                let Some(phandle) = property.data.as_cell() else {
+                    self.add_diagnostic(
+                        &property.location,
+                        ErrorCode::InvalidPhandle,
+                        format!("'{name}' must be a single cell"),
+                    );
+                    continue;
+                };
+                if phandle == 0 || phandle == u32::MAX {
+                    self.add_diagnostic(
+                        &property.location,
+                        ErrorCode::InvalidPhandle,
+                        format!("Illegal phandle value 0x{phandle:x}"),
+                    );
+                    continue;
+                }
+                match explicit {
+                    Some((existing, _)) if existing != phandle => self.add_diagnostic(
+                        &property.location,
+                        ErrorCode::InvalidPhandle,
+                        "'linux,phandle' does not match 'phandle'".to_string(),
+                    ),
+                    _ => explicit = Some((phandle, &property.location)),
+                }
+            }
+            let Some((phandle, location)) = explicit else {
+                return;
+            };
+            if let Some(other) = self.nodes.get(&phandle) {
+                let message = format!("Duplicate phandle 0x{phandle:x}; also used by {other}");
+                self.add_diagnostic(location, ErrorCode::DuplicatePhandle, message);
+            } else {
+                self.nodes.insert(phandle, path.clone());
+                self.phandles.insert(path.clone(), phandle);
+            }
+        });
+    }
+}
+
+impl DeviceTree {
+    /// Returns the phandle of the node at `path`.
+    /// Nodes without phandle are assigned the lowest free phandle.
+    pub(crate) fn ensure_phandle(&mut self, path: &Path, style: PhandleStyle) -> Option<u32> {
+        if let Some(phandle) = self.node(path)?.phandle() {
+            return Some(phandle);
+        }
+        let mut used = HashSet::new();
+        self.walk(&mut |_, node| {
+            used.extend(node.phandle());
+        });
+        let phandle = (1..).find(|phandle| !used.contains(phandle))?;
+        add_phandle_properties(self.node_mut(path)?, phandle, style);
+        Some(phandle)
+    }
+
+    /// Resolves the references in all property values like dtc does.
+    /// Referenced nodes without explicit phandle are assigned the lowest free phandle
+    /// in the order of their first reference.
+    /// Phandles are substituted into cells and full paths are inserted for path references.
+    /// References that cannot be resolved keep their placeholder;
+    /// unresolved path references are replaced by an empty string.
+    pub(crate) fn resolve_references(&mut self, style: PhandleStyle) -> Vec<Diagnostic> {
+        let mut phandles = Phandles::default();
+        phandles.add_explicit(self);
+
+        let mut next_phandle = 1;
+        let mut assigned = vec![];
+        let mut substitutions = vec![];
+        self.walk(&mut |path, node| {
+            for (idx, property) in node.properties.iter().enumerate() {
+                for marker in &property.data.markers {
+                    let MarkerKind::Phandle(reference) = &marker.kind else {
+                        continue;
+                    };
+                    let Some(target) = self.resolve(reference) else {
+                        continue;
+                    };
+                    let phandle = match phandles.phandles.get(&target) {
+                        Some(phandle) => *phandle,
+                        None => {
+                            while phandles.nodes.contains_key(&next_phandle) {
+                                next_phandle += 1;
+                            }
+                            phandles.nodes.insert(next_phandle, target.clone());
+                            phandles.phandles.insert(target.clone(), next_phandle);
+                            assigned.push((target, next_phandle));
+                            next_phandle
+                        }
+                    };
+                    substitutions.push((path.clone(), idx, marker.offset, phandle));
+                }
+            }
+        });
+
+        for (path, phandle) in assigned {
+            if let Some(node) = self.node_mut(&path) {
+                add_phandle_properties(node, phandle, style);
+            }
+        }
+        for (path, idx, offset, phandle) in substitutions {
+            let Some(node) = self.node_mut(&path) else {
+                continue;
+            };
+            let bytes = &mut node.properties[idx].data.bytes;
+            if let Some(cell) = bytes.get_mut(offset..offset + 4) {
+                cell.copy_from_slice(&phandle.to_be_bytes());
+            }
+        }
+
+        let mut paths = vec![];
+        self.walk(&mut |path, node| {
+            for (idx, property) in node.properties.iter().enumerate() {
+                let resolved: Vec<Option<Path>> = property
+                    .data
+                    .markers
+                    .iter()
+                    .filter_map(|marker| match &marker.kind {
+                        MarkerKind::Path(reference) => Some(self.resolve(reference)),
+                        _ => None,
+                    })
+                    .collect();
+                if !resolved.is_empty() {
+                    paths.push((path.clone(), idx, resolved));
+                }
+            }
+        });
+        for (path, idx, resolved) in paths {
+            if let Some(node) = self.node_mut(&path) {
+                node.properties[idx].data.insert_paths(resolved);
+            }
+        }
+        phandles.diagnostics
+    }
+}
+
+impl Data {
+    // Inserts the NUL-terminated paths at the path markers of this data
+    // and moves all subsequent markers accordingly
+    fn insert_paths(&mut self, paths: Vec<Option<Path>>) {
+        let mut paths = paths.into_iter();
+        let mut shift = 0;
+        for marker in &mut self.markers {
+            marker.offset += shift;
+            if let MarkerKind::Path(_) = marker.kind {
+                let mut path = paths
+                    .next()
+                    .flatten()
+                    .map(|path| path.to_string().into_bytes())
+                    .unwrap_or_default();
+                path.push(0);
+                shift += path.len();
+                self.bytes.splice(marker.offset..marker.offset, path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::ast::Path;
+    use crate::dts::data::{HasSource, HasSpan};
+    use crate::dts::error_codes::ErrorCode;
+    use crate::dts::phandle::PhandleStyle;
+    use crate::dts::test::Code;
+    use crate::dts::{Diagnostic, Project};
+    use assert_unordered::assert_eq_unordered;
+
+    fn cells(cells: &[u32]) -> Vec<u8> {
+        cells.iter().flat_map(|cell| cell.to_be_bytes()).collect()
+    }
+
+    #[test]
+    pub fn assigns_phandles() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    first: first {};
+    explicit: explicit {
+        phandle = <1>;
+    };
+    itself: itself {
+        phandle = <&itself>;
+    };
+    user {
+        refs = <&explicit &first &{/first} &itself>;
+        paths = &first, \"str\", &{/explicit};
+    };
+};",
+        );
+        let (diagnostics, context) = code.get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        let tree = context.tree();
+        let phandle = |path: &str| {
+            let node = tree.node(&Path::from(path)).unwrap();
+            assert!(node.property("linux,phandle").is_none());
+            node.property("phandle").unwrap().data.as_cell()
+        };
+        // The lowest free phandle is assigned in the order of references.
+        // `itself` references itself before `user` references `first`.
+        assert_eq!(phandle("/explicit"), Some(1));
+        assert_eq!(phandle("/itself"), Some(2));
+        assert_eq!(phandle("/first"), Some(3));
+
+        let user = tree.node(&Path::from("/user")).unwrap();
+        assert_eq!(
+            user.property("refs").unwrap().data.bytes,
+            cells(&[1, 3, 3, 2])
+        );
+        let paths = &user.property("paths").unwrap().data;
+        assert_eq!(paths.bytes, b"/first\0str\0/explicit\0");
+        assert_eq!(
+            paths
+                .markers
+                .iter()
+                .map(|marker| marker.offset)
+                .collect::<Vec<_>>(),
+            vec![0, 0, 7, 11, 11]
+        );
+    }
+
+    #[test]
+    pub fn phandle_styles() {
+        for (style, names) in [
+            (PhandleStyle::Epapr, vec!["phandle"]),
+            (PhandleStyle::Linux, vec!["linux,phandle"]),
+            (PhandleStyle::Both, vec!["linux,phandle", "phandle"]),
+        ] {
+            let mut project = Project::default();
+            project.phandle_style = style;
+            let (diagnostics, context) = Code::new(
+                "\
+/dts-v1/;
+
+/ {
+    ref = <&node>;
+    node: node {
+        prop;
+    };
+};",
+            )
+            .get_analyzed_file_in(&project);
+            assert_eq!(diagnostics, vec![]);
+            let node = context.tree().node(&Path::from("/node")).unwrap();
+            let mut expected = vec!["prop"];
+            expected.extend(names);
+            assert_eq!(
+                node.properties
+                    .iter()
+                    .map(|property| property.name.as_str())
+                    .collect::<Vec<_>>(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    pub fn erroneous_phandles() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    zero {
+        phandle = <0>;
+    };
+    max {
+        phandle = <0xffffffff>;
+    };
+    wide {
+        phandle = <1 2>;
+    };
+    mismatch {
+        phandle = <1>;
+        linux,phandle = <2>;
+    };
+    duplicate {
+        phandle = <1>;
+    };
+    other {
+        phandle = <&target>;
+    };
+    target: target {};
+    user {
+        ref = <&target>;
+    };
+};",
+        );
+        let (diagnostics, _) = code.get_analyzed_file();
+        assert_eq_unordered!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    code.s1("phandle = <0>;").span(),
+                    code.source(),
+                    ErrorCode::InvalidPhandle,
+                    "Illegal phandle value 0x0"
+                ),
+                Diagnostic::new(
+                    code.s1("phandle = <0xffffffff>;").span(),
+                    code.source(),
+                    ErrorCode::InvalidPhandle,
+                    "Illegal phandle value 0xffffffff"
+                ),
+                Diagnostic::new(
+                    code.s1("phandle = <1 2>;").span(),
+                    code.source(),
+                    ErrorCode::InvalidPhandle,
+                    "'phandle' must be a single cell"
+                ),
+                Diagnostic::new(
+                    code.s1("linux,phandle = <2>;").span(),
+                    code.source(),
+                    ErrorCode::InvalidPhandle,
+                    "'linux,phandle' does not match 'phandle'"
+                ),
+                Diagnostic::new(
+                    code.s1("duplicate {\n        phandle = <1>;")
+                        .s1("phandle = <1>;")
+                        .span(),
+                    code.source(),
+                    ErrorCode::DuplicatePhandle,
+                    "Duplicate phandle 0x1; also used by /mismatch"
+                ),
+                Diagnostic::new(
+                    code.s1("phandle = <&target>;").span(),
+                    code.source(),
+                    ErrorCode::InvalidPhandle,
+                    "'phandle' is a reference to another node"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::dts::literal;
+use crate::dts::preprocessor::scanner::{PpKind, PpToken};
+
+type Result<T> = std::result::Result<T, String>;
+
+/// Evaluates the controlling expression of an `#if` or `#elif` directive.
+/// All macros must already be expanded and `defined` operators must be replaced.
+/// Remaining identifiers evaluate to zero.
+pub(super) fn evaluate(tokens: &[PpToken]) -> Result<i64> {
+    let tokens: Vec<&PpToken> = tokens.iter().filter(|tok| !tok.is_whitespace()).collect();
+    if tokens.is_empty() {
+        return Err("#if with no expression".into());
+    }
+    let mut parser = ConditionParser { tokens, pos: 0 };
+    let value = parser.conditional(true)?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(value),
+        Some(tok) => Err(format!("Unexpected '{}' in expression", tok.text)),
+    }
+}
+
+struct ConditionParser<'a> {
+    tokens: Vec<&'a PpToken>,
+    pos: usize,
+}
+
+// Binary operators, ordered from lowest to highest precedence
+const BINARY_OPERATORS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", ">", "<=", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl ConditionParser<'_> {
+    fn peek_punct(&self) -> Option<&str> {
+        self.tokens
+            .get(self.pos)
+            .filter(|tok| tok.kind == PpKind::Punct)
+            .map(|tok| tok.text.as_str())
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<()> {
+        if self.peek_punct() == Some(punct) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("Expected '{punct}' in expression"))
+        }
+    }
+
+    // `eval` is false in branches that are not evaluated because of short-circuiting.
+    // These branches must still be parsed, but e.g. division by zero is not an error.
+    fn conditional(&mut self, eval: bool) -> Result<i64> {
+        let condition = self.binary(0, eval)?;
+        if self.peek_punct() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let if_true = self.conditional(eval && condition != 0)?;
+        self.expect_punct(":")?;
+        let if_false = self.conditional(eval && condition == 0)?;
+        Ok(if condition != 0 { if_true } else { if_false })
+    }
+
+    fn binary(&mut self, level: usize, eval: bool) -> Result<i64> {
+        if level == BINARY_OPERATORS.len() {
+            return self.unary(eval);
+        }
+        let mut lhs = self.binary(level + 1, eval)?;
+        while let Some(op) = self
+            .peek_punct()
+            .filter(|punct| BINARY_OPERATORS[level].contains(punct))
+        {
+            let op = op.to_owned();
+            self.pos += 1;
+            let eval_rhs = match op.as_str() {
+                "||" => eval && lhs == 0,
+                "&&" => eval && lhs != 0,
+                _ => eval,
+            };
+            let rhs = self.binary(level + 1, eval_rhs)?;
+            lhs = apply_binary(&op, lhs, rhs, eval_rhs)?;
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self, eval: bool) -> Result<i64> {
+        match self.peek_punct() {
+            Some("+") => {
+                self.pos += 1;
+                self.unary(eval)
+            }
+            Some("-") => {
+                self.pos += 1;
+                Ok(self.unary(eval)?.wrapping_neg())
+            }
+            Some("~") => {
+                self.pos += 1;
+                Ok(!self.unary(eval)?)
+            }
+            Some("!") => {
+                self.pos += 1;
+                Ok((self.unary(eval)? == 0) as i64)
+            }
+            Some("(") => {
+                self.pos += 1;
+                let value = self.conditional(eval)?;
+                self.expect_punct(")")?;
+                Ok(value)
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64> {
+        let Some(tok) = self.tokens.get(self.pos) else {
+            return Err("Unexpected end of expression".into());
+        };
+        self.pos += 1;
+        match tok.kind {
+            PpKind::Number => parse_integer(&tok.text),
+            PpKind::Char => parse_char(&tok.text),
+            PpKind::Ident => Ok(0),
+            _ => Err(format!("Unexpected '{}' in expression", tok.text)),
+        }
+    }
+}
+
+fn apply_binary(op: &str, lhs: i64, rhs: i64, eval: bool) -> Result<i64> {
+    Ok(match op {
+        "||" => (lhs != 0 || rhs != 0) as i64,
+        "&&" => (lhs != 0 && rhs != 0) as i64,
+        "|" => lhs | rhs,
+        "^" => lhs ^ rhs,
+        "&" => lhs & rhs,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "<" => (lhs < rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => {
+            if eval {
+                return Err("Division by zero in expression".into());
+            }
+            0
+        }
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        _ => unreachable!("Unknown binary operator {op}"),
+    })
+}
+
+fn parse_integer(text: &str) -> Result<i64> {
+    literal::parse_int(text)
+        .map(|value| value as i64)
+        .map_err(|err| format!("Invalid integer '{text}' in expression: {err}"))
+}
+
+fn parse_char(text: &str) -> Result<i64> {
+    let inner = text
+        .strip_prefix('\'')
+        .and_then(|text| text.strip_suffix('\''))
+        .ok_or_else(|| format!("Invalid character constant {text}"))?;
+    let bytes = inner.as_bytes();
+    match bytes {
+        [ch] => Ok(*ch as i64),
+        [b'\\', b'x', hex @ ..] => i64::from_str_radix(std::str::from_utf8(hex).unwrap(), 16)
+            .map_err(|_| format!("Invalid character constant {text}")),
+        [b'\\', octal @ ..] if octal.iter().all(|ch| (b'0'..=b'7').contains(ch)) => {
+            i64::from_str_radix(std::str::from_utf8(octal).unwrap(), 8)
+                .map_err(|_| format!("Invalid character constant {text}"))
+        }
+        [b'\\', escape] => Ok(match escape {
+            b'n' => b'\n',
+            b't' => b'\t',
+            b'r' => b'\r',
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'v' => 0x0b,
+            other => *other,
+        } as i64),
+        _ => Err(format!("Invalid character constant {text}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::data::Position;
+    use crate::dts::preprocessor::condition::evaluate;
+    use crate::dts::preprocessor::scanner::scan;
+
+    fn eval(text: &str) -> Result<i64, String> {
+        evaluate(&scan(text.as_bytes(), Position::zero()))
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("0x10 >> 2 | 1"), Ok(5));
+        assert_eq!(eval("-1 < 0"), Ok(1));
+        assert_eq!(eval("10UL % 4"), Ok(2));
+        assert_eq!(eval("017"), Ok(15));
+        assert_eq!(eval("'A' == 65"), Ok(1));
+        assert_eq!(eval("!0 && ~0"), Ok(1));
+    }
+
+    #[test]
+    fn conditional_and_short_circuit() {
+        assert_eq!(eval("1 ? 2 : 3"), Ok(2));
+        assert_eq!(eval("0 ? 2 : 0 ? 3 : 4"), Ok(4));
+        assert_eq!(eval("0 && (1 / 0)"), Ok(0));
+        assert_eq!(eval("1 || (1 / 0)"), Ok(1));
+        assert!(eval("1 / 0").is_err());
+    }
+
+    #[test]
+    fn undefined_identifiers_are_zero() {
+        assert_eq!(eval("SOME_UNDEFINED_MACRO"), Ok(0));
+        assert_eq!(eval("SOME_UNDEFINED_MACRO + 1"), Ok(1));
+    }
+
+    #[test]
+    fn malformed_expressions() {
+        assert!(eval("").is_err());
+        assert!(eval("(1 + 2").is_err());
+        assert!(eval("1 +").is_err());
+        assert!(eval("1 2").is_err());
+    }
+}
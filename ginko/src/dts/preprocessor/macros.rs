@@ -0,0 +1,438 @@
+use crate::dts::data::{Location, Position, Span};
+use crate::dts::error_codes::ErrorCode;
+use crate::dts::preprocessor::scanner::{scan, trimmed, HideSet, PpKind, PpOrigin, PpToken};
+use crate::dts::source_map::{MacroExpansion, SourceMap};
+use crate::dts::Diagnostic;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A macro defined using `#define` or on the command line.
+#[derive(Debug)]
+pub(super) struct Macro {
+    pub name: String,
+    /// The parameters of a function-like macro or `None` for object-like macros.
+    /// Does not contain `__VA_ARGS__` for variadic macros.
+    pub params: Option<Vec<String>>,
+    pub variadic: bool,
+    /// The replacement list without leading and trailing whitespace
+    pub body: Vec<PpToken>,
+    /// The location of the macro name in the definition
+    /// or `None` for macros that are defined programmatically
+    pub definition: Option<Location>,
+}
+
+impl Macro {
+    /// Returns whether two macros have the same definition.
+    /// Redefining a macro is only allowed if the definitions are the same.
+    pub fn same_definition(&self, other: &Macro) -> bool {
+        fn normalized(body: &[PpToken]) -> Vec<&str> {
+            body.iter()
+                .map(|tok| {
+                    if tok.is_whitespace() {
+                        " "
+                    } else {
+                        tok.text.as_str()
+                    }
+                })
+                .collect()
+        }
+        self.params == other.params
+            && self.variadic == other.variadic
+            && normalized(&self.body) == normalized(&other.body)
+    }
+
+    fn param_index(&self, token: &PpToken) -> Option<usize> {
+        let params = self.params.as_ref()?;
+        if token.kind != PpKind::Ident {
+            return None;
+        }
+        if self.variadic && token.text == "__VA_ARGS__" {
+            return Some(params.len());
+        }
+        params.iter().position(|param| param == &token.text)
+    }
+}
+
+enum Item {
+    Token(PpToken),
+    /// The `##` operator
+    Paste,
+    /// An empty argument that is an operand of `##`
+    Placemarker,
+}
+
+/// Expands macros in a list of tokens.
+pub(super) struct Expander<'a> {
+    pub macros: &'a HashMap<String, Arc<Macro>>,
+    pub map: &'a mut SourceMap,
+    pub source: &'a Arc<Path>,
+    pub diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+fn next_significant(tokens: &[PpToken], start: usize) -> Option<usize> {
+    (start..tokens.len()).find(|idx| !tokens[*idx].is_whitespace())
+}
+
+fn is_blank(tokens: &[PpToken]) -> bool {
+    tokens.iter().all(PpToken::is_whitespace)
+}
+
+impl Expander<'_> {
+    /// Fully expands all macros in `tokens`.
+    pub fn expand(&mut self, tokens: Vec<PpToken>) -> Vec<PpToken> {
+        let mut input: VecDeque<PpToken> = tokens.into();
+        let mut output = Vec::new();
+        while let Some(token) = input.pop_front() {
+            let Some(mac) = self.lookup(&token) else {
+                output.push(token);
+                continue;
+            };
+            let replacement = if mac.params.is_none() {
+                let expansion = self.expansion_id(&token, &mac, None);
+                self.substitute(&mac, vec![], &token.hide, expansion)
+            } else {
+                let remaining = input.make_contiguous();
+                match next_significant(remaining, 0) {
+                    Some(idx) if remaining[idx].is_punct("(") => {}
+                    // A function-like macro that is not invoked
+                    _ => {
+                        output.push(token);
+                        continue;
+                    }
+                }
+                let Some((args, consumed)) = collect_arguments(remaining, &mac) else {
+                    self.error(
+                        &token,
+                        format!("Unterminated argument list invoking macro `{}`", mac.name),
+                    );
+                    output.push(token);
+                    continue;
+                };
+                let Some(args) = self.check_arguments(&token, &mac, args) else {
+                    output.push(token);
+                    continue;
+                };
+                let close = input[consumed - 1].clone();
+                input.drain(..consumed);
+                let expansion = self.expansion_id(&token, &mac, Some(&close));
+                self.substitute(&mac, args, &token.hide, expansion)
+            };
+            for token in replacement.into_iter().rev() {
+                input.push_front(token);
+            }
+        }
+        output
+    }
+
+    fn lookup(&self, token: &PpToken) -> Option<Arc<Macro>> {
+        if token.kind != PpKind::Ident || token.hide.contains(&token.text) {
+            return None;
+        }
+        self.macros.get(&token.text).cloned()
+    }
+
+    fn error(&mut self, token: &PpToken, message: String) {
+        let span = match token.origin {
+            PpOrigin::Source(span) => span,
+            PpOrigin::Expansion(expansion) => self.map.expansion_span(expansion),
+        };
+        self.diagnostics.push(Diagnostic::new(
+            span,
+            self.source.clone(),
+            ErrorCode::MacroArguments,
+            message,
+        ))
+    }
+
+    /// Checks the number of arguments given to a function-like macro.
+    /// For variadic macros, a missing variable argument is treated as empty.
+    fn check_arguments(
+        &mut self,
+        name: &PpToken,
+        mac: &Macro,
+        mut args: Vec<Vec<PpToken>>,
+    ) -> Option<Vec<Vec<PpToken>>> {
+        let params = mac.params.as_ref().unwrap();
+        // `FOO()` is an invocation with zero arguments when `FOO` has no parameters
+        if params.is_empty() && args.len() == 1 && is_blank(&args[0]) && !mac.variadic {
+            args.clear();
+        }
+        if mac.variadic && args.len() == params.len() {
+            args.push(vec![]);
+        }
+        let expected = params.len() + mac.variadic as usize;
+        if args.len() == expected {
+            return Some(args);
+        }
+        let message = if args.len() < expected {
+            format!(
+                "Macro `{}` requires {} arguments, but only {} given",
+                mac.name,
+                expected,
+                args.len()
+            )
+        } else {
+            format!(
+                "Macro `{}` passed {} arguments, but takes just {}",
+                mac.name,
+                args.len(),
+                expected
+            )
+        };
+        self.error(name, message);
+        None
+    }
+
+    /// Returns the expansion that the tokens replacing `name` belong to.
+    /// `close` is the closing parenthesis of a function-like macro invocation.
+    /// Nested expansions are attributed to the outermost invocation in the source.
+    fn expansion_id(&mut self, name: &PpToken, mac: &Macro, close: Option<&PpToken>) -> usize {
+        let end = match close.map(|tok| &tok.origin) {
+            Some(PpOrigin::Source(span)) => Some(span.end()),
+            _ => None,
+        };
+        match name.origin {
+            PpOrigin::Source(span) => self.map.add_expansion(MacroExpansion {
+                name: mac.name.clone(),
+                invocation: Location::new(
+                    self.source.clone(),
+                    Span::new(span.start(), end.unwrap_or(span.end())),
+                ),
+                definition: mac.definition.clone(),
+                expanded: String::new(),
+            }),
+            PpOrigin::Expansion(expansion) => {
+                if let Some(end) = end {
+                    self.map.extend_expansion(expansion, end);
+                }
+                expansion
+            }
+        }
+    }
+
+    fn substitute(
+        &mut self,
+        mac: &Macro,
+        args: Vec<Vec<PpToken>>,
+        hide: &HideSet,
+        expansion: usize,
+    ) -> Vec<PpToken> {
+        // Arguments are part of the expansion, too
+        let args = args
+            .into_iter()
+            .map(|arg| {
+                arg.into_iter()
+                    .map(|tok| {
+                        if tok.is_whitespace() {
+                            whitespace(expansion)
+                        } else {
+                            PpToken {
+                                origin: PpOrigin::Expansion(expansion),
+                                ..tok
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let body = &mac.body;
+        let mut items = Vec::new();
+        let mut i = 0;
+        while i < body.len() {
+            let token = &body[i];
+            if mac.params.is_some() && token.is_punct("#") {
+                if let Some(param) = next_significant(body, i + 1)
+                    .and_then(|j| Some((j, mac.param_index(&body[j])?)))
+                {
+                    items.push(Item::Token(stringify(&args[param.1], expansion)));
+                    i = param.0 + 1;
+                    continue;
+                }
+            }
+            if token.is_punct("##") {
+                // GNU extension: `, ## __VA_ARGS__` removes the comma if the variable argument is empty
+                let next = next_significant(body, i + 1);
+                let after_comma = matches!(
+                    items.iter().rev().find(|item| !matches!(item, Item::Token(tok) if tok.is_whitespace())),
+                    Some(Item::Token(tok)) if tok.is_punct(",")
+                );
+                if mac.variadic
+                    && after_comma
+                    && next.is_some_and(|j| body[j].text == "__VA_ARGS__")
+                {
+                    if is_blank(args.last().unwrap()) {
+                        while matches!(items.last(), Some(Item::Token(tok)) if !tok.is_punct(",")) {
+                            items.pop();
+                        }
+                        items.pop();
+                        i = next.unwrap() + 1;
+                    } else {
+                        i = next.unwrap();
+                    }
+                    continue;
+                }
+                items.push(Item::Paste);
+                i += 1;
+                continue;
+            }
+            if let Some(param) = mac.param_index(token) {
+                let arg = &args[param];
+                let previous = body[..i].iter().rposition(|tok| !tok.is_whitespace());
+                let next = next_significant(body, i + 1);
+                let pasted = previous.is_some_and(|j| body[j].is_punct("##"))
+                    || next.is_some_and(|j| body[j].is_punct("##"));
+                if pasted {
+                    if is_blank(arg) {
+                        items.push(Item::Placemarker);
+                    } else {
+                        items.extend(trimmed(arg).iter().cloned().map(Item::Token));
+                    }
+                } else {
+                    let expanded = self.expand(arg.clone());
+                    items.extend(expanded.into_iter().map(Item::Token));
+                }
+                i += 1;
+                continue;
+            }
+            items.push(Item::Token(token.clone()));
+            i += 1;
+        }
+
+        let mut hide = (**hide).clone();
+        hide.insert(mac.name.clone());
+        let hide = Arc::new(hide);
+        self.paste(items, expansion)
+            .into_iter()
+            .map(|tok| {
+                let hide = if tok.hide.is_empty() {
+                    hide.clone()
+                } else {
+                    Arc::new(tok.hide.union(&hide).cloned().collect())
+                };
+                PpToken {
+                    origin: PpOrigin::Expansion(expansion),
+                    hide,
+                    ..tok
+                }
+            })
+            .collect()
+    }
+
+    /// Resolves all `##` operators
+    fn paste(&mut self, items: Vec<Item>, expansion: usize) -> Vec<PpToken> {
+        let mut result: Vec<Item> = Vec::new();
+        let mut items = items.into_iter().peekable();
+        while let Some(item) = items.next() {
+            if !matches!(item, Item::Paste) {
+                result.push(item);
+                continue;
+            }
+            while matches!(result.last(), Some(Item::Token(tok)) if tok.is_whitespace()) {
+                result.pop();
+            }
+            while matches!(items.peek(), Some(Item::Token(tok)) if tok.is_whitespace()) {
+                items.next();
+            }
+            let lhs = result.pop();
+            let rhs = items.next();
+            match (lhs, rhs) {
+                (Some(Item::Token(lhs)), Some(Item::Token(rhs))) => {
+                    let text = format!("{}{}", lhs.text, rhs.text);
+                    result.extend(
+                        scan(text.as_bytes(), Position::zero())
+                            .into_iter()
+                            .map(|tok| PpToken {
+                                origin: PpOrigin::Expansion(expansion),
+                                ..tok
+                            })
+                            .map(Item::Token),
+                    );
+                }
+                (Some(Item::Token(tok)), _) | (_, Some(Item::Token(tok))) => {
+                    result.push(Item::Token(tok))
+                }
+                // Pasting two placemarkers results in a placemarker
+                _ => {}
+            }
+        }
+        result
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Token(tok) => Some(tok),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn whitespace(expansion: usize) -> PpToken {
+    PpToken {
+        kind: PpKind::Whitespace,
+        text: " ".to_string(),
+        origin: PpOrigin::Expansion(expansion),
+        hide: HideSet::default(),
+    }
+}
+
+/// Implements the `#` operator
+fn stringify(arg: &[PpToken], expansion: usize) -> PpToken {
+    let mut text = String::from('"');
+    let mut previous_whitespace = false;
+    for tok in trimmed(arg) {
+        if tok.is_whitespace() {
+            if !previous_whitespace {
+                text.push(' ');
+            }
+            previous_whitespace = true;
+            continue;
+        }
+        previous_whitespace = false;
+        if matches!(tok.kind, PpKind::String | PpKind::Char) {
+            for ch in tok.text.chars() {
+                if ch == '"' || ch == '\\' {
+                    text.push('\\');
+                }
+                text.push(ch);
+            }
+        } else {
+            text.push_str(&tok.text);
+        }
+    }
+    text.push('"');
+    PpToken {
+        kind: PpKind::String,
+        text,
+        origin: PpOrigin::Expansion(expansion),
+        hide: HideSet::default(),
+    }
+}
+
+/// Collects the arguments of a function-like macro invocation.
+/// `tokens` must start with the opening parenthesis, optionally preceded by whitespace.
+/// Returns the arguments and the number of consumed tokens
+/// or `None`, if the closing parenthesis is missing.
+fn collect_arguments(tokens: &[PpToken], mac: &Macro) -> Option<(Vec<Vec<PpToken>>, usize)> {
+    let open = next_significant(tokens, 0)?;
+    // The variable argument of a variadic macro contains all remaining commas
+    let max_args = mac.params.as_ref().unwrap().len() + 1;
+    let mut args = vec![vec![]];
+    let mut depth = 0;
+    for (idx, token) in tokens.iter().enumerate().skip(open + 1) {
+        if token.is_punct("(") {
+            depth += 1;
+        } else if token.is_punct(")") {
+            if depth == 0 {
+                args = args.into_iter().map(|arg| trimmed(&arg).to_vec()).collect();
+                return Some((args, idx + 1));
+            }
+            depth -= 1;
+        } else if token.is_punct(",") && depth == 0 && !(mac.variadic && args.len() == max_args) {
+            args.push(vec![]);
+            continue;
+        }
+        args.last_mut().unwrap().push(token.clone());
+    }
+    None
+}
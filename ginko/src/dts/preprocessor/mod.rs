@@ -0,0 +1,1052 @@
+//! A C preprocessor for device-tree sources.
+//!
+//! Device-tree sources are commonly passed through the C preprocessor before being compiled
+//! so that they can include headers (e.g., from `include/dt-bindings`) and use macros.
+//! The preprocessor supports `#include`, object-like and function-like macros,
+//! `#undef` and conditionals. Lines starting with `#` that are not directives
+//! (such as the property `#address-cells`) are passed through unchanged.
+//!
+//! The preprocessor records where each piece of the output comes from in a [SourceMap]
+//! so that tokens can be attributed to the original files.
+
+mod condition;
+mod macros;
+mod scanner;
+
+use crate::dts::data::{Location, Position, Span};
+use crate::dts::error_codes::ErrorCode;
+use crate::dts::preprocessor::macros::{Expander, Macro};
+use crate::dts::preprocessor::scanner::{
+    advance, ends_in_comment, scan, trimmed, HideSet, PpKind, PpOrigin, PpToken,
+};
+use crate::dts::source_map::SourceMap;
+use crate::dts::Diagnostic;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const DIRECTIVES: [&str; 15] = [
+    "define",
+    "undef",
+    "include",
+    "include_next",
+    "if",
+    "ifdef",
+    "ifndef",
+    "elif",
+    "else",
+    "endif",
+    "error",
+    "warning",
+    "pragma",
+    "line",
+    "ident",
+];
+
+/// The maximum depth of nested includes
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+/// The result of preprocessing a file.
+pub struct Preprocessed {
+    /// The preprocessed text
+    pub text: String,
+    /// Maps positions in `text` back to the original files
+    pub source_map: SourceMap,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The files that were included using `#include`, directly or from other included files
+    pub includes: Vec<PathBuf>,
+}
+
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Arc<Macro>>,
+    output: Output,
+    diagnostics: Vec<Diagnostic>,
+    depth: usize,
+    /// The index of the include path in which the current file was found.
+    /// `#include_next` continues the search after this path.
+    include_path: Option<usize>,
+    /// The contents of files that are used instead of reading the files, e.g., unsaved edits
+    texts: HashMap<PathBuf, String>,
+    includes: Vec<PathBuf>,
+}
+
+struct Output {
+    text: Vec<u8>,
+    pos: Position,
+    map: SourceMap,
+    /// The expansion of the last segment, if the last segment is an expansion
+    expansion: Option<usize>,
+}
+
+impl Output {
+    fn verbatim(&mut self, text: &[u8], source: &Arc<Path>, origin: Position) {
+        if text.is_empty() {
+            return;
+        }
+        if self.expansion.is_some() || !self.map.continues(self.pos, source, origin) {
+            self.map.push_verbatim(self.pos, source.clone(), origin);
+        }
+        self.expansion = None;
+        self.append(text);
+    }
+
+    fn expansion(&mut self, text: &[u8], expansion: usize) {
+        if text.is_empty() {
+            return;
+        }
+        if self.expansion != Some(expansion) {
+            self.map.push_expansion(self.pos, expansion);
+        }
+        self.expansion = Some(expansion);
+        self.map
+            .push_expanded(expansion, &String::from_utf8_lossy(text));
+        self.append(text);
+    }
+
+    fn token(&mut self, token: &PpToken, source: &Arc<Path>) {
+        match token.origin {
+            PpOrigin::Source(span) => self.verbatim(token.text.as_bytes(), source, span.start()),
+            PpOrigin::Expansion(expansion) => self.expansion(token.text.as_bytes(), expansion),
+        }
+    }
+
+    fn append(&mut self, text: &[u8]) {
+        self.text.extend(text);
+        self.pos = advance(self.pos, text);
+    }
+}
+
+#[derive(Debug)]
+struct Conditional {
+    /// Whether the enclosing block is active
+    parent_active: bool,
+    /// Whether the current branch is active
+    active: bool,
+    /// Whether any of the branches was taken
+    taken: bool,
+    seen_else: bool,
+    span: Span,
+}
+
+/// Returns the name of the directive in a line, or `None` for lines that are not directives.
+fn directive_name(line: &[u8]) -> Option<&str> {
+    let rest = line
+        .trim_ascii_start()
+        .strip_prefix(b"#")?
+        .trim_ascii_start();
+    let len = rest
+        .iter()
+        .position(|ch| !(ch.is_ascii_alphanumeric() || *ch == b'_'))
+        .unwrap_or(rest.len());
+    let name = std::str::from_utf8(&rest[..len]).ok()?;
+    if name.is_empty() {
+        // The null directive
+        return rest.trim_ascii().is_empty().then_some(name);
+    }
+    DIRECTIVES.contains(&name).then_some(name)
+}
+
+/// Returns the indices of all lines that belong to directives or line markers,
+/// including lines that continue a directive.
+pub(crate) fn directive_lines(text: &[u8]) -> Vec<usize> {
+    let lines = text.split_inclusive(|ch| *ch == b'\n').collect::<Vec<_>>();
+    let is_line_marker = |line: &[u8]| {
+        line.trim_ascii_start()
+            .strip_prefix(b"#")
+            .is_some_and(|rest| {
+                rest.trim_ascii_start()
+                    .first()
+                    .is_some_and(u8::is_ascii_digit)
+            })
+    };
+    let mut result = vec![];
+    let mut in_comment = false;
+    let mut i = 0;
+    while i < lines.len() {
+        if in_comment || (directive_name(lines[i]).is_none() && !is_line_marker(lines[i])) {
+            in_comment = ends_in_comment(lines[i], in_comment);
+            i += 1;
+            continue;
+        }
+        let mut directive = lines[i].to_vec();
+        result.push(i);
+        i += 1;
+        while i < lines.len()
+            && (directive.trim_ascii_end().ends_with(b"\\") || ends_in_comment(&directive, false))
+        {
+            directive.extend(lines[i]);
+            result.push(i);
+            i += 1;
+        }
+    }
+    result
+}
+
+/// Returns the length of a line without the line terminator.
+fn line_len(line: &[u8]) -> u32 {
+    line.strip_suffix(b"\n").unwrap_or(line).len() as u32
+}
+
+/// Tokenizes a directive. Comments and line continuations become whitespace.
+fn directive_tokens(text: &[u8], start: Position) -> Vec<PpToken> {
+    let mut tokens = scan(text, start);
+    for i in 0..tokens.len() {
+        let continuation = tokens[i].is_punct("\\")
+            && tokens[i + 1..]
+                .iter()
+                .find(|tok| tok.kind != PpKind::Whitespace)
+                .is_none_or(|tok| tok.kind == PpKind::Newline);
+        if continuation || matches!(tokens[i].kind, PpKind::Comment | PpKind::Newline) {
+            tokens[i].kind = PpKind::Whitespace;
+            tokens[i].text = " ".to_string();
+        }
+    }
+    tokens
+}
+
+fn significant(tokens: &[PpToken]) -> impl Iterator<Item = &PpToken> {
+    tokens.iter().filter(|tok| !tok.is_whitespace())
+}
+
+fn number(value: bool, origin: PpOrigin) -> PpToken {
+    PpToken {
+        kind: PpKind::Number,
+        text: if value { "1" } else { "0" }.to_string(),
+        origin,
+        hide: HideSet::default(),
+    }
+}
+
+impl Preprocessor {
+    /// Creates a new preprocessor.
+    /// `include_paths` are searched for files included using `#include`.
+    pub fn new(include_paths: Vec<PathBuf>) -> Preprocessor {
+        Preprocessor {
+            include_paths,
+            macros: HashMap::new(),
+            output: Output {
+                text: Vec::new(),
+                pos: Position::zero(),
+                map: SourceMap::default(),
+                expansion: None,
+            },
+            diagnostics: Vec::new(),
+            depth: 0,
+            include_path: None,
+            texts: HashMap::new(),
+            includes: Vec::new(),
+        }
+    }
+
+    /// Uses `text` as the content of the file `path` when it is included.
+    /// `path` must be canonical.
+    pub fn set_text(&mut self, path: PathBuf, text: String) {
+        self.texts.insert(path, text);
+    }
+
+    /// Defines an object-like macro as if `#define name value` was
+    /// written at the start of the file.
+    pub fn define(&mut self, name: impl Into<String>, value: &str) {
+        let name = name.into();
+        let body = scan(value.as_bytes(), Position::zero());
+        self.macros.insert(
+            name.clone(),
+            Arc::new(Macro {
+                name,
+                params: None,
+                variadic: false,
+                body: trimmed(&body).to_vec(),
+                definition: None,
+            }),
+        );
+    }
+
+    /// Preprocesses `text` that is the content of the file `source`.
+    pub fn process(mut self, text: &str, source: Arc<Path>) -> Preprocessed {
+        self.process_file(text.as_bytes(), &source);
+        Preprocessed {
+            text: String::from_utf8_lossy(&self.output.text).into_owned(),
+            source_map: self.output.map,
+            diagnostics: self.diagnostics,
+            includes: self.includes,
+        }
+    }
+
+    fn process_file(&mut self, text: &[u8], source: &Arc<Path>) {
+        let lines = text.split_inclusive(|ch| *ch == b'\n').collect::<Vec<_>>();
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        // Consecutive lines of active text are expanded together
+        // so that macro invocations can span multiple lines.
+        let mut block: Vec<u8> = Vec::new();
+        let mut block_start = Position::zero();
+        let mut in_comment = false;
+        let mut i = 0;
+        while i < lines.len() {
+            let active = conditionals.last().is_none_or(|cond| cond.active);
+            let line_start = Position::new(i as u32, 0);
+            if in_comment || directive_name(lines[i]).is_none() {
+                in_comment = ends_in_comment(lines[i], in_comment);
+                if active {
+                    if block.is_empty() {
+                        block_start = line_start;
+                    }
+                    block.extend(lines[i]);
+                } else {
+                    self.flush(&mut block, block_start, source);
+                    self.newline(lines[i], i, source);
+                }
+                i += 1;
+                continue;
+            }
+            self.flush(&mut block, block_start, source);
+
+            // A directive continues on the next line if the line ends with a backslash
+            // or in the middle of a comment
+            let mut directive = lines[i].to_vec();
+            let mut count = 1;
+            while i + count < lines.len()
+                && (directive.trim_ascii_end().ends_with(b"\\")
+                    || ends_in_comment(&directive, false))
+            {
+                directive.extend(lines[i + count]);
+                count += 1;
+            }
+            let span = Span::new(line_start, Position::new(i as u32, line_len(lines[i])));
+            let tokens = directive_tokens(&directive, line_start);
+            let included = self.directive(tokens, &mut conditionals, source, span);
+            for (offset, line) in lines[i..i + count].iter().enumerate() {
+                // The newline of an `#include` directive is replaced by the included file
+                if !(included && offset == 0) {
+                    self.newline(line, i + offset, source);
+                }
+            }
+            i += count;
+        }
+        self.flush(&mut block, block_start, source);
+        for conditional in conditionals {
+            self.diagnostics.push(Diagnostic::new(
+                conditional.span,
+                source.clone(),
+                ErrorCode::UnbalancedConditional,
+                "Unterminated conditional directive",
+            ))
+        }
+    }
+
+    /// Emits the line terminator of a line that is not part of the output.
+    fn newline(&mut self, line: &[u8], line_no: usize, source: &Arc<Path>) {
+        if line.ends_with(b"\n") {
+            self.output
+                .verbatim(b"\n", source, Position::new(line_no as u32, line_len(line)));
+        }
+    }
+
+    fn expander<'a>(&'a mut self, source: &'a Arc<Path>) -> Expander<'a> {
+        Expander {
+            macros: &self.macros,
+            map: &mut self.output.map,
+            source,
+            diagnostics: &mut self.diagnostics,
+        }
+    }
+
+    /// Expands a block of text and appends it to the output.
+    fn flush(&mut self, block: &mut Vec<u8>, start: Position, source: &Arc<Path>) {
+        if block.is_empty() {
+            return;
+        }
+        let tokens = scan(block, start);
+        let tokens = self.expander(source).expand(tokens);
+        for token in tokens {
+            self.output.token(&token, source);
+        }
+        block.clear();
+    }
+
+    fn error(
+        &mut self,
+        span: Span,
+        source: &Arc<Path>,
+        code: ErrorCode,
+        message: impl Into<String>,
+    ) {
+        self.diagnostics
+            .push(Diagnostic::new(span, source.clone(), code, message));
+    }
+
+    /// Handles a directive. Returns whether a file was included.
+    fn directive(
+        &mut self,
+        tokens: Vec<PpToken>,
+        conditionals: &mut Vec<Conditional>,
+        source: &Arc<Path>,
+        span: Span,
+    ) -> bool {
+        // The first significant token is the `#`
+        let Some(name_idx) = tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, tok)| !tok.is_whitespace())
+            .nth(1)
+            .map(|(idx, _)| idx)
+        else {
+            // null directive
+            return false;
+        };
+        let name = tokens[name_idx].text.clone();
+        let rest = &tokens[name_idx + 1..];
+        let active = conditionals.last().is_none_or(|cond| cond.active);
+        match name.as_str() {
+            "if" | "ifdef" | "ifndef" => {
+                let taken = active && self.condition(&name, rest, source, span);
+                conditionals.push(Conditional {
+                    parent_active: active,
+                    active: taken,
+                    taken,
+                    seen_else: false,
+                    span,
+                });
+            }
+            "elif" | "else" => {
+                let Some(conditional) = conditionals.last_mut() else {
+                    self.error(
+                        span,
+                        source,
+                        ErrorCode::UnbalancedConditional,
+                        format!("#{name} without #if"),
+                    );
+                    return false;
+                };
+                if conditional.seen_else {
+                    self.error(
+                        span,
+                        source,
+                        ErrorCode::UnbalancedConditional,
+                        format!("#{name} after #else"),
+                    );
+                }
+                let (parent_active, taken) = (conditional.parent_active, conditional.taken);
+                let active = parent_active
+                    && !taken
+                    && (name == "else" || self.condition(&name, rest, source, span));
+                let conditional = conditionals.last_mut().unwrap();
+                conditional.active = active;
+                conditional.taken |= active;
+                conditional.seen_else |= name == "else";
+            }
+            "endif" if conditionals.pop().is_none() => {
+                self.error(
+                    span,
+                    source,
+                    ErrorCode::UnbalancedConditional,
+                    "#endif without #if",
+                );
+            }
+            "endif" => {}
+            _ if !active => {}
+            "define" => self.define_macro(rest, source, span),
+            "undef" => match significant(rest).next() {
+                Some(tok) if tok.kind == PpKind::Ident => {
+                    self.macros.remove(&tok.text);
+                }
+                _ => self.error(
+                    span,
+                    source,
+                    ErrorCode::InvalidDirective,
+                    "Macro name missing",
+                ),
+            },
+            "include" => return self.include(rest, source, span, false),
+            "include_next" => return self.include(rest, source, span, true),
+            "error" | "warning" => {
+                let message = rest.iter().map(|tok| tok.text.as_str()).collect::<String>();
+                let code = if name == "error" {
+                    ErrorCode::ErrorDirective
+                } else {
+                    ErrorCode::WarningDirective
+                };
+                self.error(span, source, code, format!("#{name} {}", message.trim()));
+            }
+            // #pragma, #line and #ident are ignored
+            _ => {}
+        }
+        false
+    }
+
+    /// Evaluates the condition of an `#if`, `#ifdef`, `#ifndef` or `#elif` directive.
+    fn condition(
+        &mut self,
+        name: &str,
+        tokens: &[PpToken],
+        source: &Arc<Path>,
+        span: Span,
+    ) -> bool {
+        if name == "ifdef" || name == "ifndef" {
+            return match significant(tokens).next() {
+                Some(tok) if tok.kind == PpKind::Ident => {
+                    self.macros.contains_key(&tok.text) == (name == "ifdef")
+                }
+                _ => {
+                    self.error(
+                        span,
+                        source,
+                        ErrorCode::InvalidDirective,
+                        "Macro name missing",
+                    );
+                    false
+                }
+            };
+        }
+        // `defined` must be evaluated before macros are expanded
+        let mut replaced = Vec::new();
+        let mut iter = tokens.iter().filter(|tok| !tok.is_whitespace());
+        while let Some(tok) = iter.next() {
+            if tok.kind != PpKind::Ident || tok.text != "defined" {
+                replaced.push(tok.clone());
+                continue;
+            }
+            let name = match iter.next() {
+                Some(tok) if tok.is_punct("(") => {
+                    let name = iter.next().filter(|tok| tok.kind == PpKind::Ident);
+                    iter.next().filter(|tok| tok.is_punct(")")).and(name)
+                }
+                Some(tok) if tok.kind == PpKind::Ident => Some(tok),
+                _ => None,
+            };
+            let Some(name) = name else {
+                self.error(
+                    span,
+                    source,
+                    ErrorCode::InvalidCondition,
+                    "Macro name expected after 'defined'",
+                );
+                return false;
+            };
+            replaced.push(number(
+                self.macros.contains_key(&name.text),
+                tok.origin.clone(),
+            ));
+        }
+        let expanded = self.expander(source).expand(replaced);
+        match condition::evaluate(&expanded) {
+            Ok(value) => value != 0,
+            Err(message) => {
+                self.error(span, source, ErrorCode::InvalidCondition, message);
+                false
+            }
+        }
+    }
+
+    fn define_macro(&mut self, tokens: &[PpToken], source: &Arc<Path>, span: Span) {
+        let Some(name_idx) = tokens.iter().position(|tok| !tok.is_whitespace()) else {
+            self.error(
+                span,
+                source,
+                ErrorCode::InvalidDirective,
+                "Macro name missing",
+            );
+            return;
+        };
+        let name = &tokens[name_idx];
+        if name.kind != PpKind::Ident || name.text == "defined" {
+            self.error(
+                span,
+                source,
+                ErrorCode::InvalidDirective,
+                "Macro names must be identifiers",
+            );
+            return;
+        }
+        let mut body_start = name_idx + 1;
+        let mut params = None;
+        let mut variadic = false;
+        // Function-like macros have the parenthesis immediately after the name
+        if tokens.get(body_start).is_some_and(|tok| tok.is_punct("(")) {
+            let mut names = Vec::new();
+            let mut iter = tokens.iter().enumerate().skip(body_start + 1);
+            loop {
+                let Some((idx, tok)) = iter.find(|(_, tok)| !tok.is_whitespace()) else {
+                    self.error(
+                        span,
+                        source,
+                        ErrorCode::InvalidDirective,
+                        "Missing ')' in macro parameter list",
+                    );
+                    return;
+                };
+                match tok.kind {
+                    PpKind::Punct if tok.text == ")" && names.is_empty() && !variadic => {
+                        body_start = idx + 1;
+                        break;
+                    }
+                    PpKind::Ident if !variadic => names.push(tok.text.clone()),
+                    PpKind::Punct if tok.text == "..." && !variadic => variadic = true,
+                    _ => {
+                        self.error(
+                            span,
+                            source,
+                            ErrorCode::InvalidDirective,
+                            format!("Unexpected '{}' in macro parameter list", tok.text),
+                        );
+                        return;
+                    }
+                }
+                match iter.find(|(_, tok)| !tok.is_whitespace()) {
+                    Some((_, tok)) if tok.is_punct(",") && !variadic => {}
+                    Some((idx, tok)) if tok.is_punct(")") => {
+                        body_start = idx + 1;
+                        break;
+                    }
+                    _ => {
+                        self.error(
+                            span,
+                            source,
+                            ErrorCode::InvalidDirective,
+                            "Expected ',' or ')' in macro parameter list",
+                        );
+                        return;
+                    }
+                }
+            }
+            params = Some(names);
+        }
+        let body = trimmed(&tokens[body_start..]).to_vec();
+        if body.first().is_some_and(|tok| tok.is_punct("##"))
+            || body.last().is_some_and(|tok| tok.is_punct("##"))
+        {
+            self.error(
+                span,
+                source,
+                ErrorCode::InvalidDirective,
+                "'##' cannot appear at either end of a macro expansion",
+            );
+            return;
+        }
+        let mac = Macro {
+            name: name.text.clone(),
+            params,
+            variadic,
+            body,
+            definition: match name.origin {
+                PpOrigin::Source(span) => Some(Location::new(source.clone(), span)),
+                PpOrigin::Expansion(_) => None,
+            },
+        };
+        if let Some(previous) = self.macros.get(&mac.name) {
+            if !previous.same_definition(&mac) {
+                self.error(
+                    span,
+                    source,
+                    ErrorCode::MacroRedefined,
+                    format!("`{}` redefined", mac.name),
+                );
+            }
+        }
+        self.macros.insert(mac.name.clone(), Arc::new(mac));
+    }
+
+    /// Returns the name of the included file and whether it is quoted (as opposed to `<...>`)
+    fn include_name(tokens: &[PpToken]) -> Option<(String, bool)> {
+        let mut iter = significant(tokens);
+        let first = iter.next()?;
+        if first.kind == PpKind::String {
+            let name = first.text.strip_prefix('"')?.strip_suffix('"')?;
+            return Some((name.to_string(), true));
+        }
+        if !first.is_punct("<") {
+            return None;
+        }
+        let start = tokens.iter().position(|tok| tok.is_punct("<"))?;
+        let len = tokens[start + 1..]
+            .iter()
+            .position(|tok| tok.is_punct(">"))?;
+        let name = tokens[start + 1..start + 1 + len]
+            .iter()
+            .map(|tok| tok.text.as_str())
+            .collect::<String>();
+        Some((name, false))
+    }
+
+    /// Returns the path of an included file and the index of the include path it was found in.
+    /// `#include_next` searches the include paths after the one containing the current file,
+    /// but never the directory of the current file.
+    fn find_include(
+        &self,
+        name: &str,
+        quoted: bool,
+        next: bool,
+        source: &Path,
+    ) -> Option<(PathBuf, Option<usize>)> {
+        // Quoted includes are first searched relative to the including file
+        let local = source
+            .parent()
+            .filter(|_| quoted && !next)
+            .map(Path::to_path_buf);
+        let first = match self.include_path {
+            Some(index) if next => index + 1,
+            _ => 0,
+        };
+        local
+            .into_iter()
+            .map(|dir| (dir, None))
+            .chain(
+                self.include_paths
+                    .iter()
+                    .enumerate()
+                    .skip(first)
+                    .map(|(index, dir)| (dir.clone(), Some(index))),
+            )
+            .map(|(dir, index)| (dir.join(name), index))
+            .find(|(path, _)| path.is_file())
+            .map(|(path, index)| (dunce::canonicalize(&path).unwrap_or(path), index))
+    }
+
+    /// Handles an `#include` or `#include_next` directive. Returns whether a file was included.
+    fn include(&mut self, tokens: &[PpToken], source: &Arc<Path>, span: Span, next: bool) -> bool {
+        let name = Self::include_name(tokens).or_else(|| {
+            // Computed include
+            let expanded = self.expander(source).expand(tokens.to_vec());
+            Self::include_name(&expanded)
+        });
+        let Some((name, quoted)) = name else {
+            self.error(
+                span,
+                source,
+                ErrorCode::InvalidDirective,
+                "#include expects \"FILENAME\" or <FILENAME>",
+            );
+            return false;
+        };
+        let Some((path, include_path)) = self.find_include(&name, quoted, next, source) else {
+            self.error(
+                span,
+                source,
+                ErrorCode::IOError,
+                format!("Cannot find include file '{name}'"),
+            );
+            return false;
+        };
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            self.error(
+                span,
+                source,
+                ErrorCode::CyclicDependencyError,
+                "#include nested too deeply",
+            );
+            return false;
+        }
+        let text = match self.texts.get(&path) {
+            Some(text) => text.clone().into_bytes(),
+            None => match fs::read(&path) {
+                Ok(text) => text,
+                Err(err) => {
+                    self.diagnostics
+                        .push(Diagnostic::io_error(span, source.clone(), err));
+                    return false;
+                }
+            },
+        };
+        if !self.includes.contains(&path) {
+            self.includes.push(path.clone());
+        }
+        let included: Arc<Path> = path.into();
+        self.depth += 1;
+        let parent_include_path = std::mem::replace(&mut self.include_path, include_path);
+        self.process_file(&text, &included);
+        self.include_path = parent_include_path;
+        self.depth -= 1;
+        if !self.output.text.is_empty() && !self.output.text.ends_with(b"\n") {
+            let end = advance(Position::zero(), &text);
+            self.output.verbatim(b"\n", &included, end);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::error_codes::ErrorCode;
+    use crate::dts::{Location, Position, Preprocessed, Preprocessor, Span};
+    use itertools::Itertools;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    fn source() -> Arc<Path> {
+        Arc::from(PathBuf::from("/test.dts"))
+    }
+
+    fn preprocess(text: &str) -> Preprocessed {
+        Preprocessor::new(vec![]).process(text, source())
+    }
+
+    fn assert_output(text: &str, expected: &str) {
+        let result = preprocess(text);
+        assert_eq!(result.diagnostics, vec![]);
+        assert_eq!(result.text, expected);
+    }
+
+    #[test]
+    fn object_and_function_like_macros() {
+        assert_output(
+            "\
+#define FOO 1
+#define ADD(a, b) ((a) + (b))
+x = <FOO ADD(2, FOO)>;
+",
+            "\n\nx = <1 ((2) + (1))>;\n",
+        );
+    }
+
+    #[test]
+    fn nested_and_recursive_macros() {
+        assert_output(
+            "\
+#define A B
+#define B A
+#define TWICE(x) x x
+#define F(x) TWICE(x)
+A F(1) F(F(2))
+",
+            "\n\n\n\nA 1 1 2 2 2 2\n",
+        );
+    }
+
+    #[test]
+    fn stringify_and_paste() {
+        assert_output(
+            "\
+#define STR(x) #x
+#define CAT(a, b) a ## b
+#define LOG(fmt, ...) f(fmt, ## __VA_ARGS__)
+STR(a  \"b\") CAT(foo, bar) CAT(, x) LOG(1) LOG(1, 2, 3)
+",
+            "\n\n\n\"a \\\"b\\\"\" foobar x f(1) f(1, 2, 3)\n",
+        );
+    }
+
+    #[test]
+    fn multi_line_invocation() {
+        assert_output(
+            "\
+#define ADD(a, b) a + b
+ADD(1,
+    2);
+",
+            "\n1 + 2;\n",
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_output(
+            "\
+#define FOO 2
+#ifdef FOO
+a
+#else
+b
+#endif
+#if FOO == 1
+c
+#elif defined(FOO) && FOO > 1
+d
+#else
+e
+#endif
+#ifndef BAR
+f
+#endif
+#if 0
+#if 1
+g
+#endif
+#else
+h
+#endif
+",
+            "\n\na\n\n\n\n\n\n\nd\n\n\n\n\nf\n\n\n\n\n\n\nh\n\n",
+        );
+    }
+
+    #[test]
+    fn directives_are_distinguished_from_properties() {
+        assert_output(
+            "\
+/ {
+    #address-cells = <1>;
+    #size-cells = <0>;
+};
+# 1 \"file.dts\"
+",
+            "\
+/ {
+    #address-cells = <1>;
+    #size-cells = <0>;
+};
+# 1 \"file.dts\"
+",
+        );
+    }
+
+    #[test]
+    fn macros_are_not_expanded_in_strings_and_comments() {
+        assert_output(
+            "\
+#define FOO 1
+\"FOO\" /* FOO
+FOO */ // FOO
+#define BAR 2 /* multi-line
+comment */
+BAR
+",
+            "\n\"FOO\" /* FOO\nFOO */ // FOO\n\n\n2\n",
+        );
+    }
+
+    #[test]
+    fn maps_positions_to_original_file() {
+        let result = preprocess(
+            "\
+#define VALUE 0x1234
+prop = <VALUE 5>;
+",
+        );
+        assert_eq!(result.text, "\nprop = <0x1234 5>;\n");
+        let map = &result.source_map;
+        // The expansion maps to the invocation
+        assert_eq!(
+            map.map_span(Span::new(Position::new(1, 8), Position::new(1, 14))),
+            Some(Location::new(
+                source(),
+                Span::new(Position::new(1, 8), Position::new(1, 13))
+            ))
+        );
+        let expansion = map.expansions().next().unwrap();
+        assert_eq!(expansion.name, "VALUE");
+        assert_eq!(expansion.expanded, "0x1234");
+        assert_eq!(
+            expansion.definition,
+            Some(Location::new(
+                source(),
+                Span::new(Position::new(0, 8), Position::new(0, 13))
+            ))
+        );
+        // Text after the expansion is shifted back
+        assert_eq!(
+            map.map_span(Span::new(Position::new(1, 15), Position::new(1, 16))),
+            Some(Location::new(
+                source(),
+                Span::new(Position::new(1, 14), Position::new(1, 15))
+            ))
+        );
+    }
+
+    #[test]
+    fn includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let include_dir = dir.path().join("include");
+        fs::create_dir_all(include_dir.join("dt-bindings")).unwrap();
+        fs::write(
+            include_dir.join("dt-bindings").join("gpio.h"),
+            "#ifndef GPIO_H\n#define GPIO_H\n#define GPIO_ACTIVE_LOW 1\n#endif\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("local.dtsi"), "/ { };").unwrap();
+        let main = dir.path().join("main.dts");
+        let text = "\
+#include <dt-bindings/gpio.h>
+#include <dt-bindings/gpio.h>
+#include \"local.dtsi\"
+x = <GPIO_ACTIVE_LOW>;
+";
+        let result =
+            Preprocessor::new(vec![include_dir.clone()]).process(text, main.clone().into());
+        assert_eq!(result.diagnostics, vec![]);
+        assert_eq!(result.text, "\n\n\n\n\n\n\n\n/ { };\nx = <1>;\n");
+
+        let gpio = dunce::canonicalize(include_dir.join("dt-bindings").join("gpio.h")).unwrap();
+        let local = dunce::canonicalize(dir.path().join("local.dtsi")).unwrap();
+        assert_eq!(result.includes, vec![gpio, local.clone()]);
+        assert_eq!(
+            result
+                .source_map
+                .map_position(Position::new(8, 2))
+                .map(|(source, pos)| (source.to_path_buf(), pos)),
+            Some((local.clone(), Position::new(0, 2)))
+        );
+
+        // Edited files are not read from disk
+        let mut preprocessor = Preprocessor::new(vec![include_dir]);
+        preprocessor.set_text(local, "/ { edited; };".to_string());
+        let result = preprocessor.process(text, main.into());
+        assert_eq!(result.text, "\n\n\n\n\n\n\n\n/ { edited; };\nx = <1>;\n");
+    }
+
+    #[test]
+    fn include_next() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        fs::write(
+            first.join("soc.h"),
+            "#define FIRST 1\n#include_next <soc.h>\n",
+        )
+        .unwrap();
+        fs::write(second.join("soc.h"), "#define SECOND 2\n").unwrap();
+        // Quoted names are not searched next to the current file
+        fs::write(first.join("local.h"), "#include_next \"soc.h\"\n").unwrap();
+        let main = dir.path().join("main.dts");
+        let text = "#include <soc.h>\n#include <local.h>\nx = <FIRST SECOND>;\n";
+        let result = Preprocessor::new(vec![first, second]).process(text, main.into());
+        assert_eq!(result.diagnostics, vec![]);
+        assert_eq!(result.text.trim_start(), "x = <1 2>;\n");
+    }
+
+    #[test]
+    fn diagnostics() {
+        let result = preprocess(
+            "\
+#define ADD(a, b) a + b
+ADD(1)
+#include \"missing.h\"
+#if 1 +
+#endif
+#error something went wrong
+#if 1
+",
+        );
+        assert_eq!(
+            result
+                .diagnostics
+                .iter()
+                .map(|diagnostic| (diagnostic.kind, diagnostic.span.start().line()))
+                .collect_vec(),
+            vec![
+                (ErrorCode::MacroArguments, 1),
+                (ErrorCode::IOError, 2),
+                (ErrorCode::InvalidCondition, 3),
+                (ErrorCode::ErrorDirective, 5),
+                (ErrorCode::UnbalancedConditional, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn redefinition() {
+        let result = preprocess("#define A 1\n#define A 1\n#define A 2\nA\n");
+        assert_eq!(
+            result
+                .diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.kind)
+                .collect_vec(),
+            vec![ErrorCode::MacroRedefined]
+        );
+        assert_eq!(result.text, "\n\n\n2\n");
+    }
+}
@@ -0,0 +1,248 @@
+use crate::dts::data::{Position, Span};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(super) enum PpKind {
+    Ident,
+    Number,
+    String,
+    Char,
+    Punct,
+    Whitespace,
+    Newline,
+    Comment,
+    Other,
+}
+
+/// Where a preprocessing token comes from.
+#[derive(Clone, Debug)]
+pub(super) enum PpOrigin {
+    /// The token is located at the given span in the file that is currently processed
+    Source(Span),
+    /// The token is the result of the expansion with the given id
+    Expansion(usize),
+}
+
+/// The set of macros that must not be expanded again when re-scanning a token.
+pub(super) type HideSet = Arc<HashSet<String>>;
+
+#[derive(Clone, Debug)]
+pub(super) struct PpToken {
+    pub kind: PpKind,
+    pub text: String,
+    pub origin: PpOrigin,
+    pub hide: HideSet,
+}
+
+impl PpToken {
+    pub fn is_whitespace(&self) -> bool {
+        matches!(
+            self.kind,
+            PpKind::Whitespace | PpKind::Newline | PpKind::Comment
+        )
+    }
+
+    pub fn is_punct(&self, punct: &str) -> bool {
+        self.kind == PpKind::Punct && self.text == punct
+    }
+}
+
+const MULTI_CHAR_PUNCTUATORS: [&str; 10] =
+    ["...", "##", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||"];
+
+fn is_ident_start(ch: u8) -> bool {
+    ch.is_ascii_alphabetic() || ch == b'_'
+}
+
+fn is_ident_char(ch: u8) -> bool {
+    ch.is_ascii_alphanumeric() || ch == b'_'
+}
+
+/// Splits text into preprocessing tokens.
+/// `start` is the position of the first byte of `text` in the source file.
+pub(super) fn scan(text: &[u8], start: Position) -> Vec<PpToken> {
+    let mut tokens = Vec::new();
+    let mut pos = start;
+    let mut i = 0;
+    while i < text.len() {
+        let begin = i;
+        let ch = text[i];
+        let kind = if ch == b'\n' {
+            i += 1;
+            PpKind::Newline
+        } else if ch.is_ascii_whitespace() {
+            while i < text.len() && text[i] != b'\n' && text[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            PpKind::Whitespace
+        } else if text[i..].starts_with(b"/*") {
+            i = match text[i + 2..].windows(2).position(|w| w == b"*/") {
+                Some(end) => i + 2 + end + 2,
+                None => text.len(),
+            };
+            PpKind::Comment
+        } else if text[i..].starts_with(b"//") {
+            while i < text.len() && text[i] != b'\n' {
+                i += 1;
+            }
+            PpKind::Comment
+        } else if is_ident_start(ch) {
+            while i < text.len() && is_ident_char(text[i]) {
+                i += 1;
+            }
+            PpKind::Ident
+        } else if ch.is_ascii_digit() {
+            while i < text.len() && (is_ident_char(text[i]) || text[i] == b'.') {
+                i += 1;
+            }
+            PpKind::Number
+        } else if ch == b'"' || ch == b'\'' {
+            i += 1;
+            while i < text.len() && text[i] != ch && text[i] != b'\n' {
+                if text[i] == b'\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(text.len());
+            if ch == b'"' {
+                PpKind::String
+            } else {
+                PpKind::Char
+            }
+        } else if let Some(punct) = MULTI_CHAR_PUNCTUATORS
+            .iter()
+            .find(|punct| text[i..].starts_with(punct.as_bytes()))
+        {
+            i += punct.len();
+            PpKind::Punct
+        } else if ch.is_ascii_punctuation() {
+            i += 1;
+            PpKind::Punct
+        } else {
+            // Keep multi-byte characters in one piece
+            i += 1;
+            while i < text.len() && !text[i].is_ascii() {
+                i += 1;
+            }
+            PpKind::Other
+        };
+        let token_text = String::from_utf8_lossy(&text[begin..i]).into_owned();
+        let end = advance(pos, token_text.as_bytes());
+        tokens.push(PpToken {
+            kind,
+            text: token_text,
+            origin: PpOrigin::Source(Span::new(pos, end)),
+            hide: HideSet::default(),
+        });
+        pos = end;
+    }
+    tokens
+}
+
+/// Removes leading and trailing whitespace from a list of tokens.
+pub(super) fn trimmed(tokens: &[PpToken]) -> &[PpToken] {
+    let start = tokens
+        .iter()
+        .position(|tok| !tok.is_whitespace())
+        .unwrap_or(tokens.len());
+    let end = tokens
+        .iter()
+        .rposition(|tok| !tok.is_whitespace())
+        .map_or(start, |end| end + 1);
+    &tokens[start..end]
+}
+
+/// Returns the position after `text` when `text` starts at `pos`.
+pub(super) fn advance(pos: Position, text: &[u8]) -> Position {
+    let mut line = pos.line();
+    let mut character = pos.character();
+    for ch in text {
+        if *ch == b'\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += 1;
+        }
+    }
+    Position::new(line, character)
+}
+
+/// Returns whether a text ends inside a multi-line comment
+/// given that it starts inside a comment (`in_comment == true`) or not.
+pub(super) fn ends_in_comment(text: &[u8], mut in_comment: bool) -> bool {
+    let mut i = 0;
+    while i < text.len() {
+        if in_comment {
+            if text[i..].starts_with(b"*/") {
+                in_comment = false;
+                i += 2;
+            } else {
+                i += 1;
+            }
+            continue;
+        }
+        match text[i] {
+            b'/' if text[i..].starts_with(b"/*") => {
+                in_comment = true;
+                i += 2;
+            }
+            b'/' if text[i..].starts_with(b"//") => return false,
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < text.len() && text[i] != quote && text[i] != b'\n' {
+                    if text[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    in_comment
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::data::Position;
+    use crate::dts::preprocessor::scanner::{ends_in_comment, scan, PpKind};
+    use itertools::Itertools;
+
+    #[test]
+    fn scan_tokens() {
+        let tokens = scan(b"#define FOO(a) (a << 0x10UL) // comment", Position::zero());
+        assert_eq!(
+            tokens
+                .iter()
+                .filter(|tok| tok.kind != PpKind::Whitespace)
+                .map(|tok| (tok.kind, tok.text.as_str()))
+                .collect_vec(),
+            vec![
+                (PpKind::Punct, "#"),
+                (PpKind::Ident, "define"),
+                (PpKind::Ident, "FOO"),
+                (PpKind::Punct, "("),
+                (PpKind::Ident, "a"),
+                (PpKind::Punct, ")"),
+                (PpKind::Punct, "("),
+                (PpKind::Ident, "a"),
+                (PpKind::Punct, "<<"),
+                (PpKind::Number, "0x10UL"),
+                (PpKind::Punct, ")"),
+                (PpKind::Comment, "// comment"),
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_state() {
+        assert!(ends_in_comment(b"foo /* bar", false));
+        assert!(!ends_in_comment(b"foo /* bar */", false));
+        assert!(!ends_in_comment(b"bar */ baz", true));
+        assert!(!ends_in_comment(b"\"/*\"", false));
+        assert!(!ends_in_comment(b"// /*", false));
+    }
+}
@@ -1,11 +1,16 @@
 use crate::dts::analysis::{Analysis, AnalysisContext};
-use crate::dts::ast::{DtsFile, Include, Reference};
+use crate::dts::ast::{DtsFile, Include, Node, Reference};
+use crate::dts::cst::SyntaxTree;
 use crate::dts::data::HasSource;
 use crate::dts::error_codes::SeverityMap;
-use crate::dts::reader::ByteReader;
-use crate::dts::tokens::Lexer;
+use crate::dts::schema::Bindings;
+use crate::dts::source_map::{MacroExpansion, SourceMap};
+use crate::dts::tree::DeviceTree;
 use crate::dts::visitor::ItemAtCursor;
-use crate::dts::{Diagnostic, FileType, HasSpan, Parser, ParserContext, Position, Severity, Span};
+use crate::dts::{
+    Diagnostic, FileType, HasSpan, ParserContext, PhandleStyle, Position, Preprocessor, Severity,
+    Span,
+};
 use itertools::Itertools;
 use std::collections::HashMap;
 use std::iter::empty;
@@ -21,6 +26,10 @@ pub struct ProjectFile {
     pub(crate) context: Option<AnalysisContext>,
     pub(crate) file_type: FileType,
     pub(crate) source: String,
+    pub(crate) source_map: SourceMap,
+    /// The files that this file includes using `#include`.
+    /// The file is parsed again when one of them changes.
+    pub(crate) includes: Vec<PathBuf>,
 }
 
 impl ProjectFile {
@@ -37,6 +46,8 @@ impl ProjectFile {
             context: None,
             source,
             analysis_diagnostics: vec![],
+            source_map: SourceMap::default(),
+            includes: vec![],
         }
     }
 
@@ -48,6 +59,8 @@ impl ProjectFile {
             source,
             context: None,
             file_type,
+            source_map: SourceMap::default(),
+            includes: vec![],
         }
     }
 
@@ -69,6 +82,22 @@ impl ProjectFile {
     pub fn source(&self) -> &String {
         &self.source
     }
+
+    /// Maps the preprocessed text of this file back to the original files.
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+}
+
+/// Attributes diagnostics that originate from a macro invocation to that invocation.
+fn attach_expansions(diagnostics: &mut [Diagnostic], source_map: &SourceMap) {
+    for diagnostic in diagnostics {
+        if diagnostic.expansion.is_none() {
+            diagnostic.expansion = source_map
+                .expansion_containing(&diagnostic.source, diagnostic.span)
+                .map(|expansion| Box::new(expansion.clone()));
+        }
+    }
 }
 
 #[derive(Default)]
@@ -76,6 +105,14 @@ pub struct Project {
     files: HashMap<PathBuf, ProjectFile>,
     pub include_paths: Vec<PathBuf>,
     pub severities: SeverityMap,
+    pub phandle_style: PhandleStyle,
+    /// Whether compiled trees contain the `__symbols__` node
+    pub generate_symbols: bool,
+    /// The base tree that each overlay is applied to.
+    /// References in overlays are resolved against the labels of their base tree.
+    pub overlay_bases: HashMap<PathBuf, PathBuf>,
+    /// Binding schemas that nodes of complete device trees are validated against
+    pub bindings: Bindings,
 }
 
 impl Project {
@@ -86,6 +123,52 @@ impl Project {
             .collect::<Vec<PathBuf>>();
     }
 
+    /// Loads the binding schemas in the given directories, replacing all loaded schemas.
+    pub fn set_binding_paths(&mut self, binding_paths: Vec<String>) -> Result<(), io::Error> {
+        let mut bindings = Bindings::default();
+        for path in binding_paths {
+            bindings.load(Path::new(&path))?;
+        }
+        self.bindings = bindings;
+        Ok(())
+    }
+
+    /// Associates an overlay with the device-tree source that it is applied to.
+    /// The base file is added to the project if it is not part of it yet.
+    pub fn set_overlay_base(&mut self, overlay: String, base: String) -> Result<(), io::Error> {
+        let overlay = dunce::canonicalize(overlay)?;
+        let base = dunce::canonicalize(base)?;
+        self.overlay_bases.insert(overlay, base.clone());
+        // Re-adding the base re-evaluates the overlay as well
+        match self.files.get(&base) {
+            Some(file) => {
+                let (text, file_type) = (file.source.clone(), file.file_type);
+                self.add_file_with_text(base, text, file_type);
+            }
+            None => {
+                let text = fs::read_to_string(&base)?;
+                let file_type = FileType::from(base.as_path());
+                self.add_file_with_text(base, text, file_type);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the base trees of all overlays and re-analyzes the overlays without them.
+    pub fn clear_overlay_bases(&mut self) {
+        if self.overlay_bases.is_empty() {
+            return;
+        }
+        self.overlay_bases.clear();
+        self.analyze_files();
+    }
+
+    /// Returns the analysis of the base tree of an overlay, if one is configured.
+    pub fn overlay_base(&self, overlay: &Path) -> Option<&AnalysisContext> {
+        let base = self.overlay_bases.get(overlay)?;
+        self.files.get(base)?.context.as_ref()
+    }
+
     pub fn add_file(&mut self, file_name: String) -> Result<(), io::Error> {
         let file_name = dunce::canonicalize(file_name)?;
         let content = fs::read_to_string(file_name.clone())?;
@@ -110,7 +193,21 @@ impl Project {
         // First step: Parse file and all dependencies.
         // Dependencies are cached.
         self.parse_file(file_name.clone(), text, file_type);
+        // Files that include the file using `#include` contain its old text
+        let including = self
+            .files
+            .iter()
+            .filter(|(_, file)| file.includes.contains(&file_name))
+            .map(|(path, file)| (path.clone(), file.source.clone(), file.file_type))
+            .collect_vec();
+        for (path, text, file_type) in including {
+            self.parse_file(path, text, file_type);
+        }
+        self.analyze_files();
+    }
 
+    // Analyzes all files, after the files that they depend on
+    fn analyze_files(&mut self) {
         let keys = self.compute_key_order();
 
         let mut analysis = Analysis::new();
@@ -124,6 +221,7 @@ impl Project {
             let proj_file = self.files.get_mut(key).unwrap();
             proj_file.context = Some(result.context);
             proj_file.analysis_diagnostics = result.diagnostics;
+            attach_expansions(&mut proj_file.analysis_diagnostics, &proj_file.source_map);
         }
     }
 
@@ -143,6 +241,18 @@ impl Project {
             for include in includes.into_iter().flatten() {
                 map.entry(include).or_default().push(path.clone())
             }
+            // Files included using `#include` are only ordered if they are part of the project
+            for include in &file.includes {
+                if self.files.contains_key(include) {
+                    map.entry(include.clone()).or_default().push(path.clone())
+                }
+            }
+        }
+        // Base trees must be analyzed before the overlays that are applied to them
+        for (overlay, base) in &self.overlay_bases {
+            if self.files.contains_key(overlay) && self.files.contains_key(base) {
+                map.entry(base.clone()).or_default().push(overlay.clone());
+            }
         }
         // This is very inefficient. Probably there is a better way.
         let mut current_order = self.files.keys().cloned().collect_vec();
@@ -160,6 +270,10 @@ impl Project {
 
     pub fn remove_file(&mut self, path: &Path) {
         if let Ok(path) = dunce::canonicalize(path) {
+            // Overlays are still checked against their base tree
+            if self.overlay_bases.values().any(|base| *base == path) {
+                return;
+            }
             self.files.remove(&path);
         }
     }
@@ -207,16 +321,45 @@ impl Project {
     }
 
     pub fn find_at_pos<'a>(&'a self, path: &Path, position: &Position) -> Option<ItemAtCursor<'a>> {
-        let file = match self.get_file(path).and_then(|file| file.file.as_ref()) {
-            None => return None,
-            Some(file) => file,
-        };
-        file.item_at_cursor(position)
+        let path = dunce::canonicalize(path).ok()?;
+        let file = self.files.get(&path).and_then(|file| file.file.as_ref())?;
+        file.item_at_cursor(&path, position)
+    }
+
+    /// Returns the macro invocation at a position in a file.
+    pub fn macro_expansion_at(&self, path: &Path, position: &Position) -> Option<&MacroExpansion> {
+        let path = dunce::canonicalize(path).ok()?;
+        self.files
+            .get(&path)?
+            .source_map
+            .expansion_at(&path, position)
     }
 
+    /// Describes the node that a reference points to using the node's doc comment.
+    /// Nodes without a doc comment are described by their name.
     pub fn document_reference(&self, path: &Path, reference: &Reference) -> Option<String> {
-        let referenced = self.get_analysis(path)?.get_referenced(reference)?;
-        Some(format!("Node {}", referenced.name.name.clone()))
+        let referenced = self.get_referenced(path, reference)?;
+        match &referenced.doc_comment {
+            Some(doc_comment) => Some(doc_comment.clone()),
+            None => Some(format!("Node {}", referenced.name.name.clone())),
+        }
+    }
+
+    /// Returns the doc comment of the node or property that a label in a file is attached to.
+    pub fn document_label(&self, path: &Path, label: &str) -> Option<String> {
+        self.get_analysis(path)?
+            .get_label_doc_comment(label)
+            .cloned()
+    }
+
+    // Returns the node that a reference in a file points to.
+    // References in overlays may point into the base tree.
+    fn get_referenced(&self, path: &Path, reference: &Reference) -> Option<&Arc<Node>> {
+        if let Some(referenced) = self.get_analysis(path)?.get_referenced(reference) {
+            return Some(referenced);
+        }
+        let path = dunce::canonicalize(path).ok()?;
+        self.overlay_base(&path)?.get_referenced(reference)
     }
 
     pub fn get_node_position(
@@ -224,8 +367,21 @@ impl Project {
         path: &Path,
         reference: &Reference,
     ) -> Option<(Span, Arc<Path>)> {
-        let referenced = self.get_analysis(path)?.get_referenced(reference)?;
-        Some((referenced.name.span(), referenced.name.source()))
+        let analysis = self.get_analysis(path)?;
+        if let Some(referenced) = self.get_referenced(path, reference) {
+            return Some((referenced.name.span(), referenced.name.source()));
+        }
+        // Labels of properties or values do not reference a node, but can still be navigated to
+        let Reference::Label(label) = reference else {
+            return None;
+        };
+        let label = analysis.get_label(label)?;
+        Some((label.span(), label.source()))
+    }
+
+    /// Returns the device tree that results from compiling a file.
+    pub fn get_tree(&self, path: &Path) -> Option<&DeviceTree> {
+        self.get_analysis(path).map(|analysis| analysis.tree())
     }
 
     pub fn get_root(&self, path: &Path) -> Option<&DtsFile> {
@@ -238,30 +394,48 @@ impl Project {
     }
 
     fn parse_file(&mut self, file_name: PathBuf, text: String, file_type: FileType) {
-        let reader = ByteReader::from_string(text.clone());
-        let lexer = Lexer::new(reader, file_name.clone().into());
+        let mut preprocessor = Preprocessor::new(self.include_paths.clone());
+        // Included files may have been changed without being saved
+        for (path, file) in &self.files {
+            preprocessor.set_text(path.clone(), file.source.clone());
+        }
+        let preprocessed = preprocessor.process(&text, file_name.clone().into());
+        let source_map = preprocessed.source_map;
+        let includes = preprocessed.includes;
+        let tree = SyntaxTree::parse_preprocessed(
+            &preprocessed.text,
+            file_name.clone().into(),
+            source_map.clone(),
+        );
         // add the file's directory to the include paths to allow local includes
         let mut include_paths = self.include_paths.clone();
         if let Some(parent) = file_name.parent() {
             include_paths.insert(0, parent.into());
         }
-        let mut parser = Parser::new(lexer, ParserContext { include_paths });
-        match parser.file() {
+        let (result, parser_diagnostics) = tree.to_ast(ParserContext { include_paths });
+        let mut diagnostics = preprocessed.diagnostics;
+        diagnostics.extend(parser_diagnostics);
+        match result {
             Ok(file) => {
                 // insert dummy file to be defined so that no cyclic dependency can occur.
                 self.files.insert(file_name.clone(), ProjectFile::default());
                 file.elements
                     .iter()
                     .filter_map(|primary| primary.as_include())
-                    .for_each(|include| self.parse_included_file(&mut parser.diagnostics, include));
-                self.files.insert(
-                    file_name,
-                    ProjectFile::parsed(parser.diagnostics, file, file_type, text),
-                );
+                    .for_each(|include| self.parse_included_file(&mut diagnostics, include));
+                let mut file = ProjectFile::parsed(diagnostics, file, file_type, text);
+                attach_expansions(&mut file.parser_diagnostics, &source_map);
+                file.source_map = source_map;
+                file.includes = includes;
+                self.files.insert(file_name, file);
             }
             Err(err) => {
-                self.files
-                    .insert(file_name, ProjectFile::unrecoverable(err, text, file_type));
+                let mut file = ProjectFile::unrecoverable(err, text, file_type);
+                file.parser_diagnostics.splice(0..0, diagnostics);
+                attach_expansions(&mut file.parser_diagnostics, &source_map);
+                file.source_map = source_map;
+                file.includes = includes;
+                self.files.insert(file_name, file);
             }
         };
     }
@@ -304,13 +478,14 @@ mod tests {
     use crate::dts::error_codes::ErrorCode;
     use crate::dts::test::Code;
     use crate::dts::tokens::TokenKind;
-    use crate::dts::{ast, Diagnostic, HasSpan, ItemAtCursor, Project};
+    use crate::dts::{ast, Diagnostic, FileType, HasSpan, ItemAtCursor, Position, Project};
     use assert_matches::assert_matches;
     use itertools::Itertools;
     use std::fs;
     use std::fs::File;
     use std::io::Write;
     use std::path::{Path, PathBuf};
+    use std::sync::Arc;
     use tempfile::tempdir;
 
     struct TempDir {
@@ -629,4 +804,383 @@ mod tests {
         assert!(project.get_file(&file2).is_some());
         assert!(project.get_file(&file3).is_some());
     }
+
+    #[test]
+    pub fn c_preprocessor_includes_and_macros() {
+        let temp_dir = TempDir::new();
+        let include_dir = temp_dir.inner.path().join("include");
+        fs::create_dir_all(include_dir.join("dt-bindings")).expect("Cannot create directory");
+        fs::write(
+            include_dir.join("dt-bindings").join("gpio.h"),
+            "#define GPIO_ACTIVE_HIGH 0\n#define GPIO_ACTIVE_LOW 1\n",
+        )
+        .expect("Cannot write to file");
+        let (code1, file1) = temp_dir.add_file(
+            "board.dtsi",
+            r#"
+/ {
+    gpio: gpio-controller {
+        #gpio-cells = <2>;
+    };
+};
+"#,
+        );
+        let (code2, file2) = temp_dir.add_file(
+            "board.dts",
+            r#"/dts-v1/;
+
+#include <dt-bindings/gpio.h>
+#include "board.dtsi"
+
+#define LED_GPIO(n) &gpio n GPIO_ACTIVE_LOW
+
+/ {
+    led {
+        gpios = <LED_GPIO(3)>;
+    };
+};
+"#,
+        );
+        let mut project = Project::default();
+        project.set_include_paths(vec![include_dir.display().to_string()]);
+        project
+            .add_file(file2.clone().into_os_string().into_string().unwrap())
+            .expect("Cannot add file to project");
+        project.assert_no_diagnostics();
+
+        // The referenced node is located in the included file
+        let item = project
+            .find_at_pos(&file2, &code2.s1("LED_GPIO(3)").start())
+            .expect("Found no item");
+        let ItemAtCursor::Reference(reference) = item else {
+            panic!("Found non-reference at cursor")
+        };
+        let (span, path) = project
+            .get_node_position(&file2, reference)
+            .expect("Reference does not reference a node");
+        assert_eq!(span, code1.s1("gpio-controller").span());
+        assert_eq!(
+            path.to_path_buf(),
+            dunce::canonicalize(&file1).expect("File does not exist")
+        );
+
+        // Items from the included file are not found at the same position in the including file
+        assert!(project
+            .find_at_pos(&file2, &code1.s1("gpio:").start())
+            .is_none());
+    }
+
+    #[test]
+    pub fn changed_headers_update_including_files() {
+        let temp_dir = TempDir::new();
+        let (_, header) = temp_dir.add_file("values.h", "#define VALUE 1\n");
+        let (_, file) = temp_dir.add_file(
+            "board.dts",
+            "/dts-v1/;\n#include \"values.h\"\n/ { value = <VALUE>; };\n",
+        );
+        let mut project = Project::default();
+        project
+            .add_file(file.clone().into_os_string().into_string().unwrap())
+            .expect("Cannot add file to project");
+        project.assert_no_diagnostics();
+        let value = |project: &Project| {
+            project
+                .get_tree(&file)
+                .and_then(|tree| tree.root.property("value"))
+                .map(|property| property.data.bytes.clone())
+        };
+        assert_eq!(value(&project), Some(vec![0, 0, 0, 1]));
+
+        // The header is edited, but not saved
+        let header = dunce::canonicalize(header).expect("File does not exist");
+        project.add_file_with_text(header, "#define VALUE 2\n".to_string(), FileType::Unknown);
+        assert_eq!(value(&project), Some(vec![0, 0, 0, 2]));
+    }
+
+    #[test]
+    pub fn diagnostics_in_macro_expansions() {
+        let temp_dir = TempDir::new();
+        let (code, file) = temp_dir.add_file(
+            "board.dts",
+            r#"/dts-v1/;
+
+#define LONG_NAME very-long-company,very-long-name
+
+/ {
+    LONG_NAME;
+};
+"#,
+        );
+        let mut project = Project::default();
+        project
+            .add_file(file.clone().into_os_string().into_string().unwrap())
+            .expect("Cannot add file to project");
+
+        let invocation = code.s("LONG_NAME", 2);
+        let diagnostics = project.get_diagnostics(&file).collect_vec();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, ErrorCode::NameTooLong);
+        assert_eq!(diagnostics[0].span, invocation.span());
+        let expansion = diagnostics[0]
+            .expansion
+            .as_ref()
+            .expect("Diagnostic is not part of an expansion");
+        assert_eq!(expansion.name, "LONG_NAME");
+        assert_eq!(
+            expansion
+                .definition
+                .as_ref()
+                .map(|location| location.span()),
+            Some(code.s1("LONG_NAME").span())
+        );
+        assert_eq!(
+            diagnostics[0].expansion_note(),
+            Some(format!(
+                "in expansion of macro `LONG_NAME` defined at {}:3",
+                dunce::canonicalize(&file).unwrap().display()
+            ))
+        );
+
+        let expansion = project
+            .macro_expansion_at(&file, &invocation.start())
+            .expect("No macro at cursor");
+        assert_eq!(expansion.expanded, "very-long-company,very-long-name");
+        assert!(project
+            .macro_expansion_at(&file, &code.s1("/ {").start())
+            .is_none());
+    }
+
+    #[test]
+    pub fn line_markers_in_preprocessed_files() {
+        let temp_dir = TempDir::new();
+        let (_, dts) = temp_dir.add_file("board.dts", "");
+        let (_, dtsi) = temp_dir.add_file("board.dtsi", "");
+        let dts = dunce::canonicalize(dts).unwrap();
+        let dtsi = dunce::canonicalize(dtsi).unwrap();
+        let (_, file) = temp_dir.add_file(
+            "board.dts.tmp",
+            format!(
+                r#"# 1 "{dts}"
+/dts-v1/;
+# 1 "{dtsi}" 1
+/ {{
+    very-long-company,very-long-name-in-dtsi;
+}};
+# 3 "{dts}" 2
+
+/ {{
+    very-long-company,very-long-name-in-dts;
+}};
+"#,
+                dts = dts.display(),
+                dtsi = dtsi.display()
+            ),
+        );
+        let mut project = Project::default();
+        project
+            .add_file(file.clone().into_os_string().into_string().unwrap())
+            .expect("Cannot add file to project");
+
+        let diagnostics = project
+            .get_diagnostics(&file)
+            .map(|diagnostic| {
+                (
+                    diagnostic.kind,
+                    diagnostic.source.to_path_buf(),
+                    diagnostic.span.start(),
+                )
+            })
+            .collect_vec();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (ErrorCode::NameTooLong, dtsi, Position::new(1, 4)),
+                (ErrorCode::NameTooLong, dts, Position::new(4, 4)),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn tree_applies_includes_in_order() {
+        let temp_dir = TempDir::new();
+        let (_, soc) = temp_dir.add_file(
+            "soc.dtsi",
+            r#"
+/ {
+    uart0: serial@1000 {
+        status = "disabled";
+    };
+};
+"#,
+        );
+        // Refers to a label of another include that it does not include itself
+        let (_, board_dtsi) = temp_dir.add_file(
+            "board.dtsi",
+            r#"
+&uart0 {
+    status = "okay";
+};
+"#,
+        );
+        let (_, board) = temp_dir.add_file(
+            "board.dts",
+            r#"/dts-v1/;
+/include/ "soc.dtsi"
+/include/ "board.dtsi"
+
+&uart0 {
+    current-speed = <115200>;
+};
+"#,
+        );
+        let mut project = Project::default();
+        for file in [&soc, &board_dtsi, &board] {
+            project
+                .add_file(file.clone().into_os_string().into_string().unwrap())
+                .expect("Cannot add file to project");
+        }
+        project.assert_no_diagnostics();
+
+        let tree = project.get_tree(&board).expect("No tree for file");
+        let uart = tree
+            .node(&ast::Path::from("/serial@1000"))
+            .expect("Node from include should be part of the tree");
+        assert_eq!(uart.property("status").unwrap().data.bytes, b"okay\0");
+        assert!(uart.property("current-speed").is_some());
+    }
+
+    #[test]
+    pub fn overlay_with_base() {
+        let temp_dir = TempDir::new();
+        let (base_code, base) = temp_dir.add_file(
+            "board.dts",
+            r#"/dts-v1/;
+
+/ {
+    soc {
+        uart0: serial@1000 {
+            status = "disabled";
+        };
+    };
+    clk: clock {};
+};
+"#,
+        );
+        let (code, overlay) = temp_dir.add_file(
+            "board-overlay.dtso",
+            r#"/dts-v1/;
+/plugin/;
+
+&uart0 {
+    status = "okay";
+    clocks = <&clk>, <&local>, <&missing>;
+    local: device {};
+};
+
+&{/soc/i2c@2000} {
+    status = "okay";
+};
+"#,
+        );
+        let mut project = Project::default();
+        project
+            .add_file(overlay.clone().into_os_string().into_string().unwrap())
+            .expect("Cannot add file to project");
+        // Without base, all references of overlays are accepted
+        project.assert_no_diagnostics();
+
+        project
+            .set_overlay_base(
+                overlay.clone().into_os_string().into_string().unwrap(),
+                base.clone().into_os_string().into_string().unwrap(),
+            )
+            .expect("Cannot set base of overlay");
+        let overlay_source: Arc<Path> = dunce::canonicalize(&overlay).unwrap().into();
+        assert_eq!(
+            project.get_diagnostics(&overlay).cloned().collect_vec(),
+            vec![
+                Diagnostic::new(
+                    code.s1("&{/soc/i2c@2000}").span(),
+                    overlay_source.clone(),
+                    ErrorCode::UnresolvedOverlayReference,
+                    "Path /soc/i2c@2000 does not exist in the base tree"
+                ),
+                Diagnostic::new(
+                    code.s1("&missing").span(),
+                    overlay_source,
+                    ErrorCode::UnresolvedOverlayReference,
+                    "Label 'missing' is not defined in the base tree"
+                ),
+            ]
+        );
+        assert_eq!(project.get_diagnostics(&base).next(), None);
+
+        // References can be followed into the base tree
+        let ItemAtCursor::Reference(reference) = project
+            .find_at_pos(&overlay, &code.s1("&clk").start())
+            .expect("Found no item")
+        else {
+            panic!("Found non-reference at cursor")
+        };
+        let (span, path) = project
+            .get_node_position(&overlay, reference)
+            .expect("Reference does not reference a node");
+        assert_eq!(span, base_code.s1("clock").span());
+        assert_eq!(path.to_path_buf(), dunce::canonicalize(&base).unwrap());
+        assert_eq!(
+            project.document_reference(&overlay, reference),
+            Some("Node clock".to_string())
+        );
+
+        // Without base, the overlay is checked on its own again
+        project.clear_overlay_bases();
+        project.assert_no_diagnostics();
+    }
+
+    #[test]
+    pub fn documents_references_and_labels() {
+        let mut project = Project::default();
+        let temp_dir = TempDir::new();
+        let (_, file) = temp_dir.add_file(
+            "tests-file.dts",
+            r#"
+/dts-v1/;
+
+/ {
+    /**
+     * The UART
+     */
+    uart: serial@0 {
+        /** Baud rate */
+        baud: current-speed = <115200>;
+    };
+    plain: node {};
+};
+"#,
+        );
+        project
+            .add_file(file.clone().into_os_string().into_string().unwrap())
+            .expect("Cannot add file");
+        project.assert_no_diagnostics();
+
+        let reference = ast::Reference::Label("uart".to_owned());
+        assert_eq!(
+            project.document_reference(&file, &reference),
+            Some("The UART".to_string())
+        );
+        let reference = ast::Reference::Label("plain".to_owned());
+        assert_eq!(
+            project.document_reference(&file, &reference),
+            Some("Node node".to_string())
+        );
+        assert_eq!(
+            project.document_label(&file, "uart"),
+            Some("The UART".to_string())
+        );
+        assert_eq!(
+            project.document_label(&file, "baud"),
+            Some("Baud rate".to_string())
+        );
+        assert_eq!(project.document_label(&file, "plain"), None);
+    }
 }
@@ -0,0 +1,327 @@
+use crate::dts::ast::{Cell, Path, PropertyValue};
+use std::fmt::{Display, Formatter};
+
+/// The type of the value of a property, as described in section 2.2.4 of the
+/// Devicetree Specification.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PropertyType {
+    /// No value, e.g. `interrupt-controller;`
+    Empty,
+    /// A single 32-bit integer, e.g. `<2>`
+    U32,
+    /// A 64-bit integer, written as `/bits/ 64 <value>` or as two 32-bit cells
+    U64,
+    /// A single string, e.g. `"okay"`.
+    /// References to nodes are strings as well, because they are replaced with the node's path.
+    String,
+    /// One or more strings, e.g. `"vendor,board", "vendor,soc"`
+    StringList,
+    /// Arrays of 32-bit integers whose meaning is defined by the property, e.g. `reg`
+    PropEncodedArray,
+    /// A reference to a single node, e.g. `<&intc>`
+    Phandle,
+    /// A list of references, each followed by its arguments, e.g. `<&gpio 1 0>, <&gpio 2 0>`
+    PhandleArgs,
+}
+
+impl PropertyType {
+    /// Whether the values of a property can be interpreted as this type.
+    /// Labels in between values are ignored and included binary files match any type except `Empty`.
+    pub fn accepts(&self, values: &[PropertyValue]) -> bool {
+        let values: Vec<&PropertyValue> = values
+            .iter()
+            .filter(|value| !matches!(value, PropertyValue::Label(_)))
+            .collect();
+        if *self != PropertyType::Empty
+            && values
+                .iter()
+                .any(|value| matches!(value, PropertyValue::Incbin(_)))
+        {
+            return true;
+        }
+        match self {
+            PropertyType::Empty => values.is_empty(),
+            PropertyType::U32 => matches!(
+                values.as_slice(),
+                [value] if value.cell_width() == Some(32)
+                    && matches!(cells(value).as_slice(), [cell] if cell.value().is_some())
+            ),
+            PropertyType::U64 => match values.as_slice() {
+                [value] => match value.cell_width() {
+                    Some(64) => cells(value).len() == 1,
+                    Some(32) => cells(value).len() == 2,
+                    _ => false,
+                },
+                _ => false,
+            },
+            PropertyType::String => matches!(values.as_slice(), [value] if is_string(value)),
+            PropertyType::StringList => {
+                !values.is_empty() && values.iter().all(|value| is_string(value))
+            }
+            PropertyType::PropEncodedArray | PropertyType::PhandleArgs => {
+                !values.is_empty() && values.iter().all(|value| value.cell_width() == Some(32))
+            }
+            PropertyType::Phandle => matches!(
+                values.as_slice(),
+                [value] if value.cell_width() == Some(32) && cells(value).len() == 1
+            ),
+        }
+    }
+}
+
+impl Display for PropertyType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PropertyType::Empty => write!(f, "empty"),
+            PropertyType::U32 => write!(f, "a single 32-bit integer"),
+            PropertyType::U64 => write!(f, "a 64-bit integer"),
+            PropertyType::String => write!(f, "a single string"),
+            PropertyType::StringList => write!(f, "a list of strings"),
+            PropertyType::PropEncodedArray => write!(f, "an array of cells"),
+            PropertyType::Phandle => write!(f, "a single phandle"),
+            PropertyType::PhandleArgs => write!(f, "a list of phandles with arguments"),
+        }
+    }
+}
+
+fn is_string(value: &PropertyValue) -> bool {
+    matches!(
+        value,
+        PropertyValue::String(_) | PropertyValue::Reference(_)
+    )
+}
+
+// The cells of an array, without labels
+fn cells(value: &PropertyValue) -> Vec<&Cell> {
+    match value {
+        PropertyValue::Cells(_, _, cells, _) => cells
+            .iter()
+            .filter(|cell| !matches!(cell, Cell::Label(_)))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// A property whose meaning is defined by the Devicetree Specification
+#[derive(Debug)]
+pub struct StandardProperty {
+    pub name: &'static str,
+    /// The types that the value may have
+    pub types: &'static [PropertyType],
+    /// The values that a string property may have. Any value is allowed if this is empty.
+    /// A trailing `-sss` stands for any condition code, as in `fail-sss`.
+    pub values: &'static [&'static str],
+    /// The path of the node whose children have this property, e.g. `/reserved-memory`.
+    /// In other nodes, the name is free to be used for other purposes.
+    /// The property applies to all nodes if this is `None`.
+    pub parent: Option<&'static str>,
+}
+
+impl StandardProperty {
+    const fn new(name: &'static str, types: &'static [PropertyType]) -> StandardProperty {
+        StandardProperty {
+            name,
+            types,
+            values: &[],
+            parent: None,
+        }
+    }
+
+    const fn with_values(
+        name: &'static str,
+        types: &'static [PropertyType],
+        values: &'static [&'static str],
+    ) -> StandardProperty {
+        StandardProperty {
+            name,
+            types,
+            values,
+            parent: None,
+        }
+    }
+
+    const fn in_children_of(
+        parent: &'static str,
+        name: &'static str,
+        types: &'static [PropertyType],
+    ) -> StandardProperty {
+        StandardProperty {
+            name,
+            types,
+            values: &[],
+            parent: Some(parent),
+        }
+    }
+
+    /// Returns the standard property with the given name.
+    /// Besides the properties of the table, all `#<specifier>-cells` properties are 32-bit integers.
+    pub fn get(name: &str) -> Option<&'static StandardProperty> {
+        static CELLS: StandardProperty = StandardProperty::new("#<specifier>-cells", &[U32]);
+        STANDARD_PROPERTIES
+            .iter()
+            .find(|property| property.name == name)
+            .or_else(|| {
+                (name.starts_with('#') && name.ends_with("-cells") && name.len() > "#-cells".len())
+                    .then_some(&CELLS)
+            })
+    }
+
+    /// Whether this property is a standard property of the node at `path`
+    pub fn applies_to(&self, path: &Path) -> bool {
+        self.parent.is_none_or(|parent| {
+            path.split_last()
+                .is_some_and(|(parent_path, _)| parent_path.to_string() == parent)
+        })
+    }
+
+    /// Whether a string is one of the allowed values of this property
+    pub fn allows(&self, value: &str) -> bool {
+        self.values.is_empty()
+            || self.values.iter().any(|allowed| {
+                allowed == &value
+                    || allowed.strip_suffix("sss").is_some_and(|prefix| {
+                        value.len() > prefix.len() && value.starts_with(prefix)
+                    })
+            })
+    }
+}
+
+use PropertyType::*;
+
+// Chapters 2.3, 2.4 and 3 of the Devicetree Specification, v0.4.
+// `compatible` and `phandle` are missing because the analysis checks them separately.
+static STANDARD_PROPERTIES: &[StandardProperty] = &[
+    // Standard properties
+    StandardProperty::new("model", &[String]),
+    StandardProperty::with_values(
+        "status",
+        &[String],
+        &["okay", "disabled", "reserved", "fail", "fail-sss"],
+    ),
+    StandardProperty::new("#address-cells", &[U32]),
+    StandardProperty::new("#size-cells", &[U32]),
+    StandardProperty::new("reg", &[PropEncodedArray]),
+    StandardProperty::new("virtual-reg", &[U32]),
+    StandardProperty::new("ranges", &[Empty, PropEncodedArray]),
+    StandardProperty::new("dma-ranges", &[Empty, PropEncodedArray]),
+    StandardProperty::new("dma-coherent", &[Empty]),
+    StandardProperty::new("dma-noncoherent", &[Empty]),
+    StandardProperty::new("name", &[String]),
+    StandardProperty::new("device_type", &[String]),
+    // Interrupts
+    StandardProperty::new("interrupts", &[PropEncodedArray]),
+    StandardProperty::new("interrupt-parent", &[Phandle]),
+    StandardProperty::new("interrupts-extended", &[PhandleArgs]),
+    StandardProperty::new("interrupt-controller", &[Empty]),
+    StandardProperty::new("interrupt-map", &[PropEncodedArray]),
+    StandardProperty::new("interrupt-map-mask", &[PropEncodedArray]),
+    // Root, /chosen and /memory nodes
+    StandardProperty::new("serial-number", &[String]),
+    StandardProperty::with_values(
+        "chassis-type",
+        &[String],
+        &[
+            "desktop",
+            "laptop",
+            "convertible",
+            "server",
+            "tablet",
+            "handset",
+            "watch",
+            "embedded",
+        ],
+    ),
+    StandardProperty::new("bootargs", &[String]),
+    StandardProperty::new("stdout-path", &[String]),
+    StandardProperty::new("stdin-path", &[String]),
+    StandardProperty::new("initial-mapped-area", &[PropEncodedArray]),
+    StandardProperty::new("hotpluggable", &[Empty]),
+    // Reserved memory
+    StandardProperty::in_children_of("/reserved-memory", "size", &[PropEncodedArray]),
+    StandardProperty::in_children_of("/reserved-memory", "alignment", &[PropEncodedArray]),
+    StandardProperty::in_children_of("/reserved-memory", "alloc-ranges", &[PropEncodedArray]),
+    StandardProperty::in_children_of("/reserved-memory", "no-map", &[Empty]),
+    StandardProperty::in_children_of("/reserved-memory", "reusable", &[Empty]),
+    StandardProperty::new("memory-region", &[PhandleArgs]),
+    StandardProperty::new("memory-region-names", &[StringList]),
+    // CPUs and caches
+    StandardProperty::new("clock-frequency", &[U32, U64]),
+    StandardProperty::new("timebase-frequency", &[U32, U64]),
+    StandardProperty::new("enable-method", &[StringList]),
+    StandardProperty::new("cpu-release-addr", &[U64]),
+    StandardProperty::new("power-isa-version", &[String]),
+    StandardProperty::new("mmu-type", &[String]),
+    StandardProperty::new("tlb-split", &[Empty]),
+    StandardProperty::new("tlb-size", &[U32]),
+    StandardProperty::new("tlb-sets", &[U32]),
+    StandardProperty::new("cache-unified", &[Empty]),
+    StandardProperty::new("cache-size", &[U32]),
+    StandardProperty::new("cache-sets", &[U32]),
+    StandardProperty::new("cache-block-size", &[U32]),
+    StandardProperty::new("cache-line-size", &[U32]),
+    StandardProperty::new("cache-level", &[U32]),
+    StandardProperty::new("next-level-cache", &[Phandle]),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::property_type::{PropertyType, StandardProperty};
+    use crate::dts::test::Code;
+    use crate::dts::{NodeItem, Parser};
+
+    fn accepts(property_type: PropertyType, value: &str) -> bool {
+        let code = Code::new(&format!("{{ prop {value}; }};"));
+        let payload = code.parse_ok_no_diagnostics(Parser::node_payload);
+        let [NodeItem::Property(property)] = payload.items.as_slice() else {
+            panic!("Expected a single property");
+        };
+        property_type.accepts(&property.values)
+    }
+
+    #[test]
+    fn matches_values_with_types() {
+        assert!(accepts(PropertyType::Empty, ""));
+        assert!(!accepts(PropertyType::Empty, "= <>"));
+        assert!(accepts(PropertyType::U32, "= <1>"));
+        assert!(accepts(PropertyType::U32, "= start: <end: (1 + 1)>"));
+        assert!(!accepts(PropertyType::U32, "= <1 2>"));
+        assert!(!accepts(PropertyType::U32, "= /bits/ 64 <1>"));
+        assert!(!accepts(PropertyType::U32, "= \"2\""));
+        assert!(accepts(PropertyType::U64, "= /bits/ 64 <1>"));
+        assert!(accepts(PropertyType::U64, "= <0 1>"));
+        assert!(!accepts(PropertyType::U64, "= <1>"));
+        assert!(accepts(PropertyType::String, "= \"okay\""));
+        assert!(accepts(PropertyType::String, "= &uart0"));
+        assert!(!accepts(PropertyType::String, "= \"a\", \"b\""));
+        assert!(accepts(PropertyType::StringList, "= \"a\", \"b\""));
+        assert!(!accepts(PropertyType::StringList, "= \"a\", <1>"));
+        assert!(accepts(PropertyType::PropEncodedArray, "= <1 2>, <3 4>"));
+        assert!(!accepts(PropertyType::PropEncodedArray, "= [01 02]"));
+        assert!(accepts(PropertyType::Phandle, "= <&intc>"));
+        assert!(!accepts(PropertyType::Phandle, "= <&intc 1>"));
+        assert!(accepts(
+            PropertyType::PhandleArgs,
+            "= <&gpio 1 0>, <&gpio 2 0>"
+        ));
+    }
+
+    #[test]
+    fn looks_up_standard_properties() {
+        assert_eq!(
+            StandardProperty::get("reg").unwrap().types,
+            &[PropertyType::PropEncodedArray]
+        );
+        assert_eq!(
+            StandardProperty::get("#gpio-cells").unwrap().types,
+            &[PropertyType::U32]
+        );
+        assert!(StandardProperty::get("#-cells").is_none());
+        assert!(StandardProperty::get("vendor,custom").is_none());
+
+        let status = StandardProperty::get("status").unwrap();
+        assert!(status.allows("okay"));
+        assert!(status.allows("fail-overheated"));
+        assert!(!status.allows("fail-"));
+        assert!(!status.allows("ok"));
+    }
+}
@@ -7,6 +7,10 @@ pub trait Reader {
 
     fn peek(&self) -> Option<u8>;
 
+    /// Returns the character `offset` characters after the next character without consuming it.
+    /// `peek_nth(0)` is equivalent to `peek()`.
+    fn peek_nth(&self, offset: usize) -> Option<u8>;
+
     fn pos(&self) -> Position;
 
     fn skip(&mut self) {
@@ -61,6 +65,10 @@ impl Reader for ByteReader {
         self.data.get(self.char_pos).copied()
     }
 
+    fn peek_nth(&self, offset: usize) -> Option<u8> {
+        self.data.get(self.char_pos + offset).copied()
+    }
+
     fn pos(&self) -> Position {
         self.pos
     }
@@ -0,0 +1,894 @@
+//! Validation of device trees against devicetree binding schemas.
+//!
+//! Bindings are JSON schemas written in YAML, as found in the `Documentation/devicetree/bindings`
+//! directory of the Linux kernel. A binding applies to all nodes whose `compatible` property
+//! contains one of the compatible strings that the binding describes.
+//!
+//! Only a subset of JSON schema is supported, namely the keywords that bindings use to describe
+//! required properties, property types, allowed values, the number of entries of arrays,
+//! lists of strings (e.g., `clock-names`) and child nodes. References to other schemas, except for the property types of
+//! `/schemas/types.yaml`, are not followed.
+
+use crate::dts::data::{HasSource, HasSpan, Location};
+use crate::dts::error_codes::ErrorCode;
+use crate::dts::tree::{DeviceTree, TreeNode, TreeProperty, TypedValue};
+use crate::dts::Diagnostic;
+use itertools::Itertools;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::{fs, io};
+use yaml_rust2::{Yaml, YamlLoader};
+
+const TYPES_PREFIX: &str = "/schemas/types.yaml#/definitions/";
+
+/// A binding schema together with the compatible strings that select it
+#[derive(Clone, Debug)]
+struct Binding {
+    // The path of the schema, relative to the directory that it was loaded from
+    name: String,
+    schema: Yaml,
+    patterns: Patterns,
+}
+
+/// The regular expressions of a schema, compiled once when the schema is added.
+/// Invalid expressions are left out.
+#[derive(Clone, Debug, Default)]
+struct Patterns(HashMap<String, Regex>);
+
+impl Patterns {
+    // Compiles the values of `pattern` and the keys of `patternProperties`
+    fn collect(&mut self, schema: &Yaml) {
+        match schema {
+            Yaml::Hash(hash) => {
+                for (key, value) in hash {
+                    match (key.as_str(), value) {
+                        (Some("pattern"), Yaml::String(pattern)) => self.add(pattern),
+                        (Some("patternProperties"), Yaml::Hash(properties)) => properties
+                            .keys()
+                            .filter_map(Yaml::as_str)
+                            .for_each(|pattern| self.add(pattern)),
+                        _ => {}
+                    }
+                    self.collect(value);
+                }
+            }
+            Yaml::Array(items) => items.iter().for_each(|item| self.collect(item)),
+            _ => {}
+        }
+    }
+
+    fn add(&mut self, pattern: &str) {
+        if !self.0.contains_key(pattern) {
+            if let Ok(regex) = Regex::new(pattern) {
+                self.0.insert(pattern.to_string(), regex);
+            }
+        }
+    }
+
+    fn get(&self, pattern: &str) -> Option<&Regex> {
+        self.0.get(pattern)
+    }
+}
+
+/// A set of binding schemas, indexed by the compatible strings that they describe
+#[derive(Clone, Debug, Default)]
+pub struct Bindings {
+    bindings: Vec<Binding>,
+    by_compatible: HashMap<String, Vec<usize>>,
+}
+
+impl Bindings {
+    /// Loads all binding schemas (files ending with `.yaml`) in a directory and its subdirectories.
+    /// Files that are not valid YAML or that do not select nodes by their compatible strings
+    /// are skipped.
+    pub fn load(&mut self, directory: &Path) -> Result<(), io::Error> {
+        self.load_directory(directory, directory)
+    }
+
+    fn load_directory(&mut self, root: &Path, directory: &Path) -> Result<(), io::Error> {
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.load_directory(root, &path)?;
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "yaml")
+            {
+                let Ok(text) = fs::read_to_string(&path) else {
+                    continue;
+                };
+                let name = path.strip_prefix(root).unwrap_or(&path);
+                self.add(&name.to_string_lossy(), &text);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a binding schema with the given name.
+    /// Returns whether the text is a schema that selects nodes by their compatible strings.
+    pub fn add(&mut self, name: &str, text: &str) -> bool {
+        let Some(schema) = YamlLoader::load_from_str(text)
+            .ok()
+            .and_then(|documents| documents.into_iter().next())
+        else {
+            return false;
+        };
+        let mut compatibles = vec![];
+        match &schema["select"] {
+            Yaml::Boolean(false) => {}
+            select @ Yaml::Hash(_) => {
+                collect_strings(&select["properties"]["compatible"], &mut compatibles)
+            }
+            _ => collect_strings(&schema["properties"]["compatible"], &mut compatibles),
+        }
+        if compatibles.is_empty() {
+            return false;
+        }
+        let index = self.bindings.len();
+        for compatible in compatibles.into_iter().unique() {
+            self.by_compatible
+                .entry(compatible)
+                .or_default()
+                .push(index);
+        }
+        let mut patterns = Patterns::default();
+        patterns.collect(&schema);
+        self.bindings.push(Binding {
+            name: name.to_string(),
+            schema,
+            patterns,
+        });
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+
+    /// Validates all nodes of a tree against the bindings that their compatible strings select.
+    /// Diagnostics point to the definitions of the offending properties and nodes.
+    pub fn validate(&self, tree: &DeviceTree) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        tree.walk(&mut |_, node| {
+            let Some(compatible) = node.property("compatible") else {
+                return;
+            };
+            let bindings = strings(compatible)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|compatible| self.by_compatible.get(compatible))
+                .flatten()
+                .unique()
+                .map(|index| &self.bindings[*index])
+                .collect_vec();
+            for binding in bindings {
+                let mut validator = Validator {
+                    binding,
+                    diagnostics: vec![],
+                };
+                validator.node(node, &binding.schema);
+                diagnostics.extend(validator.diagnostics);
+            }
+        });
+        diagnostics
+    }
+}
+
+// Collects the strings that a schema for a string list accepts,
+// e.g., the compatible strings that a binding describes
+fn collect_strings(schema: &Yaml, strings: &mut Vec<String>) {
+    if !schema.is_hash() {
+        return;
+    }
+    if let Some(string) = schema["const"].as_str() {
+        strings.push(string.to_string());
+    }
+    for keyword in ["enum", "oneOf", "anyOf", "allOf"] {
+        for item in schema[keyword].as_vec().into_iter().flatten() {
+            match item {
+                Yaml::String(string) => strings.push(string.clone()),
+                _ => collect_strings(item, strings),
+            }
+        }
+    }
+    match &schema["items"] {
+        Yaml::Array(items) => items.iter().for_each(|item| collect_strings(item, strings)),
+        items => collect_strings(items, strings),
+    }
+    collect_strings(&schema["contains"], strings);
+}
+
+// The value of a property as a list of strings
+fn strings(property: &TreeProperty) -> Option<Vec<String>> {
+    property
+        .data
+        .typed_values()
+        .into_iter()
+        .map(|value| match value {
+            TypedValue::String(string, _) => Some(String::from_utf8_lossy(string).into_owned()),
+            TypedValue::Integers(..) => None,
+        })
+        .collect()
+}
+
+// One string of a string list or one integer of an array
+#[derive(Clone, Debug)]
+enum Entry {
+    String(String),
+    Integer(u64),
+}
+
+impl Entry {
+    fn entries(property: &TreeProperty) -> Option<Vec<Entry>> {
+        if let Some(strings) = strings(property) {
+            return Some(strings.into_iter().map(Entry::String).collect());
+        }
+        let mut entries = vec![];
+        for value in property.data.typed_values() {
+            let TypedValue::Integers(_, integers) = value else {
+                return None;
+            };
+            entries.extend(
+                integers
+                    .into_iter()
+                    .map(|(integer, _)| Entry::Integer(integer)),
+            );
+        }
+        Some(entries)
+    }
+
+    fn equals(&self, value: &Yaml) -> bool {
+        match (self, value) {
+            (Entry::String(string), Yaml::String(other)) => string == other,
+            (Entry::Integer(integer), Yaml::Integer(other)) => *other as u64 == *integer,
+            _ => false,
+        }
+    }
+
+    // Checks this entry against `const`, `enum`, `pattern`, `minimum` and `maximum`
+    fn matches(&self, schema: &Yaml, patterns: &Patterns) -> bool {
+        match schema {
+            Yaml::Boolean(valid) => return *valid,
+            Yaml::Hash(_) => {}
+            _ => return true,
+        }
+        if !matches!(schema["const"], Yaml::BadValue) && !self.equals(&schema["const"]) {
+            return false;
+        }
+        if let Some(values) = schema["enum"].as_vec() {
+            if !values.iter().any(|value| self.equals(value)) {
+                return false;
+            }
+        }
+        match self {
+            Entry::String(string) => {
+                if let Some(pattern) = schema["pattern"].as_str() {
+                    if patterns
+                        .get(pattern)
+                        .is_some_and(|regex| !regex.is_match(string))
+                    {
+                        return false;
+                    }
+                }
+            }
+            Entry::Integer(integer) => {
+                if let Some(minimum) = schema["minimum"].as_i64() {
+                    if (*integer as i64) < minimum {
+                        return false;
+                    }
+                }
+                if let Some(maximum) = schema["maximum"].as_i64() {
+                    if (*integer as i64) > maximum {
+                        return false;
+                    }
+                }
+            }
+        }
+        for keyword in ["oneOf", "anyOf"] {
+            if let Some(alternatives) = schema[keyword].as_vec() {
+                if !alternatives
+                    .iter()
+                    .any(|alternative| self.matches(alternative, patterns))
+                {
+                    return false;
+                }
+            }
+        }
+        schema["allOf"]
+            .as_vec()
+            .into_iter()
+            .flatten()
+            .all(|item| self.matches(item, patterns))
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Entry::String(string) => write!(f, "\"{string}\""),
+            Entry::Integer(integer) => write!(f, "{integer:#x}"),
+        }
+    }
+}
+
+// Checks whether the value of a property has one of the types of `/schemas/types.yaml`.
+// Unknown types are always valid.
+fn has_type(property: &TreeProperty, type_name: &str) -> bool {
+    let values = property.data.typed_values();
+    // The number of integers if all values are integers of the given width
+    let integers = |bits: u32| {
+        values
+            .iter()
+            .map(|value| match value {
+                TypedValue::Integers(width, integers) if *width == bits => Some(integers.len()),
+                _ => None,
+            })
+            .sum::<Option<usize>>()
+            .filter(|count| *count > 0)
+    };
+    let (base, is_array) = match type_name
+        .strip_suffix("-array")
+        .or_else(|| type_name.strip_suffix("-matrix"))
+    {
+        Some(base) => (base, true),
+        None => (type_name, false),
+    };
+    let bits = match base {
+        "flag" => return property.data.bytes.is_empty(),
+        "string" | "non-unique-string" => {
+            return strings(property)
+                .is_some_and(|strings| !strings.is_empty() && (is_array || strings.len() == 1))
+        }
+        "uint8" | "int8" => 8,
+        "uint16" | "int16" => 16,
+        "uint32" | "int32" | "phandle" => 32,
+        // 64-bit values may also be written as two cells
+        "uint64" | "int64" if !is_array && integers(32) == Some(2) => return true,
+        "uint64" | "int64" => 64,
+        _ => return true,
+    };
+    integers(bits).is_some_and(|count| is_array || count == 1)
+}
+
+// Validates the nodes and properties that a binding applies to
+struct Validator<'a> {
+    binding: &'a Binding,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn report(&mut self, location: &Option<Location>, code: ErrorCode, message: String) {
+        // Generated nodes and properties cannot be pointed to
+        if let Some(location) = location {
+            self.diagnostics.push(Diagnostic::new(
+                location.span(),
+                location.source(),
+                code,
+                format!("{message} ({})", self.binding.name),
+            ));
+        }
+    }
+
+    // Checks whether validating with `validate` reports no diagnostics
+    fn is_valid(&self, validate: impl FnOnce(&mut Validator<'_>)) -> bool {
+        let mut validator = Validator {
+            binding: self.binding,
+            diagnostics: vec![],
+        };
+        validate(&mut validator);
+        validator.diagnostics.is_empty()
+    }
+
+    fn node(&mut self, node: &TreeNode, schema: &Yaml) {
+        if !schema.is_hash() {
+            return;
+        }
+        for name in schema["required"].as_vec().into_iter().flatten() {
+            let Some(name) = name.as_str() else {
+                continue;
+            };
+            if node.property(name).is_none() && child(node, name).is_none() {
+                self.report(
+                    &node.location,
+                    ErrorCode::MissingRequiredProperty,
+                    format!("Missing required property '{name}'"),
+                );
+            }
+        }
+        let properties = schema["properties"].as_hash();
+        for (name, property_schema) in properties.into_iter().flatten() {
+            let Some(name) = name.as_str() else {
+                continue;
+            };
+            if let Some(property) = node.property(name) {
+                self.property(property, property_schema);
+            } else if let Some(child) = child(node, name) {
+                self.child(child, property_schema);
+            }
+        }
+        let is_listed = |name: &str| {
+            properties
+                .is_some_and(|properties| properties.contains_key(&Yaml::String(name.to_string())))
+        };
+        for (pattern, pattern_schema) in schema["patternProperties"].as_hash().into_iter().flatten()
+        {
+            let Some(regex) = pattern
+                .as_str()
+                .and_then(|pattern| self.binding.patterns.get(pattern))
+            else {
+                continue;
+            };
+            for property in &node.properties {
+                if !is_listed(&property.name) && regex.is_match(&property.name) {
+                    self.property(property, pattern_schema);
+                }
+            }
+            for child in &node.children {
+                let name = child.name.to_string();
+                if !is_listed(&name) && regex.is_match(&name) {
+                    self.child(child, pattern_schema);
+                }
+            }
+        }
+        for item in schema["allOf"].as_vec().into_iter().flatten() {
+            self.node(node, item);
+        }
+        if !matches!(schema["if"], Yaml::BadValue) {
+            let branch = if self.is_valid(|validator| validator.node(node, &schema["if"])) {
+                &schema["then"]
+            } else {
+                &schema["else"]
+            };
+            self.node(node, branch);
+        }
+    }
+
+    fn child(&mut self, child: &TreeNode, schema: &Yaml) {
+        if let Yaml::Boolean(false) = schema {
+            self.report(
+                &child.location,
+                ErrorCode::UnexpectedNode,
+                format!("Node '{}' is not allowed", child.name),
+            );
+        }
+        self.node(child, schema)
+    }
+
+    fn property(&mut self, property: &TreeProperty, schema: &Yaml) {
+        let name = &property.name;
+        match schema {
+            Yaml::Boolean(false) => {
+                return self.report(
+                    &property.location,
+                    ErrorCode::InvalidPropertyValue,
+                    format!("Property '{name}' is not allowed"),
+                )
+            }
+            Yaml::Hash(_) => {}
+            _ => return,
+        }
+        let type_name = match (schema["$ref"].as_str(), schema["type"].as_str()) {
+            (Some(reference), _) => reference.strip_prefix(TYPES_PREFIX),
+            (None, Some("boolean")) => Some("flag"),
+            _ => None,
+        };
+        if let Some(type_name) = type_name {
+            if !has_type(property, type_name) {
+                self.report(
+                    &property.location,
+                    ErrorCode::UnexpectedPropertyType,
+                    format!("Property '{name}' should be of type {type_name}"),
+                );
+                // Values of the wrong type cannot be checked
+                return;
+            }
+        }
+        for item in schema["allOf"].as_vec().into_iter().flatten() {
+            self.property(property, item);
+        }
+        for keyword in ["oneOf", "anyOf"] {
+            let Some(alternatives) = schema[keyword].as_vec() else {
+                continue;
+            };
+            let is_valid = alternatives.iter().any(|alternative| {
+                self.is_valid(|validator| validator.property(property, alternative))
+            });
+            if !is_valid {
+                self.report(
+                    &property.location,
+                    ErrorCode::InvalidPropertyValue,
+                    format!("Property '{name}' does not match any of the allowed values"),
+                );
+            }
+        }
+        let Some(entries) = Entry::entries(property) else {
+            return;
+        };
+        // `const` and `enum` describe a single value,
+        // `pattern`, `minimum` and `maximum` apply to every entry
+        let is_single = !matches!(schema["const"], Yaml::BadValue) || schema["enum"].is_array();
+        let is_bounded = ["pattern", "minimum", "maximum"]
+            .iter()
+            .any(|keyword| !matches!(schema[*keyword], Yaml::BadValue));
+        if is_single && entries.len() != 1 {
+            self.report(
+                &property.location,
+                ErrorCode::InvalidPropertyValue,
+                format!("Property '{name}' should have a single value"),
+            );
+        } else if is_single || is_bounded {
+            let patterns = &self.binding.patterns;
+            if let Some(entry) = entries
+                .iter()
+                .find(|entry| !entry.matches(schema, patterns))
+            {
+                self.report(
+                    &property.location,
+                    ErrorCode::InvalidPropertyValue,
+                    format!("{entry} is not an allowed value of '{name}'"),
+                );
+            }
+        }
+        match entries.first() {
+            Some(Entry::String(_)) => self.string_list(property, &entries, schema),
+            Some(Entry::Integer(_)) => self.integer_array(property, schema),
+            None => {}
+        }
+    }
+
+    // The items of integer arrays are single cells for the `*-array` types.
+    // Otherwise, as for `reg` or `interrupts`, each `<...>` is one item,
+    // because the number of cells of an item depends on the node.
+    fn integer_array(&mut self, property: &TreeProperty, schema: &Yaml) {
+        let values = property.data.typed_values();
+        let count = if schema["$ref"]
+            .as_str()
+            .is_some_and(|reference| reference.ends_with("-array"))
+        {
+            values
+                .iter()
+                .map(|value| match value {
+                    TypedValue::Integers(_, integers) => integers.len(),
+                    TypedValue::String(..) => 0,
+                })
+                .sum()
+        } else {
+            values.len()
+        };
+        self.item_count(property, count, schema);
+    }
+
+    fn string_list(&mut self, property: &TreeProperty, entries: &[Entry], schema: &Yaml) {
+        let name = &property.name;
+        self.item_count(property, entries.len(), schema);
+        let patterns = &self.binding.patterns;
+        let mismatch = match &schema["items"] {
+            Yaml::Array(items) => entries
+                .iter()
+                .zip(items)
+                .find(|(entry, item)| !entry.matches(item, patterns)),
+            Yaml::Hash(_) => entries
+                .iter()
+                .map(|entry| (entry, &schema["items"]))
+                .find(|(entry, item)| !entry.matches(item, patterns)),
+            _ => None,
+        };
+        if let Some((entry, _)) = mismatch {
+            self.report(
+                &property.location,
+                ErrorCode::InvalidPropertyValue,
+                format!("{entry} is not an allowed value of '{name}'"),
+            );
+        }
+        if schema["contains"].is_hash()
+            && !entries
+                .iter()
+                .any(|entry| entry.matches(&schema["contains"], patterns))
+        {
+            self.report(
+                &property.location,
+                ErrorCode::InvalidPropertyValue,
+                format!("Property '{name}' does not contain any of the required values"),
+            );
+        }
+    }
+
+    // Checks the number of items against `minItems` and `maxItems`
+    fn item_count(&mut self, property: &TreeProperty, count: usize, schema: &Yaml) {
+        let name = &property.name;
+        let (min_items, max_items) = match &schema["items"] {
+            // Unless specified otherwise, a list of items describes all entries
+            Yaml::Array(items) => (
+                schema["minItems"].as_i64().unwrap_or(items.len() as i64),
+                schema["maxItems"].as_i64().unwrap_or(items.len() as i64),
+            ),
+            _ => (
+                schema["minItems"].as_i64().unwrap_or(0),
+                schema["maxItems"].as_i64().unwrap_or(i64::MAX),
+            ),
+        };
+        let count = count as i64;
+        if count < min_items {
+            self.report(
+                &property.location,
+                ErrorCode::InvalidPropertyValue,
+                format!(
+                    "Property '{name}' should have at least {min_items} entries, found {count}"
+                ),
+            );
+        } else if count > max_items {
+            self.report(
+                &property.location,
+                ErrorCode::InvalidPropertyValue,
+                format!("Property '{name}' should have at most {max_items} entries, found {count}"),
+            );
+        }
+    }
+}
+
+// Child nodes are named with or without their unit address
+fn child<'a>(node: &'a TreeNode, name: &str) -> Option<&'a TreeNode> {
+    node.children
+        .iter()
+        .find(|child| child.name.name == name || child.name.to_string() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::data::{HasSource, HasSpan};
+    use crate::dts::error_codes::ErrorCode;
+    use crate::dts::test::Code;
+    use crate::dts::{Bindings, Diagnostic, Project};
+    use assert_unordered::assert_eq_unordered;
+
+    const UART: &str = r#"
+%YAML 1.2
+---
+$id: http://devicetree.org/schemas/serial/vendor,uart.yaml#
+$schema: http://devicetree.org/meta-schemas/core.yaml#
+
+title: Vendor UART
+
+properties:
+  compatible:
+    oneOf:
+      - const: vendor,uart
+      - items:
+          - enum:
+              - vendor,soc-uart
+              - vendor,other-uart
+          - const: vendor,uart
+  reg:
+    maxItems: 1
+  clock-frequency:
+    $ref: /schemas/types.yaml#/definitions/uint32
+  reg-io-width:
+    $ref: /schemas/types.yaml#/definitions/uint32
+    enum: [1, 4]
+  clock-names:
+    minItems: 1
+    items:
+      - const: baud
+      - const: bus
+  label:
+    $ref: /schemas/types.yaml#/definitions/string
+    pattern: "^uart[0-9]$"
+  vendor,fifo:
+    type: boolean
+  vendor,offsets:
+    $ref: /schemas/types.yaml#/definitions/uint32-array
+    maxItems: 2
+
+patternProperties:
+  "^port@[0-9a-f]+$":
+    type: object
+    properties:
+      speed:
+        $ref: /schemas/types.yaml#/definitions/uint32
+        maximum: 115200
+    required:
+      - speed
+  "^dma@[0-9a-f]+$": false
+
+required:
+  - compatible
+  - reg
+  - clock-frequency
+
+allOf:
+  - if:
+      properties:
+        compatible:
+          contains:
+            const: vendor,soc-uart
+    then:
+      required:
+        - clock-names
+"#;
+
+    fn project() -> Project {
+        let mut project = Project::default();
+        assert!(project.bindings.add("serial/vendor,uart.yaml", UART));
+        project
+    }
+
+    fn diagnostic(code: &Code, code_: ErrorCode, message: &str) -> Diagnostic {
+        Diagnostic::new(
+            code.span(),
+            code.source(),
+            code_,
+            format!("{message} (serial/vendor,uart.yaml)"),
+        )
+    }
+
+    #[test]
+    fn valid_nodes() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    serial@1000 {
+        compatible = \"vendor,soc-uart\", \"vendor,uart\";
+        reg = <0x1000 0x100>;
+        clock-frequency = <48000000>;
+        reg-io-width = <4>;
+        clock-names = \"baud\", \"bus\";
+        label = \"uart0\";
+        vendor,fifo;
+        vendor,offsets = <0x10 0x20>;
+        port@0 {
+            speed = <9600>;
+        };
+    };
+    other {
+        compatible = \"vendor,unknown\";
+    };
+};",
+        );
+        let (diagnostics, _) = code.get_analyzed_file_in(&project());
+        assert_eq!(diagnostics, vec![]);
+    }
+
+    #[test]
+    fn required_properties_and_nodes() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    serial@1000 {
+        compatible = \"vendor,soc-uart\", \"vendor,uart\";
+        port@0 {};
+    };
+};",
+        );
+        let (diagnostics, _) = code.get_analyzed_file_in(&project());
+        let serial = code.s1("serial@1000 {
+        compatible = \"vendor,soc-uart\", \"vendor,uart\";
+        port@0 {};
+    };");
+        let port = code.s1("port@0 {};");
+        assert_eq_unordered!(
+            diagnostics,
+            vec![
+                diagnostic(
+                    &serial,
+                    ErrorCode::MissingRequiredProperty,
+                    "Missing required property 'reg'"
+                ),
+                diagnostic(
+                    &serial,
+                    ErrorCode::MissingRequiredProperty,
+                    "Missing required property 'clock-frequency'"
+                ),
+                diagnostic(
+                    &serial,
+                    ErrorCode::MissingRequiredProperty,
+                    "Missing required property 'clock-names'"
+                ),
+                diagnostic(
+                    &port,
+                    ErrorCode::MissingRequiredProperty,
+                    "Missing required property 'speed'"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn property_values() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    serial@1000 {
+        compatible = \"vendor,uart\";
+        reg = <0x1000 0x100>, <0x2000 0x100>;
+        clock-frequency = \"fast\";
+        reg-io-width = <2>;
+        clock-names = \"baud\", \"uart\", \"bus\";
+        label = \"console\";
+        vendor,fifo = <1>;
+        vendor,offsets = <0x10 0x20 0x30>;
+        port@0 {
+            speed = <230400>;
+        };
+        dma@0 {};
+    };
+};",
+        );
+        let (diagnostics, _) = code.get_analyzed_file_in(&project());
+        assert_eq_unordered!(
+            diagnostics,
+            vec![
+                diagnostic(
+                    &code.s1("reg = <0x1000 0x100>, <0x2000 0x100>;"),
+                    ErrorCode::InvalidPropertyValue,
+                    "Property 'reg' should have at most 1 entries, found 2"
+                ),
+                diagnostic(
+                    &code.s1("clock-frequency = \"fast\";"),
+                    ErrorCode::UnexpectedPropertyType,
+                    "Property 'clock-frequency' should be of type uint32"
+                ),
+                diagnostic(
+                    &code.s1("reg-io-width = <2>;"),
+                    ErrorCode::InvalidPropertyValue,
+                    "0x2 is not an allowed value of 'reg-io-width'"
+                ),
+                diagnostic(
+                    &code.s1("clock-names = \"baud\", \"uart\", \"bus\";"),
+                    ErrorCode::InvalidPropertyValue,
+                    "\"uart\" is not an allowed value of 'clock-names'"
+                ),
+                diagnostic(
+                    &code.s1("clock-names = \"baud\", \"uart\", \"bus\";"),
+                    ErrorCode::InvalidPropertyValue,
+                    "Property 'clock-names' should have at most 2 entries, found 3"
+                ),
+                diagnostic(
+                    &code.s1("label = \"console\";"),
+                    ErrorCode::InvalidPropertyValue,
+                    "\"console\" is not an allowed value of 'label'"
+                ),
+                diagnostic(
+                    &code.s1("vendor,fifo = <1>;"),
+                    ErrorCode::UnexpectedPropertyType,
+                    "Property 'vendor,fifo' should be of type flag"
+                ),
+                diagnostic(
+                    &code.s1("vendor,offsets = <0x10 0x20 0x30>;"),
+                    ErrorCode::InvalidPropertyValue,
+                    "Property 'vendor,offsets' should have at most 2 entries, found 3"
+                ),
+                diagnostic(
+                    &code.s1("speed = <230400>;"),
+                    ErrorCode::InvalidPropertyValue,
+                    "0x38400 is not an allowed value of 'speed'"
+                ),
+                diagnostic(
+                    &code.s1("dma@0 {};"),
+                    ErrorCode::UnexpectedNode,
+                    "Node 'dma@0' is not allowed"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_bindings_from_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("serial")).unwrap();
+        std::fs::write(dir.path().join("serial").join("vendor,uart.yaml"), UART).unwrap();
+        std::fs::write(dir.path().join("invalid.yaml"), "properties: [").unwrap();
+        std::fs::write(dir.path().join("README"), "not a binding").unwrap();
+        let mut bindings = Bindings::default();
+        assert!(bindings.is_empty());
+        bindings.load(dir.path()).unwrap();
+        assert_eq!(bindings.bindings.len(), 1);
+    }
+}
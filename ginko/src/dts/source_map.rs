@@ -0,0 +1,271 @@
+use crate::dts::data::{HasSource, HasSpan, Location, Position, Span};
+use std::path::Path;
+use std::sync::Arc;
+
+/// An invocation of a macro in the original sources.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct MacroExpansion {
+    /// The name of the invoked macro
+    pub name: String,
+    /// The location of the invocation, including the arguments of function-like macros
+    pub invocation: Location,
+    /// The location of the macro name in the definition,
+    /// or `None` if the macro is not defined in a file
+    pub definition: Option<Location>,
+    /// The text that the invocation expands to
+    pub expanded: String,
+}
+
+/// Maps positions in a generated text (for example, the output of the preprocessor)
+/// back to the files and positions that the text originated from.
+///
+/// An empty source map is the identity mapping.
+#[derive(Clone, Default, Debug)]
+pub struct SourceMap {
+    segments: Vec<Segment>,
+    expansions: Vec<MacroExpansion>,
+}
+
+#[derive(Clone, Debug)]
+struct Segment {
+    /// The position in the generated text where this segment starts
+    start: Position,
+    origin: Origin,
+}
+
+#[derive(Clone, Debug)]
+enum Origin {
+    /// Text that was copied verbatim from a file, starting at the given position
+    Verbatim(Arc<Path>, Position),
+    /// Text that was produced by expanding a macro.
+    /// All positions within the segment map to the span of the invocation.
+    Expansion(usize),
+}
+
+impl SourceMap {
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Marks that the text starting at `at` is copied from `source`, starting at `origin`.
+    pub(crate) fn push_verbatim(&mut self, at: Position, source: Arc<Path>, origin: Position) {
+        self.push(at, Origin::Verbatim(source, origin))
+    }
+
+    /// Marks that the text starting at `at` is produced by the expansion `expansion`.
+    pub(crate) fn push_expansion(&mut self, at: Position, expansion: usize) {
+        self.push(at, Origin::Expansion(expansion))
+    }
+
+    fn push(&mut self, at: Position, origin: Origin) {
+        if let Some(last) = self.segments.last_mut() {
+            if last.start == at {
+                last.origin = origin;
+                return;
+            }
+        }
+        self.segments.push(Segment { start: at, origin })
+    }
+
+    /// Registers a new macro invocation and returns its id.
+    pub(crate) fn add_expansion(&mut self, expansion: MacroExpansion) -> usize {
+        self.expansions.push(expansion);
+        self.expansions.len() - 1
+    }
+
+    /// Grows the invocation of an expansion when the invocation consumes more text
+    /// than originally anticipated.
+    pub(crate) fn extend_expansion(&mut self, expansion: usize, end: Position) {
+        let invocation = &mut self.expansions[expansion].invocation;
+        if end > invocation.end() {
+            *invocation = Location::new(invocation.source(), Span::new(invocation.start(), end));
+        }
+    }
+
+    /// Appends text to the expanded text of an expansion.
+    pub(crate) fn push_expanded(&mut self, expansion: usize, text: &str) {
+        self.expansions[expansion].expanded.push_str(text)
+    }
+
+    /// Returns the span of the macro invocation that belongs to an expansion.
+    pub(crate) fn expansion_span(&self, expansion: usize) -> Span {
+        self.expansions[expansion].invocation.span()
+    }
+
+    /// All macro invocations, in the order they appear in the generated text.
+    pub fn expansions(&self) -> impl Iterator<Item = &MacroExpansion> {
+        self.expansions.iter()
+    }
+
+    /// Returns the macro invocation at a position in an original file.
+    pub fn expansion_at(&self, source: &Path, position: &Position) -> Option<&MacroExpansion> {
+        self.expansions
+            .iter()
+            .find(|expansion| expansion.invocation.contains(source, position))
+    }
+
+    /// Returns the macro invocation that a span in an original file is part of.
+    /// Tokens that result from an expansion map to the whole invocation,
+    /// so this can be used to find out whether a token is the result of an expansion.
+    pub fn expansion_containing(&self, source: &Path, span: Span) -> Option<&MacroExpansion> {
+        self.expansions
+            .iter()
+            .find(|expansion| expansion.invocation.contains_span(source, span))
+    }
+
+    /// Returns whether text from `source` starting at `origin` that is placed at `at`
+    /// seamlessly continues the last segment, i.e., no new segment is required.
+    pub(crate) fn continues(&self, at: Position, source: &Arc<Path>, origin: Position) -> bool {
+        match self.segments.last() {
+            Some(Segment {
+                start,
+                origin: Origin::Verbatim(last_source, last_origin),
+            }) => last_source == source && translate(*start, *last_origin, at) == origin,
+            _ => false,
+        }
+    }
+
+    /// Returns the index of the segment that contains `pos`.
+    fn segment_at(&self, pos: Position) -> Option<usize> {
+        self.segments
+            .partition_point(|segment| segment.start <= pos)
+            .checked_sub(1)
+    }
+
+    /// Maps a position in the generated text to the original source and position.
+    /// Returns `None`, if the position cannot be mapped.
+    pub fn map_position(&self, pos: Position) -> Option<(Arc<Path>, Position)> {
+        let segment = &self.segments[self.segment_at(pos)?];
+        match &segment.origin {
+            Origin::Verbatim(source, origin) => {
+                Some((source.clone(), translate(segment.start, *origin, pos)))
+            }
+            Origin::Expansion(expansion) => {
+                let invocation = &self.expansions[*expansion].invocation;
+                if pos == segment.start {
+                    Some((invocation.source(), invocation.start()))
+                } else {
+                    Some((invocation.source(), invocation.end()))
+                }
+            }
+        }
+    }
+
+    /// Maps a span in the generated text to the original source and span.
+    /// Tokens that are the result of a macro expansion map to the span of the macro invocation.
+    /// Returns `None`, if the span cannot be mapped.
+    pub fn map_span(&self, span: Span) -> Option<Location> {
+        let start_idx = self.segment_at(span.start())?;
+        let segment = &self.segments[start_idx];
+        let (source, origin) = match &segment.origin {
+            Origin::Expansion(expansion) => {
+                return Some(self.expansions[*expansion].invocation.clone())
+            }
+            Origin::Verbatim(source, origin) => (source, *origin),
+        };
+        let start = translate(segment.start, origin, span.start());
+        // The end of a span is exclusive; a span ending exactly at the start of
+        // the next segment still belongs to the current segment.
+        let end_idx = self
+            .segments
+            .partition_point(|segment| segment.start < span.end())
+            .saturating_sub(1);
+        let end = if end_idx == start_idx {
+            translate(segment.start, origin, span.end())
+        } else {
+            match self.map_position(span.end()) {
+                Some((end_source, end)) if &end_source == source && end >= start => end,
+                _ => translate(segment.start, origin, span.end()),
+            }
+        };
+        Some(Location::new(source.clone(), Span::new(start, end)))
+    }
+}
+
+/// Translates `pos` in a segment starting at `start` that originates from `origin`.
+/// Lines are copied verbatim, so only positions on the first line are shifted.
+fn translate(start: Position, origin: Position, pos: Position) -> Position {
+    if pos.line() == start.line() {
+        Position::new(
+            origin.line(),
+            origin.character() + pos.character() - start.character(),
+        )
+    } else {
+        Position::new(origin.line() + pos.line() - start.line(), pos.character())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::data::{Location, Position, Span};
+    use crate::dts::source_map::{MacroExpansion, SourceMap};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    fn path(name: &str) -> Arc<Path> {
+        Arc::from(PathBuf::from(name))
+    }
+
+    #[test]
+    fn maps_verbatim_segments() {
+        let mut map = SourceMap::default();
+        map.push_verbatim(Position::zero(), path("main.dts"), Position::zero());
+        map.push_verbatim(Position::new(2, 0), path("inc.dtsi"), Position::new(0, 0));
+        map.push_verbatim(Position::new(4, 0), path("main.dts"), Position::new(3, 0));
+
+        assert_eq!(
+            map.map_position(Position::new(1, 4)),
+            Some((path("main.dts"), Position::new(1, 4)))
+        );
+        assert_eq!(
+            map.map_position(Position::new(3, 2)),
+            Some((path("inc.dtsi"), Position::new(1, 2)))
+        );
+        assert_eq!(
+            map.map_span(Span::new(Position::new(4, 1), Position::new(4, 5))),
+            Some(Location::new(
+                path("main.dts"),
+                Span::new(Position::new(3, 1), Position::new(3, 5))
+            ))
+        );
+    }
+
+    #[test]
+    fn maps_expansions_to_invocation() {
+        let mut map = SourceMap::default();
+        map.push_verbatim(Position::zero(), path("main.dts"), Position::zero());
+        let invocation = Location::new(
+            path("main.dts"),
+            Span::new(Position::new(0, 7), Position::new(0, 10)),
+        );
+        let expansion = map.add_expansion(MacroExpansion {
+            name: "FOO".to_string(),
+            invocation: invocation.clone(),
+            definition: None,
+            expanded: "12345".to_string(),
+        });
+        map.push_expansion(Position::new(0, 7), expansion);
+        map.push_verbatim(Position::new(0, 12), path("main.dts"), Position::new(0, 10));
+
+        assert_eq!(
+            map.map_span(Span::new(Position::new(0, 8), Position::new(0, 9))),
+            Some(invocation)
+        );
+        assert_eq!(
+            map.map_span(Span::new(Position::new(0, 12), Position::new(0, 13))),
+            Some(Location::new(
+                path("main.dts"),
+                Span::new(Position::new(0, 10), Position::new(0, 11))
+            ))
+        );
+        assert_eq!(
+            map.expansion_at(&path("main.dts"), &Position::new(0, 8))
+                .map(|expansion| expansion.name.as_str()),
+            Some("FOO")
+        );
+        assert_eq!(
+            map.expansion_at(&path("main.dts"), &Position::new(0, 11)),
+            None
+        );
+    }
+}
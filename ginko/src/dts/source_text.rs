@@ -0,0 +1,109 @@
+use crate::dts::data::Position;
+use crate::dts::preprocessor::directive_lines;
+
+/// The text of a source file, prepared for lexing.
+///
+/// In source files, lines of preprocessor directives are blanked out so that the lexer
+/// does not see them. This keeps the positions of all other tokens intact,
+/// which allows tools to map the tokens back to the text.
+pub(crate) struct SourceText<'a> {
+    pub text: &'a str,
+    /// The text that the lexer reads
+    pub lexed: String,
+    /// The indices of all lines that belong to directives or line markers, in ascending order
+    pub directives: Vec<usize>,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceText<'a> {
+    pub fn new(text: &'a str) -> SourceText<'a> {
+        let directives = directive_lines(text.as_bytes());
+        let lexed = text
+            .split_inclusive('\n')
+            .enumerate()
+            .map(|(i, line)| {
+                if directives.contains(&i) {
+                    line.bytes()
+                        .map(|ch| if ch == b'\n' { '\n' } else { ' ' })
+                        .collect()
+                } else {
+                    line.to_string()
+                }
+            })
+            .collect::<String>();
+        SourceText::with_lexed(text, lexed, directives)
+    }
+
+    /// Prepares the output of the preprocessor. Directives have been resolved,
+    /// but line markers are left to the lexer, which maps positions using them.
+    pub fn preprocessed(text: &'a str) -> SourceText<'a> {
+        SourceText::with_lexed(text, text.to_string(), directive_lines(text.as_bytes()))
+    }
+
+    fn with_lexed(text: &'a str, lexed: String, directives: Vec<usize>) -> SourceText<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        SourceText {
+            text,
+            lexed,
+            directives,
+            line_starts,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts[line]
+    }
+
+    /// The offset of the line break at the end of `line`, or the end of the text for the last line
+    pub fn line_end(&self, line: usize) -> usize {
+        self.line_starts
+            .get(line + 1)
+            .map_or(self.text.len(), |start| start - 1)
+    }
+
+    pub fn offset(&self, position: Position) -> usize {
+        let start = self.line_starts[position.line() as usize];
+        (start + position.character() as usize).min(self.text.len())
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        Position::new(line as u32, (offset - self.line_starts[line]) as u32)
+    }
+
+    /// The lexer produces one token per byte for non-ASCII characters outside of
+    /// strings and comments. The first of these tokens contains the character,
+    /// the others are empty.
+    pub fn char_boundary(&self, mut offset: usize) -> usize {
+        while !self.text.is_char_boundary(offset) {
+            offset += 1;
+        }
+        offset
+    }
+
+    /// The lexer stops at unterminated strings and comments.
+    /// Returns the offset of the text after `end` that the lexer did not reach,
+    /// or `None` if there is only whitespace and directives left.
+    pub fn unlexed(&self, end: usize) -> Option<usize> {
+        let mut offset = end;
+        loop {
+            let rest = &self.lexed[offset..];
+            let trimmed = rest.trim_start();
+            if trimmed.is_empty() {
+                return None;
+            }
+            offset += rest.len() - trimmed.len();
+            let line = self.position(offset).line() as usize;
+            if !self.directives.contains(&line) {
+                return Some(offset);
+            }
+            offset = self.line_end(line);
+        }
+    }
+}
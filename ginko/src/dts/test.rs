@@ -2,6 +2,7 @@ use crate::dts::analysis::{Analysis, AnalysisContext, AnalysisResult};
 use crate::dts::data::HasSource;
 use crate::dts::reader::{ByteReader, Reader};
 use crate::dts::tokens::{Lexer, Token};
+use crate::dts::tree::DeviceTree;
 use crate::dts::{Diagnostic, FileType, HasSpan, Parser, ParserContext, Position, Project, Span};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -83,7 +84,7 @@ impl Code {
 
     pub fn parse<F, T>(&self, parse_fn: F) -> (Result<T, Diagnostic>, Vec<Diagnostic>)
     where
-        F: FnOnce(&mut Parser<ByteReader>) -> Result<T, Diagnostic>,
+        F: FnOnce(&mut Parser<Lexer<ByteReader>>) -> Result<T, Diagnostic>,
     {
         let mut reader = ByteReader::from_string(self.code.clone());
         reader.seek(self.pos.start());
@@ -94,7 +95,7 @@ impl Code {
 
     pub fn parse_ok<F, T>(&self, parse_fn: F) -> (T, Vec<Diagnostic>)
     where
-        F: FnOnce(&mut Parser<ByteReader>) -> Result<T, Diagnostic>,
+        F: FnOnce(&mut Parser<Lexer<ByteReader>>) -> Result<T, Diagnostic>,
     {
         let (res, diagnostics) = self.parse(parse_fn);
         (res.expect("Unexpectedly found non-ok value"), diagnostics)
@@ -102,7 +103,7 @@ impl Code {
 
     pub fn parse_ok_no_diagnostics<F, T>(&self, parse_fn: F) -> T
     where
-        F: FnOnce(&mut Parser<ByteReader>) -> Result<T, Diagnostic>,
+        F: FnOnce(&mut Parser<Lexer<ByteReader>>) -> Result<T, Diagnostic>,
     {
         let (res, diagnostics) = self.parse(parse_fn);
         assert!(
@@ -113,14 +114,18 @@ impl Code {
     }
 
     pub fn get_analyzed_file(&self) -> (Vec<Diagnostic>, AnalysisContext) {
+        self.get_analyzed_file_in(&Project::default())
+    }
+
+    // Analyzes the code using the settings of a project
+    pub fn get_analyzed_file_in(&self, project: &Project) -> (Vec<Diagnostic>, AnalysisContext) {
         let (file, mut parse_diagnostics) = self.parse_ok(Parser::file);
-        let fake_project = Project::default();
         let mut analysis = Analysis::new();
         let AnalysisResult {
             context,
             mut diagnostics,
             ..
-        } = analysis.analyze_file(&file, FileType::DtSource, &fake_project);
+        } = analysis.analyze_file(&file, FileType::DtSource, project);
         diagnostics.append(&mut parse_diagnostics);
         (diagnostics, context)
     }
@@ -151,3 +156,10 @@ impl Code {
             .expect("Expected token")
     }
 }
+
+/// Compiles the code into a tree, which must succeed without diagnostics
+pub fn compile_tree(code: &str) -> DeviceTree {
+    let (diagnostics, context) = Code::new(code).get_analyzed_file();
+    assert_eq!(diagnostics, vec![]);
+    context.tree().clone()
+}
@@ -1,10 +1,12 @@
-use crate::dts::data::{HasSource, Position};
+use crate::dts::data::{HasSource, HasSpan, Location, Position, Span};
 use crate::dts::error_codes::ErrorCode;
 use crate::dts::reader::{ByteReader, Reader};
+use crate::dts::source_map::SourceMap;
 use crate::dts::tokens::token::{Token, TokenKind};
 use crate::dts::tokens::Reference;
 use crate::dts::Diagnostic;
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 enum LexerState {
@@ -18,8 +20,18 @@ where
 {
     reader: R,
     source: Arc<Path>,
+    source_map: SourceMap,
+    // Maps positions in the lexed file, or in the files that it includes,
+    // to the files named by their line markers
+    line_markers: HashMap<Arc<Path>, SourceMap>,
+    at_line_start: bool,
     state: LexerState,
+    // The number of open parentheses, i.e., the nesting depth of expressions
+    parens: usize,
     last_pos: Position,
+    // The doc comment in front of the token that was returned last when iterating
+    doc_comment: Option<String>,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Lexer<ByteReader> {
@@ -33,13 +45,56 @@ where
     R: Reader + Sized,
 {
     pub fn new(reader: R, source: Arc<Path>) -> Lexer<R> {
+        Lexer::with_source_map(reader, source, SourceMap::default())
+    }
+
+    /// Creates a lexer for generated text, such as the output of the preprocessor.
+    /// Positions and sources of tokens are mapped to the original files using `source_map`.
+    pub fn with_source_map(reader: R, source: Arc<Path>, source_map: SourceMap) -> Lexer<R> {
         Lexer {
             reader,
             source,
+            source_map,
+            line_markers: HashMap::new(),
+            at_line_start: true,
             state: LexerState::ExpectingNodeOrPropertyName,
+            parens: 0,
             last_pos: Position::zero(),
+            doc_comment: None,
+            diagnostics: vec![],
         }
     }
+
+    /// The file that is lexed, as opposed to the file that the current token originates from.
+    pub fn file_source(&self) -> Arc<Path> {
+        self.source.clone()
+    }
+
+    /// Returns the next token, including comments, which are skipped when iterating.
+    /// The span of the token refers to the lexed text,
+    /// the location to the file and position that the token originates from.
+    pub(crate) fn next_with_comments(&mut self) -> Option<(Token, Location)> {
+        self.last_pos = self.pos();
+        let mut token = self.read_token()?;
+        token.source = self.source.clone();
+        let location = self.map_span(token.span);
+        Some((token, location))
+    }
+
+    /// Returns the diagnostics found while lexing, such as malformed escape sequences.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn push_diagnostic(&mut self, span: Span, code: ErrorCode, message: impl Into<String>) {
+        let location = self.map_span(span);
+        self.diagnostics.push(Diagnostic::new(
+            location.span(),
+            location.source(),
+            code,
+            message,
+        ))
+    }
 }
 
 impl<R> HasSource for Lexer<R>
@@ -47,7 +102,127 @@ where
     R: Reader + Sized,
 {
     fn source(&self) -> Arc<Path> {
-        self.source.clone()
+        self.map_position(self.reader.pos()).0
+    }
+}
+
+impl<R> Lexer<R>
+where
+    R: Reader + Sized,
+{
+    /// Maps a position in the lexed text to the original file and position.
+    /// First, the source map is applied.
+    /// Then, the line markers of the resulting file are applied.
+    fn map_position(&self, pos: Position) -> (Arc<Path>, Position) {
+        let (source, pos) = self
+            .source_map
+            .map_position(pos)
+            .unwrap_or((self.source.clone(), pos));
+        if let Some(mapped) = self
+            .line_markers
+            .get(&source)
+            .and_then(|line_markers| line_markers.map_position(pos))
+        {
+            return mapped;
+        }
+        (source, pos)
+    }
+
+    /// Maps a span in the lexed text to the original file and span.
+    fn map_span(&self, span: Span) -> Location {
+        let location = self
+            .source_map
+            .map_span(span)
+            .unwrap_or_else(|| Location::new(self.source.clone(), span));
+        if let Some(mapped) = self
+            .line_markers
+            .get(&location.source())
+            .and_then(|line_markers| line_markers.map_span(location.span()))
+        {
+            return mapped;
+        }
+        location
+    }
+
+    // Line markers, as emitted by the C preprocessor, have the form
+    // `# 12 "file.dts" 1 3` and must be on a line of their own.
+    fn is_line_marker(&self) -> bool {
+        if !self.at_line_start || self.reader.peek() != Some(b'#') {
+            return false;
+        }
+        let mut offset = 1;
+        while matches!(self.reader.peek_nth(offset), Some(b' ' | b'\t')) {
+            offset += 1;
+        }
+        self.reader
+            .peek_nth(offset)
+            .is_some_and(|ch| ch.is_ascii_digit())
+    }
+
+    // precondition: `is_line_marker()` is true
+    // # 12 "file.dts" 1
+    // ^~~ cursor is here
+    fn line_marker(&mut self) {
+        self.reader.skip();
+        self.read_while(|ch| ch == b' ' || ch == b'\t');
+        let line = String::from_utf8(self.read_while(|ch| ch.is_ascii_digit()))
+            .unwrap()
+            .parse::<u32>()
+            .unwrap_or(1);
+        self.read_while(|ch| ch == b' ' || ch == b'\t');
+        let file_name = if self.reader.peek() == Some(b'"') {
+            self.reader.skip();
+            let mut name = Vec::new();
+            while let Some(ch) = self.reader.peek() {
+                if ch == b'"' || ch == b'\n' {
+                    break;
+                }
+                self.reader.skip();
+                if ch == b'\\' {
+                    if let Some(escaped) = self.reader.consume() {
+                        name.push(escaped);
+                    }
+                } else {
+                    name.push(ch);
+                }
+            }
+            Some(String::from_utf8_lossy(&name).into_owned())
+        } else {
+            None
+        };
+        // Ignore flags
+        self.read_while(|ch| ch != b'\n');
+        self.reader.skip();
+        let (source, pos) = self
+            .source_map
+            .map_position(self.reader.pos())
+            .unwrap_or((self.source.clone(), self.reader.pos()));
+        let marked_source = match file_name {
+            Some(name) => self.resolve_marked_file(&name),
+            None => self.map_position(self.reader.pos()).0,
+        };
+        self.line_markers.entry(source).or_default().push_verbatim(
+            pos,
+            marked_source,
+            Position::new(line.saturating_sub(1), 0),
+        );
+    }
+
+    // Paths in line markers are usually relative to the directory where the preprocessor
+    // was invoked. As this is unknown, the directory of the lexed file is tried as well.
+    fn resolve_marked_file(&self, name: &str) -> Arc<Path> {
+        let path = PathBuf::from(name);
+        if let Ok(path) = dunce::canonicalize(&path) {
+            return path.into();
+        }
+        if let Some(Ok(path)) = self
+            .source
+            .parent()
+            .map(|parent| dunce::canonicalize(parent.join(&path)))
+        {
+            return path.into();
+        }
+        path.into()
     }
 }
 
@@ -56,7 +231,15 @@ where
     R: Reader + Sized,
 {
     fn skip_whitespace(&mut self) {
-        while self.reader.skip_if(|ch| ch.is_ascii_whitespace()) {}
+        while let Some(ch) = self.reader.peek() {
+            if !ch.is_ascii_whitespace() {
+                break;
+            }
+            if ch == b'\n' {
+                self.at_line_start = true;
+            }
+            self.reader.skip();
+        }
     }
 
     fn read_while<F>(&mut self, cond: F) -> Vec<u8>
@@ -76,7 +259,7 @@ where
     }
 
     pub fn pos(&self) -> Position {
-        self.reader.pos()
+        self.map_position(self.reader.pos()).1
     }
 
     // precondition: cursor is past '&' token
@@ -150,6 +333,7 @@ where
     // // comment
     //   ^~~ cursor is here
     fn line_comment(&mut self, pos: Position) -> Token {
+        self.doc_comment = None;
         let comment = self.read_while(|ch| ch != b'\n');
         let comment_str = String::from_utf8(comment).unwrap();
         Token {
@@ -171,6 +355,7 @@ where
                     if self.reader.peek() == Some(b'/') {
                         self.reader.skip();
                         let str = String::from_utf8(buf).unwrap();
+                        self.doc_comment = doc_comment_text(&str);
                         return Some(Token {
                             span: pos.to(self.reader.pos()),
                             kind: TokenKind::Comment(str),
@@ -202,6 +387,7 @@ where
             "delete-node" => DeleteNode,
             "omit-if-no-ref" => OmitIfNoRef,
             "include" => Include,
+            "incbin" => Incbin,
             other => Other(other.into()),
         };
         Token {
@@ -211,43 +397,144 @@ where
         }
     }
 
+    // precondition: cursor is past the backslash
+    // "foo\tbar"
+    //      ^~~ cursor is here
+    // Escape sequences are decoded like dtc does.
+    fn escape(&mut self, start: Position) -> Option<u8> {
+        let ch = self.reader.consume()?;
+        Some(match ch {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'v' => 0x0b,
+            b'f' => 0x0c,
+            b'r' => b'\r',
+            b'\\' | b'"' | b'\'' => ch,
+            b'0'..=b'7' => {
+                let mut value = (ch - b'0') as u32;
+                for _ in 0..2 {
+                    match self.reader.peek() {
+                        Some(digit @ b'0'..=b'7') => {
+                            self.reader.skip();
+                            value = value * 8 + (digit - b'0') as u32;
+                        }
+                        _ => break,
+                    }
+                }
+                if value > 0xff {
+                    self.push_diagnostic(
+                        start.to(self.reader.pos()),
+                        ErrorCode::InvalidEscape,
+                        "Octal escape sequence out of range",
+                    );
+                }
+                value as u8
+            }
+            b'x' => {
+                let mut value: Option<u8> = None;
+                for _ in 0..2 {
+                    match self.reader.peek() {
+                        Some(digit) if digit.is_ascii_hexdigit() => {
+                            self.reader.skip();
+                            let digit = (digit as char).to_digit(16).unwrap() as u8;
+                            value = Some(value.unwrap_or(0) * 16 + digit);
+                        }
+                        _ => break,
+                    }
+                }
+                value.unwrap_or_else(|| {
+                    self.push_diagnostic(
+                        start.to(self.reader.pos()),
+                        ErrorCode::InvalidEscape,
+                        "\\x used with no following hex digits",
+                    );
+                    0
+                })
+            }
+            _ => {
+                self.push_diagnostic(
+                    start.to(self.reader.pos()),
+                    ErrorCode::UnknownEscape,
+                    format!("Unknown escape sequence '\\{}'", ch.escape_ascii()),
+                );
+                ch
+            }
+        })
+    }
+
     // precondition: cursor is past first quote
     // "Hello, World!"
     //  ^~~ cursor is here
     fn string(&mut self, pos: Position) -> Option<Token> {
-        let mut is_escaped: bool = false;
-        let mut str = String::new();
+        let mut bytes: Vec<u8> = vec![];
         loop {
+            let escape_start = self.reader.pos();
             let ch = self.reader.consume()?;
             match ch {
-                b'\\' => {
-                    if is_escaped {
-                        str.push(b'\\' as char);
-                        is_escaped = false;
-                    } else {
-                        is_escaped = true;
-                    }
-                }
+                b'\\' => bytes.push(self.escape(escape_start)?),
                 b'"' => {
-                    if is_escaped {
-                        str.push(b'"' as char);
-                        is_escaped = false;
-                    } else {
-                        return Some(Token {
-                            span: pos.to(self.reader.pos()),
-                            kind: TokenKind::String(str),
-                            source: self.source(),
-                        });
-                    }
+                    return Some(Token {
+                        span: pos.to(self.reader.pos()),
+                        kind: TokenKind::String(bytes),
+                        source: self.source(),
+                    });
                 }
-                _ => {
-                    if is_escaped {
-                        is_escaped = false
-                    }
-                    str.push(ch as char)
+                _ => bytes.push(ch),
+            }
+        }
+    }
+
+    // precondition: cursor is past first quote
+    // 'a'
+    //  ^~~ cursor is here
+    fn char_literal(&mut self, pos: Position) -> Option<Token> {
+        let mut bytes: Vec<u8> = vec![];
+        loop {
+            let escape_start = self.reader.pos();
+            match self.reader.peek() {
+                None | Some(b'\n') => {
+                    self.push_diagnostic(
+                        pos.to(self.reader.pos()),
+                        ErrorCode::InvalidCharLiteral,
+                        "Unterminated character literal",
+                    );
+                    break;
+                }
+                Some(b'\'') => {
+                    self.reader.skip();
+                    break;
+                }
+                Some(b'\\') => {
+                    self.reader.skip();
+                    bytes.push(self.escape(escape_start)?);
+                }
+                Some(ch) => {
+                    self.reader.skip();
+                    bytes.push(ch);
                 }
             }
         }
+        let span = pos.to(self.reader.pos());
+        match bytes.len() {
+            0 => self.push_diagnostic(
+                span,
+                ErrorCode::InvalidCharLiteral,
+                "Empty character literal",
+            ),
+            1 => {}
+            len => self.push_diagnostic(
+                span,
+                ErrorCode::InvalidCharLiteral,
+                format!("Character literal has {len} characters instead of 1"),
+            ),
+        }
+        Some(Token {
+            span,
+            kind: TokenKind::Char(bytes.first().copied().unwrap_or_default()),
+            source: self.source(),
+        })
     }
 
     fn number(&mut self, pos: Position) -> Token {
@@ -260,29 +547,25 @@ where
         }
     }
 
-    fn insert_pseudo_kind(&mut self, kind: TokenKind) {
-        match kind {
-            TokenKind::Semicolon => {
-                self.state = LexerState::ExpectingNodeOrPropertyName;
-            }
-            TokenKind::Equal => {
-                self.state = LexerState::Other;
-            }
-            TokenKind::CloseParen => {
-                self.state = LexerState::ExpectingNodeOrPropertyName;
-            }
-            _ => {}
-        }
-    }
-
     #[cfg(test)]
     fn has_next(&self) -> bool {
         self.reader.peek().is_some()
     }
 
     fn consume(&mut self) -> Option<Token> {
-        self.last_pos = self.pos();
+        let (mut token, location) = self.next_with_comments()?;
+        token.source = location.source();
+        token.span = location.span();
+        Some(token)
+    }
+
+    fn read_token(&mut self) -> Option<Token> {
         self.skip_whitespace();
+        while self.is_line_marker() {
+            self.line_marker();
+            self.skip_whitespace();
+        }
+        self.at_line_start = false;
         let ch = self.reader.peek()?;
         let start_pos = self.reader.pos();
         let source = self.source();
@@ -312,6 +595,10 @@ where
                 self.reader.skip();
                 self.string(start_pos)
             }
+            b'\'' => {
+                self.reader.skip();
+                self.char_literal(start_pos)
+            }
             b'/' => {
                 self.reader.skip();
                 let Some(next_ch) = self.reader.peek() else {
@@ -340,6 +627,7 @@ where
             }
             b';' => {
                 self.state = LexerState::ExpectingNodeOrPropertyName;
+                self.parens = 0;
                 simple_token(Semicolon)
             }
             b'=' => {
@@ -348,8 +636,14 @@ where
             }
             b'[' => simple_token(OpenBracket),
             b']' => simple_token(CloseBracket),
-            b'(' => simple_token(OpenParen),
-            b')' => simple_token(CloseParen),
+            b'(' => {
+                self.parens += 1;
+                simple_token(OpenParen)
+            }
+            b')' => {
+                self.parens = self.parens.saturating_sub(1);
+                simple_token(CloseParen)
+            }
             b'{' => {
                 self.state = LexerState::ExpectingNodeOrPropertyName;
                 simple_token(OpenBrace)
@@ -358,53 +652,129 @@ where
             b'<' => simple_token(ChevronLeft),
             b'>' => simple_token(ChevronRight),
             b',' => simple_token(Comma),
-            ch => simple_token(Unknown(ch)),
+            ch => {
+                // Outside of expressions, the parser skips unknown characters
+                // in place of a missing semicolon
+                if self.parens == 0 {
+                    self.state = LexerState::ExpectingNodeOrPropertyName;
+                }
+                simple_token(Unknown(ch))
+            }
         }
     }
 }
 
+/// A stream of tokens, without comments, that the parser consumes.
+/// Next to the tokens, the parser needs positions for diagnostics at the end of the stream.
+pub trait TokenStream: Iterator<Item = Token> + HasSource {
+    /// The position after the last consumed token
+    fn pos(&self) -> Position;
+
+    /// The position before the last consumed token
+    fn last_pos(&self) -> Position;
+
+    /// The file that the tokens are read from
+    fn file_source(&self) -> Arc<Path>;
+
+    /// The doc comment, e.g., `/** A description */`, directly in front of the last consumed token
+    fn doc_comment(&self) -> Option<String>;
+
+    fn take_diagnostics(&mut self) -> Vec<Diagnostic>;
+}
+
+impl<R> TokenStream for Lexer<R>
+where
+    R: Reader + Sized,
+{
+    fn pos(&self) -> Position {
+        Lexer::pos(self)
+    }
+
+    fn last_pos(&self) -> Position {
+        self.last_pos
+    }
+
+    fn file_source(&self) -> Arc<Path> {
+        Lexer::file_source(self)
+    }
+
+    fn doc_comment(&self) -> Option<String> {
+        self.doc_comment.clone()
+    }
+
+    fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        Lexer::take_diagnostics(self)
+    }
+}
+
+/// Returns the text of a doc comment, given the contents of a block comment.
+/// Doc comments start with `/**`; leading asterisks of continuation lines are removed.
+pub(crate) fn doc_comment_text(contents: &str) -> Option<String> {
+    let contents = contents.strip_prefix('*')?;
+    let lines = contents
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            match line.strip_prefix('*') {
+                Some(rest) => rest.trim_start(),
+                None => line,
+            }
+        })
+        .skip_while(|line| line.is_empty())
+        .collect::<Vec<_>>();
+    let end = lines.iter().rposition(|line| !line.is_empty())?;
+    Some(lines[..=end].join("\n"))
+}
+
 // This is a simple copy of the `Peekable` interface.
 // We cannot just use the peekable interface because for operations
 // like getting the EOF position, we still need a handle to the lexer.
-pub struct PeekingLexer<R>
+pub struct PeekingLexer<T>
 where
-    R: Reader + Sized,
+    T: TokenStream,
 {
-    lexer: Lexer<R>,
+    lexer: T,
     peeked: Option<Option<Token>>,
+    peeked_doc_comment: Option<String>,
+    doc_comment: Option<String>,
 }
 
-impl<R> From<Lexer<R>> for PeekingLexer<R>
+impl<T> From<T> for PeekingLexer<T>
 where
-    R: Reader + Sized,
+    T: TokenStream,
 {
-    fn from(value: Lexer<R>) -> Self {
+    fn from(value: T) -> Self {
         PeekingLexer {
             lexer: value,
             peeked: None,
+            peeked_doc_comment: None,
+            doc_comment: None,
         }
     }
 }
 
-impl<R> HasSource for PeekingLexer<R>
+impl<T> HasSource for PeekingLexer<T>
 where
-    R: Reader + Sized,
+    T: TokenStream,
 {
     fn source(&self) -> Arc<Path> {
         self.lexer.source()
     }
 }
 
-impl<R> PeekingLexer<R>
+impl<T> PeekingLexer<T>
 where
-    R: Reader + Sized,
+    T: TokenStream,
 {
     // Important: Only `peek` (and the Iterator implementation itself)
     // should call next on the lexer itself. Other implementations should
     // call next directly on the `PeekingLexer`
     pub fn peek(&mut self) -> Option<&Token> {
-        let iter = &mut self.lexer;
-        self.peeked.get_or_insert_with(|| iter.next()).as_ref()
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lexer.next());
+            self.peeked_doc_comment = self.lexer.doc_comment();
+        }
+        self.peeked.as_ref().and_then(Option::as_ref)
     }
 
     pub fn peek_kind(&mut self) -> Option<&TokenKind> {
@@ -465,24 +835,40 @@ where
         }
     }
 
-    pub fn insert_pseudo_kind(&mut self, kind: TokenKind) {
-        self.lexer.insert_pseudo_kind(kind)
+    pub fn last_pos(&self) -> Position {
+        self.lexer.last_pos()
     }
 
-    pub fn last_pos(&self) -> Position {
-        self.lexer.last_pos
+    pub fn file_source(&self) -> Arc<Path> {
+        self.lexer.file_source()
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.lexer.take_diagnostics()
+    }
+
+    /// The doc comment in front of the token that was returned last by `next`
+    pub fn doc_comment(&self) -> Option<String> {
+        self.doc_comment.clone()
     }
 }
 
-impl<R> Iterator for PeekingLexer<R>
+impl<T> Iterator for PeekingLexer<T>
 where
-    R: Reader + Sized,
+    T: TokenStream,
 {
     type Item = Token;
     fn next(&mut self) -> Option<Token> {
         match self.peeked.take() {
-            Some(v) => v,
-            None => self.lexer.next(),
+            Some(v) => {
+                self.doc_comment = self.peeked_doc_comment.take();
+                v
+            }
+            None => {
+                let next = self.lexer.next();
+                self.doc_comment = self.lexer.doc_comment();
+                next
+            }
         }
     }
 }
@@ -494,6 +880,7 @@ where
     type Item = Token;
 
     fn next(&mut self) -> Option<Token> {
+        self.doc_comment = None;
         loop {
             let next = self.consume()?;
             if !matches!(next.kind, TokenKind::Comment(_)) {
@@ -516,12 +903,15 @@ where
 #[cfg(test)]
 mod test {
     use crate::dts::data::Position;
+    use crate::dts::error_codes::ErrorCode;
     use crate::dts::reader::ByteReader;
-    use crate::dts::tokens::lexer::Reference;
+    use crate::dts::tokens::lexer::{doc_comment_text, Reference};
     use crate::dts::tokens::CompilerDirective;
     use crate::dts::tokens::TokenKind::*;
     use crate::dts::tokens::{Lexer, Token};
+    use crate::dts::{Diagnostic, Preprocessor};
     use itertools::Itertools;
+    use std::fs;
     use std::path::{Path, PathBuf};
     use std::sync::Arc;
 
@@ -871,6 +1261,91 @@ third line
         }
     }
 
+    #[test]
+    pub fn string_escapes() {
+        let strings: [(&str, &[u8]); 6] = [
+            (r#""\x41\101\t""#, b"AA\t"),
+            (r#""\a\b\v\f\r\n""#, &[0x07, 0x08, 0x0b, 0x0c, b'\r', b'\n']),
+            (r#""\'\"\\""#, br#"'"\"#),
+            (r#""\0\x7""#, &[0, 7]),
+            (r#""\1234""#, b"S4"),
+            (r#""\xff\xc3\xa4""#, &[0xff, 0xc3, 0xa4]),
+        ];
+
+        for (raw_str, expected) in strings {
+            let (_, mut lexer) = new_lexer(raw_str);
+            assert_eq!(lexer.next_expect().kind, String(expected.to_vec()));
+            assert!(lexer.take_diagnostics().is_empty());
+        }
+    }
+
+    #[test]
+    pub fn malformed_escapes() {
+        let (source, mut lexer) = new_lexer(r#""\x \777 \q""#);
+        assert_eq!(lexer.next_expect().kind, String(b"\0 \xff q".to_vec()));
+        assert_eq!(
+            lexer.take_diagnostics(),
+            vec![
+                Diagnostic::new(
+                    Position::new(0, 1).char_to(3),
+                    source.clone(),
+                    ErrorCode::InvalidEscape,
+                    "\\x used with no following hex digits"
+                ),
+                Diagnostic::new(
+                    Position::new(0, 4).char_to(8),
+                    source.clone(),
+                    ErrorCode::InvalidEscape,
+                    "Octal escape sequence out of range"
+                ),
+                Diagnostic::new(
+                    Position::new(0, 9).char_to(11),
+                    source.clone(),
+                    ErrorCode::UnknownEscape,
+                    "Unknown escape sequence '\\q'"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn char_literals() {
+        let (_, lexer) = new_lexer(r#"x = <'a' '\n' '\x41' '\''>;"#);
+        let tokens = lexer.map(|tok| tok.kind).collect_vec();
+        assert_eq!(
+            tokens,
+            vec![
+                Ident("x".into()),
+                Equal,
+                ChevronLeft,
+                Char(b'a'),
+                Char(b'\n'),
+                Char(b'A'),
+                Char(b'\''),
+                ChevronRight,
+                Semicolon,
+            ]
+        );
+
+        for (text, message) in [
+            ("''", "Empty character literal"),
+            ("'ab'", "Character literal has 2 characters instead of 1"),
+            ("'a", "Unterminated character literal"),
+        ] {
+            let (source, mut lexer) = new_lexer(text);
+            lexer.next_expect();
+            assert_eq!(
+                lexer.take_diagnostics(),
+                vec![Diagnostic::new(
+                    Position::zero().char_to(text.len() as u32),
+                    source,
+                    ErrorCode::InvalidCharLiteral,
+                    message
+                )]
+            );
+        }
+    }
+
     #[test]
     pub fn conflicting_node_names() {
         let (_, lexer) = new_lexer("some_node { ,property-name = <1>,<2>; };");
@@ -895,4 +1370,111 @@ third line
             ]
         )
     }
+
+    #[test]
+    pub fn line_markers() {
+        let (source, lexer) = new_lexer(
+            r#"# 1 "inline source"
+/dts-v1/;
+# 10 "/some/dir/other.dtsi" 1
+node {};
+# 3 "inline source" 2
+# 1 "<command-line>"
+&ref;"#,
+        );
+        let tokens = lexer
+            .map(|tok| (tok.kind, tok.source, tok.span.start()))
+            .collect_vec();
+        let other: Arc<Path> = PathBuf::from("/some/dir/other.dtsi").into();
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    Directive(CompilerDirective::DTSVersionHeader),
+                    source.clone(),
+                    Position::new(0, 0)
+                ),
+                (Semicolon, source.clone(), Position::new(0, 8)),
+                (Ident("node".into()), other.clone(), Position::new(9, 0)),
+                (OpenBrace, other.clone(), Position::new(9, 5)),
+                (CloseBrace, other.clone(), Position::new(9, 6)),
+                (Semicolon, other.clone(), Position::new(9, 7)),
+                (
+                    Ref(Reference::Simple("ref".into())),
+                    PathBuf::from("<command-line>").into(),
+                    Position::new(0, 0)
+                ),
+                (
+                    Semicolon,
+                    PathBuf::from("<command-line>").into(),
+                    Position::new(0, 4)
+                ),
+            ]
+        )
+    }
+
+    #[test]
+    pub fn line_markers_in_included_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("generated.dtsi"),
+            "# 10 \"/some/dir/other.dtsi\"\nnode {};\n",
+        )
+        .unwrap();
+        let source: Arc<Path> = dir.path().join("main.dts").into();
+        let preprocessed = Preprocessor::new(vec![])
+            .process("#include \"generated.dtsi\"\n&ref;\n", source.clone());
+        let lexer = Lexer::with_source_map(
+            ByteReader::from_string(preprocessed.text),
+            source.clone(),
+            preprocessed.source_map,
+        );
+        let tokens = lexer
+            .map(|tok| (tok.kind, tok.source, tok.span.start()))
+            .collect_vec();
+        let other: Arc<Path> = PathBuf::from("/some/dir/other.dtsi").into();
+        assert_eq!(
+            tokens,
+            vec![
+                (Ident("node".into()), other.clone(), Position::new(9, 0)),
+                (OpenBrace, other.clone(), Position::new(9, 5)),
+                (CloseBrace, other.clone(), Position::new(9, 6)),
+                (Semicolon, other.clone(), Position::new(9, 7)),
+                // The line marker only applies to the included file
+                (
+                    Ref(Reference::Simple("ref".into())),
+                    source.clone(),
+                    Position::new(1, 0)
+                ),
+                (Semicolon, source, Position::new(1, 4)),
+            ]
+        )
+    }
+
+    #[test]
+    pub fn hash_inside_lines_is_not_a_line_marker() {
+        let (_, lexer) = new_lexer("node { #address-cells = <1>; };");
+        let tokens = lexer.map(|tok| tok.kind).collect_vec();
+        assert_eq!(tokens[2], Ident("#address-cells".into()));
+    }
+
+    #[test]
+    pub fn doc_comments() {
+        let (_, mut lexer) = new_lexer("/** doc */ a /* no doc */ b /** doc */ // line\nc");
+        assert_eq!(lexer.next_expect().kind, Ident("a".into()));
+        assert_eq!(lexer.doc_comment, Some("doc".into()));
+        assert_eq!(lexer.next_expect().kind, Ident("b".into()));
+        assert_eq!(lexer.doc_comment, None);
+        assert_eq!(lexer.next_expect().kind, Ident("c".into()));
+        assert_eq!(lexer.doc_comment, None);
+
+        assert_eq!(doc_comment_text("* doc "), Some("doc".into()));
+        assert_eq!(
+            doc_comment_text("*\n * first\n *\n *   second\n "),
+            Some("first\n\nsecond".into())
+        );
+        assert_eq!(doc_comment_text(""), None);
+        assert_eq!(doc_comment_text("*"), None);
+        assert_eq!(doc_comment_text(" regular "), None);
+    }
 }
@@ -1,5 +1,6 @@
 mod lexer;
 mod token;
 
-pub use lexer::{Lexer, PeekingLexer};
+pub(crate) use lexer::doc_comment_text;
+pub use lexer::{Lexer, PeekingLexer, TokenStream};
 pub use token::{CompilerDirective, Reference, Token, TokenKind};
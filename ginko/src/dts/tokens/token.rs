@@ -33,7 +33,10 @@ pub enum TokenKind {
     Ident(String),
     // The most basic identifier, representing everything from node-name to byte string
     Label(String),
-    String(String),
+    // The decoded contents of a string, i.e., with escape sequences resolved
+    String(Vec<u8>),
+    // A character literal such as 'a' or '\n', used in cells
+    Char(u8),
     // Since numbers can appear in various circumstances,
     // this simply represents a string starting with a number.
     // Verifying this number is done by the parser when more context is available.
@@ -54,6 +57,7 @@ pub enum CompilerDirective {
     Bits,
     OmitIfNoRef,
     Include,
+    Incbin,
     Other(String),
 }
 
@@ -68,6 +72,7 @@ impl Display for CompilerDirective {
             CompilerDirective::Bits => write!(f, "/bits/"),
             CompilerDirective::OmitIfNoRef => write!(f, "/omit-if-no-ref/"),
             CompilerDirective::Include => write!(f, "/include/"),
+            CompilerDirective::Incbin => write!(f, "/incbin/"),
             CompilerDirective::Other(other) => write!(f, "/{other}/"),
         }
     }
@@ -83,7 +88,10 @@ pub enum Reference {
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Token {
+    // The kind of a token is implied by the syntax element that it is part of
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub kind: TokenKind,
     pub span: Span,
     pub source: Arc<Path>,
@@ -0,0 +1,1200 @@
+use crate::dts::ast::{
+    AnyDirective, ByteString, Cell, DtsFile, Node, NodeItem, NodeName, NodePayload, Path, Primary,
+    Property, PropertyValue, Reference, ReferencedNode, WithToken,
+};
+use crate::dts::data::{HasSource, HasSpan, Location};
+use crate::dts::error_codes::ErrorCode;
+use crate::dts::{Diagnostic, Project};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// The type of value as written in the source.
+/// Types are only used to reproduce the source representation of a value
+/// and have no meaning in the binary representation.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ValueType {
+    String,
+    // Cells with their width in bits
+    Cells(u32),
+    Bytes,
+}
+
+// Guesses the type of untyped data the same way dtc does
+pub(crate) fn guess_type(bytes: &[u8]) -> ValueType {
+    let is_string_char =
+        |byte: &u8| byte.is_ascii_graphic() || b" \0\x07\x08\t\n\x0b\x0c\r".contains(byte);
+    let nul_count = bytes.iter().filter(|byte| **byte == 0).count();
+    if bytes.last() == Some(&0)
+        && bytes.iter().all(is_string_char)
+        && nul_count <= bytes.len() - nul_count
+    {
+        ValueType::String
+    } else if bytes.len().is_multiple_of(4) {
+        ValueType::Cells(32)
+    } else {
+        ValueType::Bytes
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum MarkerKind {
+    /// A value of the given type starts at this offset
+    Type(ValueType),
+    Label(String),
+    /// A reference that will be replaced by the phandle of the referenced node.
+    /// The data contains a placeholder cell at this offset.
+    Phandle(Reference),
+    /// A reference that will be replaced by the full path of the referenced node.
+    /// The data contains no placeholder; the path is inserted at this offset.
+    Path(Reference),
+}
+
+/// Annotates a position in the data of a property.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Marker {
+    pub offset: usize,
+    pub kind: MarkerKind,
+}
+
+/// One entry in the list of values of a property
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum TypedValue<'a> {
+    /// A string without its terminating zero,
+    /// together with the reference that it is the path of, if any
+    String(&'a [u8], Option<&'a Reference>),
+    /// An array of integers with the given width in bits.
+    /// Integers that are phandles carry the reference that they resolve.
+    Integers(u32, Vec<(u64, Option<&'a Reference>)>),
+}
+
+/// The binary value of a property, together with markers
+/// that reference positions in this value.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Data {
+    pub bytes: Vec<u8>,
+    pub markers: Vec<Marker>,
+}
+
+impl Data {
+    fn push_marker(&mut self, kind: MarkerKind) {
+        self.markers.push(Marker {
+            offset: self.bytes.len(),
+            kind,
+        })
+    }
+
+    /// Evaluates the values of a property.
+    pub fn from_values(values: &[PropertyValue]) -> Data {
+        let mut data = Data::default();
+        for value in values {
+            data.push_value(value);
+        }
+        data
+    }
+
+    fn push_value(&mut self, value: &PropertyValue) {
+        match value {
+            PropertyValue::String(string) => {
+                self.push_marker(MarkerKind::Type(ValueType::String));
+                self.bytes.extend(string.item());
+                self.bytes.push(0);
+            }
+            PropertyValue::Cells(_, _, cells, _) => {
+                let width = value.cell_width().unwrap_or(32);
+                self.push_marker(MarkerKind::Type(ValueType::Cells(width)));
+                for cell in cells {
+                    let cell_value = match cell {
+                        Cell::Number(_) | Cell::Expression(..) => cell.value().unwrap_or_default(),
+                        Cell::Reference(reference) => {
+                            self.push_marker(MarkerKind::Phandle(reference.item().clone()));
+                            u64::MAX
+                        }
+                        Cell::Label(label) => {
+                            self.push_marker(MarkerKind::Label(label.item().clone()));
+                            continue;
+                        }
+                    };
+                    let bytes = cell_value.to_be_bytes();
+                    self.bytes.extend(&bytes[8 - (width / 8) as usize..]);
+                }
+            }
+            PropertyValue::Reference(reference) => {
+                self.push_marker(MarkerKind::Type(ValueType::String));
+                self.push_marker(MarkerKind::Path(reference.item().clone()));
+            }
+            PropertyValue::ByteStrings(_, strings, _) => {
+                self.push_marker(MarkerKind::Type(ValueType::Bytes));
+                for string in strings {
+                    match string {
+                        ByteString::Bytes(bytes) => self.bytes.extend(bytes.item()),
+                        ByteString::Label(label) => {
+                            self.push_marker(MarkerKind::Label(label.item().clone()))
+                        }
+                    }
+                }
+            }
+            PropertyValue::Incbin(incbin) => {
+                self.push_marker(MarkerKind::Type(ValueType::Bytes));
+                // Errors are reported when parsing the directive
+                self.bytes.extend(incbin.bytes().unwrap_or_default());
+            }
+            PropertyValue::Label(label) => {
+                self.push_marker(MarkerKind::Label(label.item().clone()))
+            }
+        }
+    }
+
+    /// The type of the first value.
+    /// The type of data without type information, e.g., data read from a blob,
+    /// is guessed from its content.
+    pub fn value_type(&self) -> ValueType {
+        self.markers
+            .iter()
+            .find_map(|marker| match marker.kind {
+                MarkerKind::Type(value_type) if marker.offset == 0 => Some(value_type),
+                _ => None,
+            })
+            .unwrap_or_else(|| guess_type(&self.bytes))
+    }
+
+    /// The value of data that consists of exactly one cell
+    pub fn as_cell(&self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes.as_slice().try_into().ok()?))
+    }
+
+    /// All labels that point into this data
+    pub fn labels(&self) -> impl Iterator<Item = &String> {
+        self.markers.iter().filter_map(|marker| match &marker.kind {
+            MarkerKind::Label(label) => Some(label),
+            _ => None,
+        })
+    }
+
+    /// All references to nodes, both as phandles and as paths
+    pub fn references(&self) -> impl Iterator<Item = &Reference> {
+        self.markers.iter().filter_map(|marker| match &marker.kind {
+            MarkerKind::Phandle(reference) | MarkerKind::Path(reference) => Some(reference),
+            _ => None,
+        })
+    }
+
+    /// Splits this data into typed values.
+    /// Each type marker starts a new value that extends up to the next type marker.
+    /// Data without type information is split according to a guessed type.
+    pub fn typed_values(&self) -> Vec<TypedValue<'_>> {
+        let mut starts = self
+            .markers
+            .iter()
+            .filter_map(|marker| match marker.kind {
+                MarkerKind::Type(value_type) => Some((marker.offset, value_type, true)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        match starts.first() {
+            Some((0, _, _)) => {}
+            first => {
+                let end = first.map_or(self.bytes.len(), |(offset, _, _)| *offset);
+                starts.insert(0, (0, guess_type(&self.bytes[..end]), false));
+            }
+        }
+        let mut values = vec![];
+        for (i, (start, value_type, typed)) in starts.iter().enumerate() {
+            let end = starts
+                .get(i + 1)
+                .map_or(self.bytes.len(), |(offset, _, _)| *offset)
+                .min(self.bytes.len());
+            let bytes = &self.bytes[(*start).min(end)..end];
+            if bytes.is_empty() {
+                continue;
+            }
+            match value_type {
+                // Typed strings are kept as they are, while guessed strings are split
+                ValueType::String if *typed => values.push(TypedValue::String(
+                    bytes.strip_suffix(&[0]).unwrap_or(bytes),
+                    self.marker_at(*start, |kind| match kind {
+                        MarkerKind::Path(reference) => Some(reference),
+                        _ => None,
+                    }),
+                )),
+                ValueType::String => values.extend(
+                    bytes
+                        .strip_suffix(&[0])
+                        .unwrap_or(bytes)
+                        .split(|byte| *byte == 0)
+                        .map(|string| TypedValue::String(string, None)),
+                ),
+                ValueType::Cells(bits) => values.push(self.integers(*start, bytes, *bits)),
+                ValueType::Bytes => values.push(self.integers(*start, bytes, 8)),
+            }
+        }
+        values
+    }
+
+    fn integers<'a>(&'a self, start: usize, bytes: &[u8], bits: u32) -> TypedValue<'a> {
+        let width = (bits / 8) as usize;
+        let integers = bytes
+            .chunks(width)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let value = chunk
+                    .iter()
+                    .fold(0u64, |value, byte| (value << 8) | *byte as u64);
+                // Only 32-bit cells can hold phandles
+                let reference = if bits == 32 {
+                    self.marker_at(start + i * width, |kind| match kind {
+                        MarkerKind::Phandle(reference) => Some(reference),
+                        _ => None,
+                    })
+                } else {
+                    None
+                };
+                (value, reference)
+            })
+            .collect();
+        TypedValue::Integers(bits, integers)
+    }
+
+    fn marker_at<'a, T>(
+        &'a self,
+        offset: usize,
+        f: impl Fn(&'a MarkerKind) -> Option<T>,
+    ) -> Option<T> {
+        self.markers
+            .iter()
+            .filter(|marker| marker.offset == offset)
+            .find_map(|marker| f(&marker.kind))
+    }
+}
+
+// Data is serialized as the list of its typed values
+#[cfg(feature = "serde")]
+impl serde::Serialize for Data {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.typed_values())
+    }
+}
+
+// Strings are serialized as `{"type": "string", "value": "..."}`
+// and arrays of integers as `{"type": "u32", "value": [...]}`.
+// Paths and phandles additionally name the reference that they resolve.
+#[cfg(feature = "serde")]
+impl serde::Serialize for TypedValue<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            TypedValue::String(string, reference) => {
+                map.serialize_entry("type", "string")?;
+                map.serialize_entry("value", &String::from_utf8_lossy(string))?;
+                if let Some(reference) = reference {
+                    map.serialize_entry("reference", reference)?;
+                }
+            }
+            TypedValue::Integers(bits, integers) => {
+                map.serialize_entry("type", &format!("u{bits}"))?;
+                map.serialize_entry(
+                    "value",
+                    &integers.iter().map(|(value, _)| value).collect::<Vec<_>>(),
+                )?;
+                let references = integers
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, (_, reference))| {
+                        reference.map(|reference| PhandleReference { index, reference })
+                    })
+                    .collect::<Vec<_>>();
+                if !references.is_empty() {
+                    map.serialize_entry("references", &references)?;
+                }
+            }
+        }
+        map.end()
+    }
+}
+
+// A phandle within an array of integers
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct PhandleReference<'a> {
+    index: usize,
+    reference: &'a Reference,
+}
+
+/// A property of the resolved device tree
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TreeProperty {
+    pub name: String,
+    pub labels: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(rename = "values"))]
+    pub data: Data,
+    /// The definition that the value of this property originates from
+    pub location: Option<Location>,
+}
+
+impl From<&Property> for TreeProperty {
+    fn from(property: &Property) -> Self {
+        TreeProperty {
+            name: property.name.item().clone(),
+            labels: property
+                .label
+                .iter()
+                .map(|label| label.item().clone())
+                .collect(),
+            data: Data::from_values(&property.values),
+            location: Some(Location::new(property.name.source(), property.span())),
+        }
+    }
+}
+
+/// A node of the resolved device tree.
+/// Properties and children are stored in the order of their first definition.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TreeNode {
+    pub name: NodeName,
+    pub labels: Vec<String>,
+    pub properties: Vec<TreeProperty>,
+    pub children: Vec<TreeNode>,
+    /// The first definition of this node
+    pub location: Option<Location>,
+    /// Whether this node is removed from the tree when it is not referenced
+    pub omit_if_no_ref: bool,
+}
+
+impl TreeNode {
+    pub fn new(name: NodeName) -> TreeNode {
+        TreeNode {
+            name,
+            labels: vec![],
+            properties: vec![],
+            children: vec![],
+            location: None,
+            omit_if_no_ref: false,
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Option<&TreeProperty> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+    }
+
+    /// The phandle of this node, given by either the `phandle` or the `linux,phandle` property
+    pub fn phandle(&self) -> Option<u32> {
+        ["phandle", "linux,phandle"]
+            .iter()
+            .find_map(|name| self.property(name)?.data.as_cell())
+    }
+
+    pub fn child(&self, name: &NodeName) -> Option<&TreeNode> {
+        self.children.iter().find(|child| child.name == *name)
+    }
+
+    pub fn child_mut(&mut self, name: &NodeName) -> Option<&mut TreeNode> {
+        self.children.iter_mut().find(|child| child.name == *name)
+    }
+
+    pub(crate) fn add_label(&mut self, label: &str) {
+        if !self.labels.iter().any(|existing| existing == label) {
+            self.labels.push(label.to_string())
+        }
+    }
+
+    /// Adds a property or replaces the value of an existing property with the same name.
+    /// Labels of the existing property are kept.
+    pub fn set_property(&mut self, property: TreeProperty) {
+        match self
+            .properties
+            .iter_mut()
+            .find(|existing| existing.name == property.name)
+        {
+            Some(existing) => {
+                for label in property.labels {
+                    if !existing.labels.contains(&label) {
+                        existing.labels.push(label)
+                    }
+                }
+                existing.data = property.data;
+                existing.location = property.location;
+            }
+            None => self.properties.push(property),
+        }
+    }
+
+    fn find_label(&self, label: &str, path: Path) -> Option<Path> {
+        if self.labels.iter().any(|existing| existing == label) {
+            return Some(path);
+        }
+        self.children
+            .iter()
+            .find_map(|child| child.find_label(label, path.with_child(child.name.clone())))
+    }
+
+    // Collects the labels of this node and all of its descendants
+    fn collect_labels(&self, labels: &mut HashSet<String>) {
+        labels.extend(self.labels.iter().cloned());
+        for child in &self.children {
+            child.collect_labels(labels);
+        }
+    }
+}
+
+/// An entry of the memory reservation block
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Reservation {
+    pub address: u64,
+    pub length: u64,
+}
+
+/// The device tree that results from applying all root nodes, referenced nodes
+/// and included files of a source file in order.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceTree {
+    pub reservations: Vec<Reservation>,
+    pub root: TreeNode,
+}
+
+impl Default for DeviceTree {
+    fn default() -> Self {
+        DeviceTree {
+            reservations: vec![],
+            root: TreeNode::new(NodeName::simple("")),
+        }
+    }
+}
+
+impl DeviceTree {
+    pub fn node(&self, path: &Path) -> Option<&TreeNode> {
+        path.iter()
+            .try_fold(&self.root, |node, name| node.child(name))
+    }
+
+    pub fn node_mut(&mut self, path: &Path) -> Option<&mut TreeNode> {
+        path.iter()
+            .try_fold(&mut self.root, |node, name| node.child_mut(name))
+    }
+
+    /// Returns the path of the node with the given label.
+    pub fn path_of_label(&self, label: &str) -> Option<Path> {
+        self.root.find_label(label, Path::empty())
+    }
+
+    /// Returns the path of the node that a reference points to, if that node exists.
+    pub fn resolve(&self, reference: &Reference) -> Option<Path> {
+        match reference {
+            Reference::Label(label) => self.path_of_label(label),
+            Reference::Path(path) => self.node(path).map(|_| path.clone()),
+        }
+    }
+
+    pub fn node_by_reference(&self, reference: &Reference) -> Option<&TreeNode> {
+        self.node(&self.resolve(reference)?)
+    }
+
+    /// Builds the tree for a file, descending into files included using `/include/`,
+    /// and resolves all references.
+    /// Returns diagnostics for deletions, references and phandles that can only be detected
+    /// on the complete tree.
+    ///
+    /// Referenced nodes of overlays become fragments that target the referenced node.
+    /// Overlays additionally contain the fixups that are needed to apply them.
+    pub(crate) fn build(
+        file: &DtsFile,
+        project: &Project,
+        is_plugin: bool,
+    ) -> (DeviceTree, Vec<Diagnostic>) {
+        let mut builder = TreeBuilder {
+            project,
+            is_plugin,
+            fragment_count: 0,
+            tree: DeviceTree::default(),
+            include_stack: vec![file.source.to_path_buf()],
+            included: HashSet::default(),
+            diagnostics: vec![],
+            deleted_labels: HashSet::default(),
+            deleted_paths: vec![],
+        };
+        builder.add_file(file);
+        // Like dtc, phandles are assigned before unreferenced nodes are removed
+        let diagnostics = builder.tree.resolve_references(project.phandle_style);
+        for diagnostic in diagnostics {
+            if !builder.included.contains(diagnostic.source.as_ref()) {
+                builder.diagnostics.push(diagnostic);
+            }
+        }
+        builder.prune_unreferenced();
+        builder.check_references();
+        if project.generate_symbols {
+            builder.tree.add_symbols(project.phandle_style);
+        }
+        if is_plugin {
+            let diagnostics = builder.tree.add_fixups();
+            builder.diagnostics.extend(diagnostics);
+        }
+        (builder.tree, builder.diagnostics)
+    }
+
+    /// Returns whether a label is defined anywhere in the tree,
+    /// i.e., on a node, a property or inside a property value.
+    pub fn has_label(&self, label: &str) -> bool {
+        fn has_label(node: &TreeNode, label: &str) -> bool {
+            node.labels.iter().any(|existing| existing == label)
+                || node.properties.iter().any(|property| {
+                    property.labels.iter().any(|existing| existing == label)
+                        || property.data.labels().any(|existing| existing == label)
+                })
+                || node.children.iter().any(|child| has_label(child, label))
+        }
+        has_label(&self.root, label)
+    }
+
+    /// Calls `f` for every node of the tree together with its path, parents before children.
+    pub fn walk<'a>(&'a self, f: &mut impl FnMut(&Path, &'a TreeNode)) {
+        fn walk<'a>(node: &'a TreeNode, path: Path, f: &mut impl FnMut(&Path, &'a TreeNode)) {
+            f(&path, node);
+            for child in &node.children {
+                walk(child, path.with_child(child.name.clone()), f);
+            }
+        }
+        walk(&self.root, Path::empty(), f)
+    }
+
+    /// Removes the node at `path` and returns it.
+    pub fn remove_node(&mut self, path: &Path) -> Option<TreeNode> {
+        let (parent, name) = path.split_last()?;
+        let parent = self.node_mut(&parent)?;
+        let idx = parent
+            .children
+            .iter()
+            .position(|child| child.name == *name)?;
+        Some(parent.children.remove(idx))
+    }
+}
+
+struct TreeBuilder<'a> {
+    project: &'a Project,
+    is_plugin: bool,
+    fragment_count: u32,
+    tree: DeviceTree,
+    // Guards against cyclic includes. These are reported during analysis.
+    include_stack: Vec<PathBuf>,
+    // All files included using `/include/`
+    included: HashSet<PathBuf>,
+    diagnostics: Vec<Diagnostic>,
+    // Labels and paths of deleted nodes, used to explain unresolved references
+    deleted_labels: HashSet<String>,
+    deleted_paths: Vec<Path>,
+}
+
+impl TreeBuilder<'_> {
+    fn add_diagnostic(&mut self, diagnostic: Diagnostic) {
+        // Included files report their own diagnostics
+        if self.include_stack.len() == 1 {
+            self.diagnostics.push(diagnostic)
+        }
+    }
+
+    fn add_file(&mut self, file: &DtsFile) {
+        for primary in &file.elements {
+            match primary {
+                Primary::Directive(AnyDirective::Memreserve(memreserve)) => {
+                    self.tree.reservations.push(Reservation {
+                        address: memreserve.address(),
+                        length: memreserve.length(),
+                    })
+                }
+                Primary::Directive(AnyDirective::Include(include)) => {
+                    let Ok(path) = include.path() else {
+                        continue;
+                    };
+                    if self.include_stack.contains(&path) {
+                        continue;
+                    }
+                    let Some(included) = self.project.get_root(&path) else {
+                        continue;
+                    };
+                    self.included.insert(path.clone());
+                    self.include_stack.push(path);
+                    self.add_file(included);
+                    self.include_stack.pop();
+                }
+                Primary::Directive(AnyDirective::DeletedNode(_, reference)) => {
+                    if let Some(path) = self.resolve(reference) {
+                        self.delete_node(&path);
+                    }
+                }
+                Primary::Directive(AnyDirective::OmitIfNoRef(_, reference)) => {
+                    if let Some(path) = self.resolve(reference) {
+                        if let Some(node) = self.tree.node_mut(&path) {
+                            node.omit_if_no_ref = true;
+                        }
+                    }
+                }
+                Primary::Directive(_) => {}
+                Primary::Root(root) => self.merge_node(&Path::empty(), root),
+                Primary::ReferencedNode(referenced) if self.is_plugin => {
+                    self.add_fragment(referenced)
+                }
+                Primary::ReferencedNode(referenced) => {
+                    if let Some(path) = self.resolve(&referenced.reference) {
+                        self.merge_payload(&path, &referenced.payload);
+                    }
+                }
+            }
+        }
+    }
+
+    // Adds a fragment with the contents of a referenced node of an overlay.
+    // Labels are targeted by phandle and paths by the `target-path` property.
+    fn add_fragment(&mut self, referenced: &ReferencedNode) {
+        let reference = &referenced.reference;
+        let target = match reference.item() {
+            Reference::Label(_) => TreeProperty {
+                name: "target".to_string(),
+                labels: vec![],
+                data: Data {
+                    bytes: vec![0xff; 4],
+                    markers: vec![
+                        Marker {
+                            offset: 0,
+                            kind: MarkerKind::Type(ValueType::Cells(32)),
+                        },
+                        Marker {
+                            offset: 0,
+                            kind: MarkerKind::Phandle(reference.item().clone()),
+                        },
+                    ],
+                },
+                location: Some(Location::new(reference.source(), reference.span())),
+            },
+            Reference::Path(path) => {
+                let mut bytes = path.to_string().into_bytes();
+                bytes.push(0);
+                TreeProperty {
+                    name: "target-path".to_string(),
+                    labels: vec![],
+                    data: Data {
+                        bytes,
+                        markers: vec![Marker {
+                            offset: 0,
+                            kind: MarkerKind::Type(ValueType::String),
+                        }],
+                    },
+                    location: Some(Location::new(reference.source(), reference.span())),
+                }
+            }
+        };
+        let name = NodeName::with_address("fragment", self.fragment_count.to_string());
+        self.fragment_count += 1;
+        let mut fragment = TreeNode::new(name.clone());
+        fragment.location = Some(Location::new(reference.source(), referenced.span()));
+        fragment.properties.push(target);
+        fragment
+            .children
+            .push(TreeNode::new(NodeName::simple("__overlay__")));
+        self.tree.root.children.push(fragment);
+        let path = Path::empty()
+            .with_child(name)
+            .with_child(NodeName::simple("__overlay__"));
+        self.merge_payload(&path, &referenced.payload);
+    }
+
+    fn was_deleted(&self, reference: &Reference) -> bool {
+        match reference {
+            Reference::Label(label) => self.deleted_labels.contains(label),
+            Reference::Path(path) => self
+                .deleted_paths
+                .iter()
+                .any(|deleted| path.starts_with(deleted)),
+        }
+    }
+
+    // References that cannot be resolved at all are reported during analysis.
+    // Only references to nodes that have been deleted are reported here.
+    fn resolve(&mut self, reference: &WithToken<Reference>) -> Option<Path> {
+        let path = self.tree.resolve(reference.item());
+        if path.is_none() && self.was_deleted(reference.item()) {
+            self.add_diagnostic(Diagnostic::new(
+                reference.span(),
+                reference.source(),
+                ErrorCode::ReferenceToDeletedNode,
+                format!("Reference {} points to a deleted node", reference.item()),
+            ));
+        }
+        path
+    }
+
+    fn delete_node(&mut self, path: &Path) {
+        let Some(node) = self.tree.remove_node(path) else {
+            return;
+        };
+        node.collect_labels(&mut self.deleted_labels);
+        self.deleted_paths.push(path.clone());
+    }
+
+    fn merge_node(&mut self, path: &Path, node: &Node) {
+        let Some(target) = self.tree.node_mut(path) else {
+            return;
+        };
+        if let Some(label) = &node.label {
+            target.add_label(label.item());
+        }
+        if target.location.is_none() {
+            target.location = Some(Location::new(node.name.source(), node.span()));
+        }
+        if node.omit_if_no_ref.is_some() {
+            target.omit_if_no_ref = true;
+        }
+        self.merge_payload(path, &node.payload);
+    }
+
+    fn merge_payload(&mut self, path: &Path, payload: &NodePayload) {
+        for item in &payload.items {
+            let Some(target) = self.tree.node_mut(path) else {
+                return;
+            };
+            match item {
+                NodeItem::Property(property) => target.set_property(property.as_ref().into()),
+                NodeItem::Node(node) => {
+                    let name = node.name.item();
+                    if target.child(name).is_none() {
+                        target.children.push(TreeNode::new(name.clone()));
+                    }
+                    self.merge_node(&path.with_child(name.clone()), node);
+                }
+                NodeItem::DeletedNode(_, name) => {
+                    if target.child(name.item()).is_some() {
+                        self.delete_node(&path.with_child(name.item().clone()));
+                    } else {
+                        self.add_diagnostic(Diagnostic::new(
+                            name.span(),
+                            name.source(),
+                            ErrorCode::DeleteNonexistent,
+                            format!("Cannot delete node '{}': no such node", name.item()),
+                        ));
+                    }
+                }
+                NodeItem::DeletedProperty(_, name) => {
+                    match target
+                        .properties
+                        .iter()
+                        .position(|property| property.name == *name.item())
+                    {
+                        Some(idx) => {
+                            target.properties.remove(idx);
+                        }
+                        None => self.add_diagnostic(Diagnostic::new(
+                            name.span(),
+                            name.source(),
+                            ErrorCode::DeleteNonexistent,
+                            format!("Cannot delete property '{}': no such property", name.item()),
+                        )),
+                    }
+                }
+            }
+        }
+    }
+
+    fn referenced_paths(&self) -> HashSet<Path> {
+        let mut referenced = HashSet::new();
+        self.tree.walk(&mut |_, node| {
+            for property in &node.properties {
+                for reference in property.data.references() {
+                    referenced.extend(self.tree.resolve(reference));
+                }
+            }
+        });
+        referenced
+    }
+
+    // Removes nodes marked with `/omit-if-no-ref/` that are not referenced by any property
+    fn prune_unreferenced(&mut self) {
+        let referenced = self.referenced_paths();
+        let mut unreferenced = vec![];
+        self.tree.walk(&mut |path, node| {
+            let parent_pruned = unreferenced
+                .iter()
+                .any(|pruned: &Path| path.starts_with(pruned));
+            if node.omit_if_no_ref && !referenced.contains(path) && !parent_pruned {
+                unreferenced.push(path.clone());
+            }
+        });
+        for path in unreferenced {
+            self.tree.remove_node(&path);
+        }
+    }
+
+    // Reports references in property values that point to deleted nodes
+    fn check_references(&mut self) {
+        let mut diagnostics = vec![];
+        self.tree.walk(&mut |_, node| {
+            for property in &node.properties {
+                let Some(location) = &property.location else {
+                    continue;
+                };
+                for reference in property.data.references() {
+                    if self.tree.resolve(reference).is_none() && self.was_deleted(reference) {
+                        diagnostics.push(Diagnostic::new(
+                            location.span(),
+                            location.source(),
+                            ErrorCode::ReferenceToDeletedNode,
+                            format!("Reference {reference} points to a deleted node"),
+                        ));
+                    }
+                }
+            }
+        });
+        self.diagnostics.extend(diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dts::ast::{NodeName, Path, Reference};
+    use crate::dts::data::{HasSource, HasSpan};
+    use crate::dts::error_codes::ErrorCode;
+    use crate::dts::test::Code;
+    use crate::dts::tree::{Data, Marker, MarkerKind, Reservation, TypedValue, ValueType};
+    use crate::dts::Diagnostic;
+    use assert_unordered::assert_eq_unordered;
+    use itertools::Itertools;
+
+    fn marker(offset: usize, kind: MarkerKind) -> Marker {
+        Marker { offset, kind }
+    }
+
+    #[test]
+    pub fn merges_root_and_referenced_nodes() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+/memreserve/ 0x1000 0x100;
+
+/ {
+    model = \"first\";
+    uart0: serial@1000 {
+        status = \"disabled\";
+        reg = <0x1000 0x100>;
+    };
+};
+
+/ {
+    model = \"second\";
+    cpus {};
+};
+
+&uart0 {
+    status = \"okay\";
+    current-speed = <115200>;
+};
+
+&{/cpus} {
+    cpu0: cpu@0 {};
+};",
+        );
+        let (diagnostics, context) = code.get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        let tree = context.tree();
+        assert_eq!(
+            tree.reservations,
+            vec![Reservation {
+                address: 0x1000,
+                length: 0x100
+            }]
+        );
+        assert_eq!(tree.root.property("model").unwrap().data.bytes, b"second\0");
+        assert_eq!(
+            tree.root
+                .children
+                .iter()
+                .map(|child| child.name.to_string())
+                .collect_vec(),
+            vec!["serial@1000", "cpus"]
+        );
+
+        let uart = tree.node(&Path::from("/serial@1000")).unwrap();
+        assert_eq!(uart.labels, vec!["uart0"]);
+        assert_eq!(
+            uart.properties
+                .iter()
+                .map(|property| property.name.as_str())
+                .collect_vec(),
+            vec!["status", "reg", "current-speed"]
+        );
+        assert_eq!(uart.property("status").unwrap().data.bytes, b"okay\0");
+
+        assert_eq!(tree.path_of_label("cpu0"), Some(Path::from("/cpus/cpu@0")));
+        assert_eq!(
+            tree.node_by_reference(&Reference::Label("cpu0".into()))
+                .map(|node| &node.name),
+            Some(&NodeName::with_address("cpu", "0"))
+        );
+        assert!(tree.resolve(&Reference::Path("/gpio".into())).is_none());
+    }
+
+    #[test]
+    pub fn evaluates_property_values() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    node: node {
+        cells = <1 (1 + 1) mid: &node>, /bits/ 16 <0x1234>, /bits/ 8 <0xab>;
+        mixed = \"ab\", start: [01 02], &node;
+    };
+};",
+        );
+        // References are resolved; `node` is assigned the first free phandle
+        let (diagnostics, context) = code.get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        let node = context.tree().node(&Path::from("/node")).unwrap();
+        assert_eq!(
+            node.property("cells").unwrap().data,
+            Data {
+                bytes: vec![0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1, 0x12, 0x34, 0xab],
+                markers: vec![
+                    marker(0, MarkerKind::Type(ValueType::Cells(32))),
+                    marker(8, MarkerKind::Label("mid".into())),
+                    marker(8, MarkerKind::Phandle(Reference::Label("node".into()))),
+                    marker(12, MarkerKind::Type(ValueType::Cells(16))),
+                    marker(14, MarkerKind::Type(ValueType::Cells(8))),
+                ]
+            }
+        );
+        assert_eq!(
+            node.property("mixed").unwrap().data,
+            Data {
+                bytes: b"ab\0\x01\x02/node\0".to_vec(),
+                markers: vec![
+                    marker(0, MarkerKind::Type(ValueType::String)),
+                    marker(3, MarkerKind::Label("start".into())),
+                    marker(3, MarkerKind::Type(ValueType::Bytes)),
+                    marker(5, MarkerKind::Type(ValueType::String)),
+                    marker(5, MarkerKind::Path(Reference::Label("node".into()))),
+                ]
+            }
+        );
+    }
+
+    #[test]
+    pub fn deletes_nodes_and_properties() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    parent {
+        prop = <1>;
+        other = <2>;
+        keep: keep {};
+        gone: gone {
+            nested: nested {};
+        };
+    };
+    by_ref: by-ref {};
+};
+
+&{/parent} {
+    /delete-node/ gone;
+    /delete-property/ prop;
+};
+
+/delete-node/ &by_ref;",
+        );
+        let (diagnostics, context) = code.get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        let tree = context.tree();
+        let parent = tree.node(&Path::from("/parent")).unwrap();
+        assert_eq!(
+            parent
+                .children
+                .iter()
+                .map(|child| child.name.to_string())
+                .collect_vec(),
+            vec!["keep"]
+        );
+        assert!(parent.property("prop").is_none());
+        assert!(parent.property("other").is_some());
+        assert!(tree.node(&Path::from("/by-ref")).is_none());
+
+        assert!(context.get_node_by_label("keep").is_some());
+        for label in ["gone", "nested", "by_ref"] {
+            assert!(context.get_node_by_label(label).is_none());
+        }
+        assert!(context
+            .get_node_by_path(&Path::from("/parent/gone/nested"))
+            .is_none());
+    }
+
+    #[test]
+    pub fn erroneous_deletions() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    node: node {
+        /delete-node/ missing;
+        /delete-property/ missing-prop;
+    };
+    user {
+        ref = <&node>;
+    };
+};
+
+/delete-node/ &node;
+
+&node {};",
+        );
+        let (diagnostics, _) = code.get_analyzed_file();
+        assert_eq_unordered!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    code.s1("missing;").s1("missing").span(),
+                    code.source(),
+                    ErrorCode::DeleteNonexistent,
+                    "Cannot delete node 'missing': no such node"
+                ),
+                Diagnostic::new(
+                    code.s1("missing-prop").span(),
+                    code.source(),
+                    ErrorCode::DeleteNonexistent,
+                    "Cannot delete property 'missing-prop': no such property"
+                ),
+                Diagnostic::new(
+                    code.s1("&node {}").s1("&node").span(),
+                    code.source(),
+                    ErrorCode::ReferenceToDeletedNode,
+                    "Reference &node points to a deleted node"
+                ),
+                Diagnostic::new(
+                    code.s1("ref = <&node>;").span(),
+                    code.source(),
+                    ErrorCode::ReferenceToDeletedNode,
+                    "Reference &node points to a deleted node"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn prunes_unreferenced_nodes() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    /omit-if-no-ref/ used: used {};
+    /omit-if-no-ref/ unused {
+        child {};
+    };
+    by_directive: by-directive {};
+    user {
+        ref = <&used>;
+    };
+};
+
+/omit-if-no-ref/ &by_directive;",
+        );
+        let (diagnostics, context) = code.get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(
+            context
+                .tree()
+                .root
+                .children
+                .iter()
+                .map(|child| child.name.to_string())
+                .collect_vec(),
+            vec!["used", "user"]
+        );
+    }
+
+    #[test]
+    pub fn splits_data_into_typed_values() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    target: target {};
+    node {
+        mixed = \"a\", <1 &target>, /bits/ 16 <2>, [03 04], &target;
+    };
+};",
+        );
+        let (diagnostics, context) = code.get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        let tree = context.tree();
+        let target = Reference::Label("target".to_string());
+        assert_eq!(
+            tree.node(&Path::from("/node"))
+                .unwrap()
+                .property("mixed")
+                .unwrap()
+                .data
+                .typed_values(),
+            vec![
+                TypedValue::String(b"a", None),
+                TypedValue::Integers(32, vec![(1, None), (1, Some(&target))]),
+                TypedValue::Integers(16, vec![(2, None)]),
+                TypedValue::Integers(8, vec![(3, None), (4, None)]),
+                TypedValue::String(b"/target", Some(&target)),
+            ]
+        );
+
+        // Data without type information is split according to its guessed type
+        let data = Data {
+            bytes: b"first\0second\0".to_vec(),
+            markers: vec![],
+        };
+        assert_eq!(
+            data.typed_values(),
+            vec![
+                TypedValue::String(b"first", None),
+                TypedValue::String(b"second", None)
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn serializes_tree_as_json() {
+        let code = Code::new(
+            "\
+/dts-v1/;
+
+/ {
+    target: target {};
+    node@10 {
+        values = \"a\", <1 &target>, /bits/ 8 <2>, &target;
+    };
+};",
+        );
+        let (diagnostics, context) = code.get_analyzed_file();
+        assert_eq!(diagnostics, vec![]);
+        let node = context.tree().node(&Path::from("/node@10")).unwrap();
+        let json = serde_json::to_value(node).unwrap();
+        assert_eq!(json["name"], "node@10");
+        assert_eq!(json["location"]["span"]["start"]["line"], 4);
+        assert_eq!(
+            json["properties"][0]["values"],
+            serde_json::json!([
+                {"type": "string", "value": "a"},
+                {
+                    "type": "u32",
+                    "value": [1, 1],
+                    "references": [{"index": 1, "reference": "&target"}]
+                },
+                {"type": "u8", "value": [2]},
+                {"type": "string", "value": "/target", "reference": "&target"},
+            ])
+        );
+    }
+}
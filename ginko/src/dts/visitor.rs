@@ -2,7 +2,8 @@ use crate::dts::ast::{
     AnyDirective, Cell, DtsFile, Include, Node, NodePayload, Primary, Property, PropertyValue,
     Reference, ReferencedNode, WithToken,
 };
-use crate::dts::{HasSpan, NodeItem, Position};
+use crate::dts::{HasSource, HasSpan, NodeItem, Position};
+use std::path::Path;
 
 #[allow(unused)]
 pub enum ItemAtCursor<'a> {
@@ -13,10 +14,16 @@ pub enum ItemAtCursor<'a> {
     Include(&'a Include),
 }
 
+/// Returns whether `item` is located at the cursor in `source`.
+/// Files can contain items from other files when they include them using `#include`.
+fn is_at_cursor(item: &(impl HasSpan + HasSource), source: &Path, cursor: &Position) -> bool {
+    item.span().contains(cursor) && *item.source() == *source
+}
+
 impl DtsFile {
-    pub fn item_at_cursor(&self, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+    pub fn item_at_cursor(&self, source: &Path, cursor: &Position) -> Option<ItemAtCursor<'_>> {
         for element in &self.elements {
-            if let Some(item) = element.item_at_cursor(cursor) {
+            if let Some(item) = element.item_at_cursor(source, cursor) {
                 return Some(item);
             }
         }
@@ -25,30 +32,28 @@ impl DtsFile {
 }
 
 impl Primary {
-    pub fn item_at_cursor(&self, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+    pub fn item_at_cursor(&self, source: &Path, cursor: &Position) -> Option<ItemAtCursor<'_>> {
         match self {
-            Primary::Directive(directive) => directive.item_at_cursor(cursor),
-            Primary::Root(root) => root.item_at_cursor(cursor),
-            Primary::ReferencedNode(node) => node.item_at_cursor(cursor),
-            Primary::CStyleInclude(_) => None,
+            Primary::Directive(directive) => directive.item_at_cursor(source, cursor),
+            Primary::Root(root) => root.item_at_cursor(source, cursor),
+            Primary::ReferencedNode(node) => node.item_at_cursor(source, cursor),
         }
     }
 }
 
 impl AnyDirective {
-    pub fn item_at_cursor(&self, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+    pub fn item_at_cursor(&self, source: &Path, cursor: &Position) -> Option<ItemAtCursor<'_>> {
         match self {
             AnyDirective::Include(include) => {
-                if include.span().contains(cursor) {
+                if is_at_cursor(include, source, cursor) {
                     Some(ItemAtCursor::Include(include))
                 } else {
                     None
                 }
             }
-            AnyDirective::DeletedNode(_, node_ref) => {
-                Some(ItemAtCursor::Reference(node_ref.item()))
-            }
-            AnyDirective::OmitIfNoRef(_, node_ref) => {
+            AnyDirective::DeletedNode(_, node_ref) | AnyDirective::OmitIfNoRef(_, node_ref)
+                if is_at_cursor(node_ref, source, cursor) =>
+            {
                 Some(ItemAtCursor::Reference(node_ref.item()))
             }
             _ => None,
@@ -57,29 +62,29 @@ impl AnyDirective {
 }
 
 impl ReferencedNode {
-    pub fn item_at_cursor(&self, cursor: &Position) -> Option<ItemAtCursor<'_>> {
-        if self.reference.span().contains(cursor) {
+    pub fn item_at_cursor(&self, source: &Path, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+        if is_at_cursor(&self.reference, source, cursor) {
             return Some(ItemAtCursor::Reference(self.reference.item()));
         }
-        self.payload.item_at_cursor(cursor)
+        self.payload.item_at_cursor(source, cursor)
     }
 }
 
 impl Node {
-    pub fn item_at_cursor(&self, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+    pub fn item_at_cursor(&self, source: &Path, cursor: &Position) -> Option<ItemAtCursor<'_>> {
         if let Some(label) = &self.label {
-            if label.span().contains(cursor) {
+            if is_at_cursor(label, source, cursor) {
                 return Some(ItemAtCursor::Label(label));
             }
         }
-        self.payload.item_at_cursor(cursor)
+        self.payload.item_at_cursor(source, cursor)
     }
 }
 
 impl NodePayload {
-    pub fn item_at_cursor(&self, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+    pub fn item_at_cursor(&self, source: &Path, cursor: &Position) -> Option<ItemAtCursor<'_>> {
         for node in &self.items {
-            if let Some(item) = node.item_at_cursor(cursor) {
+            if let Some(item) = node.item_at_cursor(source, cursor) {
                 return Some(item);
             }
         }
@@ -88,10 +93,10 @@ impl NodePayload {
 }
 
 impl NodeItem {
-    pub fn item_at_cursor(&self, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+    pub fn item_at_cursor(&self, source: &Path, cursor: &Position) -> Option<ItemAtCursor<'_>> {
         match self {
-            NodeItem::Property(property) => property.item_at_cursor(cursor),
-            NodeItem::Node(node) => node.item_at_cursor(cursor),
+            NodeItem::Property(property) => property.item_at_cursor(source, cursor),
+            NodeItem::Node(node) => node.item_at_cursor(source, cursor),
             NodeItem::DeletedNode(..) => None,
             NodeItem::DeletedProperty(..) => None,
         }
@@ -99,14 +104,14 @@ impl NodeItem {
 }
 
 impl Property {
-    pub fn item_at_cursor(&self, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+    pub fn item_at_cursor(&self, source: &Path, cursor: &Position) -> Option<ItemAtCursor<'_>> {
         if let Some(label) = &self.label {
-            if label.span().contains(cursor) {
+            if is_at_cursor(label, source, cursor) {
                 return Some(ItemAtCursor::Label(label));
             }
         }
         for value in &self.values {
-            if let Some(item) = value.item_at_cursor(cursor) {
+            if let Some(item) = value.item_at_cursor(source, cursor) {
                 return Some(item);
             }
         }
@@ -115,15 +120,21 @@ impl Property {
 }
 
 impl PropertyValue {
-    pub fn item_at_cursor(&self, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+    pub fn item_at_cursor(&self, source: &Path, cursor: &Position) -> Option<ItemAtCursor<'_>> {
+        if let Some(label) = self
+            .labels()
+            .find(|label| is_at_cursor(*label, source, cursor))
+        {
+            return Some(ItemAtCursor::Label(label));
+        }
         match self {
-            PropertyValue::String(_) => None,
-            PropertyValue::Cells(_, cells, _) => {
+            PropertyValue::String(_) | PropertyValue::Label(_) => None,
+            PropertyValue::Cells(_, _, cells, _) => {
                 for cell in cells {
                     match cell {
-                        Cell::Number(_) | Cell::Expression => {}
+                        Cell::Number(_) | Cell::Expression(..) | Cell::Label(_) => {}
                         Cell::Reference(reference) => {
-                            if reference.span().contains(cursor) {
+                            if is_at_cursor(reference, source, cursor) {
                                 return Some(ItemAtCursor::Reference(reference.item()));
                             }
                         }
@@ -132,13 +143,13 @@ impl PropertyValue {
                 None
             }
             PropertyValue::Reference(reference) => {
-                if reference.span().contains(cursor) {
+                if is_at_cursor(reference, source, cursor) {
                     Some(ItemAtCursor::Reference(reference.item()))
                 } else {
                     None
                 }
             }
-            PropertyValue::ByteStrings(..) => None,
+            PropertyValue::ByteStrings(..) | PropertyValue::Incbin(..) => None,
         }
     }
 }
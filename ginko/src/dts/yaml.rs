@@ -0,0 +1,265 @@
+use crate::dts::tree::{Data, DeviceTree, TreeNode, TypedValue};
+
+// Lines are wrapped in between values once they exceed this column, like libyaml does
+const LINE_WIDTH: usize = 80;
+
+// Writes YAML in the layout of libyaml, which dtc uses to emit YAML
+struct Emitter {
+    yaml: String,
+    column: usize,
+}
+
+impl Emitter {
+    fn write(&mut self, text: &str) {
+        self.yaml.push_str(text);
+        match text.rfind('\n') {
+            Some(line_start) => self.column = text.len() - line_start - 1,
+            None => self.column += text.len(),
+        }
+    }
+
+    fn line_break(&mut self, indent: usize) {
+        self.write("\n");
+        self.write(&" ".repeat(indent));
+    }
+
+    // Separates the items of a flow sequence.
+    // Items are moved to the next line if the current line is too long.
+    fn separator(&mut self, first: bool, indent: usize) {
+        if !first {
+            self.write(",");
+        }
+        if self.column > LINE_WIDTH {
+            self.line_break(indent);
+        } else if !first {
+            self.write(" ");
+        }
+    }
+
+    // Keys are written as plain scalars unless they start with an indicator character
+    fn key(&mut self, key: &str) {
+        let needs_quotes = key.is_empty()
+            || key.starts_with([
+                '#', ',', '[', ']', '{', '}', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`',
+            ])
+            || key.starts_with("---")
+            || key.starts_with("...");
+        if needs_quotes {
+            self.write(&format!("'{}':", key.replace('\'', "''")));
+        } else {
+            self.write(&format!("{key}:"));
+        }
+    }
+
+    fn string(&mut self, string: &[u8], indent: usize) {
+        self.write("\"");
+        let mut spaces = false;
+        for (i, byte) in string.iter().enumerate() {
+            if *byte == b' ' {
+                // Long strings are broken at spaces. An escaped space keeps a following space.
+                if !spaces && self.column > LINE_WIDTH && i != 0 && i != string.len() - 1 {
+                    self.line_break(indent);
+                    if string.get(i + 1) == Some(&b' ') {
+                        self.write("\\");
+                    }
+                } else {
+                    self.write(" ");
+                }
+                spaces = true;
+                continue;
+            }
+            spaces = false;
+            let escaped = match byte {
+                0 => "\\0".to_string(),
+                0x07 => "\\a".to_string(),
+                0x08 => "\\b".to_string(),
+                b'\t' => "\\t".to_string(),
+                b'\n' => "\\n".to_string(),
+                0x0b => "\\v".to_string(),
+                0x0c => "\\f".to_string(),
+                b'\r' => "\\r".to_string(),
+                0x1b => "\\e".to_string(),
+                b'"' => "\\\"".to_string(),
+                b'\\' => "\\\\".to_string(),
+                0x20..=0x7e => (*byte as char).to_string(),
+                _ => format!("\\x{byte:02X}"),
+            };
+            self.write(&escaped);
+        }
+        self.write("\"");
+    }
+
+    fn value(&mut self, data: &Data, indent: usize) {
+        let values = data.typed_values();
+        if values.is_empty() {
+            self.write(" true");
+            return;
+        }
+        self.write(" [");
+        for (i, value) in values.iter().enumerate() {
+            self.separator(i == 0, indent);
+            match value {
+                TypedValue::String(string, _) => self.string(string, indent),
+                TypedValue::Integers(bits, integers) => {
+                    match bits {
+                        8 => self.write("!u8 "),
+                        16 => self.write("!u16 "),
+                        64 => self.write("!u64 "),
+                        _ => {}
+                    }
+                    self.write("[");
+                    for (j, (integer, reference)) in integers.iter().enumerate() {
+                        self.separator(j == 0, indent + 2);
+                        if reference.is_some() {
+                            self.write("!phandle ");
+                        }
+                        self.write(&format!("{integer:#x}"));
+                    }
+                    self.write("]");
+                }
+            }
+        }
+        self.write("]");
+    }
+
+    // Writes the properties and children of a node as a mapping
+    // whose keys are indented by `indent`.
+    // The first key of the root node follows the `- ` of the enclosing sequence.
+    fn node(&mut self, node: &TreeNode, indent: usize, is_root: bool) {
+        if node.properties.is_empty() && node.children.is_empty() {
+            self.write(" {}");
+            return;
+        }
+        if is_root {
+            self.write(" ");
+        }
+        let mut first = is_root;
+        for property in &node.properties {
+            if !first {
+                self.line_break(indent);
+            }
+            first = false;
+            self.key(&property.name);
+            self.value(&property.data, indent + 2);
+        }
+        for child in &node.children {
+            if !first {
+                self.line_break(indent);
+            }
+            first = false;
+            self.key(&child.name.to_string());
+            self.node(child, indent + 2, false);
+        }
+    }
+}
+
+impl DeviceTree {
+    /// Prints this tree as YAML, using the encoding of `dtc -O yaml`.
+    /// The root node is the only element of a sequence and nodes are mappings of
+    /// their properties and children. Values of properties are lists of strings
+    /// and arrays of integers. Arrays of integers that are not 32 bits wide are tagged
+    /// with their width (`!u8`, `!u16` or `!u64`) and phandles are tagged with `!phandle`.
+    /// Properties without a value are `true`.
+    pub fn to_yaml(&self) -> String {
+        let mut emitter = Emitter {
+            yaml: String::new(),
+            column: 0,
+        };
+        emitter.write("---\n-");
+        emitter.node(&self.root, 2, true);
+        emitter.write("\n...\n");
+        emitter.yaml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dts::test::compile_tree;
+    use crate::dts::tree::DeviceTree;
+
+    #[test]
+    fn empty_tree() {
+        assert_eq!(
+            compile_tree("/dts-v1/; / {};").to_yaml(),
+            "---\n- {}\n...\n"
+        );
+    }
+
+    #[test]
+    fn typed_values() {
+        let tree = compile_tree(
+            r#"
+/dts-v1/;
+/ {
+    #address-cells = <1>;
+    compatible = "vendor,board", "vendor,soc";
+    intc: interrupt-controller {
+        interrupt-controller;
+    };
+    node@10 {
+        reg = <0x10 0x20>, <0x30 0x40>;
+        interrupt-parent = <&intc>;
+        mac = [00 11 22];
+        wide = /bits/ 64 <0x100000000>, /bits/ 16 <1 2>;
+        escaped = "tab\there", "quote\"";
+        path = &intc;
+        empty {};
+    };
+};"#,
+        );
+        assert_eq!(
+            tree.to_yaml(),
+            r#"---
+- '#address-cells': [[0x1]]
+  compatible: ["vendor,board", "vendor,soc"]
+  interrupt-controller:
+    interrupt-controller: true
+    phandle: [[0x1]]
+  node@10:
+    reg: [[0x10, 0x20], [0x30, 0x40]]
+    interrupt-parent: [[!phandle 0x1]]
+    mac: [!u8 [0x0, 0x11, 0x22]]
+    wide: [!u64 [0x100000000], !u16 [0x1, 0x2]]
+    escaped: ["tab\there", "quote\""]
+    path: ["/interrupt-controller"]
+    empty: {}
+...
+"#
+        );
+    }
+
+    #[test]
+    fn untyped_values() {
+        let blob = compile_tree(
+            "/dts-v1/; / { strings = \"a\", \"b\"; cells = <1 2>; bytes = [01 02 03]; };",
+        )
+        .to_dtb(&Default::default());
+        let tree = DeviceTree::from_dtb(&blob).unwrap();
+        assert_eq!(
+            tree.to_yaml(),
+            r#"---
+- strings: ["a", "b"]
+  cells: [[0x1, 0x2]]
+  bytes: [!u8 [0x1, 0x2, 0x3]]
+...
+"#
+        );
+    }
+
+    #[test]
+    fn wraps_long_values() {
+        let tree = compile_tree(
+            "/dts-v1/; / { node { ranges = <0x10000000 0x20000000 0x30000000 0x40000000 \
+             0x50000000 0x60000000 0x70000000 0x80000000 0x90000000>; }; };",
+        );
+        assert_eq!(
+            tree.to_yaml(),
+            "---
+- node:
+    ranges: [[0x10000000, 0x20000000, 0x30000000, 0x40000000, 0x50000000, 0x60000000,
+        0x70000000, 0x80000000, 0x90000000]]
+...
+"
+        );
+    }
+}
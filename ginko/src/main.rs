@@ -1,45 +1,448 @@
 use clap::Parser;
-use ginko::dts::{DiagnosticPrinter, Project, SeverityMap};
+#[cfg(feature = "serde")]
+use ginko::dts::DtsFile;
+use ginko::dts::{
+    format_source, parse_int, DeviceTree, Diagnostic, DiagnosticPrinter, DtbOptions, FormatOptions,
+    PhandleStyle, Project, Severity, SeverityMap,
+};
 use itertools::Itertools;
 use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::Arc;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+enum InputFormat {
+    /// Device-tree source
+    Dts,
+    /// Flattened device tree blob
+    Dtb,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    /// Device-tree source
+    Dts,
+    /// Flattened device tree blob
+    Dtb,
+    /// YAML as emitted by `dtc -O yaml`, e.g., for validation with dt-schema
+    Yaml,
+    /// Assembler source as emitted by `dtc -O asm`, e.g., for linking the blob into firmware
+    Asm,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum PhandleFormat {
+    /// `phandle` property as defined by the Devicetree Specification
+    Epapr,
+    /// Legacy `linux,phandle` property
+    Linux,
+    /// Both properties
+    Both,
+}
+
+impl From<PhandleFormat> for PhandleStyle {
+    fn from(format: PhandleFormat) -> Self {
+        match format {
+            PhandleFormat::Epapr => PhandleStyle::Epapr,
+            PhandleFormat::Linux => PhandleStyle::Linux,
+            PhandleFormat::Both => PhandleStyle::Both,
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Format device-tree source files in place
+    Fmt(FmtArgs),
+    /// Export the syntax trees and the resolved device tree of a source file
+    #[cfg(feature = "serde")]
+    Export(ExportArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct FmtArgs {
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    #[arg(
+        long,
+        help = "Only check whether the files are formatted. Exits with 1 if any file is not"
+    )]
+    check: bool,
+    #[arg(long, help = "Indent using this many spaces instead of tabs")]
+    spaces: Option<usize>,
+    #[arg(
+        long,
+        default_value = "80",
+        help = "Wrap properties that exceed this width"
+    )]
+    line_width: usize,
+}
+
+#[cfg(feature = "serde")]
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ExportFormat {
+    /// JSON with the syntax trees of all files and the resolved device tree
+    Json,
+}
+
+#[cfg(feature = "serde")]
+#[derive(clap::Args, Debug)]
+struct ExportArgs {
+    file: String,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        default_value = "json",
+        help = "Export format"
+    )]
+    format: ExportFormat,
+    #[arg(short, long, help = "Add a path to search for include files")]
+    include: Option<Vec<String>>,
+    #[arg(short, long, help = "Write the export to a file")]
+    out: Option<PathBuf>,
+}
+
+// The syntax trees of the exported file and the files that it includes,
+// ordered by their path, together with the resolved device tree
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct Export<'a> {
+    files: Vec<&'a DtsFile>,
+    tree: &'a DeviceTree,
+}
 
 #[derive(clap::Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    file: Option<String>,
     #[arg(short, long, help = "Add a path to search for include files")]
     include: Option<Vec<String>>,
+    #[arg(
+        short = 'I',
+        long,
+        value_enum,
+        help = "Input format. Guessed from the file extension if not given"
+    )]
+    in_format: Option<InputFormat>,
+    #[arg(short, long, help = "Write the compiled device tree to a file")]
+    out: Option<PathBuf>,
+    #[arg(
+        short = 'O',
+        long,
+        value_enum,
+        help = "Output format. Defaults to dtb for source files and to dts for blobs"
+    )]
+    out_format: Option<OutputFormat>,
+    #[arg(short, long, default_value = "0", value_parser = parse_number::<u32>, help = "Physical ID of the boot CPU")]
+    boot_cpu: u32,
+    #[arg(short, long, value_parser = parse_number::<usize>, help = "Add padding bytes to the blob")]
+    pad: Option<usize>,
+    #[arg(short = 'S', long, value_parser = parse_number::<usize>, help = "Make the blob at least this many bytes large")]
+    space: Option<usize>,
+    #[arg(short, long, value_parser = parse_alignment, help = "Align the blob size to a power of two")]
+    align: Option<usize>,
+    #[arg(
+        short = 'H',
+        long,
+        value_enum,
+        default_value = "epapr",
+        help = "Properties that hold the phandles of referenced nodes"
+    )]
+    phandle: PhandleFormat,
+    #[arg(
+        short = '@',
+        long,
+        help = "Add the __symbols__ node, which allows applying overlays"
+    )]
+    symbols: bool,
+    #[arg(long, help = "Restore labels from the __symbols__ node")]
+    restore_labels: bool,
+    #[arg(
+        long,
+        help = "Restore references in properties that are known to contain phandles"
+    )]
+    restore_references: bool,
+    #[arg(
+        long,
+        help = "Apply an overlay (source or blob) to the input. Can be given multiple times"
+    )]
+    overlay: Vec<String>,
+    #[arg(
+        long,
+        help = "Validate nodes against the binding schemas in this directory. Can be given multiple times"
+    )]
+    bindings: Vec<String>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    let mut project = Project::default();
+// Blobs are recognized by their file extension
+fn is_blob(file: &str) -> bool {
+    matches!(
+        Path::new(file).extension().and_then(|ext| ext.to_str()),
+        Some("dtb" | "dtbo")
+    )
+}
 
-    project.set_include_paths(args.include.unwrap_or_default());
-    project.add_file(args.file)?;
+impl Args {
+    fn file(&self) -> &str {
+        self.file.as_deref().unwrap_or_default()
+    }
 
-    let mut has_errors = false;
-    for file in project.project_files() {
-        let diag = file.diagnostics().cloned().collect_vec();
-        if diag.is_empty() {
-            continue;
+    fn in_format(&self) -> InputFormat {
+        self.in_format.unwrap_or(if is_blob(self.file()) {
+            InputFormat::Dtb
         } else {
-            has_errors = true;
+            InputFormat::Dts
+        })
+    }
+
+    fn out_format(&self) -> OutputFormat {
+        self.out_format.unwrap_or(match self.in_format() {
+            InputFormat::Dts => OutputFormat::Dtb,
+            InputFormat::Dtb => OutputFormat::Dts,
+        })
+    }
+
+    // Output is written to stdout if no output file is given.
+    // Source files are only checked if neither an output file, a format nor overlays are given.
+    fn has_output(&self) -> bool {
+        self.out.is_some()
+            || self.out_format.is_some()
+            || !self.overlay.is_empty()
+            || self.in_format() == InputFormat::Dtb
+    }
+}
+
+// Numbers are written as integer literals of device-tree sources
+fn parse_number<T: TryFrom<u64>>(value: &str) -> Result<T, String> {
+    let number = parse_int(value).map_err(|err| err.to_string())?;
+    T::try_from(number).map_err(|_| format!("{value} is out of range"))
+}
+
+fn parse_alignment(value: &str) -> Result<usize, String> {
+    let alignment: usize = parse_number(value)?;
+    if !alignment.is_power_of_two() {
+        return Err(format!("{value} is not a power of two"));
+    }
+    Ok(alignment)
+}
+
+fn write_output(mut tree: DeviceTree, args: &Args) -> Result<(), Box<dyn Error>> {
+    if args.restore_labels {
+        tree.restore_labels();
+    }
+    if args.restore_references {
+        tree.restore_references();
+    }
+    let options = DtbOptions {
+        boot_cpuid_phys: args.boot_cpu,
+        padding: args.pad,
+        min_size: args.space,
+        alignment: args.align,
+    };
+    let contents = match args.out_format() {
+        OutputFormat::Dts => tree.to_dts().into_bytes(),
+        OutputFormat::Yaml => tree.to_yaml().into_bytes(),
+        OutputFormat::Dtb => tree.to_dtb(&options),
+        OutputFormat::Asm => tree.to_asm(&options).into_bytes(),
+    };
+    match &args.out {
+        Some(out) => fs::write(out, contents)?,
+        None => io::stdout().write_all(&contents)?,
+    }
+    Ok(())
+}
+
+fn read_blob(file: &str) -> Result<DeviceTree, Box<dyn Error>> {
+    match DeviceTree::from_dtb(&fs::read(file)?) {
+        Ok(tree) => Ok(tree),
+        Err(err) => {
+            eprintln!("{file}: {err}");
+            exit(1);
         }
-        let code = file.source().lines().map(|it| it.to_owned()).collect_vec();
+    }
+}
+
+// Prints diagnostics grouped by the file they point into
+fn print_diagnostics(
+    diagnostics: impl IntoIterator<Item = Diagnostic>,
+    severity_map: SeverityMap,
+    text_of: impl Fn(&Path) -> String,
+) {
+    let diagnostics_by_source = diagnostics
+        .into_iter()
+        .into_group_map_by(|diagnostic| diagnostic.source.clone());
+    for (source, diagnostics) in diagnostics_by_source
+        .into_iter()
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+    {
+        let text = text_of(&source);
         let printer = DiagnosticPrinter {
-            code,
-            diagnostics: &diag,
-            severity_map: SeverityMap::default(),
+            code: text.lines().map(|it| it.to_owned()).collect_vec(),
+            diagnostics: &diagnostics,
+            severity_map,
         };
         println!("{}", printer);
     }
+}
+
+// Applies all overlays in the given order.
+// Returns whether applying any of the overlays caused an error.
+fn apply_overlays(
+    tree: &mut DeviceTree,
+    project: &Project,
+    args: &Args,
+    severity_map: SeverityMap,
+) -> Result<bool, Box<dyn Error>> {
+    let mut has_errors = false;
+    for file in &args.overlay {
+        let overlay = if is_blob(file) {
+            read_blob(file)?
+        } else {
+            project
+                .get_tree(Path::new(file))
+                .ok_or("The overlay could not be compiled")?
+                .clone()
+        };
+        let diagnostics = tree.apply_overlay(&overlay, Arc::from(Path::new(file)));
+        has_errors |= diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity(&severity_map) == Severity::Error);
+        // Blobs have no text to show
+        print_diagnostics(diagnostics, severity_map, |source| {
+            fs::read_to_string(source).unwrap_or_default()
+        });
+    }
+    Ok(has_errors)
+}
+
+// Formats files in place or checks whether they are formatted
+fn format_files(args: &FmtArgs) -> Result<(), Box<dyn Error>> {
+    let options = FormatOptions {
+        use_tabs: args.spaces.is_none(),
+        indent_width: args.spaces.unwrap_or(FormatOptions::default().indent_width),
+        line_width: args.line_width,
+    };
+    let mut unformatted = false;
+    for file in &args.files {
+        let text = fs::read_to_string(file)?;
+        let formatted = format_source(&text, &options);
+        if formatted == text {
+            continue;
+        }
+        if args.check {
+            println!("{} is not formatted", file.display());
+            unformatted = true;
+        } else {
+            fs::write(file, formatted)?;
+        }
+    }
+    if unformatted {
+        exit(1);
+    }
+    Ok(())
+}
+
+// Prints the diagnostics of all files of the project.
+// Returns whether there are any diagnostics and whether any of them is an error.
+fn check_files(project: &Project, severity_map: SeverityMap) -> (bool, bool) {
+    let mut has_diagnostics = false;
+    let mut has_errors = false;
+    for path in project.files() {
+        let file = project.get_file(path).unwrap();
+        has_errors |= file.has_errors(&severity_map);
+        has_diagnostics |= file.diagnostics().next().is_some();
+        // Diagnostics may point into files that are included using `#include`
+        print_diagnostics(file.diagnostics().cloned(), severity_map, |source| {
+            if source == path {
+                file.source().clone()
+            } else {
+                fs::read_to_string(source).unwrap_or_default()
+            }
+        });
+    }
+    (has_diagnostics, has_errors)
+}
+
+// Exports a source file and its includes. Files with errors are not exported.
+#[cfg(feature = "serde")]
+fn export_file(args: &ExportArgs) -> Result<(), Box<dyn Error>> {
+    let mut project = Project::default();
+    let severity_map = SeverityMap::default();
+    project.set_include_paths(args.include.clone().unwrap_or_default());
+    project.add_file(args.file.clone())?;
+    if check_files(&project, severity_map).1 {
+        exit(1);
+    }
+    let export = Export {
+        files: project
+            .files()
+            .sorted()
+            .filter_map(|path| project.get_root(path))
+            .collect(),
+        tree: project
+            .get_tree(Path::new(&args.file))
+            .ok_or("The input file could not be compiled")?,
+    };
+    let contents = match args.format {
+        ExportFormat::Json => serde_json::to_string_pretty(&export)?,
+    };
+    match &args.out {
+        Some(out) => fs::write(out, contents)?,
+        None => println!("{contents}"),
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::Fmt(fmt_args)) => return format_files(fmt_args),
+        #[cfg(feature = "serde")]
+        Some(Command::Export(export_args)) => return export_file(export_args),
+        None => {}
+    }
 
+    let mut project = Project::default();
+    let severity_map = SeverityMap::default();
+
+    project.set_include_paths(args.include.clone().unwrap_or_default());
+    project.set_binding_paths(args.bindings.clone())?;
+    project.phandle_style = args.phandle.into();
+    // Overlays can only refer to labels of the base tree if it has symbols
+    project.generate_symbols = args.symbols || !args.overlay.is_empty();
+    if args.in_format() == InputFormat::Dts {
+        project.add_file(args.file().to_string())?;
+    }
+    for overlay in args.overlay.iter().filter(|overlay| !is_blob(overlay)) {
+        project.add_file(overlay.clone())?;
+    }
+
+    let (has_diagnostics, has_errors) = check_files(&project, severity_map);
     if has_errors {
-        println!("OK; No issues found");
-        exit(0);
-    } else {
         exit(1);
     }
+    if args.has_output() {
+        let mut tree = match args.in_format() {
+            InputFormat::Dtb => read_blob(args.file())?,
+            InputFormat::Dts => project
+                .get_tree(Path::new(args.file()))
+                .ok_or("The input file could not be compiled")?
+                .clone(),
+        };
+        if apply_overlays(&mut tree, &project, &args, severity_map)? {
+            exit(1);
+        }
+        return write_output(tree, &args);
+    }
+    if !has_diagnostics {
+        println!("OK; No issues found");
+    }
+    Ok(())
 }
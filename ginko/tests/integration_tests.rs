@@ -41,3 +41,37 @@ fn no_diagnostics_for_file_with_delete_node() {
         .expect("File should be present");
     check_no_diagnostics(&project);
 }
+
+// Runs the command-line tool on a source file and returns its exit code and output
+fn check_source(source: &str) -> (i32, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let file_name = dir.path().join("test.dts");
+    std::fs::write(&file_name, source).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ginko"))
+        .arg(&file_name)
+        .output()
+        .unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn exit_code_reflects_errors() {
+    let (code, output) = check_source("/dts-v1/;\n/ {\n    node {};\n};\n");
+    assert_eq!(code, 0);
+    assert_eq!(output.trim(), "OK; No issues found");
+
+    // Warnings are printed, but do not fail the check
+    let (code, output) =
+        check_source("/dts-v1/;\n/ {\n    very-long-company,very-long-name;\n};\n");
+    assert_eq!(code, 0);
+    assert!(!output.contains("OK"));
+    assert!(output.contains("property should only have 31 characters"));
+
+    let (code, output) = check_source("/dts-v1/;\n/ {\n    node {}\n};\n");
+    assert_eq!(code, 1);
+    assert!(!output.contains("OK"));
+    assert!(output.contains("Expected ';'"));
+}
@@ -1,11 +1,12 @@
 use ginko::dts::{
-    AnyDirective, FileType, HasSpan, ItemAtCursor, Node, NodeItem, NodePayload, Primary, Project,
-    Severity, SeverityMap, Span,
+    AnyDirective, FileType, HasSource, HasSpan, ItemAtCursor, Node, NodeItem, NodePayload, Primary,
+    Project, Property, Severity, SeverityMap, Span,
 };
 use itertools::Itertools;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
@@ -16,6 +17,9 @@ pub(crate) struct Backend {
     client: Client,
     project: RwLock<Project>,
     severities: SeverityMap,
+    // Files that diagnostics were published for.
+    // Used to clear diagnostics of files that no longer have any.
+    published: RwLock<HashSet<PathBuf>>,
 }
 
 impl Backend {
@@ -24,6 +28,7 @@ impl Backend {
             client,
             project: RwLock::new(Project::default()),
             severities: SeverityMap::default(),
+            published: RwLock::new(HashSet::new()),
         }
     }
 }
@@ -31,6 +36,12 @@ impl Backend {
 #[derive(Deserialize, Serialize, Default, Debug)]
 struct ProjectConfig {
     pub includes: Vec<String>,
+    /// Maps overlays to the device-tree source that they are applied to
+    #[serde(default)]
+    pub overlays: HashMap<String, String>,
+    /// Directories with binding schemas that nodes are validated against
+    #[serde(default)]
+    pub bindings: Vec<String>,
 }
 
 impl ProjectConfig {
@@ -58,6 +69,16 @@ fn lsp_pos_from_pos(pos: ginko::dts::Position) -> Position {
 impl Backend {
     fn lsp_diag_from_diag(&self, diagnostic: &ginko::dts::Diagnostic) -> Diagnostic {
         let span = diagnostic.span();
+        let related_information = diagnostic.expansion_note().and_then(|note| {
+            let definition = diagnostic.expansion.as_ref()?.definition.as_ref()?;
+            Some(vec![DiagnosticRelatedInformation {
+                location: Location::new(
+                    Url::from_file_path(definition.source()).ok()?,
+                    lsp_range_from_span(definition.span()),
+                ),
+                message: note,
+            }])
+        });
         Diagnostic {
             range: lsp_range_from_span(span),
             message: diagnostic.message.clone(),
@@ -66,6 +87,7 @@ impl Backend {
                 diagnostic.severity(&self.severities),
             )),
             source: Some("ginko_ls".to_string()),
+            related_information,
             ..Default::default()
         }
     }
@@ -85,22 +107,62 @@ impl Backend {
         }
     }
 
+    async fn configure(&self, config: ProjectConfig) {
+        let mut errors = vec![];
+        {
+            let mut project = self.project.write();
+            project.set_include_paths(config.includes);
+            if let Err(err) = project.set_binding_paths(config.bindings) {
+                errors.push(format!("Cannot load binding schemas: {err}"));
+            }
+            // Overlays whose base was removed are re-analyzed without it
+            project.clear_overlay_bases();
+            for (overlay, base) in config.overlays {
+                if let Err(err) = project.set_overlay_base(overlay.clone(), base.clone()) {
+                    errors.push(format!("Cannot use {base} as base of {overlay}: {err}"));
+                }
+            }
+        }
+        for error in errors {
+            self.client.show_message(MessageType::ERROR, error).await;
+        }
+    }
+
     async fn publish_diagnostics(&self) {
-        let file_paths = self
-            .project
-            .read()
-            .files()
-            .map(|file| file.to_owned())
-            .collect_vec();
-        for file in file_paths {
-            let diagnostics = self
-                .project
-                .read()
-                .get_diagnostics(&file)
-                .map(|diag| self.lsp_diag_from_diag(diag))
+        // Diagnostics can point into files that are included using `#include`.
+        // These are published for the included file.
+        let mut diagnostics: HashMap<PathBuf, Vec<Diagnostic>> = HashMap::new();
+        {
+            let project = self.project.read();
+            for file in project.files() {
+                diagnostics.entry(file.to_owned()).or_default();
+                for diagnostic in project.get_diagnostics(file) {
+                    diagnostics
+                        .entry(diagnostic.source().to_path_buf())
+                        .or_default()
+                        .push(self.lsp_diag_from_diag(diagnostic));
+                }
+            }
+        }
+        let stale = {
+            let mut published = self.published.write();
+            let stale = published
+                .iter()
+                .filter(|file| !diagnostics.contains_key(*file))
+                .cloned()
                 .collect_vec();
+            *published = diagnostics.keys().cloned().collect();
+            stale
+        };
+        for file in stale {
+            diagnostics.insert(file, vec![]);
+        }
+        for (file, diagnostics) in diagnostics {
+            let Ok(url) = Url::from_file_path(&file) else {
+                continue;
+            };
             self.client
-                .publish_diagnostics(Url::from_file_path(&file).unwrap(), diagnostics, None)
+                .publish_diagnostics(url, diagnostics, None)
                 .await
         }
     }
@@ -110,7 +172,7 @@ impl Backend {
 impl LanguageServer for Backend {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
         let config = ProjectConfig::from_value(params.initialization_options.unwrap_or_default());
-        self.project.write().set_include_paths(config.includes);
+        self.configure(config).await;
 
         Ok(InitializeResult {
             server_info: None,
@@ -128,7 +190,7 @@ impl LanguageServer for Backend {
 
     async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
         let config = ProjectConfig::from_value(params.settings);
-        self.project.write().set_include_paths(config.includes);
+        self.configure(config).await;
         self.publish_diagnostics().await
     }
 
@@ -186,7 +248,16 @@ impl LanguageServer for Backend {
         let pos = position_to_ginko_position(params.text_document_position_params.position);
         let project = self.project.read();
         let Some(item) = project.find_at_pos(&file_path, &pos) else {
-            return Ok(None);
+            // Go to the definition of a macro
+            let definition = project
+                .macro_expansion_at(&file_path, &pos)
+                .and_then(|expansion| expansion.definition.as_ref());
+            return Ok(definition.and_then(|definition| {
+                Some(GotoDefinitionResponse::Scalar(Location::new(
+                    Url::from_file_path(definition.source()).ok()?,
+                    ginko_span_to_range(definition.span()),
+                )))
+            }));
         };
         match item {
             ItemAtCursor::Reference(reference) => {
@@ -225,7 +296,16 @@ impl LanguageServer for Backend {
         let pos = position_to_ginko_position(params.text_document_position_params.position);
         let project = self.project.read();
         let Some(item) = project.find_at_pos(&file_path, &pos) else {
-            return Ok(None);
+            return Ok(project
+                .macro_expansion_at(&file_path, &pos)
+                .map(|expansion| Hover {
+                    contents: HoverContents::Scalar(MarkedString::String(format!(
+                        "Macro `{}` expands to `{}`",
+                        expansion.name,
+                        expansion.expanded.trim()
+                    ))),
+                    range: Some(ginko_span_to_range(expansion.invocation.span())),
+                }));
         };
         let str = match item {
             ItemAtCursor::Reference(reference) => {
@@ -234,7 +314,9 @@ impl LanguageServer for Backend {
                     None => return Ok(None),
                 }
             }
-            ItemAtCursor::Label(name) => name.item().clone(),
+            ItemAtCursor::Label(name) => project
+                .document_label(&file_path, name.item())
+                .unwrap_or_else(|| name.item().clone()),
             ItemAtCursor::Include(include) => include.file_name.item().clone(),
             _ => return Ok(None),
         };
@@ -269,7 +351,7 @@ impl LanguageServer for Backend {
                         deprecated: None,
                         range: ginko_span_to_range(prop.span()),
                         selection_range: ginko_span_to_range(prop.name.span()),
-                        children: None,
+                        children: value_labels_to_symbols(prop),
                     },
                     NodeItem::Node(node) => node_to_symbol(node),
                     NodeItem::DeletedNode(start_tok, deleted_node) => DocumentSymbol {
@@ -304,6 +386,27 @@ impl LanguageServer for Backend {
             children
         }
         #[allow(deprecated)]
+        fn value_labels_to_symbols(property: &Property) -> Option<Vec<DocumentSymbol>> {
+            let labels = property
+                .value_labels()
+                .map(|label| DocumentSymbol {
+                    name: format!("{}:", label.item()),
+                    detail: None,
+                    kind: SymbolKind::KEY,
+                    tags: None,
+                    deprecated: None,
+                    range: ginko_span_to_range(label.span()),
+                    selection_range: ginko_span_to_range(label.span()),
+                    children: None,
+                })
+                .collect_vec();
+            if labels.is_empty() {
+                None
+            } else {
+                Some(labels)
+            }
+        }
+        #[allow(deprecated)]
         fn node_to_symbol(node: &Node) -> DocumentSymbol {
             DocumentSymbol {
                 name: node.name.name.clone(),
@@ -316,10 +419,22 @@ impl LanguageServer for Backend {
                 children: Some(node_payload_to_symbol(&node.payload)),
             }
         }
+        // Skip elements that are included from other files using `#include`
+        let source = root.source();
+        let is_local = |el: &&Primary| match el {
+            Primary::Root(node) => node.name.source() == source,
+            Primary::ReferencedNode(ref_node) => ref_node.reference.source() == source,
+            Primary::Directive(AnyDirective::Include(include)) => include.source() == source,
+            Primary::Directive(
+                AnyDirective::DeletedNode(token, _) | AnyDirective::OmitIfNoRef(token, _),
+            ) => token.source() == source,
+            _ => true,
+        };
         #[allow(deprecated)]
         let nodes = root
             .elements
             .iter()
+            .filter(is_local)
             .filter_map(|el| match el {
                 Primary::Root(node) => Some(node_to_symbol(node)),
                 Primary::ReferencedNode(ref_node) => Some(DocumentSymbol {